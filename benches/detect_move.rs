@@ -0,0 +1,52 @@
+//! `detect_move` over long sequences of field updates, as a player straightening
+//! pieces before making the move leaves them.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use jackolope::game::{detect_move, StartPosition};
+use jackolope::protocol::{ChessBoard, ChessMove, RawPiece};
+use jackolope::rules::Position;
+
+/// `adjustments` pieces lifted and put back in place, then e2-e4
+fn noisy_updates(board: &ChessBoard, adjustments: usize) -> Vec<ChessMove> {
+    let occupied: Vec<u8> = (0..64)
+        .filter(|&grid| board.board[grid as usize] != RawPiece::Empty)
+        .collect();
+    let mut updates = Vec::new();
+    for grid in occupied.iter().cycle().take(adjustments) {
+        updates.push(ChessMove {
+            grid: *grid,
+            piece: RawPiece::Empty,
+        });
+        updates.push(ChessMove {
+            grid: *grid,
+            piece: board.board[*grid as usize],
+        });
+    }
+    updates.push(ChessMove {
+        grid: 52,
+        piece: RawPiece::Empty,
+    });
+    updates.push(ChessMove {
+        grid: 36,
+        piece: RawPiece::WhitePawn,
+    });
+    updates
+}
+
+fn detect(c: &mut Criterion) {
+    let board = StartPosition::Mirror.layout(&Position::starting());
+    let mut group = c.benchmark_group("detect_move");
+    for adjustments in [0, 32, 1024] {
+        let updates = noisy_updates(&board, adjustments);
+        assert!(detect_move(&board, &updates).is_some());
+        group.bench_with_input(
+            BenchmarkId::from_parameter(adjustments),
+            &updates,
+            |b, updates| b.iter(|| detect_move(black_box(&board), black_box(updates))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, detect);
+criterion_main!(benches);
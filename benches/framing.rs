@@ -0,0 +1,66 @@
+//! Throughput of the framer over a stream of mixed frames, handed to it in reads the
+//! size a serial port gives.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use jackolope::game::StartPosition;
+use jackolope::protocol::{Framer, MessageType, RawFrame};
+use jackolope::rules::Position;
+
+/// About 64 kB of what a board sends during a game: mostly field updates and clock
+/// times, a board dump now and then, and a byte of noise between some frames
+fn stream() -> Vec<u8> {
+    let board = StartPosition::Mirror.layout(&Position::starting());
+    let frame = |message_type: MessageType, data: Vec<u8>| {
+        RawFrame {
+            message_type: message_type as u8,
+            data,
+        }
+        .to_bytes()
+    };
+    let dump = frame(
+        MessageType::BoardDump,
+        board.board.map(|piece| piece as u8).to_vec(),
+    );
+    let mut bytes = Vec::new();
+    let mut i = 0u8;
+    while bytes.len() < 64 * 1024 {
+        bytes.extend(frame(MessageType::FieldUpdate, vec![i % 64, 0x00]));
+        bytes.extend(frame(MessageType::FieldUpdate, vec![(i + 8) % 64, 0x01]));
+        bytes.extend(frame(
+            MessageType::BWTime,
+            vec![0x00, 0x05, i % 60, 0x00, 0x04, 0x59, 0x08],
+        ));
+        if i.is_multiple_of(16) {
+            bytes.extend(&dump);
+            bytes.push(0x00);
+        }
+        i = i.wrapping_add(1);
+    }
+    bytes
+}
+
+fn framer(c: &mut Criterion) {
+    let bytes = stream();
+    let mut group = c.benchmark_group("framer");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    for read in [1, 64, 4096] {
+        group.bench_with_input(BenchmarkId::new("feed", read), &read, |b, &read| {
+            // One framer and response buffer for every run, as a board keeps them
+            let mut framer = Framer::new();
+            let mut responses = Vec::new();
+            b.iter(|| {
+                let mut count = 0;
+                for chunk in bytes.chunks(read) {
+                    responses.clear();
+                    framer.feed_into(black_box(chunk), &mut responses);
+                    count += responses.len();
+                }
+                count
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, framer);
+criterion_main!(benches);
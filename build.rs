@@ -0,0 +1,27 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // The C header is generated into OUT_DIR from src/ffi.rs alone, so nothing else of
+    // the crate ends up in it. The copy kept in the repository for C programs built
+    // without a Rust toolchain is refreshed by hand, a warning says when it is stale.
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        println!("cargo:rerun-if-changed=include/jackolope.h");
+        let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let header = format!("{}/jackolope.h", std::env::var("OUT_DIR").unwrap());
+        cbindgen::Builder::new()
+            .with_src(format!("{}/src/ffi.rs", dir))
+            .with_config(cbindgen::Config::from_file(format!("{}/cbindgen.toml", dir)).unwrap())
+            .generate()
+            .expect("failed to generate the C header")
+            .write_to_file(&header);
+        let kept = std::fs::read_to_string(format!("{}/include/jackolope.h", dir));
+        if kept.ok() != std::fs::read_to_string(&header).ok() {
+            println!(
+                "cargo:warning=include/jackolope.h is out of date, regenerate it with \
+                 `cbindgen --config cbindgen.toml --output include/jackolope.h src/ffi.rs`"
+            );
+        }
+    }
+}
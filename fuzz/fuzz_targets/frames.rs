@@ -0,0 +1,30 @@
+//! Arbitrary bytes through the framer and the response parser, run with
+//! `cargo +nightly fuzz run frames`
+
+#![no_main]
+
+use jackolope::board::read_frame;
+use jackolope::protocol::{Framer, MessageType, Response};
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    // As a serial stream, every read consumes at least a byte
+    let mut reader = Cursor::new(data);
+    while (reader.position() as usize) < data.len() {
+        let _ = read_frame(&mut reader);
+    }
+    // Pushed in pieces of every size the first byte names
+    if let Some((&first, rest)) = data.split_first() {
+        let mut framer = Framer::new();
+        for chunk in rest.chunks(first as usize % 16 + 1) {
+            let _ = framer.feed(chunk);
+        }
+    }
+    // As the body of a message of the type named by the first byte
+    if let Some((&first, body)) = data.split_first() {
+        if let Some(message_type) = MessageType::try_from_byte(first) {
+            let _ = Response::try_from_raw(message_type, body);
+        }
+    }
+});
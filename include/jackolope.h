@@ -0,0 +1,123 @@
+/* Generated by cbindgen from src/ffi.rs, do not edit */
+
+#ifndef JACKOLOPE_H
+#define JACKOLOPE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum JackolopeEventKind {
+  /**
+   * The whole board, in `board`
+   */
+  JACKOLOPE_EVENT_KIND_BOARD,
+  /**
+   * A piece was lifted or placed, see `square` and `piece`
+   */
+  JACKOLOPE_EVENT_KIND_FIELD_UPDATE,
+  /**
+   * A legal move was completed, in UCI notation in `text`
+   */
+  JACKOLOPE_EVENT_KIND_MOVE,
+  /**
+   * Clock times and status
+   */
+  JACKOLOPE_EVENT_KIND_CLOCK,
+  /**
+   * The serial number of the board, in `text`
+   */
+  JACKOLOPE_EVENT_KIND_SERIAL_NUMBER,
+  /**
+   * The firmware version of the board, in `text`
+   */
+  JACKOLOPE_EVENT_KIND_VERSION,
+} JackolopeEventKind;
+
+/**
+ * A connected board and the game followed on it
+ */
+typedef struct JackolopeBoard JackolopeBoard;
+
+/**
+ * Something the board reported. Only the fields named by `kind` are set, the rest
+ * are zero.
+ */
+typedef struct JackolopeEvent {
+  enum JackolopeEventKind kind;
+  /**
+   * Square of a field update, 0 to 63 with a8 first
+   */
+  uint8_t square;
+  /**
+   * DGT piece code placed on the square, 0 for a lift
+   */
+  uint8_t piece;
+  /**
+   * DGT piece codes of the whole board, a8 first
+   */
+  uint8_t board[64];
+  uint32_t white_seconds;
+  uint32_t black_seconds;
+  /**
+   * 0 without a clock, 1 when white is to move, 2 for black
+   */
+  uint8_t clock_status;
+  /**
+   * Text of the event, NUL terminated
+   */
+  char text[32];
+} JackolopeEvent;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Open the DGT board on serial port `port` at `baud`, 0 for the default rate, and
+ * start following it. Returns NULL when the port can not be opened.
+ *
+ * # Safety
+ *
+ * `port` must be a NUL terminated string.
+ */
+struct JackolopeBoard *jackolope_connect(const char *port, uint32_t baud);
+
+/**
+ * Close the board
+ *
+ * # Safety
+ *
+ * `board` must come from `jackolope_connect` and is not valid afterwards.
+ */
+void jackolope_disconnect(struct JackolopeBoard *board);
+
+/**
+ * Wait up to `timeout_ms` for the next event and store it in `event`. Returns 1 for
+ * an event, 0 when there was none and -1 when the board is gone.
+ *
+ * # Safety
+ *
+ * `board` must come from `jackolope_connect` and `event` point to writable memory.
+ */
+int jackolope_poll_event(struct JackolopeBoard *board,
+                         struct JackolopeEvent *event,
+                         uint32_t timeout_ms);
+
+/**
+ * Send a command byte, e.g. 0x42 to ask for the board. Returns 0 when sent and -1
+ * on failure.
+ *
+ * # Safety
+ *
+ * `board` must come from `jackolope_connect`.
+ */
+int jackolope_send_command(struct JackolopeBoard *board, uint8_t command);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* JACKOLOPE_H */
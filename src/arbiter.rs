@@ -0,0 +1,309 @@
+//! Control channel for the arbiter of a game: pause the clock, give or take time,
+//! correct the recorded moves and set the result. A commentator given the token can
+//! attach notes to moves, which end up in the PGN as comments. Commands are lines of
+//! text sent over a Unix socket, the first of which has to be the shared token.
+//!
+//! ```text
+//! token s3cret
+//! time white +60
+//! moves e2e4 e7e5 g1f3
+//! note 2... Black keeps it symmetrical
+//! result 1/2-1/2
+//! ```
+
+use crate::protocol::PieceColor;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Something the arbiter changed in the game. Interventions go through the event
+/// pipeline like anything the board reports, so the session log records them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Intervention {
+    /// Stop both clocks
+    Pause,
+    /// Start the clock of the side to move again
+    Resume,
+    /// Give `colour` this many seconds, or take them away when negative
+    AdjustClock { colour: PieceColor, seconds: i32 },
+    /// Replace the moves recorded so far, in UCI notation
+    SetMoves(Vec<String>),
+    /// End the game, `winner` is PieceColor::None for a draw
+    SetResult(PieceColor),
+    /// Comment on move `number` of `colour`, for the PGN
+    Note {
+        number: u16,
+        colour: PieceColor,
+        text: String,
+    },
+}
+
+impl FromStr for Intervention {
+    type Err = String;
+
+    /// Read a command line: pause, resume, time <white|black> <+-seconds>,
+    /// moves <uci>..., result <1-0|0-1|1/2-1/2> or note <12.|12...> <text>
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = words.next().unwrap_or_default().to_ascii_lowercase();
+        let rest: Vec<&str> = words.collect();
+        match (command.as_str(), rest.as_slice()) {
+            ("pause", []) => Ok(Intervention::Pause),
+            ("resume", []) => Ok(Intervention::Resume),
+            ("time", [colour, seconds]) => {
+                let colour = match colour.to_ascii_lowercase().as_str() {
+                    "white" => PieceColor::White,
+                    "black" => PieceColor::Black,
+                    _ => return Err(format!("no side {:?}, white or black", colour)),
+                };
+                let seconds = seconds
+                    .trim_start_matches('+')
+                    .parse()
+                    .map_err(|_| format!("not a number of seconds: {:?}", seconds))?;
+                Ok(Intervention::AdjustClock { colour, seconds })
+            }
+            ("moves", moves) => Ok(Intervention::SetMoves(
+                moves.iter().map(|uci| uci.to_string()).collect(),
+            )),
+            ("result", [result]) => match *result {
+                "1-0" => Ok(Intervention::SetResult(PieceColor::White)),
+                "0-1" => Ok(Intervention::SetResult(PieceColor::Black)),
+                "1/2-1/2" => Ok(Intervention::SetResult(PieceColor::None)),
+                _ => Err(format!("no result {:?}, 1-0, 0-1 or 1/2-1/2", result)),
+            },
+            ("note", [number, text @ ..]) if !text.is_empty() => {
+                let (number, colour) = match number.strip_suffix("...") {
+                    Some(number) => (number, PieceColor::Black),
+                    None => (number.trim_end_matches('.'), PieceColor::White),
+                };
+                let number = number
+                    .parse()
+                    .map_err(|_| format!("no move number {:?}, like 12. or 12...", number))?;
+                Ok(Intervention::Note {
+                    number,
+                    colour,
+                    text: text.join(" "),
+                })
+            }
+            _ => Err(format!("unknown arbiter command {:?}", s.trim())),
+        }
+    }
+}
+
+impl fmt::Display for Intervention {
+    /// The command line that asks for the intervention
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Intervention::Pause => f.write_str("pause"),
+            Intervention::Resume => f.write_str("resume"),
+            Intervention::AdjustClock { colour, seconds } => {
+                let side = if *colour == PieceColor::Black {
+                    "black"
+                } else {
+                    "white"
+                };
+                write!(f, "time {} {:+}", side, seconds)
+            }
+            Intervention::SetMoves(moves) if moves.is_empty() => f.write_str("moves"),
+            Intervention::SetMoves(moves) => write!(f, "moves {}", moves.join(" ")),
+            Intervention::SetResult(winner) => f.write_str(match winner {
+                PieceColor::White => "result 1-0",
+                PieceColor::Black => "result 0-1",
+                PieceColor::None => "result 1/2-1/2",
+            }),
+            Intervention::Note {
+                number,
+                colour,
+                text,
+            } => {
+                let dots = if *colour == PieceColor::Black {
+                    "..."
+                } else {
+                    "."
+                };
+                write!(f, "note {}{} {}", number, dots, text)
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use self::socket::{serve, Client};
+
+#[cfg(unix)]
+mod socket {
+    use super::Intervention;
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+    use tracing::{info, warn};
+
+    /// How long an arbiter has to send the token before the console moves on
+    const TOKEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Listen for arbiters on a Unix socket at `path`, handing every command to
+    /// `intervene`. Arbiters are served one at a time and have to start with
+    /// `token <token>`; the socket itself is only open to its owner.
+    pub fn serve(
+        path: &Path,
+        token: String,
+        intervene: impl Fn(Intervention) + Send + 'static,
+    ) -> io::Result<JoinHandle<()>> {
+        // A socket left behind by an earlier run would make the bind fail
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} is not a socket", path.display()),
+                ))
+            }
+            Err(_) => {}
+        }
+        let listener = UnixListener::bind(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        info!(path = %path.display(), "arbiter console listening");
+        thread::Builder::new()
+            .name("arbiter".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if let Err(e) = stream.and_then(|stream| session(stream, &token, &intervene)) {
+                        warn!(error = %e, "arbiter connection failed");
+                    }
+                }
+            })
+    }
+
+    /// Answer the lines of one arbiter with ok or an error until they hang up
+    fn session(
+        stream: UnixStream,
+        token: &str,
+        intervene: &dyn Fn(Intervention),
+    ) -> io::Result<()> {
+        let mut reply = stream.try_clone()?;
+        // Someone who never sends the token must not hold the console for everyone
+        stream.set_read_timeout(Some(TOKEN_TIMEOUT))?;
+        let mut lines = BufReader::new(stream).lines();
+        let first = lines.next().transpose()?.unwrap_or_default();
+        if first.trim().strip_prefix("token ").map(str::trim) != Some(token) {
+            warn!("arbiter connection with a wrong token");
+            return writeln!(reply, "error: wrong token");
+        }
+        reply.set_read_timeout(None)?;
+        writeln!(reply, "ok")?;
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match line.parse::<Intervention>() {
+                Ok(intervention) => {
+                    intervene(intervention);
+                    writeln!(reply, "ok")?;
+                }
+                Err(e) => writeln!(reply, "error: {}", e)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Connection of an arbiter to a running driver
+    pub struct Client {
+        stream: UnixStream,
+        replies: io::Lines<BufReader<UnixStream>>,
+    }
+
+    impl Client {
+        /// Connect to the socket at `path` and authenticate with `token`
+        pub fn connect(path: &Path, token: &str) -> io::Result<Client> {
+            let stream = UnixStream::connect(path)?;
+            let mut client = Client {
+                replies: BufReader::new(stream.try_clone()?).lines(),
+                stream,
+            };
+            client.send(&format!("token {}", token))?;
+            Ok(client)
+        }
+
+        /// Send a command line, an error when the driver refuses it
+        pub fn send(&mut self, line: &str) -> io::Result<()> {
+            writeln!(self.stream, "{}", line)?;
+            let reply = self.replies.next().transpose()?.unwrap_or_default();
+            match reply.strip_prefix("error: ") {
+                Some(e) => Err(io::Error::other(e)),
+                None if reply == "ok" => Ok(()),
+                None => Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "no reply from the driver",
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let commands = [
+            "pause",
+            "resume",
+            "time white +60",
+            "time black -30",
+            "moves e2e4 e7e5",
+            "moves",
+            "result 1/2-1/2",
+            "note 12... A bold reply",
+        ];
+        for command in commands {
+            let intervention: Intervention = command.parse().unwrap();
+            assert_eq!(intervention.to_string(), command);
+        }
+        assert_eq!(
+            "TIME Black 15".parse(),
+            Ok(Intervention::AdjustClock {
+                colour: PieceColor::Black,
+                seconds: 15
+            })
+        );
+        assert!("time red 15".parse::<Intervention>().is_err());
+        assert!("result 2-0".parse::<Intervention>().is_err());
+        assert!("pause now".parse::<Intervention>().is_err());
+        assert_eq!(
+            "note 3 Sharp".parse(),
+            Ok(Intervention::Note {
+                number: 3,
+                colour: PieceColor::White,
+                text: "Sharp".to_string()
+            })
+        );
+        assert!("note 3...".parse::<Intervention>().is_err());
+
+        #[cfg(unix)]
+        {
+            let path = std::env::temp_dir().join(format!("jackolope-{}.sock", std::process::id()));
+            let (tx, rx) = std::sync::mpsc::channel();
+            serve(&path, "s3cret".to_string(), move |intervention| {
+                let _ = tx.send(intervention);
+            })
+            .unwrap();
+            assert!(Client::connect(&path, "guess").is_err());
+            let mut client = Client::connect(&path, "s3cret").unwrap();
+            client.send("result 0-1").unwrap();
+            assert!(client.send("result 2-0").is_err());
+            assert_eq!(rx.recv(), Ok(Intervention::SetResult(PieceColor::Black)));
+            let _ = std::fs::remove_file(&path);
+
+            // Anything but a socket at the path is left alone
+            std::fs::write(&path, "notes").unwrap();
+            assert!(serve(&path, "s3cret".to_string(), |_| {}).is_err());
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), "notes");
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
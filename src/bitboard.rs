@@ -0,0 +1,224 @@
+//! Piece placement as bitboards, one bit per square with a1 as bit 0 like
+//! `rules::Square`. Attack queries become a handful of table lookups and bit
+//! operations instead of walking the squares around, which keeps legal move
+//! generation cheap on small board computers such as a Raspberry Pi Zero.
+
+use crate::protocol::{PieceColor, PieceKind, RawPiece};
+use crate::rules::{Ply, PlyKind, Square};
+
+/// Squares reached by one of `steps` from every square
+const fn leaper(steps: &[(i8, i8)]) -> [u64; 64] {
+    let mut out = [0; 64];
+    let mut sq = 0;
+    while sq < 64 {
+        let mut i = 0;
+        while i < steps.len() {
+            let file = (sq % 8) as i8 + steps[i].0;
+            let rank = (sq / 8) as i8 + steps[i].1;
+            if file >= 0 && file < 8 && rank >= 0 && rank < 8 {
+                out[sq] |= 1 << (rank * 8 + file);
+            }
+            i += 1;
+        }
+        sq += 1;
+    }
+    out
+}
+
+/// Squares from every square to the edge of the board in direction `step`, not
+/// including the square itself
+const fn ray(step: (i8, i8)) -> [u64; 64] {
+    let mut out = [0; 64];
+    let mut sq = 0;
+    while sq < 64 {
+        let mut file = (sq % 8) as i8 + step.0;
+        let mut rank = (sq / 8) as i8 + step.1;
+        while file >= 0 && file < 8 && rank >= 0 && rank < 8 {
+            out[sq] |= 1 << (rank * 8 + file);
+            file += step.0;
+            rank += step.1;
+        }
+        sq += 1;
+    }
+    out
+}
+
+const KNIGHT: [u64; 64] = leaper(&[
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+]);
+const KING: [u64; 64] = leaper(&[
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+]);
+/// Squares attacked by a white and by a black pawn
+const PAWN: [[u64; 64]; 2] = [leaper(&[(-1, 1), (1, 1)]), leaper(&[(-1, -1), (1, -1)])];
+
+/// Rays towards higher squares, whose nearest blocker is the lowest set bit
+const RAYS_UP: [[u64; 64]; 4] = [ray((0, 1)), ray((1, 0)), ray((1, 1)), ray((-1, 1))];
+/// Rays towards lower squares, whose nearest blocker is the highest set bit
+const RAYS_DOWN: [[u64; 64]; 4] = [ray((0, -1)), ray((-1, 0)), ray((-1, -1)), ray((1, -1))];
+
+/// Squares a slider on `sq` reaches along the rays `dirs` (straight 0..2, diagonal
+/// 2..4), up to and including the first piece in the way
+fn slide(sq: Square, occupied: u64, dirs: std::ops::Range<usize>) -> u64 {
+    let sq = sq as usize;
+    let mut attacks = 0;
+    for dir in dirs {
+        let up = RAYS_UP[dir][sq];
+        attacks |= match up & occupied {
+            0 => up,
+            blockers => up ^ RAYS_UP[dir][blockers.trailing_zeros() as usize],
+        };
+        let down = RAYS_DOWN[dir][sq];
+        attacks |= match down & occupied {
+            0 => down,
+            blockers => down ^ RAYS_DOWN[dir][63 - blockers.leading_zeros() as usize],
+        };
+    }
+    attacks
+}
+
+fn colour_index(colour: PieceColor) -> Option<usize> {
+    match colour {
+        PieceColor::White => Some(0),
+        PieceColor::Black => Some(1),
+        PieceColor::None => None,
+    }
+}
+
+/// Where the pieces stand, a bitboard for each kind and colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Bitboards {
+    /// Indexed by piece code minus one
+    pieces: [u64; 12],
+    /// White pieces, then black pieces
+    colours: [u64; 2],
+}
+
+impl Bitboards {
+    /// Bitboards of a board indexed from a1
+    pub fn new(board: &[RawPiece; 64]) -> Self {
+        let mut bitboards = Bitboards::default();
+        for (sq, piece) in board.iter().enumerate() {
+            bitboards.toggle(sq as Square, *piece);
+        }
+        bitboards
+    }
+
+    /// Put `piece` on `sq` if it is not there, take it off if it is
+    fn toggle(&mut self, sq: Square, piece: RawPiece) {
+        let Some(colour) = colour_index(piece.get_colour()) else {
+            return;
+        };
+        let bit = 1u64 << sq;
+        self.pieces[piece as usize - 1] ^= bit;
+        self.colours[colour] ^= bit;
+    }
+
+    /// Squares holding `piece`
+    pub fn pieces(&self, piece: RawPiece) -> u64 {
+        match piece {
+            RawPiece::Empty => !self.occupied(),
+            piece => self.pieces[piece as usize - 1],
+        }
+    }
+
+    /// Squares holding a piece of `colour`
+    pub fn colour(&self, colour: PieceColor) -> u64 {
+        colour_index(colour).map_or(0, |colour| self.colours[colour])
+    }
+
+    pub fn occupied(&self) -> u64 {
+        self.colours[0] | self.colours[1]
+    }
+
+    /// Square of the king of `colour`, the lowest one if there are several
+    pub fn king(&self, colour: PieceColor) -> Option<Square> {
+        let kings = self.pieces(RawPiece::from_kind(PieceKind::King, colour));
+        (kings != 0).then(|| kings.trailing_zeros() as Square)
+    }
+
+    /// Pieces of `by` that attack `sq`
+    pub fn attackers(&self, sq: Square, by: PieceColor) -> u64 {
+        let Some(them) = colour_index(by) else {
+            return 0;
+        };
+        let piece = |kind| self.pieces(RawPiece::from_kind(kind, by));
+        let i = sq as usize;
+        let occupied = self.occupied();
+        let queens = piece(PieceKind::Queen);
+        // A pawn of `by` attacks `sq` from where a pawn of the other colour on `sq`
+        // would attack
+        (PAWN[1 - them][i] & piece(PieceKind::Pawn))
+            | (KNIGHT[i] & piece(PieceKind::Knight))
+            | (KING[i] & piece(PieceKind::King))
+            | (slide(sq, occupied, 0..2) & (piece(PieceKind::Rook) | queens))
+            | (slide(sq, occupied, 2..4) & (piece(PieceKind::Bishop) | queens))
+    }
+
+    /// Whether any piece of `by` attacks `sq`
+    pub fn is_attacked(&self, sq: Square, by: PieceColor) -> bool {
+        self.attackers(sq, by) != 0
+    }
+
+    /// The placement after `ply`, which is assumed to be playable
+    pub fn play(&self, ply: &Ply) -> Bitboards {
+        let mut next = *self;
+        if ply.kind != PlyKind::Drop {
+            next.toggle(ply.from, ply.piece);
+        }
+        next.toggle(ply.capture_square(), ply.captured);
+        match ply.kind {
+            PlyKind::Promotion(piece) => next.toggle(ply.to, piece),
+            PlyKind::Castle { rook_from, rook_to } => {
+                let rook = RawPiece::from_kind(PieceKind::Rook, ply.piece.get_colour());
+                next.toggle(rook_from, rook);
+                next.toggle(rook_to, rook);
+                next.toggle(ply.to, ply.piece);
+            }
+            _ => next.toggle(ply.to, ply.piece),
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::{parse_square, Position};
+
+    #[test]
+    fn test_attacks_match_position() {
+        let position = Position::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        let bitboards = Bitboards::new(&position.board);
+        assert_eq!(bitboards.king(PieceColor::Black), parse_square("e8"));
+        let attackers = |name, by| bitboards.attackers(parse_square(name).unwrap(), by);
+        // The e6 pawn and both knights
+        assert_eq!(attackers("d5", PieceColor::Black).count_ones(), 3);
+        // Only the h3 pawn, the a6 bishop is blocked on e2
+        assert_eq!(attackers("g2", PieceColor::Black).count_ones(), 1);
+        // Rook, king, knight and the e2 bishop, which blocks the queen
+        assert_eq!(attackers("d1", PieceColor::White).count_ones(), 4);
+        assert!(!bitboards.is_attacked(parse_square("a1").unwrap(), PieceColor::Black));
+        for ply in position.legal_moves() {
+            let after = Bitboards::new(&position.play(&ply).board);
+            assert_eq!(bitboards.play(&ply), after, "{}", ply.uci());
+        }
+    }
+}
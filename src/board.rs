@@ -0,0 +1,987 @@
+use crate::clock::{ClockAck, ClockCommand, ClockModel};
+use crate::metrics::METRICS;
+use crate::pacing::{Pacer, Pacing};
+use crate::protocol::*;
+use crate::status::{ExitStatus, Failure};
+use crate::transport::{LineControl, Transport};
+use serde::Serialize;
+use serialport::SerialPort;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+pub type Error = Box<dyn std::error::Error>;
+
+/// The vendor facing side of the driver. Every board reports its squares in the DGT grid
+/// layout through `Response`, so the game logic does not care which hardware it follows.
+pub trait ElectronicBoard: Send {
+    /// Name of the board family, for log messages
+    fn name(&self) -> &str;
+
+    /// Reset the board and read the position on it
+    fn board(&mut self) -> Result<ChessBoard, Error>;
+
+    /// Serial number, None when the board has none
+    fn serial_number(&mut self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    /// Everything the board tells about itself
+    fn device_info(&mut self) -> Result<DeviceInfo, Error> {
+        Ok(DeviceInfo {
+            board: self.name().to_string(),
+            serial_number: self.serial_number()?,
+            ..DeviceInfo::default()
+        })
+    }
+
+    /// Ask the board to report every change, read them with `next_response`
+    fn start_updates(&mut self) -> Result<(), Error>;
+
+    /// Put the board back into its idle mode, where it only answers requests
+    fn stop_updates(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Wait for the next message from the board, an error after a quiet period
+    fn next_response(&mut self) -> Result<Response, Error>;
+
+    /// Wait for the next message like `next_response`, then take every message already
+    /// received along with it, so a burst of updates is handled in one pass
+    fn next_responses(&mut self) -> Result<Vec<Response>, Error> {
+        self.next_response().map(|response| vec![response])
+    }
+
+    /// Ask for the position without leaving update mode, it arrives through
+    /// `next_response` as a board dump. False if there is no way to.
+    fn request_board(&mut self) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    /// Forward a message to a clock attached to the board, false if there is no way to
+    fn send_clock(&mut self, _command: &ClockCommand) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    /// Spacing of the commands sent to the board
+    fn set_pacing(&mut self, _pacing: Pacing) {}
+
+    /// Pass on board dumps that fail `ChessBoard::check` as plain dumps instead of
+    /// `Response::ImplausibleDump`, for variants that need more than one set of pieces
+    fn allow_implausible_dumps(&mut self, _allow: bool) {}
+
+    /// Accept frames padded past the length the protocol gives, see `ParseMode`
+    fn set_parse_mode(&mut self, _mode: ParseMode) {}
+
+    /// Hand frames of `msg_type` to `handler` instead of decoding them, see
+    /// `Framer::register_raw_handler`. Boards speaking another protocol ignore it.
+    fn register_raw_handler(&mut self, _msg_type: u8, _handler: RawHandler) {}
+
+    /// The square lights, None for boards without them
+    fn leds(&mut self) -> Option<&mut dyn LedControl> {
+        None
+    }
+}
+
+/// Lights on or around the squares, addressed by grid index like the field updates
+pub trait LedControl {
+    /// Light the squares of a move, replacing whatever was lit before
+    fn highlight_move(&mut self, from: u8, to: u8) -> Result<(), Error>;
+
+    /// Blink a single square, replacing whatever was lit before
+    fn flash_square(&mut self, grid: u8) -> Result<(), Error>;
+
+    /// Turn all lights off
+    fn clear(&mut self) -> Result<(), Error>;
+}
+
+/// Identification of a board and the clock attached to it, None where the board did
+/// not answer
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DeviceInfo {
+    pub board: String,
+    pub serial_number: Option<String>,
+    pub long_serial_number: Option<String>,
+    pub version: Option<String>,
+    pub trademark: Option<String>,
+    pub bus_address: Option<u16>,
+    /// Firmware version of a DGT3000 clock
+    pub clock_version: Option<String>,
+}
+
+/// Supported board families
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Dgt,
+    #[cfg(feature = "millennium")]
+    Millennium,
+}
+
+impl std::str::FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dgt" => Ok(Kind::Dgt),
+            #[cfg(feature = "millennium")]
+            "millennium" => Ok(Kind::Millennium),
+            _ => Err(format!("unknown or disabled board type {:?}", s)),
+        }
+    }
+}
+
+/// Serial line speed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Baud {
+    Fixed(u32),
+    /// Try the usual rates until the board answers
+    Auto,
+}
+
+impl std::str::FromStr for Baud {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Baud::Auto),
+            _ => s
+                .parse()
+                .map(Baud::Fixed)
+                .map_err(|_| format!("invalid baud rate {:?}", s)),
+        }
+    }
+}
+
+/// Rates tried by `Baud::Auto`, the DGT default first
+pub const PROBE_RATES: [u32; 5] = [9600, 19200, 38400, 57600, 115200];
+
+/// Open the board of type `kind` on serial port `port`, at the default rate of the
+/// board family when `baud` is None. The line settings follow from the transport,
+/// which is detected when not given.
+pub fn open(
+    kind: Kind,
+    port: &str,
+    baud: Option<Baud>,
+    transport: Option<Transport>,
+) -> Result<Box<dyn ElectronicBoard>, Error> {
+    Ok(match kind {
+        Kind::Dgt => Box::new(open_dgt(port, baud, transport)?),
+        #[cfg(feature = "millennium")]
+        Kind::Millennium => {
+            let transport = transport.unwrap_or_else(|| Transport::detect(port));
+            let line = LineControl::for_board(kind, transport);
+            debug!(?transport, ?line, "line settings");
+            let rate = match baud {
+                Some(Baud::Fixed(rate)) => rate,
+                _ => crate::millennium::MillenniumBoard::DEFAULT_BAUD,
+            };
+            Box::new(crate::millennium::MillenniumBoard::open(port, rate, line)?)
+        }
+    })
+}
+
+/// Like `open`, for callers that need the DGT board itself rather than the trait
+pub fn open_dgt(
+    port: &str,
+    baud: Option<Baud>,
+    transport: Option<Transport>,
+) -> Result<DgtBoard, Error> {
+    let transport = transport.unwrap_or_else(|| Transport::detect(port));
+    let line = LineControl::for_board(Kind::Dgt, transport);
+    debug!(?transport, ?line, "line settings");
+    Ok(match baud {
+        None => DgtBoard::open(port, DgtBoard::DEFAULT_BAUD, line)?,
+        Some(Baud::Fixed(rate)) => DgtBoard::open(port, rate, line)?,
+        Some(Baud::Auto) => DgtBoard::negotiate(port, line)?,
+    })
+}
+
+/// How long a request waits for its reply unless told otherwise
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+const REPLY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Lets another thread give up on a request that is waiting for its reply
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn new() -> Self {
+        Cancel::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How a request ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply<T> {
+    Answer(T),
+    TimedOut,
+    Cancelled,
+}
+
+/// Whether the board can be heard from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum ConnectionStatus {
+    #[default]
+    Connected,
+    /// Updates are paused with `pause_updates`
+    Paused,
+    /// A read failed in a way that means the board is gone
+    Disconnected,
+}
+
+/// What the driver knows about the board at one moment, all parts from the same moment
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct BoardSnapshot {
+    /// The last board dump with the field updates after it, None before the first dump
+    pub board: Option<ChessBoard>,
+    /// Last times and status reported by the clock
+    pub clock: Option<(Remaining, Remaining, ClockStatus)>,
+    /// Times the clock passed the turn from one side to the other, the number of half
+    /// moves played where the players press the clock
+    pub moves: u32,
+    pub connection: ConnectionStatus,
+    /// Messages taken in so far, so a consumer can tell whether anything changed
+    pub sequence: u64,
+}
+
+impl BoardSnapshot {
+    /// Bring the snapshot up to date with a message from the board
+    pub fn take_in(&mut self, response: &Response) {
+        match response {
+            Response::BoardDump(board) | Response::ImplausibleDump { board, .. } => {
+                self.board = Some(*board)
+            }
+            Response::FieldUpdate(mv) => {
+                if let Some(board) = &mut self.board {
+                    if let Some(square) = board.board.get_mut(mv.grid as usize) {
+                        *square = mv.piece;
+                    }
+                }
+            }
+            Response::BWTime {
+                white_time,
+                black_time,
+                status,
+            } => {
+                let turned = matches!(
+                    (self.clock.map(|(_, _, before)| before), status),
+                    (Some(ClockStatus::WhitesTurn), ClockStatus::BlacksTurn)
+                        | (Some(ClockStatus::BlacksTurn), ClockStatus::WhitesTurn)
+                );
+                if turned {
+                    self.moves += 1;
+                }
+                self.clock = Some((*white_time, *black_time, *status));
+            }
+            _ => {}
+        }
+        self.sequence += 1;
+    }
+}
+
+/// Whether a read error means the board is gone rather than just quiet or garbled
+pub fn is_disconnect(error: &Error) -> bool {
+    error
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() != std::io::ErrorKind::TimedOut)
+}
+
+/// DGT board speaking the binary serial protocol
+pub struct DgtBoard {
+    port: BufReader<Box<dyn SerialPort>>,
+    /// Responses received while paused or resuming, handed out before reading more
+    queued: VecDeque<Response>,
+    /// Position when updates were paused
+    paused: Option<ChessBoard>,
+    /// Every write waits for its turn here
+    pacer: Pacer,
+    /// Parts of a split board dump received so far
+    dumps: DumpAssembler,
+    /// How to ask for the board, None until the first reset finds out
+    dump_command: Option<Command>,
+    /// Pass on board dumps that fail `ChessBoard::check` as plain dumps
+    allow_implausible: bool,
+    /// Kept between reads, with the parse mode set by `set_parse_mode`
+    framer: Framer,
+    /// How to send text to the clock, found out by `device_info`
+    clock_model: ClockModel,
+    /// Updated under the lock as messages are read, see `snapshot`
+    state: Arc<Mutex<BoardSnapshot>>,
+}
+
+/// Field updates turning `before` into `after`, pieces lifted before pieces placed
+pub fn changes(before: &ChessBoard, after: &ChessBoard) -> Vec<ChessMove> {
+    let changed =
+        (0..64u8).filter(|&grid| before.board[grid as usize] != after.board[grid as usize]);
+    let (lifted, placed): (Vec<u8>, Vec<u8>) =
+        changed.partition(|&grid| after.board[grid as usize] == RawPiece::Empty);
+    lifted
+        .into_iter()
+        .chain(placed)
+        .map(|grid| ChessMove {
+            grid,
+            piece: after.board[grid as usize],
+        })
+        .collect()
+}
+
+impl DgtBoard {
+    pub const DEFAULT_BAUD: u32 = 9600;
+
+    pub fn open(name: &str, baud: u32, line: LineControl) -> Result<Self, serialport::Error> {
+        let port = line.open(
+            serialport::new(name, baud)
+                .data_bits(serialport::DataBits::Eight)
+                .parity(serialport::Parity::None)
+                .stop_bits(serialport::StopBits::One)
+                .timeout(Duration::from_millis(1000)),
+        )?;
+        Ok(DgtBoard {
+            port: BufReader::new(port),
+            queued: VecDeque::new(),
+            paused: None,
+            pacer: Pacer::default(),
+            dumps: DumpAssembler::default(),
+            dump_command: None,
+            allow_implausible: false,
+            framer: Framer::new(),
+            clock_model: ClockModel::default(),
+            state: Arc::default(),
+        })
+    }
+
+    /// Everything known about the board, taken in one go so the parts agree
+    pub fn snapshot(&self) -> BoardSnapshot {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// The state behind `snapshot`, for threads that do not own the board to look at
+    pub fn shared_state(&self) -> Arc<Mutex<BoardSnapshot>> {
+        self.state.clone()
+    }
+
+    fn set_connection(&self, connection: ConnectionStatus) {
+        self.state.lock().unwrap().connection = connection;
+    }
+
+    /// Open the port at each of `PROBE_RATES` until the board answers a version request
+    pub fn negotiate(name: &str, line: LineControl) -> Result<Self, Error> {
+        for rate in PROBE_RATES {
+            let mut board = DgtBoard::open(name, rate, line)?;
+            board.port.get_mut().clear(serialport::ClearBuffer::All)?;
+            let version = board.query(Command::RequestVersion, |response| match response {
+                Response::Version(version) => Some(version.clone()),
+                _ => None,
+            })?;
+            if let Some(version) = version {
+                info!(baud = rate, %version, "board answered");
+                return Ok(board);
+            }
+            debug!(baud = rate, "no answer");
+        }
+        Err(Failure::new(
+            ExitStatus::HandshakeFailed,
+            format!("no answer from a DGT board at {:?}", PROBE_RATES),
+        )
+        .into())
+    }
+
+    /// Beep the clock attached to the board for about `duration`
+    pub fn clock_beep(&mut self, duration: Duration) -> Result<(), Error> {
+        self.send_clock(&ClockCommand::beep(duration)).map(|_| ())
+    }
+
+    pub fn send(&mut self, command: Command) -> std::io::Result<()> {
+        self.write(&command.as_byte(), command == Command::Reset)
+    }
+
+    /// Write bytes as they are, for exploring the protocol. Bytes starting with a
+    /// reset get the settle time of one.
+    pub fn send_raw(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.write(bytes, bytes.first() == Some(&(Command::Reset as u8)))
+    }
+
+    /// The next frame undecoded, None when none arrives within `timeout`
+    pub fn read_raw(&mut self, timeout: Duration) -> Result<Option<RawFrame>, Error> {
+        let deadline = Instant::now() + timeout;
+        while !self.input_waiting()? {
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(REPLY_POLL_INTERVAL);
+        }
+        read_raw_frame_using(&mut self.port, &mut self.framer).map(Some)
+    }
+
+    /// The next message like `next_response`, None when none arrives within `timeout`
+    pub fn poll_response(&mut self, timeout: Duration) -> Result<Option<Response>, Error> {
+        let deadline = Instant::now() + timeout;
+        while self.queued.is_empty() && !self.input_waiting()? {
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(REPLY_POLL_INTERVAL);
+        }
+        self.next_response().map(Some)
+    }
+
+    /// Whether bytes were received that are not read yet
+    fn input_waiting(&self) -> Result<bool, Error> {
+        Ok(!self.port.buffer().is_empty() || self.port.get_ref().bytes_to_read()? > 0)
+    }
+
+    /// Write to the board once the previous command has had its time
+    fn write(&mut self, bytes: &[u8], reset: bool) -> std::io::Result<()> {
+        self.pacer.wait();
+        let result = self.port.get_mut().write_all(bytes);
+        self.pacer.sent(reset);
+        result
+    }
+
+    /// Send `command` and wait for the response `pick` accepts, for at most `timeout`
+    /// or until `cancel` fires from another thread. Messages the board sends on its
+    /// own meanwhile are kept for `next_response`.
+    pub fn request<T>(
+        &mut self,
+        command: Command,
+        pick: impl Fn(&Response) -> Option<T>,
+        timeout: Duration,
+        cancel: &Cancel,
+    ) -> Result<Reply<T>, Error> {
+        self.send(command)?;
+        self.await_reply(pick, timeout, cancel)
+    }
+
+    fn await_reply<T>(
+        &mut self,
+        pick: impl Fn(&Response) -> Option<T>,
+        timeout: Duration,
+        cancel: &Cancel,
+    ) -> Result<Reply<T>, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if cancel.is_cancelled() {
+                debug!("request cancelled");
+                return Ok(Reply::Cancelled);
+            }
+            // Only read once bytes are there, so waiting never outlasts a cancel by
+            // more than a poll interval
+            if self.port.buffer().is_empty() && self.port.get_ref().bytes_to_read()? == 0 {
+                if Instant::now() >= deadline {
+                    return Ok(Reply::TimedOut);
+                }
+                thread::sleep(REPLY_POLL_INTERVAL);
+                continue;
+            }
+            match self.read_response() {
+                Ok(response) => match pick(&response) {
+                    Some(answer) => return Ok(Reply::Answer(answer)),
+                    None => self.queued.push_back(response),
+                },
+                Err(e) => debug!(error = %e, "skipping unreadable message"),
+            }
+        }
+    }
+
+    /// Wait for the response `pick` accepts. Older boards ignore some requests, so
+    /// no answer within `REPLY_TIMEOUT` gives None rather than an error.
+    fn answer<T>(&mut self, pick: impl Fn(&Response) -> Option<T>) -> Result<Option<T>, Error> {
+        match self.await_reply(pick, REPLY_TIMEOUT, &Cancel::default())? {
+            Reply::Answer(answer) => Ok(Some(answer)),
+            Reply::TimedOut | Reply::Cancelled => {
+                debug!("no answer");
+                Ok(None)
+            }
+        }
+    }
+
+    fn query<T>(
+        &mut self,
+        command: Command,
+        pick: impl Fn(&Response) -> Option<T>,
+    ) -> Result<Option<T>, Error> {
+        self.send(command)?;
+        self.answer(pick)
+    }
+
+    /// Read and decode the next frame, skipping bytes until a frame start. The parts
+    /// of a split board dump are read up to the last and returned as one dump.
+    pub fn read_response(&mut self) -> Result<Response, Error> {
+        let response = self.read_assembled();
+        match &response {
+            Ok(response) => self.state.lock().unwrap().take_in(response),
+            Err(e) if is_disconnect(e) => self.set_connection(ConnectionStatus::Disconnected),
+            Err(_) => {}
+        }
+        response
+    }
+
+    fn read_assembled(&mut self) -> Result<Response, Error> {
+        loop {
+            let response = match read_frame_using(&mut self.port, &mut self.framer)? {
+                Response::PartialDump { first, pieces } => match self.dumps.add(first, &pieces) {
+                    Some(board) => Response::checked_dump(board),
+                    None => continue,
+                },
+                response => response,
+            };
+            return Ok(response.allowing_implausible(self.allow_implausible));
+        }
+    }
+
+    /// Ask for the position as a 0x69 dump first, which newer firmware answers in one
+    /// frame, and settle on `RequestBoard` for boards that ignore it
+    fn detect_dump_command(&mut self) -> Result<Option<ChessBoard>, Error> {
+        let board = self.query(Command::RequestDump93, Response::dump)?;
+        let command = match board {
+            Some(_) => Command::RequestDump93,
+            None => Command::RequestBoard,
+        };
+        debug!(?command, "dump command");
+        self.dump_command = Some(command);
+        Ok(board)
+    }
+
+    /// Ask for the position, keeping the responses that arrive before it for later
+    fn read_position(&mut self) -> Result<ChessBoard, Error> {
+        self.send(self.dump_command.unwrap_or(Command::RequestBoard))?;
+        loop {
+            let response = self.read_response()?;
+            match response.dump() {
+                Some(board) => return Ok(board),
+                None => self.queued.push_back(response),
+            }
+        }
+    }
+
+    /// Stop the update stream while the host is not reading events, e.g. while the
+    /// arbiter fixes the board. The board goes idle; frames already on their way are
+    /// kept and `resume_updates` reports what changed in the meantime.
+    pub fn pause_updates(&mut self) -> Result<(), Error> {
+        if self.paused.is_some() {
+            return Ok(());
+        }
+        self.send(Command::Reset)?;
+        let board = self.read_position()?;
+        debug!(queued = self.queued.len(), "updates paused");
+        self.paused = Some(board);
+        self.set_connection(ConnectionStatus::Paused);
+        Ok(())
+    }
+
+    /// Turn the update stream back on. The squares that changed while paused come
+    /// first, as field updates with lifts before placements.
+    pub fn resume_updates(&mut self) -> Result<(), Error> {
+        let Some(before) = self.paused else {
+            return Ok(());
+        };
+        let after = self.read_position()?;
+        let changes = changes(&before, &after);
+        debug!(changes = changes.len(), "updates resumed");
+        self.queued
+            .extend(changes.into_iter().map(Response::FieldUpdate));
+        self.paused = None;
+        self.set_connection(ConnectionStatus::Connected);
+        self.send(Command::RequestUpdate)?;
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+}
+
+/// Read one DGT frame from `reader`. Reads go through a buffer, so a whole frame
+/// usually costs a single system call.
+pub fn read_frame(reader: &mut impl BufRead) -> Result<Response, Error> {
+    read_frame_with(reader, ParseMode::Strict)
+}
+
+/// Like `read_frame`, with the length of the frame held to `mode`
+pub fn read_frame_with(reader: &mut impl BufRead, mode: ParseMode) -> Result<Response, Error> {
+    let mut framer = Framer::new();
+    framer.set_parse_mode(mode);
+    read_frame_using(reader, &mut framer)
+}
+
+/// Like `read_frame`, with `framer` keeping its buffer and any partial frame from one
+/// call to the next, so reading allocates nothing for frames without strings in them
+pub fn read_frame_using(reader: &mut impl BufRead, framer: &mut Framer) -> Result<Response, Error> {
+    match fill(reader, framer, Framer::next_response)? {
+        Ok(response) => {
+            debug!(?response, "received response");
+            METRICS.frames.inc();
+            Ok(response)
+        }
+        Err(e) => {
+            if matches!(e, FrameError::Parse(_)) {
+                warn!(error = ?e, "failed to decode response");
+            }
+            METRICS.parse_errors.inc();
+            Err(e.into())
+        }
+    }
+}
+
+/// Read one DGT frame from `reader` without decoding it
+pub fn read_raw_frame(reader: &mut impl BufRead, mode: ParseMode) -> Result<RawFrame, Error> {
+    let mut framer = Framer::new();
+    framer.set_parse_mode(mode);
+    read_raw_frame_using(reader, &mut framer)
+}
+
+fn read_raw_frame_using(reader: &mut impl BufRead, framer: &mut Framer) -> Result<RawFrame, Error> {
+    fill(reader, framer, Framer::next_frame)?.map_err(|e| {
+        METRICS.parse_errors.inc();
+        e.into()
+    })
+}
+
+/// Hand `framer` bytes from `reader` until `next` takes a frame out of it. Only as
+/// many bytes as the frame needs are taken, the rest stay in `reader` for the next
+/// call.
+fn fill<T>(
+    reader: &mut impl BufRead,
+    framer: &mut Framer,
+    next: impl Fn(&mut Framer) -> Option<Result<T, FrameError>>,
+) -> Result<Result<T, FrameError>, Error> {
+    loop {
+        if let Some(frame) = next(framer) {
+            return Ok(frame);
+        }
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        let taken = available.len().min(framer.needed());
+        framer.push(&available[..taken]);
+        reader.consume(taken);
+    }
+}
+
+impl ElectronicBoard for DgtBoard {
+    fn name(&self) -> &str {
+        "DGT"
+    }
+
+    fn board(&mut self) -> Result<ChessBoard, Error> {
+        self.queued.clear();
+        self.paused = None;
+        self.set_connection(ConnectionStatus::Connected);
+        self.dumps = DumpAssembler::default();
+        self.send(Command::Reset)?;
+        let detected = match self.dump_command {
+            None => self.detect_dump_command()?,
+            Some(_) => None,
+        };
+        // A board sending clock times keeps doing so after the reset, those frames can
+        // come before the dump and are kept for `next_response`
+        let board = match detected {
+            Some(board) => board,
+            None => {
+                let command = self.dump_command.unwrap_or(Command::RequestBoard);
+                match self.request(command, Response::dump, REPLY_TIMEOUT, &Cancel::default())? {
+                    Reply::Answer(board) => board,
+                    Reply::TimedOut | Reply::Cancelled => {
+                        return Err(Failure::new(
+                            ExitStatus::HandshakeFailed,
+                            "no board dump in answer",
+                        )
+                        .into())
+                    }
+                }
+            }
+        };
+        // The dump shows the squares that changed before it already
+        self.queued
+            .retain(|response| !matches!(response, Response::FieldUpdate(_)));
+        Ok(board)
+    }
+
+    fn serial_number(&mut self) -> Result<Option<String>, Error> {
+        self.query(Command::RequestSerialNumber, |response| match response {
+            Response::SerialNumber(serial) => Some(serial.clone()),
+            _ => None,
+        })
+    }
+
+    fn device_info(&mut self) -> Result<DeviceInfo, Error> {
+        let serial_number = self.serial_number()?;
+        let long_serial_number =
+            self.query(
+                Command::RequestLongSerialNumber,
+                |response| match response {
+                    Response::LongSerialNumber(serial) => Some(serial.clone()),
+                    _ => None,
+                },
+            )?;
+        let version = self.query(Command::RequestVersion, |response| match response {
+            Response::Version(version) => Some(version.clone()),
+            _ => None,
+        })?;
+        let trademark = self.query(Command::RequestTrademark, |response| match response {
+            Response::Trademark(trademark) => Some(trademark.clone()),
+            _ => None,
+        })?;
+        let bus_address = self.query(Command::RequestBusAddress, |response| match response {
+            Response::BusAddress(address) => Some(*address),
+            _ => None,
+        })?;
+        self.send_clock(&ClockCommand::Version)?;
+        let clock_version = self.answer(|response| match response {
+            Response::ClockAck(ClockAck::Version { major, minor }) => {
+                Some(format!("{}.{}", major, minor))
+            }
+            _ => None,
+        })?;
+        self.clock_model = ClockModel::from_version(clock_version.as_deref());
+        debug!(model = ?self.clock_model, "clock text encoding");
+        Ok(DeviceInfo {
+            board: self.name().to_string(),
+            serial_number,
+            long_serial_number,
+            version,
+            trademark,
+            bus_address,
+            clock_version,
+        })
+    }
+
+    fn start_updates(&mut self) -> Result<(), Error> {
+        Ok(self.send(Command::RequestUpdate)?)
+    }
+
+    fn stop_updates(&mut self) -> Result<(), Error> {
+        Ok(self.send(Command::Reset)?)
+    }
+
+    fn set_pacing(&mut self, pacing: Pacing) {
+        self.pacer.set_pacing(pacing);
+    }
+
+    fn allow_implausible_dumps(&mut self, allow: bool) {
+        self.allow_implausible = allow;
+    }
+
+    fn set_parse_mode(&mut self, mode: ParseMode) {
+        self.framer.set_parse_mode(mode);
+    }
+
+    fn register_raw_handler(&mut self, msg_type: u8, handler: RawHandler) {
+        self.framer
+            .register_raw_handler(msg_type, move |data| handler(data));
+    }
+
+    fn request_board(&mut self) -> Result<bool, Error> {
+        self.send(self.dump_command.unwrap_or(Command::RequestBoard))?;
+        Ok(true)
+    }
+
+    fn next_response(&mut self) -> Result<Response, Error> {
+        if let Some(response) = self.queued.pop_front() {
+            return Ok(response);
+        }
+        if self.paused.is_some() {
+            return Err("updates paused".into());
+        }
+        self.read_response()
+    }
+
+    fn next_responses(&mut self) -> Result<Vec<Response>, Error> {
+        let mut responses = vec![self.next_response()?];
+        responses.extend(self.queued.drain(..));
+        if self.paused.is_some() {
+            return Ok(responses);
+        }
+        // A frame that fails to read ends the batch, the next call reports it again
+        // if the board is gone
+        while self.input_waiting().unwrap_or(false) {
+            match self.read_response() {
+                Ok(response) => responses.push(response),
+                Err(e) => {
+                    debug!(error = %e, "ending batch");
+                    break;
+                }
+            }
+        }
+        Ok(responses)
+    }
+
+    fn send_clock(&mut self, command: &ClockCommand) -> Result<bool, Error> {
+        self.write(&command.to_bytes_for(self.clock_model), false)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn test_read_frames() {
+        let mut stream = Cursor::new(vec![
+            0x00, // noise before the first frame
+            0x93, 0x00, 0x05, 0x01, 0x02, // version 1.2
+            0x8e, 0x00, 0x05, 0x24, 0x01, // white pawn on grid 36
+        ]);
+        assert!(matches!(
+            read_frame(&mut stream).unwrap(),
+            Response::Version(v) if v == "1.2"
+        ));
+        assert!(matches!(
+            read_frame(&mut stream).unwrap(),
+            Response::FieldUpdate(ChessMove {
+                grid: 36,
+                piece: RawPiece::WhitePawn
+            })
+        ));
+        assert!(read_frame(&mut stream).is_err());
+
+        // Every message type with bodies of the wrong length or bad contents is an
+        // error, never a panic
+        for resp_type in 0x80..=0xffu8 {
+            for length in 0..=70u8 {
+                let mut frame = vec![resp_type, 0x00, length + 3];
+                frame.extend((0..length).map(|i| i.wrapping_mul(37) & 0x7f));
+                let _ = read_frame(&mut Cursor::new(frame));
+            }
+        }
+    }
+    #[test]
+    fn test_snapshot_follows_responses() {
+        let mut snapshot = BoardSnapshot::default();
+        let empty = ChessBoard {
+            board: [RawPiece::Empty; 64],
+        };
+        let clock = |status| Response::BWTime {
+            white_time: Remaining::from_seconds(60),
+            black_time: Remaining::from_seconds(60),
+            status,
+        };
+        snapshot.take_in(&Response::FieldUpdate(ChessMove {
+            grid: 36,
+            piece: RawPiece::WhitePawn,
+        }));
+        assert_eq!(snapshot.board, None);
+        snapshot.take_in(&Response::BoardDump(empty));
+        snapshot.take_in(&Response::FieldUpdate(ChessMove {
+            grid: 36,
+            piece: RawPiece::WhitePawn,
+        }));
+        for status in [
+            ClockStatus::WhitesTurn,
+            ClockStatus::WhitesTurn,
+            ClockStatus::BlacksTurn,
+            ClockStatus::WhitesTurn,
+        ] {
+            snapshot.take_in(&clock(status));
+        }
+        assert_eq!(
+            snapshot.board.map(|board| board.board[36]),
+            Some(RawPiece::WhitePawn)
+        );
+        assert_eq!(snapshot.moves, 2);
+        assert_eq!(snapshot.sequence, 7);
+        assert_eq!(snapshot.connection, ConnectionStatus::Connected);
+    }
+
+    /// A board in update-board-and-clock mode sends the clock times whenever they
+    /// change, also between a request for the position and the dump answering it
+    #[cfg(unix)]
+    #[test]
+    fn test_clock_frames_interleaved_with_dump() {
+        let (mut master, slave) = serialport::TTYPort::pair().unwrap();
+        master.set_timeout(Duration::from_millis(50)).unwrap();
+        let frame = |message_type: MessageType, data: &[u8]| {
+            RawFrame {
+                message_type: message_type as u8,
+                data: data.to_vec(),
+            }
+            .to_bytes()
+        };
+        let clock = frame(
+            MessageType::BWTime,
+            &[0x00, 0x05, 0x00, 0x00, 0x04, 0x59, 0x00],
+        );
+        let mut squares = [RawPiece::Empty as u8; 64];
+        squares[36] = RawPiece::WhitePawn as u8;
+        let fake = thread::spawn(move || {
+            let mut command = [0; 1];
+            let mut dumps = 0;
+            while dumps < 2 {
+                if master.read(&mut command).is_err() {
+                    continue;
+                }
+                match Command::try_from(command[0]).ok() {
+                    Some(Command::Reset) => master.write_all(&clock).unwrap(),
+                    // Answered with the clock only, so the board settles on 0x42
+                    Some(Command::RequestDump93) => master.write_all(&clock).unwrap(),
+                    Some(Command::RequestBoard) => {
+                        dumps += 1;
+                        let update = frame(MessageType::FieldUpdate, &[36, squares[36]]);
+                        master.write_all(&clock).unwrap();
+                        master.write_all(&update).unwrap();
+                        master.write_all(&clock).unwrap();
+                        master
+                            .write_all(&frame(MessageType::BoardDump, &squares))
+                            .unwrap();
+                    }
+                    _ => {}
+                }
+            }
+            master
+        });
+        let line = LineControl::for_board(Kind::Dgt, Transport::Serial);
+        let mut board = DgtBoard::open(slave.name().as_deref().unwrap(), 9600, line).unwrap();
+        for _ in 0..2 {
+            let dump = board.board().unwrap();
+            assert_eq!(dump.board[36], RawPiece::WhitePawn);
+            // The clock frames are kept, the field update the dump stands in for is not
+            assert!(board
+                .queued
+                .iter()
+                .all(|response| matches!(response, Response::BWTime { .. })));
+            assert!(!board.queued.is_empty());
+        }
+        assert!(matches!(
+            board.next_response().unwrap(),
+            Response::BWTime {
+                status: ClockStatus::WhitesTurn,
+                ..
+            }
+        ));
+
+        // A burst of updates is read in one go, after what was still queued
+        let mut master = fake.join().unwrap();
+        let burst = [(52, RawPiece::Empty), (36, RawPiece::WhitePawn)]
+            .map(|(grid, piece)| frame(MessageType::FieldUpdate, &[grid, piece as u8]))
+            .concat();
+        master.write_all(&burst).unwrap();
+        // Until the terminal has passed it on
+        thread::sleep(Duration::from_millis(50));
+        let responses = board.next_responses().unwrap();
+        let updates: Vec<_> = responses
+            .iter()
+            .skip_while(|response| matches!(response, Response::BWTime { .. }))
+            .map(|response| match response {
+                Response::FieldUpdate(update) => update.grid,
+                _ => panic!("unexpected {}", response),
+            })
+            .collect();
+        assert_eq!(updates, [52, 36]);
+        assert!(board.queued.is_empty());
+    }
+}
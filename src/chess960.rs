@@ -0,0 +1,128 @@
+use crate::protocol::*;
+
+/// Knight placements on the five squares left after bishops and queen, indexed by the Scharnagl KRN digit
+const KNIGHT_TABLE: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// The classical start position in Chess960 numbering
+pub const CLASSICAL: u16 = 518;
+
+/// White back rank (files a to h) of a Chess960 start position, None for numbers above 959
+pub fn back_rank(number: u16) -> Option<[RawPiece; 8]> {
+    if number >= 960 {
+        return None;
+    }
+    let mut n = number as usize;
+    let mut rank = [RawPiece::Empty; 8];
+    // Light squared bishop on b, d, f or h
+    rank[2 * (n % 4) + 1] = RawPiece::WhiteBishop;
+    n /= 4;
+    // Dark squared bishop on a, c, e or g
+    rank[2 * (n % 4)] = RawPiece::WhiteBishop;
+    n /= 4;
+    let free = |rank: &[RawPiece; 8]| -> Vec<usize> {
+        (0..8).filter(|&i| rank[i] == RawPiece::Empty).collect()
+    };
+    rank[free(&rank)[n % 6]] = RawPiece::WhiteQueen;
+    n /= 6;
+    let empty = free(&rank);
+    let (a, b) = KNIGHT_TABLE[n];
+    rank[empty[a]] = RawPiece::WhiteKnight;
+    rank[empty[b]] = RawPiece::WhiteKnight;
+    let empty = free(&rank);
+    rank[empty[0]] = RawPiece::WhiteRook;
+    rank[empty[1]] = RawPiece::WhiteKing;
+    rank[empty[2]] = RawPiece::WhiteRook;
+    Some(rank)
+}
+
+/// Scharnagl number of a white back rank (files a to h), None if it is not a Chess960 start rank
+pub fn number(rank: &[RawPiece; 8]) -> Option<u16> {
+    let files = |piece: RawPiece| -> Vec<usize> { (0..8).filter(|&i| rank[i] == piece).collect() };
+    let bishops = files(RawPiece::WhiteBishop);
+    let (light, dark) = match bishops.as_slice() {
+        &[a, b] if a % 2 != b % 2 => {
+            if a % 2 == 1 {
+                (a, b)
+            } else {
+                (b, a)
+            }
+        }
+        _ => return None,
+    };
+    let rest: Vec<usize> = (0..8).filter(|&i| i != light && i != dark).collect();
+    let queen = match files(RawPiece::WhiteQueen).as_slice() {
+        &[q] => rest.iter().position(|&i| i == q)?,
+        _ => return None,
+    };
+    let rest: Vec<usize> = rest
+        .into_iter()
+        .filter(|&i| rank[i] != RawPiece::WhiteQueen)
+        .collect();
+    let knights: Vec<usize> = (0..5)
+        .filter(|&i| rank[rest[i]] == RawPiece::WhiteKnight)
+        .collect();
+    let krn = match knights.as_slice() {
+        &[a, b] => KNIGHT_TABLE.iter().position(|&k| k == (a, b))?,
+        _ => return None,
+    };
+    let number = (light / 2) + 4 * (dark / 2) + 16 * queen + 96 * krn;
+    // Rook, king, rook must fill the remaining squares in that order
+    (back_rank(number as u16)? == *rank).then_some(number as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RawPiece::*;
+
+    #[test]
+    fn test_classical_number() {
+        let classical = [
+            WhiteRook,
+            WhiteKnight,
+            WhiteBishop,
+            WhiteQueen,
+            WhiteKing,
+            WhiteBishop,
+            WhiteKnight,
+            WhiteRook,
+        ];
+        assert_eq!(number(&classical), Some(CLASSICAL));
+        assert_eq!(back_rank(CLASSICAL), Some(classical));
+    }
+
+    #[test]
+    fn test_all_numbers_roundtrip() {
+        for n in 0..960 {
+            assert_eq!(number(&back_rank(n).unwrap()), Some(n));
+        }
+        assert_eq!(back_rank(960), None);
+    }
+
+    #[test]
+    fn test_invalid_rank() {
+        // King outside the rooks
+        let rank = [
+            WhiteKing,
+            WhiteRook,
+            WhiteBishop,
+            WhiteQueen,
+            WhiteRook,
+            WhiteBishop,
+            WhiteKnight,
+            WhiteKnight,
+        ];
+        assert_eq!(number(&rank), None);
+    }
+}
@@ -0,0 +1,1069 @@
+use crate::protocol::{ClockStatus, PieceColor, Remaining};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Board command that forwards a message to a DGT3000 clock attached to the board
+pub const CLOCK_MESSAGE: u8 = 0x2b;
+const START_MESSAGE: u8 = 0x03;
+const END_MESSAGE: u8 = 0x00;
+
+const CLOCK_DISPLAY: u8 = 0x01;
+const CLOCK_END: u8 = 0x03;
+const CLOCK_VERSION: u8 = 0x09;
+const CLOCK_SET_AND_RUN: u8 = 0x0a;
+const CLOCK_BEEP: u8 = 0x0b;
+const CLOCK_ASCII: u8 = 0x0c;
+
+/// Characters the DGT3000 shows at once
+pub const TEXT_WIDTH: usize = 8;
+/// Characters the seven segment display of a DGT XL shows at once
+pub const XL_TEXT_WIDTH: usize = 6;
+
+/// Generation of DGT clock, which decides how text is sent to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClockModel {
+    /// Takes ASCII text
+    #[default]
+    Dgt3000,
+    /// Older clock with seven segment digits, which takes the segments to light
+    Xl,
+}
+
+impl ClockModel {
+    /// The model behind the answer to `ClockCommand::Version`, which only the DGT3000
+    /// gives
+    pub fn from_version(version: Option<&str>) -> Self {
+        match version {
+            Some(_) => ClockModel::Dgt3000,
+            None => ClockModel::Xl,
+        }
+    }
+}
+
+/// Segments of a character on the XL display, bit 0 for the top segment a through
+/// bit 6 for the middle segment g. Letters without a good likeness come out blank.
+fn xl_segments(c: char) -> u8 {
+    match c.to_ascii_lowercase() {
+        '0' => 0x3f,
+        '1' => 0x06,
+        '2' | 'z' => 0x5b,
+        '3' => 0x4f,
+        '4' => 0x66,
+        '5' | 's' => 0x6d,
+        '6' => 0x7d,
+        '7' => 0x07,
+        '8' => 0x7f,
+        '9' => 0x6f,
+        'a' => 0x5f,
+        'b' => 0x7c,
+        'c' => 0x58,
+        'd' => 0x5e,
+        'e' => 0x7b,
+        'f' => 0x71,
+        'g' => 0x3d,
+        'h' => 0x74,
+        'i' => 0x10,
+        'j' => 0x1e,
+        'k' => 0x75,
+        'l' => 0x38,
+        'm' => 0x55,
+        'n' => 0x54,
+        'o' => 0x5c,
+        'p' => 0x73,
+        'q' => 0x67,
+        'r' => 0x50,
+        't' => 0x78,
+        'u' => 0x3e,
+        'v' => 0x2a,
+        'w' => 0x7e,
+        'x' => 0x64,
+        'y' => 0x6e,
+        '-' => 0x40,
+        '=' => 0x48,
+        '_' => 0x08,
+        '/' => 0x52,
+        '?' => 0x53,
+        _ => 0x00,
+    }
+}
+
+/// Messages for a DGT3000 clock, in the framing DGT Pi and PicoChess installations use.
+/// The clock answers each of them with an acknowledgement disguised as a clock time
+/// message, see `ClockAck`. A DGT XL takes the same messages, except that text goes to
+/// it as display segments, see `to_bytes_for`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClockCommand {
+    /// Show text instead of the times, cut or padded to `TEXT_WIDTH` characters
+    Text { text: String, beep: bool },
+    /// Go back to showing the times
+    EndText,
+    /// Set both times and start the side given, or stop the clock with None
+    SetAndRun {
+        left: Remaining,
+        right: Remaining,
+        run: Option<ClockSide>,
+    },
+    /// Beep for `duration` units of 64 ms
+    Beep(u8),
+    /// Ask for the clock firmware version
+    Version,
+}
+
+/// Side of the clock as seen by the arbiter, left is white in the usual setup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSide {
+    Left,
+    Right,
+}
+
+fn hms(time: Remaining) -> [u8; 3] {
+    let seconds = time.total_seconds();
+    [
+        (seconds / 3600).min(9) as u8,
+        (seconds / 60 % 60) as u8,
+        (seconds % 60) as u8,
+    ]
+}
+
+impl ClockCommand {
+    /// A beep of about `duration`, which the clock counts in steps of 64 ms
+    pub fn beep(duration: Duration) -> Self {
+        ClockCommand::Beep((duration.as_millis() / 64).clamp(1, u8::MAX as u128) as u8)
+    }
+
+    /// The bytes to write to the board for a DGT3000
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_for(ClockModel::Dgt3000)
+    }
+
+    /// The bytes to write to the board for a clock of `model`
+    pub fn to_bytes_for(&self, model: ClockModel) -> Vec<u8> {
+        let body = match self {
+            ClockCommand::Text { text, beep } if model == ClockModel::Xl => {
+                let mut segments = [0; XL_TEXT_WIDTH];
+                for (segment, c) in segments.iter_mut().zip(text.chars()) {
+                    *segment = xl_segments(c);
+                }
+                // Each half of the display takes its three digits right to left
+                let mut body = vec![CLOCK_DISPLAY];
+                body.extend([2, 1, 0, 5, 4, 3].map(|i| segments[i]));
+                // No icons, then the beep
+                body.push(0x00);
+                body.push(if *beep { 0x03 } else { 0x01 });
+                body
+            }
+            ClockCommand::Text { text, beep } => {
+                let mut body = vec![CLOCK_ASCII];
+                body.extend(
+                    text.chars()
+                        .map(|c| if c.is_ascii() { c as u8 } else { b'?' })
+                        .chain(std::iter::repeat(b' '))
+                        .take(TEXT_WIDTH),
+                );
+                body.push(if *beep { 0x03 } else { 0x01 });
+                body
+            }
+            ClockCommand::EndText => vec![CLOCK_END],
+            ClockCommand::SetAndRun { left, right, run } => {
+                let mut body = vec![CLOCK_SET_AND_RUN];
+                body.extend(hms(*left));
+                body.extend(hms(*right));
+                body.push(match run {
+                    None => 0x00,
+                    Some(ClockSide::Left) => 0x01,
+                    Some(ClockSide::Right) => 0x02,
+                });
+                body
+            }
+            ClockCommand::Beep(duration) => vec![CLOCK_BEEP, *duration],
+            ClockCommand::Version => vec![CLOCK_VERSION],
+        };
+        // The length counts everything after itself
+        let mut bytes = vec![CLOCK_MESSAGE, body.len() as u8 + 2, START_MESSAGE];
+        bytes.extend(body);
+        bytes.push(END_MESSAGE);
+        bytes
+    }
+}
+
+/// Reply of a DGT3000 clock to a `ClockCommand` or a button press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockAck {
+    /// The clock executed the command with this code
+    Done(u8),
+    /// A single front button was pressed, numbered 0 to 4 from the left
+    Button(u8),
+    /// Several buttons were pressed at once, raw code as sent by the clock
+    Buttons(u8),
+    Version {
+        major: u8,
+        minor: u8,
+    },
+}
+
+impl ClockAck {
+    /// Whether a 7 byte clock time message is really an acknowledgement
+    pub fn is_ack(data: &[u8]) -> bool {
+        data.len() == 7 && (data[0] & 0x0f == 0x0a || data[3] & 0x0f == 0x0a)
+    }
+
+    /// Decode an acknowledgement, the payload bytes are spread over the time fields
+    /// with their high bits stored in the nibbles of the hour bytes
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if !Self::is_ack(data) {
+            return None;
+        }
+        let ack0 = (data[1] & 0x7f) | ((data[3] << 3) & 0x80);
+        let ack1 = (data[2] & 0x7f) | ((data[3] << 2) & 0x80);
+        let ack2 = (data[4] & 0x7f) | ((data[0] << 3) & 0x80);
+        let ack3 = (data[5] & 0x7f) | ((data[0] << 2) & 0x80);
+        if ack0 != 0x10 {
+            return None;
+        }
+        Some(match ack1 {
+            0x88 => match (ack2, ack3) {
+                (0x05, 0x31) => ClockAck::Button(0),
+                (0x21, 0x34) => ClockAck::Button(1),
+                (0x11, 0x33) => ClockAck::Button(2),
+                (0x09, 0x32) => ClockAck::Button(3),
+                (0x41, 0x35) => ClockAck::Button(4),
+                _ => ClockAck::Buttons(ack2),
+            },
+            CLOCK_VERSION => ClockAck::Version {
+                major: ack2 >> 4,
+                minor: ack2 & 0x0f,
+            },
+            code => ClockAck::Done(code),
+        })
+    }
+
+    /// The buttons held down, for button presses
+    pub fn pressed(self) -> Option<ButtonSet> {
+        match self {
+            ClockAck::Button(button) => Some(ButtonSet::single(button)),
+            ClockAck::Buttons(code) => Some(ButtonSet(
+                BUTTON_BITS
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, bit)| code & **bit != 0)
+                    .fold(0, |set, (button, _)| set | 1 << button),
+            )),
+            _ => None,
+        }
+    }
+
+    /// The clock time message data carrying this acknowledgement, as a clock sends it
+    pub fn to_data(self) -> [u8; 7] {
+        let (ack1, ack2, ack3) = match self {
+            ClockAck::Done(code) => (code, 0, 0),
+            ClockAck::Button(button) => {
+                let codes = [
+                    (0x05, 0x31),
+                    (0x21, 0x34),
+                    (0x11, 0x33),
+                    (0x09, 0x32),
+                    (0x41, 0x35),
+                ];
+                let (ack2, ack3) = codes[button.min(4) as usize];
+                (0x88, ack2, ack3)
+            }
+            ClockAck::Buttons(code) => (0x88, code, 0),
+            ClockAck::Version { major, minor } => (CLOCK_VERSION, major << 4 | minor & 0x0f, 0),
+        };
+        encode([0x10, ack1, ack2, ack3])
+    }
+}
+
+/// Bit of each button, from the left, in the code of a button press
+const BUTTON_BITS: [u8; 5] = [0x04, 0x20, 0x10, 0x08, 0x40];
+
+/// Front buttons of the clock pressed together, bit n for button n from the left.
+/// Written as the button numbers joined by `+`, e.g. "0+4", or "none".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct ButtonSet(pub u8);
+
+impl ButtonSet {
+    pub fn single(button: u8) -> Self {
+        ButtonSet(1 << button.min(4))
+    }
+}
+
+impl fmt::Display for ButtonSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return f.write_str("none");
+        }
+        let buttons: Vec<String> = (0..5)
+            .filter(|button| self.0 & 1 << button != 0)
+            .map(|button| button.to_string())
+            .collect();
+        f.write_str(&buttons.join("+"))
+    }
+}
+
+impl FromStr for ButtonSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().eq_ignore_ascii_case("none") {
+            return Ok(ButtonSet(0));
+        }
+        s.split('+')
+            .map(|button| match button.trim().parse::<u8>() {
+                Ok(button) if button < 5 => Ok(ButtonSet::single(button)),
+                _ => Err(format!("not a clock button from 0 to 4: {:?}", button)),
+            })
+            .try_fold(ButtonSet(0), |set, button| Ok(ButtonSet(set.0 | button?.0)))
+    }
+}
+
+/// What players can tell from the clock without touching the computer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSignal {
+    DrawOffered,
+    Resigned,
+    Paused,
+}
+
+/// The buttons that signal each `ClockSignal`, no buttons to leave it unused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonMap {
+    pub draw: ButtonSet,
+    pub resign: ButtonSet,
+    pub pause: ButtonSet,
+}
+
+impl ButtonMap {
+    /// The signal given by pressing `pressed`
+    pub fn signal(&self, pressed: ButtonSet) -> Option<ClockSignal> {
+        [
+            (self.draw, ClockSignal::DrawOffered),
+            (self.resign, ClockSignal::Resigned),
+            (self.pause, ClockSignal::Paused),
+        ]
+        .into_iter()
+        .find_map(|(buttons, signal)| (buttons.0 != 0 && buttons == pressed).then_some(signal))
+    }
+}
+
+/// A draw offered on the clock, agreed once the opponent of the side offering signals a
+/// draw too before another move is made
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DrawOffer {
+    /// Moves played when the draw was offered, and by whom
+    offered: Option<(usize, PieceColor)>,
+}
+
+impl DrawOffer {
+    /// `colour` signals a draw after `plies` moves. True when that accepts the offer
+    /// of the opponent, otherwise it stands as an offer of its own.
+    pub fn signal(&mut self, colour: PieceColor, plies: usize) -> bool {
+        match self.offered {
+            Some((at, by)) if at == plies && by != colour => {
+                self.offered = None;
+                true
+            }
+            _ => {
+                self.offered = Some((plies, colour));
+                false
+            }
+        }
+    }
+
+    /// Forget the offer, once a move is taken back or the game is over
+    pub fn withdraw(&mut self) {
+        self.offered = None;
+    }
+}
+
+impl Default for ButtonMap {
+    /// The outer pair resigns and the inner pair offers a draw, both too awkward to
+    /// press by accident. The play/pause button in the middle pauses.
+    fn default() -> Self {
+        ButtonMap {
+            draw: ButtonSet(0b01010),
+            resign: ButtonSet(0b10001),
+            pause: ButtonSet::single(2),
+        }
+    }
+}
+
+/// Beeps confirming what happened on the board, for players who rely on hearing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Beeps {
+    /// A short beep for every move recognised
+    pub moves: bool,
+    /// A long beep when the board stops matching the game, usually an illegal move
+    pub illegal: bool,
+    /// A beep when the time of the player to move drops below this many seconds
+    pub low_time: Option<u32>,
+}
+
+impl Beeps {
+    pub const MOVE: Duration = Duration::from_millis(128);
+    pub const ILLEGAL: Duration = Duration::from_millis(1024);
+    pub const LOW_TIME: Duration = Duration::from_millis(512);
+}
+
+/// How the seconds a player gets per move are given
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    /// Added after each move, written "+"
+    #[default]
+    Increment,
+    /// Waited out before the time starts running at each move, US style, written "d"
+    Delay,
+    /// Given back after each move as far as they were used, written "b"
+    Bronstein,
+}
+
+impl Timing {
+    fn separator(self) -> char {
+        match self {
+            Timing::Increment => '+',
+            Timing::Delay => 'd',
+            Timing::Bronstein => 'b',
+        }
+    }
+
+    /// The name written in the PGN TimeControlTiming tag
+    fn name(self) -> &'static str {
+        match self {
+            Timing::Increment => "increment",
+            Timing::Delay => "delay",
+            Timing::Bronstein => "bronstein",
+        }
+    }
+}
+
+/// Time one player starts a game with and gains per move, written as minutes and
+/// seconds of increment like "90+30", or just the minutes. A delay or Bronstein
+/// increment takes a "d" or "b" instead of the "+", like "90d5".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allowance {
+    /// Seconds on the clock at the start
+    pub base: u32,
+    /// Seconds gained per move, in the way `timing` says
+    pub increment: u32,
+    pub timing: Timing,
+}
+
+impl Allowance {
+    /// The PGN TimeControl field, seconds and increment like "5400+30". The standard
+    /// has no way to write a delay or Bronstein increment, so its seconds are written
+    /// the same and the timing is left to `TimeControl::timing_tag`.
+    pub fn pgn_tag(&self) -> String {
+        match self.increment {
+            0 => self.base.to_string(),
+            increment => format!("{}+{}", self.base, increment),
+        }
+    }
+}
+
+impl fmt::Display for Allowance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            self.base / 60,
+            self.timing.separator(),
+            self.increment
+        )
+    }
+}
+
+impl FromStr for Allowance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("not a time control like 90+30: {:?}", s);
+        let s = s.trim();
+        let (minutes, increment, timing) = [Timing::Increment, Timing::Delay, Timing::Bronstein]
+            .into_iter()
+            .find_map(|timing| {
+                let (minutes, increment) = s.split_once(timing.separator())?;
+                Some((minutes, increment, timing))
+            })
+            .unwrap_or((s, "0", Timing::Increment));
+        Ok(Allowance {
+            base: minutes.trim().parse::<u32>().map_err(|_| invalid())? * 60,
+            increment: increment.trim().parse().map_err(|_| invalid())?,
+            timing,
+        })
+    }
+}
+
+/// Time white and black start a game with and gain per move, written like "90+30" when
+/// both get the same. Time odds give white's and black's apart, like "5+3/3+2".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    pub white: Allowance,
+    pub black: Allowance,
+}
+
+impl TimeControl {
+    /// The same time for both players, `base` and `increment` in seconds
+    pub fn new(base: u32, increment: u32) -> Self {
+        let allowance = Allowance {
+            base,
+            increment,
+            timing: Timing::Increment,
+        };
+        TimeControl {
+            white: allowance,
+            black: allowance,
+        }
+    }
+
+    /// The time `side` gets, None for no side
+    pub fn of(&self, side: PieceColor) -> Option<Allowance> {
+        match side {
+            PieceColor::White => Some(self.white),
+            PieceColor::Black => Some(self.black),
+            PieceColor::None => None,
+        }
+    }
+
+    /// Whether the players get different times
+    pub fn is_odds(&self) -> bool {
+        self.white != self.black
+    }
+
+    /// Both clocks set to the starting time and stopped, until the first move
+    pub fn start(&self) -> ClockCommand {
+        ClockCommand::SetAndRun {
+            left: Remaining::from_seconds(self.white.base),
+            right: Remaining::from_seconds(self.black.base),
+            run: None,
+        }
+    }
+
+    /// The PGN TimeControl tag, seconds and increment like "5400+30". The tag has no
+    /// way to give time odds, which are left to `WhiteTimeControl` and
+    /// `BlackTimeControl` instead.
+    pub fn pgn_tag(&self) -> Option<String> {
+        (!self.is_odds()).then(|| self.white.pgn_tag())
+    }
+
+    /// The custom TimeControlTiming tag, "delay" or "bronstein" for what the seconds of
+    /// the TimeControl tag mean, like "delay/increment" for time odds. None when both
+    /// players get a plain increment.
+    pub fn timing_tag(&self) -> Option<String> {
+        let (white, black) = (self.white.timing, self.black.timing);
+        match (white, black) {
+            (Timing::Increment, Timing::Increment) => None,
+            _ if white == black => Some(white.name().to_string()),
+            _ => Some(format!("{}/{}", white.name(), black.name())),
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_odds() {
+            write!(f, "{}/{}", self.white, self.black)
+        } else {
+            write!(f, "{}", self.white)
+        }
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (white, black) = match s.split_once('/') {
+            Some((white, black)) => (white.parse()?, black.parse()?),
+            None => {
+                let both = s.parse()?;
+                (both, both)
+            }
+        };
+        Ok(TimeControl { white, black })
+    }
+}
+
+/// A clock kept on the host from a time control, for boards without one. The time
+/// left is worked out from the instant the running side started rather than counted
+/// down on ticks, so a host that wakes up late never makes it drift. A player whose
+/// time ran out gains nothing more, whatever the timing.
+#[derive(Debug, Clone)]
+pub struct LocalClock {
+    /// Milliseconds white and black had left when the running side started
+    left_ms: [u64; 2],
+    /// Side whose time runs, and since when
+    running: Option<(PieceColor, Instant)>,
+    /// Milliseconds white and black gain per move
+    increment_ms: [u64; 2],
+    timing: [Timing; 2],
+}
+
+fn side_index(side: PieceColor) -> Option<usize> {
+    match side {
+        PieceColor::White => Some(0),
+        PieceColor::Black => Some(1),
+        PieceColor::None => None,
+    }
+}
+
+impl LocalClock {
+    /// Both sides at the starting time, stopped until the first move
+    pub fn new(control: TimeControl) -> Self {
+        let ms = |seconds: u32| seconds as u64 * 1000;
+        LocalClock {
+            left_ms: [ms(control.white.base), ms(control.black.base)],
+            running: None,
+            increment_ms: [ms(control.white.increment), ms(control.black.increment)],
+            timing: [control.white.timing, control.black.timing],
+        }
+    }
+
+    /// Milliseconds `side` has left at `now`
+    pub fn left_ms(&self, side: PieceColor, now: Instant) -> u64 {
+        let Some(index) = side_index(side) else {
+            return 0;
+        };
+        match self.running {
+            Some((running, since)) if running == side => {
+                self.left_ms[index].saturating_sub(self.spent_ms(index, since, now))
+            }
+            _ => self.left_ms[index],
+        }
+    }
+
+    /// Milliseconds side `index` lost to the turn it began at `since`, by `now`
+    fn spent_ms(&self, index: usize, since: Instant, now: Instant) -> u64 {
+        let elapsed = now.saturating_duration_since(since).as_millis() as u64;
+        match self.timing[index] {
+            Timing::Delay => elapsed.saturating_sub(self.increment_ms[index]),
+            Timing::Increment | Timing::Bronstein => elapsed,
+        }
+    }
+
+    /// Times left at `now` in whole seconds, as a clock shows them
+    pub fn remaining(&self, now: Instant) -> (Remaining, Remaining) {
+        let seconds = |side| Remaining::from_seconds((self.left_ms(side, now) / 1000) as u32);
+        (seconds(PieceColor::White), seconds(PieceColor::Black))
+    }
+
+    /// Side whose time runs, None while stopped
+    pub fn running(&self) -> PieceColor {
+        self.running.map_or(PieceColor::None, |(side, _)| side)
+    }
+
+    /// A move was made and `side` is to move from `now` on. The side that moved stops
+    /// and gets its increment, or the part of it the move took with Bronstein timing.
+    pub fn start_turn(&mut self, side: PieceColor, now: Instant) {
+        let moved = self.running;
+        self.run(side, now);
+        let Some((moved, since)) = moved.filter(|(moved, _)| *moved != side) else {
+            return;
+        };
+        let Some(index) = side_index(moved).filter(|index| self.left_ms[*index] > 0) else {
+            return;
+        };
+        let elapsed = now.saturating_duration_since(since).as_millis() as u64;
+        self.left_ms[index] += match self.timing[index] {
+            Timing::Increment => self.increment_ms[index],
+            Timing::Delay => 0,
+            Timing::Bronstein => elapsed.min(self.increment_ms[index]),
+        };
+    }
+
+    /// Stop both sides at `now`
+    pub fn stop(&mut self, now: Instant) {
+        self.run(PieceColor::None, now);
+    }
+
+    /// Set both times and run `side` from `now` on, none for stopping the clock
+    pub fn set(&mut self, white: Remaining, black: Remaining, side: PieceColor, now: Instant) {
+        self.left_ms = [white, black].map(|time| time.total_seconds() as u64 * 1000);
+        self.running = side_index(side).map(|_| (side, now));
+    }
+
+    /// Take over the times a clock reported at `now`. It reports whole seconds, so a
+    /// time within the reported second keeps its fraction and only one off by more is
+    /// replaced. The turn goes on from when it began while the same side runs, so a
+    /// delay is not waited out again.
+    pub fn reconcile(
+        &mut self,
+        white: Remaining,
+        black: Remaining,
+        status: ClockStatus,
+        now: Instant,
+    ) {
+        let side = match status {
+            ClockStatus::WhitesTurn => PieceColor::White,
+            ClockStatus::BlacksTurn => PieceColor::Black,
+            ClockStatus::NoCock => return,
+        };
+        let since = match self.running {
+            Some((running, since)) if running == side => since,
+            _ => now,
+        };
+        for (index, (colour, reported)) in [(PieceColor::White, white), (PieceColor::Black, black)]
+            .into_iter()
+            .enumerate()
+        {
+            let local = self.left_ms(colour, now);
+            let reported = reported.total_seconds() as u64 * 1000;
+            let left = match local.checked_sub(reported) {
+                Some(fraction) if fraction < 1000 => local,
+                _ => reported,
+            };
+            // Worked back to what the running side had when its turn began
+            self.left_ms[index] = match colour == side {
+                true => left + self.spent_ms(index, since, now),
+                false => left,
+            };
+        }
+        self.running = Some((side, since));
+    }
+
+    /// A command setting a clock to the times left at `now` and running the side to
+    /// move. The DGT3000 takes no timing of its own through the board, so a clock
+    /// kept in step with this after every move shows the increment or Bronstein time
+    /// given back. It counts down through a delay all the same, which is given back
+    /// with the next command.
+    pub fn command(&self, now: Instant) -> ClockCommand {
+        let (left, right) = self.remaining(now);
+        ClockCommand::SetAndRun {
+            left,
+            right,
+            run: match self.running() {
+                PieceColor::White => Some(ClockSide::Left),
+                PieceColor::Black => Some(ClockSide::Right),
+                PieceColor::None => None,
+            },
+        }
+    }
+
+    /// Freeze the time of the running side at `now` and run `side` from then on
+    fn run(&mut self, side: PieceColor, now: Instant) {
+        if let Some((running, _)) = self.running {
+            if let Some(index) = side_index(running) {
+                self.left_ms[index] = self.left_ms(running, now);
+            }
+        }
+        self.running = side_index(side).map(|_| (side, now));
+    }
+}
+
+/// A DGT3000 whose lever ends a turn, used on its own or beside a game on the board.
+/// The clock takes no increment or delay through the board and counts plain time down,
+/// so once a press is reported the side that pressed it gets its increment, Bronstein
+/// time or delay here and the clock is set again.
+#[derive(Debug, Clone)]
+pub struct StandaloneClock {
+    local: LocalClock,
+}
+
+impl StandaloneClock {
+    pub fn new(control: TimeControl) -> Self {
+        StandaloneClock {
+            local: LocalClock::new(control),
+        }
+    }
+
+    /// The times the clock reported at `now`. Returns the command setting it again when
+    /// the lever handed the turn to the other side and the clock shows another time
+    /// for that side than it has here.
+    pub fn report(
+        &mut self,
+        white: Remaining,
+        black: Remaining,
+        status: ClockStatus,
+        now: Instant,
+    ) -> Option<ClockCommand> {
+        let side = match status {
+            ClockStatus::WhitesTurn => PieceColor::White,
+            ClockStatus::BlacksTurn => PieceColor::Black,
+            ClockStatus::NoCock => return None,
+        };
+        let moved = self.local.running();
+        if moved == PieceColor::None || moved == side {
+            self.local.reconcile(white, black, status, now);
+            return None;
+        }
+        self.local.start_turn(side, now);
+        let reported = match moved {
+            PieceColor::White => white,
+            _ => black,
+        };
+        (self.local.left_ms(moved, now) / 1000 != reported.total_seconds() as u64)
+            .then(|| self.local.command(now))
+    }
+}
+
+/// Spread ack bytes over a time message the way the clock does
+fn encode(ack: [u8; 4]) -> [u8; 7] {
+    [
+        0x0a | ((ack[2] & 0x80) >> 3) | ((ack[3] & 0x80) >> 2),
+        ack[0] & 0x7f,
+        ack[1] & 0x7f,
+        0x0a | ((ack[0] & 0x80) >> 3) | ((ack[1] & 0x80) >> 2),
+        ack[2] & 0x7f,
+        ack[3] & 0x7f,
+        0,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_clock() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut clock = LocalClock::new("1+2".parse().unwrap());
+        assert_eq!(clock.running(), PieceColor::None);
+        assert_eq!(clock.left_ms(PieceColor::White, at(5000)), 60_000);
+        // White's first move starts black's time, nothing was running to add to
+        clock.start_turn(PieceColor::Black, at(0));
+        // However late the host looks, the time comes from the instant the turn began
+        assert_eq!(clock.left_ms(PieceColor::Black, at(10_400)), 49_600);
+        let (white, black) = clock.remaining(at(10_400));
+        assert_eq!((white.total_seconds(), black.total_seconds()), (60, 49));
+        clock.start_turn(PieceColor::White, at(10_400));
+        assert_eq!(clock.left_ms(PieceColor::Black, at(30_000)), 51_600);
+        assert_eq!(clock.left_ms(PieceColor::White, at(30_000)), 40_400);
+        // Out of time stays at zero
+        assert_eq!(clock.left_ms(PieceColor::White, at(200_000)), 0);
+
+        // A reported time within the second keeps its fraction, one further off wins
+        clock.reconcile(
+            Remaining::from_seconds(40),
+            Remaining::from_seconds(45),
+            ClockStatus::WhitesTurn,
+            at(30_000),
+        );
+        assert_eq!(clock.left_ms(PieceColor::White, at(30_000)), 40_400);
+        assert_eq!(clock.left_ms(PieceColor::Black, at(30_000)), 45_000);
+        clock.reconcile(
+            Remaining::from_seconds(45),
+            Remaining::from_seconds(45),
+            ClockStatus::NoCock,
+            at(31_000),
+        );
+        assert_eq!(clock.left_ms(PieceColor::White, at(31_000)), 39_400);
+
+        clock.stop(at(31_000));
+        assert_eq!(clock.left_ms(PieceColor::White, at(90_000)), 39_400);
+        clock.set(
+            Remaining::from_seconds(100),
+            Remaining::from_seconds(50),
+            PieceColor::Black,
+            at(90_000),
+        );
+        assert_eq!(clock.left_ms(PieceColor::Black, at(91_000)), 49_000);
+
+        // With time odds each side starts with and gains its own time
+        let mut odds = LocalClock::new("1+2/2".parse().unwrap());
+        assert_eq!(odds.left_ms(PieceColor::Black, at(0)), 120_000);
+        odds.start_turn(PieceColor::Black, at(0));
+        odds.start_turn(PieceColor::White, at(1_000));
+        odds.start_turn(PieceColor::Black, at(3_000));
+        assert_eq!(odds.left_ms(PieceColor::Black, at(3_000)), 119_000);
+        assert_eq!(odds.left_ms(PieceColor::White, at(3_000)), 60_000);
+    }
+
+    #[test]
+    fn test_delay_and_bronstein() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        let mut delay = LocalClock::new("1d5".parse().unwrap());
+        delay.start_turn(PieceColor::Black, at(0));
+        assert_eq!(delay.left_ms(PieceColor::Black, at(5_000)), 60_000);
+        assert_eq!(delay.left_ms(PieceColor::Black, at(8_000)), 57_000);
+        // Nothing is added at the move, the time used past the delay stays gone
+        delay.start_turn(PieceColor::White, at(8_000));
+        assert_eq!(delay.left_ms(PieceColor::Black, at(8_000)), 57_000);
+        // A move made right as the delay ends costs nothing
+        delay.start_turn(PieceColor::Black, at(13_000));
+        assert_eq!(delay.left_ms(PieceColor::White, at(13_000)), 60_000);
+        // A report during the turn does not grant the delay again
+        delay.reconcile(
+            Remaining::from_seconds(60),
+            Remaining::from_seconds(55),
+            ClockStatus::BlacksTurn,
+            at(20_000),
+        );
+        assert_eq!(delay.left_ms(PieceColor::Black, at(21_000)), 54_000);
+        assert_eq!(
+            delay.command(at(21_000)),
+            ClockCommand::SetAndRun {
+                left: Remaining::from_seconds(60),
+                right: Remaining::from_seconds(54),
+                run: Some(ClockSide::Right),
+            }
+        );
+
+        let mut bronstein = LocalClock::new("1b5".parse().unwrap());
+        bronstein.start_turn(PieceColor::Black, at(0));
+        // The time runs at once, and what the move took comes back up to the increment
+        assert_eq!(bronstein.left_ms(PieceColor::Black, at(3_000)), 57_000);
+        bronstein.start_turn(PieceColor::White, at(3_000));
+        assert_eq!(bronstein.left_ms(PieceColor::Black, at(3_000)), 60_000);
+        bronstein.start_turn(PieceColor::Black, at(11_000));
+        assert_eq!(bronstein.left_ms(PieceColor::White, at(11_000)), 57_000);
+        // A player out of time gets nothing back
+        bronstein.start_turn(PieceColor::White, at(80_000));
+        assert_eq!(bronstein.left_ms(PieceColor::Black, at(80_000)), 0);
+
+        let mut fischer = LocalClock::new("1+2".parse().unwrap());
+        fischer.start_turn(PieceColor::Black, at(0));
+        fischer.start_turn(PieceColor::White, at(61_000));
+        assert_eq!(fischer.left_ms(PieceColor::Black, at(61_000)), 0);
+
+        let control: TimeControl = "90d5/60b10".parse().unwrap();
+        assert_eq!(control.white.timing, Timing::Delay);
+        assert_eq!(control.black.timing, Timing::Bronstein);
+        assert_eq!(control.to_string(), "90d5/60b10");
+        assert_eq!(control.white.pgn_tag(), "5400+5");
+        assert_eq!(control.timing_tag().as_deref(), Some("delay/bronstein"));
+        let delay: TimeControl = "90d5".parse().unwrap();
+        assert_eq!(delay.pgn_tag().as_deref(), Some("5400+5"));
+        assert_eq!(delay.timing_tag().as_deref(), Some("delay"));
+        assert_eq!(TimeControl::new(5400, 30).timing_tag(), None);
+    }
+
+    #[test]
+    fn test_draw_offer() {
+        let mut offer = DrawOffer::default();
+        assert!(!offer.signal(PieceColor::White, 10));
+        // Pressing again does not agree with oneself
+        assert!(!offer.signal(PieceColor::White, 10));
+        assert!(offer.signal(PieceColor::Black, 10));
+        // A move made declines the offer
+        assert!(!offer.signal(PieceColor::Black, 11));
+        assert!(!offer.signal(PieceColor::White, 12));
+        offer.withdraw();
+        assert!(!offer.signal(PieceColor::Black, 12));
+        assert!(offer.signal(PieceColor::White, 12));
+    }
+
+    #[test]
+    fn test_standalone_clock() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let s = Remaining::from_seconds;
+        let mut clock = StandaloneClock::new("1+2".parse().unwrap());
+        assert!(clock
+            .report(s(60), s(60), ClockStatus::WhitesTurn, at(0))
+            .is_none());
+        assert!(clock
+            .report(s(55), s(60), ClockStatus::WhitesTurn, at(5_000))
+            .is_none());
+        // White pressed the lever and gets the increment back
+        let Some(ClockCommand::SetAndRun { left, right, run }) =
+            clock.report(s(50), s(60), ClockStatus::BlacksTurn, at(10_000))
+        else {
+            panic!("no increment given");
+        };
+        assert_eq!((left.total_seconds(), right.total_seconds()), (52, 60));
+        assert_eq!(run, Some(ClockSide::Right));
+        assert!(clock
+            .report(s(52), s(59), ClockStatus::BlacksTurn, at(11_000))
+            .is_none());
+        assert!(clock
+            .report(s(52), s(59), ClockStatus::NoCock, at(11_500))
+            .is_none());
+
+        // Without an increment the clock is left alone
+        let mut sudden_death = StandaloneClock::new("1".parse().unwrap());
+        sudden_death.report(s(60), s(60), ClockStatus::WhitesTurn, at(0));
+        assert!(sudden_death
+            .report(s(58), s(60), ClockStatus::BlacksTurn, at(2_000))
+            .is_none());
+
+        // The clock counted down through white's delay, which is given back
+        let mut delay = StandaloneClock::new("1d2".parse().unwrap());
+        delay.report(s(60), s(60), ClockStatus::WhitesTurn, at(0));
+        let Some(ClockCommand::SetAndRun { left, .. }) =
+            delay.report(s(55), s(60), ClockStatus::BlacksTurn, at(5_000))
+        else {
+            panic!("no delay given back");
+        };
+        assert_eq!(left.total_seconds(), 57);
+    }
+
+    #[test]
+    fn test_text_and_acks() {
+        let bytes = ClockCommand::Text {
+            text: "e4".to_string(),
+            beep: false,
+        }
+        .to_bytes();
+        assert_eq!(
+            bytes,
+            [0x2b, 0x0c, 0x03, 0x0c, b'e', b'4', b' ', b' ', b' ', b' ', b' ', b' ', 0x01, 0x00]
+        );
+        assert_eq!(
+            ClockAck::parse(&encode([0x10, 0x88, 0x21, 0x34])),
+            Some(ClockAck::Button(1))
+        );
+        assert_eq!(
+            ClockAck::parse(&encode([0x10, 0x09, 0x21, 0x00])),
+            Some(ClockAck::Version { major: 2, minor: 1 })
+        );
+        assert_eq!(
+            ClockAck::parse(&encode([0x10, 0x0c, 0x00, 0x00])),
+            Some(ClockAck::Done(0x0c))
+        );
+        let resign = ClockAck::parse(&encode([0x10, 0x88, 0x45, 0x00]));
+        assert_eq!(resign, Some(ClockAck::Buttons(0x45)));
+        let pressed = resign.and_then(ClockAck::pressed).unwrap();
+        assert_eq!(pressed, "0+4".parse().unwrap());
+        assert_eq!(pressed.to_string(), "0+4");
+        let map = ButtonMap::default();
+        assert_eq!(map.signal(pressed), Some(ClockSignal::Resigned));
+        assert_eq!(map.signal(ButtonSet::single(0)), None);
+        assert!("5".parse::<ButtonSet>().is_err());
+        let off = ButtonMap {
+            resign: "none".parse().unwrap(),
+            ..map
+        };
+        assert_eq!(off.signal(pressed), None);
+        let version = ClockAck::Version { major: 1, minor: 2 };
+        assert_eq!(ClockAck::parse(&version.to_data()), Some(version));
+        let xl = ClockCommand::Text {
+            text: "Nf3".to_string(),
+            beep: true,
+        }
+        .to_bytes_for(ClockModel::Xl);
+        assert_eq!(
+            xl,
+            [0x2b, 0x0b, 0x03, 0x01, 0x4f, 0x71, 0x54, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00]
+        );
+        assert_eq!(ClockModel::from_version(None), ClockModel::Xl);
+        assert_eq!(ClockCommand::beep(Beeps::MOVE), ClockCommand::Beep(2));
+        assert_eq!(ClockCommand::beep(Duration::ZERO), ClockCommand::Beep(1));
+        let rapid: TimeControl = "15+10".parse().unwrap();
+        assert_eq!(rapid, TimeControl::new(900, 10));
+        assert_eq!(rapid.pgn_tag().as_deref(), Some("900+10"));
+        assert_eq!("5".parse::<TimeControl>().unwrap().to_string(), "5+0");
+        assert!("5+x".parse::<TimeControl>().is_err());
+        // Time odds set each side of the clock to its own player's time
+        let odds: TimeControl = "5+3/3".parse().unwrap();
+        assert!(odds.is_odds());
+        assert_eq!(
+            odds.of(PieceColor::Black).map(|black| black.base),
+            Some(180)
+        );
+        assert_eq!(odds.to_string(), "5+3/3+0");
+        assert_eq!(odds.pgn_tag(), None);
+        assert_eq!(odds.white.pgn_tag(), "300+3");
+        assert_eq!(
+            odds.start().to_bytes(),
+            [0x2b, 0x0a, 0x03, 0x0a, 0, 5, 0, 0, 3, 0, 0x00, 0x00]
+        );
+        assert!("5/3/1".parse::<TimeControl>().is_err());
+        assert!(!ClockAck::is_ack(&[
+            0x01, 0x30, 0x00, 0x01, 0x30, 0x00, 0x01
+        ]));
+    }
+}
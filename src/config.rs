@@ -0,0 +1,335 @@
+use crate::pgn::GameTags;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    UnknownProfile(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "cannot read config file: {}", e),
+            Error::Toml(e) => write!(f, "invalid config file: {}", e),
+            Error::UnknownProfile(name) => write!(f, "no profile {:?} in the config file", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Serial speed, a number or "auto"
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Baud {
+    Rate(u32),
+    Name(String),
+}
+
+impl fmt::Display for Baud {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Baud::Rate(rate) => write!(f, "{}", rate),
+            Baud::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+/// Option defaults, either at the top of the file or in a profile
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Settings {
+    pub port: Option<String>,
+    pub board: Option<String>,
+    pub baud: Option<Baud>,
+    /// serial, usb or bluetooth
+    pub transport: Option<String>,
+    /// Accept frames padded past their length
+    pub lenient: Option<bool>,
+    pub variant: Option<String>,
+    /// Analysis engine
+    pub engine: Option<PathBuf>,
+    pub lichess_token: Option<String>,
+    pub pgn: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    /// Session logs of earlier games kept
+    pub record_keep: Option<usize>,
+    /// Unix domain socket or named pipe the events are streamed to
+    pub event_socket: Option<PathBuf>,
+    pub review_engine: Option<PathBuf>,
+    pub review_depth: Option<u32>,
+    /// The board reports h1 as its first square
+    pub rotated: Option<bool>,
+    /// Milliseconds within which a repeated field update is dropped, 0 to keep all
+    pub dedup_window: Option<u64>,
+    /// Clock buttons that offer a draw, like "1+3"
+    pub draw_buttons: Option<String>,
+    pub resign_buttons: Option<String>,
+    pub pause_buttons: Option<String>,
+    /// Clock setting for new games, like "90+30"
+    pub time_control: Option<String>,
+    /// Keep the time on the host, for boards without a clock
+    pub local_clock: Option<bool>,
+    /// Wait for the clock to be pressed before taking a move as made
+    pub confirm_moves: Option<bool>,
+    /// Forgive moves leaving the king in check, pawns left on the last rank and moves
+    /// taken back
+    pub casual: Option<bool>,
+    /// What the clock beeps for, like "move,illegal"
+    pub beep: Option<String>,
+    /// Seconds left below which the clock beeps
+    pub low_time_beep: Option<u32>,
+    /// Speech synthesizer announcing the moves, like "espeak"
+    pub speak: Option<String>,
+    /// Language of what the clock shows, speech says and the overlay labels, like "de"
+    pub locale: Option<String>,
+    /// How the console draws pieces: unicode, letters or coloured
+    pub pieces: Option<String>,
+    /// Side the console draws at the bottom of the board
+    pub bottom: Option<String>,
+    pub training: Option<bool>,
+    /// SQLite file finished games are archived in
+    pub database: Option<PathBuf>,
+    /// Address the streaming overlay is served on
+    pub overlay: Option<String>,
+    pub overlay_png: Option<PathBuf>,
+    /// Directory of text files with the FEN, last move and clocks for overlay tools
+    pub position_files: Option<PathBuf>,
+    /// Run unattended under a service manager
+    pub daemon: Option<bool>,
+    pub state_dir: Option<PathBuf>,
+    /// Carry on with an unfinished game of an earlier run: never, ask or auto
+    pub resume: Option<String>,
+    /// File the log is also written to
+    pub log_file: Option<PathBuf>,
+    /// Size the log file is started afresh at, like "50M"
+    pub log_max_size: Option<String>,
+    /// Age the log file is started afresh at, like "1d"
+    pub log_max_age: Option<String>,
+    /// Old log files kept
+    pub log_keep: Option<usize>,
+}
+
+impl Settings {
+    /// Values of `other` where set, else ours
+    fn overridden_by(self, other: &Settings) -> Settings {
+        let other = other.clone();
+        Settings {
+            port: other.port.or(self.port),
+            board: other.board.or(self.board),
+            baud: other.baud.or(self.baud),
+            transport: other.transport.or(self.transport),
+            lenient: other.lenient.or(self.lenient),
+            variant: other.variant.or(self.variant),
+            engine: other.engine.or(self.engine),
+            lichess_token: other.lichess_token.or(self.lichess_token),
+            pgn: other.pgn.or(self.pgn),
+            record: other.record.or(self.record),
+            record_keep: other.record_keep.or(self.record_keep),
+            event_socket: other.event_socket.or(self.event_socket),
+            review_engine: other.review_engine.or(self.review_engine),
+            review_depth: other.review_depth.or(self.review_depth),
+            rotated: other.rotated.or(self.rotated),
+            dedup_window: other.dedup_window.or(self.dedup_window),
+            draw_buttons: other.draw_buttons.or(self.draw_buttons),
+            resign_buttons: other.resign_buttons.or(self.resign_buttons),
+            pause_buttons: other.pause_buttons.or(self.pause_buttons),
+            time_control: other.time_control.or(self.time_control),
+            local_clock: other.local_clock.or(self.local_clock),
+            confirm_moves: other.confirm_moves.or(self.confirm_moves),
+            casual: other.casual.or(self.casual),
+            beep: other.beep.or(self.beep),
+            low_time_beep: other.low_time_beep.or(self.low_time_beep),
+            speak: other.speak.or(self.speak),
+            locale: other.locale.or(self.locale),
+            pieces: other.pieces.or(self.pieces),
+            bottom: other.bottom.or(self.bottom),
+            training: other.training.or(self.training),
+            database: other.database.or(self.database),
+            overlay: other.overlay.or(self.overlay),
+            overlay_png: other.overlay_png.or(self.overlay_png),
+            position_files: other.position_files.or(self.position_files),
+            daemon: other.daemon.or(self.daemon),
+            state_dir: other.state_dir.or(self.state_dir),
+            resume: other.resume.or(self.resume),
+            log_file: other.log_file.or(self.log_file),
+            log_max_size: other.log_max_size.or(self.log_max_size),
+            log_max_age: other.log_max_age.or(self.log_max_age),
+            log_keep: other.log_keep.or(self.log_keep),
+        }
+    }
+
+    /// The settings as the environment variables the command line options fall back to
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let path = |p: &Option<PathBuf>| p.as_ref().map(|p| p.display().to_string());
+        [
+            ("JACKOLOPE_PORT", self.port.clone()),
+            ("JACKOLOPE_BOARD", self.board.clone()),
+            ("JACKOLOPE_BAUD", self.baud.as_ref().map(Baud::to_string)),
+            ("JACKOLOPE_TRANSPORT", self.transport.clone()),
+            ("JACKOLOPE_LENIENT", self.lenient.map(|l| l.to_string())),
+            ("JACKOLOPE_VARIANT", self.variant.clone()),
+            ("JACKOLOPE_ENGINE", path(&self.engine)),
+            ("LICHESS_TOKEN", self.lichess_token.clone()),
+            ("JACKOLOPE_PGN", path(&self.pgn)),
+            ("JACKOLOPE_RECORD", path(&self.record)),
+            (
+                "JACKOLOPE_RECORD_KEEP",
+                self.record_keep.map(|n| n.to_string()),
+            ),
+            ("JACKOLOPE_EVENT_SOCKET", path(&self.event_socket)),
+            ("JACKOLOPE_REVIEW_ENGINE", path(&self.review_engine)),
+            (
+                "JACKOLOPE_REVIEW_DEPTH",
+                self.review_depth.map(|d| d.to_string()),
+            ),
+            ("JACKOLOPE_ROTATED", self.rotated.map(|r| r.to_string())),
+            (
+                "JACKOLOPE_DEDUP_WINDOW",
+                self.dedup_window.map(|ms| ms.to_string()),
+            ),
+            ("JACKOLOPE_DRAW_BUTTONS", self.draw_buttons.clone()),
+            ("JACKOLOPE_RESIGN_BUTTONS", self.resign_buttons.clone()),
+            ("JACKOLOPE_PAUSE_BUTTONS", self.pause_buttons.clone()),
+            ("JACKOLOPE_TIME_CONTROL", self.time_control.clone()),
+            (
+                "JACKOLOPE_LOCAL_CLOCK",
+                self.local_clock.map(|l| l.to_string()),
+            ),
+            (
+                "JACKOLOPE_CONFIRM_MOVES",
+                self.confirm_moves.map(|c| c.to_string()),
+            ),
+            ("JACKOLOPE_CASUAL", self.casual.map(|c| c.to_string())),
+            ("JACKOLOPE_BEEP", self.beep.clone()),
+            (
+                "JACKOLOPE_LOW_TIME_BEEP",
+                self.low_time_beep.map(|s| s.to_string()),
+            ),
+            ("JACKOLOPE_SPEAK", self.speak.clone()),
+            ("JACKOLOPE_LOCALE", self.locale.clone()),
+            ("JACKOLOPE_PIECES", self.pieces.clone()),
+            ("JACKOLOPE_BOTTOM", self.bottom.clone()),
+            ("JACKOLOPE_TRAINING", self.training.map(|t| t.to_string())),
+            ("JACKOLOPE_DATABASE", path(&self.database)),
+            ("JACKOLOPE_OVERLAY", self.overlay.clone()),
+            ("JACKOLOPE_OVERLAY_PNG", path(&self.overlay_png)),
+            ("JACKOLOPE_POSITION_FILES", path(&self.position_files)),
+            ("JACKOLOPE_DAEMON", self.daemon.map(|d| d.to_string())),
+            ("JACKOLOPE_STATE_DIR", path(&self.state_dir)),
+            ("JACKOLOPE_RESUME", self.resume.clone()),
+            ("JACKOLOPE_LOG_FILE", path(&self.log_file)),
+            ("JACKOLOPE_LOG_MAX_SIZE", self.log_max_size.clone()),
+            ("JACKOLOPE_LOG_MAX_AGE", self.log_max_age.clone()),
+            ("JACKOLOPE_LOG_KEEP", self.log_keep.map(|n| n.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+        .collect()
+    }
+}
+
+/// Contents of `config.toml`: defaults for all runs plus named profiles on top of them,
+/// and the labels and players of boards by serial number
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Config {
+    #[serde(flatten)]
+    pub defaults: Settings,
+    #[serde(default)]
+    pub profiles: HashMap<String, Settings>,
+    #[serde(default)]
+    pub boards: HashMap<String, GameTags>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, Error> {
+        toml::from_str(text).map_err(Error::Toml)
+    }
+
+    /// Load the config file, an empty config if it does not exist
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Config::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(Error::Io(e)),
+        }
+    }
+
+    /// Label and players configured for the board with serial number `serial`
+    pub fn board_tags(&self, serial: &str) -> Option<&GameTags> {
+        self.boards.get(serial)
+    }
+
+    /// The defaults with `profile` applied
+    pub fn settings(&self, profile: Option<&str>) -> Result<Settings, Error> {
+        match profile {
+            None => Ok(self.defaults.clone()),
+            Some(name) => self
+                .profiles
+                .get(name)
+                .map(|profile| self.defaults.clone().overridden_by(profile))
+                .ok_or_else(|| Error::UnknownProfile(name.to_string())),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/jackolope/config.toml`, falling back to `~/.config`
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(base.join("jackolope").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        let config = Config::parse(
+            r#"
+            port = "/dev/ttyUSB0"
+            baud = 19200
+            engine = "/usr/bin/stockfish"
+            dedup_window = 0
+            resign_buttons = "none"
+
+            [profiles.club]
+            port = "/dev/ttyUSB1"
+            board = "millennium"
+
+            [boards.A01234]
+            board = "Board 3"
+            white = "Smith"
+            black = "Jones"
+            "#,
+        )
+        .unwrap();
+        let club = config.settings(Some("club")).unwrap();
+        assert_eq!(club.port.as_deref(), Some("/dev/ttyUSB1"));
+        assert_eq!(club.engine, Some(PathBuf::from("/usr/bin/stockfish")));
+        assert_eq!(
+            config.settings(None).unwrap().port.as_deref(),
+            Some("/dev/ttyUSB0")
+        );
+        assert!(config.settings(Some("home")).is_err());
+        assert_eq!(club.baud, Some(Baud::Rate(19200)));
+        assert!(club
+            .env_vars()
+            .contains(&("JACKOLOPE_BOARD", "millennium".to_string())));
+        assert!(club
+            .env_vars()
+            .contains(&("JACKOLOPE_DEDUP_WINDOW", "0".to_string())));
+        assert!(club
+            .env_vars()
+            .contains(&("JACKOLOPE_RESIGN_BUTTONS", "none".to_string())));
+        let tags = config.board_tags("A01234").unwrap();
+        assert_eq!(tags.label().as_deref(), Some("Board 3: Smith - Jones"));
+        assert_eq!(config.board_tags("B56789"), None);
+    }
+}
@@ -0,0 +1,166 @@
+//! Running unattended under a service manager such as systemd: log lines the journal
+//! understands, tell the manager when the board is being followed, and keep the PID,
+//! session log and status file in one state directory so a restarted driver finds the
+//! game it was following.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{debug, Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::{self, FormatEvent, FormatFields};
+use tracing_subscriber::fmt::FmtContext;
+use tracing_subscriber::registry::LookupSpan;
+
+/// State directory when neither the command line nor the service manager names one
+pub const DEFAULT_STATE_DIR: &str = "/var/lib/jackolope";
+pub const PID_FILE: &str = "jackolope.pid";
+pub const SESSION_LOG: &str = "session.jsonl";
+pub const STATUS_FILE: &str = "status.json";
+
+/// `dir` if given, else the first directory systemd set up with `StateDirectory=`,
+/// else `DEFAULT_STATE_DIR`
+pub fn state_dir(dir: Option<PathBuf>) -> PathBuf {
+    dir.or_else(|| {
+        let dirs = std::env::var_os("STATE_DIRECTORY")?;
+        std::env::split_paths(&dirs).next()
+    })
+    .unwrap_or_else(|| PathBuf::from(DEFAULT_STATE_DIR))
+}
+
+/// The PID of this process in a file, removed again when dropped
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Create `dir` if need be and write the PID file into it
+    pub fn create(dir: &Path) -> io::Result<PidFile> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(PID_FILE);
+        std::fs::write(&path, format!("{}\n", std::process::id()))?;
+        Ok(PidFile { path })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            debug!(error = %e, path = %self.path.display(), "failed to remove PID file");
+        }
+    }
+}
+
+/// Tell the service manager the board is being followed
+pub fn notify_ready() {
+    #[cfg(feature = "systemd")]
+    notify(&[sd_notify::NotifyState::Ready]);
+}
+
+/// Tell the service manager what the driver is doing, shown by `systemctl status`
+pub fn notify_status(status: &str) {
+    #[cfg(feature = "systemd")]
+    notify(&[sd_notify::NotifyState::Status(status)]);
+    #[cfg(not(feature = "systemd"))]
+    let _ = status;
+}
+
+/// Tell the service manager the driver is shutting down
+pub fn notify_stopping() {
+    #[cfg(feature = "systemd")]
+    notify(&[sd_notify::NotifyState::Stopping]);
+}
+
+/// Does nothing when not started by a service manager that listens
+#[cfg(feature = "systemd")]
+fn notify(state: &[sd_notify::NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        debug!(error = %e, "failed to notify the service manager");
+    }
+}
+
+/// Log lines for the journal: no time, which the journal adds itself, and the syslog
+/// priority in front so it can filter by level. Meant for a subscriber without ANSI
+/// colours.
+pub struct Journal;
+
+impl<S, N> FormatEvent<S, N> for Journal
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        write!(
+            writer,
+            "<{}>{}: ",
+            priority(*metadata.level()),
+            metadata.target()
+        )?;
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
+
+fn priority(level: Level) -> u8 {
+    match level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        _ => 7,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Lines(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_journal_lines_and_pid_file() {
+        let lines = Lines::default();
+        let writer = lines.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .event_format(Journal)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(port = "/dev/ttyUSB0", "board disconnected");
+            tracing::info!("resumed");
+        });
+        let text = String::from_utf8(lines.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            text,
+            "<4>jackolope::daemon::tests: board disconnected port=\"/dev/ttyUSB0\"\n\
+             <6>jackolope::daemon::tests: resumed\n"
+        );
+
+        let dir = std::env::temp_dir().join(format!("jackolope-daemon-{}", std::process::id()));
+        assert_eq!(state_dir(Some(dir.clone())), dir);
+        let pid = PidFile::create(&dir).unwrap();
+        let written = std::fs::read_to_string(dir.join(PID_FILE)).unwrap();
+        assert_eq!(written.trim(), std::process::id().to_string());
+        drop(pid);
+        assert!(!dir.join(PID_FILE).exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
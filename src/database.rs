@@ -0,0 +1,299 @@
+//! Archive of finished games in an SQLite file, so a club machine collects a searchable
+//! record of everything played on it rather than loose PGN files.
+
+use crate::game::GameBoard;
+use crate::pgn;
+use crate::relay::{Error, Relay};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fmt;
+use std::path::Path;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS games (
+    id INTEGER PRIMARY KEY,
+    played_at TEXT NOT NULL DEFAULT (datetime('now')),
+    event TEXT,
+    round TEXT,
+    board TEXT,
+    serial TEXT,
+    white TEXT,
+    black TEXT,
+    result TEXT NOT NULL,
+    moves INTEGER NOT NULL,
+    pgn TEXT NOT NULL,
+    -- FEN after every move, one per line, the initial position first
+    fens TEXT NOT NULL,
+    -- JSON array of the milliseconds every move took, null where not timed
+    timings TEXT NOT NULL
+)";
+
+/// A game as kept in the archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredGame {
+    pub id: i64,
+    /// UTC, like "2024-05-01 19:30:00"
+    pub played_at: String,
+    pub board: Option<String>,
+    pub white: Option<String>,
+    pub black: Option<String>,
+    pub result: String,
+    /// Half moves played
+    pub moves: u32,
+    pub pgn: String,
+}
+
+impl fmt::Display for StoredGame {
+    /// One line for a list of games
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>5}  {}  {} - {}  {}  {} moves",
+            self.id,
+            self.played_at,
+            self.white.as_deref().unwrap_or("?"),
+            self.black.as_deref().unwrap_or("?"),
+            self.result,
+            self.moves.div_ceil(2)
+        )?;
+        if let Some(board) = &self.board {
+            write!(f, "  {}", board)?;
+        }
+        Ok(())
+    }
+}
+
+/// Which games to list, every condition that is set has to hold
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    /// Part of the name of either player
+    pub player: Option<String>,
+    /// Label or serial number of the board
+    pub board: Option<String>,
+    /// PGN result, like "1-0"
+    pub result: Option<String>,
+    /// At most this many games, the latest ones
+    pub limit: Option<u32>,
+}
+
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Open the archive at `path`, creating it if there is none
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute(SCHEMA, [])?;
+        Ok(Database { connection })
+    }
+
+    /// Archive `game`, played on the board with `serial`, and return its id
+    pub fn save(&self, game: &GameBoard, serial: Option<&str>) -> rusqlite::Result<i64> {
+        let tags = game.tags();
+        let (fens, timings) = history(game);
+        self.connection.execute(
+            "INSERT INTO games (event, round, board, serial, white, black, result, moves, pgn, fens, timings)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                tags.event,
+                tags.round,
+                tags.board,
+                serial,
+                tags.white,
+                tags.black,
+                pgn::result(game),
+                game.moves().len() as u32,
+                pgn::to_pgn(game),
+                fens,
+                timings,
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    /// Archive `game` again under `id`, where an earlier state of it was saved
+    pub fn update(&self, id: i64, game: &GameBoard, serial: Option<&str>) -> rusqlite::Result<()> {
+        let tags = game.tags();
+        let (fens, timings) = history(game);
+        self.connection.execute(
+            "UPDATE games SET event = ?2, round = ?3, board = ?4, serial = ?5, white = ?6,
+                 black = ?7, result = ?8, moves = ?9, pgn = ?10, fens = ?11, timings = ?12
+             WHERE id = ?1",
+            params![
+                id,
+                tags.event,
+                tags.round,
+                tags.board,
+                serial,
+                tags.white,
+                tags.black,
+                pgn::result(game),
+                game.moves().len() as u32,
+                pgn::to_pgn(game),
+                fens,
+                timings,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The games matching `query`, the latest first
+    pub fn search(&self, query: &Query) -> rusqlite::Result<Vec<StoredGame>> {
+        let like = |s: &Option<String>| s.as_ref().map(|s| format!("%{}%", s));
+        let mut statement = self.connection.prepare(
+            "SELECT id, played_at, board, white, black, result, moves, pgn FROM games
+             WHERE (?1 IS NULL OR white LIKE ?1 OR black LIKE ?1)
+               AND (?2 IS NULL OR board LIKE ?2 OR serial = ?3)
+               AND (?4 IS NULL OR result = ?4)
+             ORDER BY id DESC LIMIT ?5",
+        )?;
+        let games = statement.query_map(
+            params![
+                like(&query.player),
+                like(&query.board),
+                query.board,
+                query.result,
+                query.limit.map_or(-1, i64::from),
+            ],
+            stored_game,
+        )?;
+        games.collect()
+    }
+
+    /// The game with `id`, if there is one
+    pub fn get(&self, id: i64) -> rusqlite::Result<Option<StoredGame>> {
+        self.connection
+            .query_row(
+                "SELECT id, played_at, board, white, black, result, moves, pgn FROM games
+                 WHERE id = ?1",
+                [id],
+                stored_game,
+            )
+            .optional()
+    }
+}
+
+/// FEN after every move, one per line, and the JSON array of the move times
+fn history(game: &GameBoard) -> (String, String) {
+    let fens: Vec<String> = (0..=game.moves().len())
+        .filter_map(|ply| game.position_at(ply))
+        .map(|position| position.to_fen())
+        .collect();
+    let timings: Vec<Option<u64>> = game
+        .timing_stats()
+        .moves
+        .iter()
+        .map(|m| m.elapsed_ms)
+        .collect();
+    (
+        fens.join("\n"),
+        serde_json::to_string(&timings).unwrap_or_default(),
+    )
+}
+
+fn stored_game(row: &rusqlite::Row) -> rusqlite::Result<StoredGame> {
+    Ok(StoredGame {
+        id: row.get(0)?,
+        played_at: row.get(1)?,
+        board: row.get(2)?,
+        white: row.get(3)?,
+        black: row.get(4)?,
+        result: row.get(5)?,
+        moves: row.get(6)?,
+        pgn: row.get(7)?,
+    })
+}
+
+/// Archives every game as it ends
+pub struct GameStore {
+    database: Database,
+    /// Serial number of the board the games are played on
+    serial: Option<String>,
+    /// Id of the current game once saved, a result reported again updates it
+    stored: Option<i64>,
+}
+
+impl GameStore {
+    pub fn new(database: Database, serial: Option<String>) -> Self {
+        GameStore {
+            database,
+            serial,
+            stored: None,
+        }
+    }
+}
+
+impl Relay for GameStore {
+    fn name(&self) -> &str {
+        "database"
+    }
+
+    fn on_new_game(&mut self, _game: &GameBoard) -> Result<(), Error> {
+        self.stored = None;
+        Ok(())
+    }
+
+    fn on_move(&mut self, _game: &GameBoard) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn on_result(&mut self, game: &GameBoard, _result: &str) -> Result<(), Error> {
+        let serial = self.serial.as_deref();
+        match self.stored {
+            Some(id) => self.database.update(id, game, serial)?,
+            None => self.stored = Some(self.database.save(game, serial)?),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::GameTags;
+    use crate::protocol::{ChessBoard, RawPiece};
+    use crate::rules::Position;
+
+    #[test]
+    fn test_save_and_search() {
+        let database = Database::open(":memory:").unwrap();
+        let mut game = GameBoard::new(ChessBoard {
+            board: [RawPiece::Empty; 64],
+        });
+        game.reset_to(Position::starting());
+        game.set_tags(GameTags {
+            white: Some("Smith".to_string()),
+            black: Some("Jones".to_string()),
+            board: Some("Board 1".to_string()),
+            ..GameTags::default()
+        });
+        game.replace_moves(&["e2e4".to_string(), "e7e5".to_string()])
+            .unwrap();
+        let id = database.save(&game, Some("SIM01")).unwrap();
+        let query = |player: &str, board: &str| Query {
+            player: Some(player.to_string()),
+            board: Some(board.to_string()),
+            ..Query::default()
+        };
+        let games = database.search(&query("smi", "SIM01")).unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].id, id);
+        assert_eq!(games[0].moves, 2);
+        assert!(games[0].pgn.contains("1. e4 e5"));
+        assert!(database.search(&query("Carlsen", "")).unwrap().is_empty());
+        assert_eq!(database.get(id).unwrap(), Some(games[0].clone()));
+
+        // A result reported twice, as after a resync, keeps a single record
+        let mut store = GameStore::new(database, None);
+        store.on_new_game(&game).unwrap();
+        store.on_result(&game, "*").unwrap();
+        game.replace_moves(&["e2e4".to_string(), "e7e5".to_string(), "g1f3".to_string()])
+            .unwrap();
+        store.on_result(&game, "*").unwrap();
+        let games = store.database.search(&query("Smith", "")).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].moves, 3);
+        store.on_new_game(&game).unwrap();
+        store.on_result(&game, "*").unwrap();
+        assert_eq!(store.database.search(&query("Smith", "")).unwrap().len(), 3);
+    }
+}
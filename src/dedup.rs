@@ -0,0 +1,97 @@
+use crate::event::Event;
+use crate::protocol::ChessMove;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Drops field updates a board sends twice. Some boards repeat updates after a
+/// retransmission request or when polled twice, and a repeated lift after the piece
+/// went back down would undo the move on the tracked board. A hand can not lift and
+/// place the same piece on a square within a fraction of a second, so the same update
+/// again within `window` is taken for an echo.
+#[derive(Debug, Clone)]
+pub struct Dedup {
+    window: Duration,
+    /// Updates passed on within the window, oldest first
+    recent: VecDeque<(ChessMove, Instant)>,
+}
+
+impl Dedup {
+    /// Window used when none is configured
+    pub const DEFAULT_WINDOW: Duration = Duration::from_millis(150);
+
+    /// Suppress repeats within `window`, a zero window lets everything through
+    pub fn new(window: Duration) -> Self {
+        Dedup {
+            window,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Whether `event` arriving at `now` should be passed on. A board dump starts
+    /// over, as it replaces whatever the updates said.
+    pub fn accept(&mut self, event: &Event, now: Instant) -> bool {
+        match event {
+            Event::BoardDump(_) => {
+                self.recent.clear();
+                true
+            }
+            Event::FieldUpdate(mv) => self.accept_update(*mv, now),
+            _ => true,
+        }
+    }
+
+    fn accept_update(&mut self, mv: ChessMove, now: Instant) -> bool {
+        if self.window.is_zero() {
+            return true;
+        }
+        while let Some((_, at)) = self.recent.front() {
+            if now.saturating_duration_since(*at) < self.window {
+                break;
+            }
+            self.recent.pop_front();
+        }
+        if self.recent.iter().any(|(seen, _)| *seen == mv) {
+            debug!(%mv, "dropping repeated field update");
+            return false;
+        }
+        self.recent.push_back((mv, now));
+        true
+    }
+}
+
+impl Default for Dedup {
+    fn default() -> Self {
+        Dedup::new(Dedup::DEFAULT_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{MessageType, Response};
+
+    fn update(grid: u8, piece: u8) -> Event {
+        let response = Response::try_from_raw(MessageType::FieldUpdate, &[grid, piece]).unwrap();
+        Event::from_response(response).unwrap()
+    }
+
+    #[test]
+    fn test_dedup() {
+        let mut dedup = Dedup::new(Duration::from_millis(100));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert!(dedup.accept(&update(52, 0), at(0)));
+        assert!(dedup.accept(&update(36, 1), at(10)));
+        // Echo of the lift, after the piece was placed
+        assert!(!dedup.accept(&update(52, 0), at(20)));
+        assert!(!dedup.accept(&update(36, 1), at(50)));
+        // The same update later is a real one
+        assert!(dedup.accept(&update(36, 0), at(400)));
+        assert!(dedup.accept(&update(36, 1), at(500)));
+
+        let mut off = Dedup::new(Duration::ZERO);
+        assert!(off.accept(&update(52, 0), at(0)));
+        assert!(off.accept(&update(52, 0), at(0)));
+    }
+}
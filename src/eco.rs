@@ -0,0 +1,473 @@
+use crate::rules::{Ply, Position};
+use crate::zobrist;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// A named opening line from the ECO classification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opening {
+    pub eco: &'static str,
+    pub name: &'static str,
+    /// Number of plies in the line that identified the opening
+    pub plies: usize,
+}
+
+/// ECO code, opening name and the line in UCI notation
+const OPENINGS: &[(&str, &str, &str)] = &[
+    ("A00", "Polish Opening", "b2b4"),
+    ("A00", "Grob Opening", "g2g4"),
+    ("A01", "Nimzo-Larsen Attack", "b2b3"),
+    ("A02", "Bird Opening", "f2f4"),
+    ("A03", "Bird Opening: Dutch Variation", "f2f4 d7d5"),
+    ("A04", "Zukertort Opening", "g1f3"),
+    ("A05", "Zukertort Opening: Quiet System", "g1f3 g8f6"),
+    ("A06", "Zukertort Opening", "g1f3 d7d5"),
+    ("A07", "King's Indian Attack", "g1f3 d7d5 g2g3"),
+    ("A09", "Réti Opening", "g1f3 d7d5 c2c4"),
+    ("A10", "English Opening", "c2c4"),
+    ("A13", "English Opening: Agincourt Defense", "c2c4 e7e6"),
+    ("A15", "English Opening: Anglo-Indian Defense", "c2c4 g8f6"),
+    (
+        "A16",
+        "English Opening: Anglo-Indian Defense, Queen's Knight Variation",
+        "c2c4 g8f6 b1c3",
+    ),
+    (
+        "A20",
+        "English Opening: King's English Variation",
+        "c2c4 e7e5",
+    ),
+    ("A30", "English Opening: Symmetrical Variation", "c2c4 c7c5"),
+    ("A40", "Queen's Pawn Game", "d2d4"),
+    ("A41", "Queen's Pawn Game: Modern Defense", "d2d4 d7d6"),
+    ("A43", "Benoni Defense: Old Benoni", "d2d4 c7c5"),
+    ("A45", "Indian Defense", "d2d4 g8f6"),
+    ("A45", "Trompowsky Attack", "d2d4 g8f6 c1g5"),
+    ("A46", "Indian Defense: Knights Variation", "d2d4 g8f6 g1f3"),
+    ("A50", "Indian Defense: Normal Variation", "d2d4 g8f6 c2c4"),
+    (
+        "A51",
+        "Indian Defense: Budapest Defense",
+        "d2d4 g8f6 c2c4 e7e5",
+    ),
+    ("A56", "Benoni Defense", "d2d4 g8f6 c2c4 c7c5"),
+    ("A57", "Benko Gambit", "d2d4 g8f6 c2c4 c7c5 d4d5 b7b5"),
+    ("A60", "Modern Benoni", "d2d4 g8f6 c2c4 c7c5 d4d5 e7e6"),
+    ("A80", "Dutch Defense", "d2d4 f7f5"),
+    ("A82", "Dutch Defense: Staunton Gambit", "d2d4 f7f5 e2e4"),
+    ("B00", "King's Pawn Game", "e2e4"),
+    ("B00", "Nimzowitsch Defense", "e2e4 b8c6"),
+    ("B01", "Scandinavian Defense", "e2e4 d7d5"),
+    (
+        "B01",
+        "Scandinavian Defense: Mieses-Kotroc Variation",
+        "e2e4 d7d5 e4d5 d8d5",
+    ),
+    ("B02", "Alekhine Defense", "e2e4 g8f6"),
+    ("B06", "Modern Defense", "e2e4 g7g6"),
+    ("B07", "Pirc Defense", "e2e4 d7d6 d2d4 g8f6"),
+    ("B10", "Caro-Kann Defense", "e2e4 c7c6"),
+    (
+        "B12",
+        "Caro-Kann Defense: Advance Variation",
+        "e2e4 c7c6 d2d4 d7d5 e4e5",
+    ),
+    (
+        "B13",
+        "Caro-Kann Defense: Exchange Variation",
+        "e2e4 c7c6 d2d4 d7d5 e4d5 c6d5",
+    ),
+    ("B15", "Caro-Kann Defense", "e2e4 c7c6 d2d4 d7d5 b1c3"),
+    ("B20", "Sicilian Defense", "e2e4 c7c5"),
+    (
+        "B21",
+        "Sicilian Defense: Smith-Morra Gambit",
+        "e2e4 c7c5 d2d4 c5d4 c2c3",
+    ),
+    (
+        "B22",
+        "Sicilian Defense: Alapin Variation",
+        "e2e4 c7c5 c2c3",
+    ),
+    ("B23", "Sicilian Defense: Closed", "e2e4 c7c5 b1c3"),
+    ("B27", "Sicilian Defense", "e2e4 c7c5 g1f3"),
+    (
+        "B30",
+        "Sicilian Defense: Old Sicilian",
+        "e2e4 c7c5 g1f3 b8c6",
+    ),
+    (
+        "B30",
+        "Sicilian Defense: Nyezhmetdinov-Rossolimo Attack",
+        "e2e4 c7c5 g1f3 b8c6 f1b5",
+    ),
+    (
+        "B32",
+        "Sicilian Defense: Open",
+        "e2e4 c7c5 g1f3 b8c6 d2d4 c5d4 f3d4",
+    ),
+    (
+        "B33",
+        "Sicilian Defense: Lasker-Pelikan Variation",
+        "e2e4 c7c5 g1f3 b8c6 d2d4 c5d4 f3d4 g8f6 b1c3 e7e5",
+    ),
+    (
+        "B40",
+        "Sicilian Defense: French Variation",
+        "e2e4 c7c5 g1f3 e7e6",
+    ),
+    ("B50", "Sicilian Defense", "e2e4 c7c5 g1f3 d7d6"),
+    (
+        "B54",
+        "Sicilian Defense: Open",
+        "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4",
+    ),
+    (
+        "B56",
+        "Sicilian Defense: Classical Variation",
+        "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3",
+    ),
+    (
+        "B70",
+        "Sicilian Defense: Dragon Variation",
+        "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3 g7g6",
+    ),
+    (
+        "B80",
+        "Sicilian Defense: Scheveningen Variation",
+        "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3 e7e6",
+    ),
+    (
+        "B90",
+        "Sicilian Defense: Najdorf Variation",
+        "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3 a7a6",
+    ),
+    ("C00", "French Defense", "e2e4 e7e6"),
+    (
+        "C01",
+        "French Defense: Exchange Variation",
+        "e2e4 e7e6 d2d4 d7d5 e4d5",
+    ),
+    (
+        "C02",
+        "French Defense: Advance Variation",
+        "e2e4 e7e6 d2d4 d7d5 e4e5",
+    ),
+    (
+        "C03",
+        "French Defense: Tarrasch Variation",
+        "e2e4 e7e6 d2d4 d7d5 b1d2",
+    ),
+    (
+        "C10",
+        "French Defense: Paulsen Variation",
+        "e2e4 e7e6 d2d4 d7d5 b1c3",
+    ),
+    (
+        "C11",
+        "French Defense: Classical Variation",
+        "e2e4 e7e6 d2d4 d7d5 b1c3 g8f6",
+    ),
+    (
+        "C15",
+        "French Defense: Winawer Variation",
+        "e2e4 e7e6 d2d4 d7d5 b1c3 f8b4",
+    ),
+    ("C20", "King's Pawn Game", "e2e4 e7e5"),
+    ("C21", "Center Game", "e2e4 e7e5 d2d4 e5d4"),
+    ("C21", "Danish Gambit", "e2e4 e7e5 d2d4 e5d4 c2c3"),
+    ("C23", "Bishop's Opening", "e2e4 e7e5 f1c4"),
+    ("C25", "Vienna Game", "e2e4 e7e5 b1c3"),
+    ("C30", "King's Gambit", "e2e4 e7e5 f2f4"),
+    (
+        "C31",
+        "King's Gambit Declined: Falkbeer Countergambit",
+        "e2e4 e7e5 f2f4 d7d5",
+    ),
+    ("C33", "King's Gambit Accepted", "e2e4 e7e5 f2f4 e5f4"),
+    ("C40", "King's Knight Opening", "e2e4 e7e5 g1f3"),
+    ("C40", "Latvian Gambit", "e2e4 e7e5 g1f3 f7f5"),
+    ("C41", "Philidor Defense", "e2e4 e7e5 g1f3 d7d6"),
+    ("C42", "Petrov's Defense", "e2e4 e7e5 g1f3 g8f6"),
+    (
+        "C44",
+        "King's Knight Opening: Normal Variation",
+        "e2e4 e7e5 g1f3 b8c6",
+    ),
+    ("C44", "Ponziani Opening", "e2e4 e7e5 g1f3 b8c6 c2c3"),
+    ("C44", "Scotch Game", "e2e4 e7e5 g1f3 b8c6 d2d4"),
+    ("C45", "Scotch Game", "e2e4 e7e5 g1f3 b8c6 d2d4 e5d4 f3d4"),
+    ("C46", "Three Knights Opening", "e2e4 e7e5 g1f3 b8c6 b1c3"),
+    ("C47", "Four Knights Game", "e2e4 e7e5 g1f3 b8c6 b1c3 g8f6"),
+    ("C50", "Italian Game", "e2e4 e7e5 g1f3 b8c6 f1c4"),
+    (
+        "C50",
+        "Italian Game: Hungarian Defense",
+        "e2e4 e7e5 g1f3 b8c6 f1c4 f8e7",
+    ),
+    (
+        "C50",
+        "Italian Game: Giuoco Piano",
+        "e2e4 e7e5 g1f3 b8c6 f1c4 f8c5",
+    ),
+    (
+        "C51",
+        "Italian Game: Evans Gambit",
+        "e2e4 e7e5 g1f3 b8c6 f1c4 f8c5 b2b4",
+    ),
+    (
+        "C53",
+        "Italian Game: Classical Variation",
+        "e2e4 e7e5 g1f3 b8c6 f1c4 f8c5 c2c3",
+    ),
+    (
+        "C55",
+        "Italian Game: Two Knights Defense",
+        "e2e4 e7e5 g1f3 b8c6 f1c4 g8f6",
+    ),
+    (
+        "C57",
+        "Italian Game: Two Knights Defense, Knight Attack",
+        "e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 f3g5",
+    ),
+    ("C60", "Ruy Lopez", "e2e4 e7e5 g1f3 b8c6 f1b5"),
+    (
+        "C62",
+        "Ruy Lopez: Steinitz Defense",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 d7d6",
+    ),
+    (
+        "C63",
+        "Ruy Lopez: Schliemann Defense",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 f7f5",
+    ),
+    (
+        "C65",
+        "Ruy Lopez: Berlin Defense",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 g8f6",
+    ),
+    (
+        "C68",
+        "Ruy Lopez: Exchange Variation",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5c6",
+    ),
+    (
+        "C70",
+        "Ruy Lopez: Morphy Defense",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6",
+    ),
+    (
+        "C77",
+        "Ruy Lopez: Morphy Defense",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6",
+    ),
+    (
+        "C78",
+        "Ruy Lopez: Morphy Defense",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6 e1g1",
+    ),
+    (
+        "C80",
+        "Ruy Lopez: Open",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6 e1g1 f6e4",
+    ),
+    (
+        "C84",
+        "Ruy Lopez: Closed",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6 e1g1 f8e7",
+    ),
+    ("D00", "Queen's Pawn Game", "d2d4 d7d5"),
+    (
+        "D00",
+        "Queen's Pawn Game: Accelerated London System",
+        "d2d4 d7d5 c1f4",
+    ),
+    (
+        "D02",
+        "Queen's Pawn Game: Zukertort Variation",
+        "d2d4 d7d5 g1f3",
+    ),
+    (
+        "D02",
+        "Queen's Pawn Game: London System",
+        "d2d4 d7d5 g1f3 g8f6 c1f4",
+    ),
+    ("D06", "Queen's Gambit", "d2d4 d7d5 c2c4"),
+    (
+        "D07",
+        "Queen's Gambit Declined: Chigorin Defense",
+        "d2d4 d7d5 c2c4 b8c6",
+    ),
+    (
+        "D08",
+        "Queen's Gambit Declined: Albin Countergambit",
+        "d2d4 d7d5 c2c4 e7e5",
+    ),
+    ("D10", "Slav Defense", "d2d4 d7d5 c2c4 c7c6"),
+    ("D20", "Queen's Gambit Accepted", "d2d4 d7d5 c2c4 d5c4"),
+    ("D30", "Queen's Gambit Declined", "d2d4 d7d5 c2c4 e7e6"),
+    (
+        "D31",
+        "Queen's Gambit Declined: Queen's Knight Variation",
+        "d2d4 d7d5 c2c4 e7e6 b1c3",
+    ),
+    ("D32", "Tarrasch Defense", "d2d4 d7d5 c2c4 e7e6 b1c3 c7c5"),
+    (
+        "D35",
+        "Queen's Gambit Declined: Exchange Variation",
+        "d2d4 d7d5 c2c4 e7e6 b1c3 g8f6 c4d5",
+    ),
+    (
+        "D43",
+        "Semi-Slav Defense",
+        "d2d4 d7d5 c2c4 c7c6 g1f3 g8f6 b1c3 e7e6",
+    ),
+    ("D80", "Grünfeld Defense", "d2d4 g8f6 c2c4 g7g6 b1c3 d7d5"),
+    (
+        "E00",
+        "Indian Defense: East Indian Defense",
+        "d2d4 g8f6 c2c4 e7e6",
+    ),
+    ("E01", "Catalan Opening", "d2d4 g8f6 c2c4 e7e6 g2g3"),
+    (
+        "E10",
+        "Indian Defense: Anglo-Indian",
+        "d2d4 g8f6 c2c4 e7e6 g1f3",
+    ),
+    (
+        "E11",
+        "Bogo-Indian Defense",
+        "d2d4 g8f6 c2c4 e7e6 g1f3 f8b4",
+    ),
+    (
+        "E12",
+        "Queen's Indian Defense",
+        "d2d4 g8f6 c2c4 e7e6 g1f3 b7b6",
+    ),
+    (
+        "E20",
+        "Nimzo-Indian Defense",
+        "d2d4 g8f6 c2c4 e7e6 b1c3 f8b4",
+    ),
+    ("E60", "King's Indian Defense", "d2d4 g8f6 c2c4 g7g6"),
+    ("E61", "King's Indian Defense", "d2d4 g8f6 c2c4 g7g6 b1c3"),
+    (
+        "E70",
+        "King's Indian Defense: Normal Variation",
+        "d2d4 g8f6 c2c4 g7g6 b1c3 f8g7 e2e4 d7d6",
+    ),
+    (
+        "E80",
+        "King's Indian Defense: Sämisch Variation",
+        "d2d4 g8f6 c2c4 g7g6 b1c3 f8g7 e2e4 d7d6 f2f3",
+    ),
+    (
+        "E90",
+        "King's Indian Defense: Normal Variation",
+        "d2d4 g8f6 c2c4 g7g6 b1c3 f8g7 e2e4 d7d6 g1f3",
+    ),
+    (
+        "E97",
+        "King's Indian Defense: Orthodox Variation",
+        "d2d4 g8f6 c2c4 g7g6 b1c3 f8g7 e2e4 d7d6 g1f3 e8g8 f1e2 e7e5 e1g1 b8c6",
+    ),
+];
+
+/// The most specific opening whose line is a prefix of `moves`, played from the
+/// classical start position. Transpositions into a line are not recognised, `by_key`
+/// finds those.
+pub fn classify(moves: &[Ply]) -> Option<Opening> {
+    let played: Vec<String> = moves.iter().map(Ply::uci).collect();
+    OPENINGS
+        .iter()
+        .filter_map(|&(eco, name, line)| {
+            let line: Vec<&str> = line.split(' ').collect();
+            let matches =
+                line.len() <= played.len() && line.iter().zip(&played).all(|(a, b)| a == b);
+            matches.then_some(Opening {
+                eco,
+                name,
+                plies: line.len(),
+            })
+        })
+        // Later entries refine earlier ones of the same length
+        .max_by_key(|opening| opening.plies)
+}
+
+/// The opening whose line ends in the position with Zobrist key `key`, whatever
+/// order its moves were played in
+pub fn by_key(key: u64) -> Option<Opening> {
+    static BY_KEY: OnceLock<HashMap<u64, Opening>> = OnceLock::new();
+    let openings = BY_KEY.get_or_init(|| {
+        let mut openings = HashMap::new();
+        for &(eco, name, line) in OPENINGS {
+            let mut position = Position::starting();
+            let mut plies = 0;
+            for uci in line.split(' ') {
+                let Some(ply) = position
+                    .legal_moves()
+                    .into_iter()
+                    .find(|ply| ply.uci() == uci)
+                else {
+                    break;
+                };
+                position = position.play(&ply);
+                plies += 1;
+            }
+            // Later entries refine earlier ones ending in the same position
+            openings.insert(zobrist::hash(&position), Opening { eco, name, plies });
+        }
+        openings
+    });
+    openings.get(&key).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Position;
+
+    fn play(line: &str) -> Vec<Ply> {
+        let mut position = Position::starting();
+        let mut moves = Vec::new();
+        for uci in line.split(' ') {
+            let ply = position
+                .legal_moves()
+                .into_iter()
+                .find(|ply| ply.uci() == uci)
+                .unwrap();
+            position = position.play(&ply);
+            moves.push(ply);
+        }
+        moves
+    }
+
+    #[test]
+    fn test_every_line_is_legal() {
+        for (_, _, line) in OPENINGS {
+            assert_eq!(play(line).len(), line.split(' ').count());
+        }
+    }
+
+    #[test]
+    fn test_longest_line_wins() {
+        let opening = classify(&play(
+            "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3 a7a6 c1e3",
+        ))
+        .unwrap();
+        assert_eq!(opening.eco, "B90");
+        assert_eq!(opening.plies, 10);
+        assert_eq!(classify(&play("a2a3")), None);
+    }
+
+    #[test]
+    fn test_transposition() {
+        let direct = classify(&play("d2d4 d7d5 g1f3")).unwrap();
+        let transposed = play("g1f3 d7d5 d2d4");
+        assert_ne!(classify(&transposed), Some(direct));
+        let position = transposed
+            .iter()
+            .fold(Position::starting(), |position, ply| position.play(ply));
+        assert_eq!(by_key(zobrist::hash(&position)), Some(direct));
+    }
+}
@@ -0,0 +1,279 @@
+//! A DGT board in software toward other programs, such as chess GUIs with DGT support:
+//! it speaks the board protocol on a serial port or pseudo-terminal and shows whatever
+//! position it is given, so the game can come from another type of board, a replayed
+//! game or network play.
+
+use crate::game::{GameBoard, StartPosition};
+use crate::protocol::{ChessBoard, ClockStatus, PieceColor, Remaining};
+use crate::relay::{self, Relay};
+use crate::simulator::Simulator;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Endpoint name standing for a new pseudo-terminal
+pub const PTY: &str = "pty";
+
+/// Read timeout of the port, what is shown goes out at the latest this late
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+enum Shown {
+    /// The board and the side to move
+    Board(ChessBoard, PieceColor),
+    Clock(Remaining, Remaining),
+}
+
+/// Shows positions to the program on the other end of a port, from a thread of its own
+/// that answers the program like a DGT board would. Dropping it stops the thread.
+pub struct Emulator {
+    shown: Sender<Shown>,
+}
+
+impl Emulator {
+    /// Serve the board protocol on `port`, which should have a short read timeout.
+    /// The board shows the starting position until told otherwise.
+    pub fn serve<P: Read + Write + Send + 'static>(port: P) -> io::Result<Emulator> {
+        let (shown, rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("virtual board".to_string())
+            .spawn(move || {
+                if let Err(e) = serve(Simulator::new(port), rx) {
+                    warn!(error = %e, "virtual board stopped");
+                }
+            })?;
+        Ok(Emulator { shown })
+    }
+
+    /// Serve on `endpoint`, the name of a serial port such as one end of a null modem
+    /// pair, or `pty` for a new pseudo-terminal. Returns the path for the other program
+    /// to open.
+    pub fn open(endpoint: &str) -> Result<(Emulator, String), Box<dyn std::error::Error>> {
+        #[cfg(unix)]
+        if endpoint == PTY {
+            use serialport::SerialPort;
+
+            let (mut master, slave) = serialport::TTYPort::pair()?;
+            master.set_timeout(POLL_INTERVAL)?;
+            let path = slave.name().ok_or("pseudo-terminal has no name")?;
+            let emulator = Emulator::serve(PtyPort {
+                master,
+                _slave: slave,
+            })?;
+            info!(%path, "virtual board ready");
+            return Ok((emulator, path));
+        }
+        let path = crate::transport::port_path(endpoint);
+        let port = serialport::new(&path, 9600).timeout(POLL_INTERVAL).open()?;
+        info!(%path, "virtual board ready");
+        Ok((Emulator::serve(port)?, path))
+    }
+
+    /// Show the current position of `game`
+    pub fn show(&self, game: &GameBoard) {
+        let board = StartPosition::Mirror.layout(game.position());
+        let _ = self
+            .shown
+            .send(Shown::Board(board, game.position().to_move));
+    }
+
+    /// Show the times of the clock, with the side to move of the last game shown
+    pub fn show_clock(&self, white: Remaining, black: Remaining) {
+        let _ = self.shown.send(Shown::Clock(white, black));
+    }
+}
+
+impl Relay for Emulator {
+    fn name(&self) -> &str {
+        "virtual board"
+    }
+
+    fn on_new_game(&mut self, game: &GameBoard) -> Result<(), relay::Error> {
+        self.show(game);
+        Ok(())
+    }
+
+    fn on_move(&mut self, game: &GameBoard) -> Result<(), relay::Error> {
+        self.show(game);
+        Ok(())
+    }
+
+    fn on_clock(&mut self, white: Remaining, black: Remaining) -> Result<(), relay::Error> {
+        self.show_clock(white, black);
+        Ok(())
+    }
+
+    fn on_result(&mut self, game: &GameBoard, _result: &str) -> Result<(), relay::Error> {
+        self.show(game);
+        Ok(())
+    }
+}
+
+/// Both ends of a pseudo-terminal, the slave kept open so the terminal survives the
+/// other program closing it
+#[cfg(unix)]
+struct PtyPort {
+    master: serialport::TTYPort,
+    _slave: serialport::TTYPort,
+}
+
+#[cfg(unix)]
+impl Read for PtyPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.master.read(buf)
+    }
+}
+
+#[cfg(unix)]
+impl Write for PtyPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.master.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.master.flush()
+    }
+}
+
+/// Answer the program and show what arrives on `shown` until the emulator is dropped
+fn serve<P: Read + Write>(mut simulator: Simulator<P>, shown: Receiver<Shown>) -> io::Result<()> {
+    let mut to_move = PieceColor::White;
+    loop {
+        simulator.poll()?;
+        loop {
+            match shown.try_recv() {
+                Ok(Shown::Board(board, side)) => {
+                    to_move = side;
+                    simulator.show(&board)?;
+                }
+                Ok(Shown::Clock(white, black)) => {
+                    let status = match to_move {
+                        PieceColor::Black => ClockStatus::BlacksTurn,
+                        _ => ClockStatus::WhitesTurn,
+                    };
+                    simulator.show_clock(white, black, status)?;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::read_frame;
+    use crate::protocol::{ChessMove, Command, RawPiece, Response};
+    use crate::rules::Position;
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    /// Commands queued for the emulator, its answers collected
+    #[derive(Clone, Default)]
+    struct Wire {
+        commands: Arc<Mutex<Vec<u8>>>,
+        answers: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Read for Wire {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut commands = self.commands.lock().unwrap();
+            if commands.is_empty() || buf.is_empty() {
+                thread::sleep(Duration::from_millis(1));
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            buf[0] = commands.remove(0);
+            Ok(1)
+        }
+    }
+
+    impl Write for Wire {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.answers.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Wire {
+        /// Wait until the commands are read and `frames` answers have come in
+        fn wait(&self, frames: usize) -> Vec<Response> {
+            for _ in 0..2000 {
+                let mut answers = Cursor::new(self.answers.lock().unwrap().clone());
+                let responses: Vec<_> =
+                    std::iter::from_fn(|| read_frame(&mut answers).ok()).collect();
+                if responses.len() >= frames && self.commands.lock().unwrap().is_empty() {
+                    self.answers.lock().unwrap().clear();
+                    return responses;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            panic!("no answer from the virtual board");
+        }
+
+        fn ask(&self, command: Command, frames: usize) -> Vec<Response> {
+            self.commands.lock().unwrap().push(command as u8);
+            self.wait(frames)
+        }
+    }
+
+    #[test]
+    fn test_virtual_board() {
+        let wire = Wire::default();
+        let mut emulator = Emulator::serve(wire.clone()).unwrap();
+        let mut game = GameBoard::new(StartPosition::Mirror.layout(&Position::starting()));
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        game.reset_to(Position::from_fen(fen).unwrap());
+        emulator.on_move(&game).unwrap();
+        // The command may overtake the position, which is shown soon after
+        let shown = (0..100).any(|_| {
+            matches!(
+                wire.ask(Command::RequestBoard, 1).remove(0),
+                Response::BoardDump(board) if board.board[36] == RawPiece::WhitePawn
+                    && board.board[52] == RawPiece::Empty
+            )
+        });
+        assert!(shown);
+
+        // Once updates are asked for, the moves go out as a board would send them
+        assert!(wire.ask(Command::EnableUpdate, 0).is_empty());
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+        game.reset_to(Position::from_fen(fen).unwrap());
+        emulator.on_move(&game).unwrap();
+        emulator
+            .on_clock(Remaining::from_seconds(300), Remaining::from_seconds(295))
+            .unwrap();
+        let responses = wire.wait(3);
+        assert!(matches!(
+            &responses[..2],
+            [
+                Response::FieldUpdate(ChessMove {
+                    grid: 12,
+                    piece: RawPiece::Empty
+                }),
+                Response::FieldUpdate(ChessMove {
+                    grid: 28,
+                    piece: RawPiece::BlackPawn
+                }),
+            ]
+        ));
+        assert!(matches!(
+            &responses[2..],
+            [Response::BWTime {
+                white_time,
+                black_time,
+                status: ClockStatus::WhitesTurn
+            }] if white_time.total_seconds() == 300 && black_time.total_seconds() == 295
+        ));
+        // Asked for, the clock is told again
+        assert!(matches!(
+            &wire.ask(Command::RequestClock, 1)[..],
+            [Response::BWTime { white_time, .. }] if white_time.total_seconds() == 300
+        ));
+    }
+}
@@ -0,0 +1,196 @@
+use crate::protocol::*;
+use crate::rules::{self, Pockets, Position};
+
+/// Material value in pawns, with the king counted as zero
+pub fn piece_value(kind: PieceKind) -> i32 {
+    match kind {
+        PieceKind::Pawn => 1,
+        PieceKind::Knight | PieceKind::Bishop => 3,
+        PieceKind::Rook => 5,
+        PieceKind::Queen => 9,
+        PieceKind::King => 0,
+    }
+}
+
+/// Value in centipawns used by the static evaluation
+fn centipawns(kind: PieceKind) -> i32 {
+    match kind {
+        PieceKind::Pawn => 100,
+        PieceKind::Knight => 320,
+        PieceKind::Bishop => 330,
+        PieceKind::Rook => 500,
+        PieceKind::Queen => 900,
+        PieceKind::King => 0,
+    }
+}
+
+// Piece-square tables seen from white, rank 8 first
+#[rustfmt::skip]
+const PAWN: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+fn table(kind: PieceKind) -> &'static [i32; 64] {
+    match kind {
+        PieceKind::Pawn => &PAWN,
+        PieceKind::Knight => &KNIGHT,
+        PieceKind::Bishop => &BISHOP,
+        PieceKind::Rook => &ROOK,
+        PieceKind::Queen => &QUEEN,
+        PieceKind::King => &KING,
+    }
+}
+
+/// Material of both sides in pawns, pieces in a crazyhouse pocket included
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Material {
+    pub white: i32,
+    pub black: i32,
+}
+
+impl Material {
+    pub fn of(position: &Position) -> Self {
+        let mut material = Material::default();
+        for piece in position.board {
+            if let Some(kind) = piece.kind() {
+                *material.side_mut(piece.get_colour()) += piece_value(kind);
+            }
+        }
+        for colour in [PieceColor::White, PieceColor::Black] {
+            *material.side_mut(colour) += pocket_value(position.pockets.get(colour), piece_value);
+        }
+        material
+    }
+
+    /// White's material minus black's
+    pub fn balance(&self) -> i32 {
+        self.white - self.black
+    }
+
+    fn side_mut(&mut self, colour: PieceColor) -> &mut i32 {
+        match colour {
+            PieceColor::Black => &mut self.black,
+            _ => &mut self.white,
+        }
+    }
+}
+
+fn pocket_value(pocket: &[u8; 5], value: fn(PieceKind) -> i32) -> i32 {
+    [
+        PieceKind::Pawn,
+        PieceKind::Knight,
+        PieceKind::Bishop,
+        PieceKind::Rook,
+        PieceKind::Queen,
+    ]
+    .into_iter()
+    .map(|kind| pocket[Pockets::slot(kind).unwrap()] as i32 * value(kind))
+    .sum()
+}
+
+/// Static evaluation in centipawns from white's point of view: material plus
+/// piece-square bonuses. No search is done, so tactics are invisible to it.
+pub fn evaluate(position: &Position) -> i32 {
+    let mut score = 0;
+    for (sq, piece) in position.board.iter().enumerate() {
+        let Some(kind) = piece.kind() else {
+            continue;
+        };
+        let (file, rank) = (rules::file_of(sq as u8), rules::rank_of(sq as u8));
+        let (index, sign) = match piece.get_colour() {
+            PieceColor::White => ((7 - rank) * 8 + file, 1),
+            _ => (rank * 8 + file, -1),
+        };
+        score += sign * (centipawns(kind) + table(kind)[index as usize]);
+    }
+    score + pocket_value(position.pockets.get(PieceColor::White), centipawns)
+        - pocket_value(position.pockets.get(PieceColor::Black), centipawns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_position_is_balanced() {
+        let position = Position::starting();
+        assert_eq!(Material::of(&position).white, 39);
+        assert_eq!(Material::of(&position).balance(), 0);
+        assert_eq!(evaluate(&position), 0);
+    }
+
+    #[test]
+    fn test_extra_knight() {
+        let position = Position::from_fen("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(Material::of(&position).balance(), 3);
+        // Centralised knight gets its bonus
+        assert_eq!(evaluate(&position), 340);
+    }
+}
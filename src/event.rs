@@ -0,0 +1,176 @@
+use crate::arbiter::Intervention;
+use crate::clock::{ButtonSet, ClockAck};
+use crate::game::{DetectionState, DrawReason, SquareChange, StartPosition};
+use crate::protocol::*;
+use crate::rules::{self, Square};
+use crate::uci::Score;
+use serde::{Deserialize, Serialize};
+
+/// High-level events produced while following a game on the board
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Event {
+    /// A complete board state was received
+    BoardDump(ChessBoard),
+    /// A single square changed on the physical board
+    FieldUpdate(ChessMove),
+    /// A piece was lifted or put down, as told by a field update and the board before it
+    Micro(MicroEvent),
+    /// Clock data for both players and active color
+    Clock {
+        white_time: Remaining,
+        black_time: Remaining,
+        status: ClockStatus,
+    },
+    /// A front button of the clock was pressed, numbered from the left
+    ClockButton(u8),
+    /// Several front buttons of the clock were pressed together
+    ClockButtons(ButtonSet),
+    /// A player offered a draw from the clock, a second offer before the next move
+    /// accepts it
+    DrawOffered(PieceColor),
+    /// A player resigned from the clock
+    Resigned(PieceColor),
+    /// The clock was paused, e.g. to call the arbiter
+    ClockPaused,
+    /// The player who moved confirmed the move some other way than pressing the clock,
+    /// such as from the keyboard
+    MoveConfirmed,
+    /// The pieces were set up for the next game after moves were played in this one
+    NewGameStarted,
+    /// Pieces were knocked over or swept off their squares, see `SyncState::Disturbed`
+    BoardDisturbed,
+    /// The disturbed board shows the position again, or a legal move from it
+    BoardRestored,
+    /// Move detection moved on to another state
+    Detection(DetectionState),
+    /// The arbiter changed the clock, the moves or the result
+    Arbiter(Intervention),
+    /// The board reported its serial number
+    SerialNumber(String),
+    /// The board sent a message of a type the driver does not know, see
+    /// `Response::Unknown`
+    Unknown { msg_type: u8, data: Vec<u8> },
+    /// The player to move may claim a draw
+    DrawClaimable(DrawReason),
+    /// The game is drawn without any claim
+    AutoDraw(DrawReason),
+    /// The moves played reached a new named opening line
+    Opening { eco: String, name: String },
+    /// Balance after a move, both from white's point of view
+    Evaluation {
+        /// Material difference in pawns
+        material: i32,
+        /// Static evaluation in centipawns
+        centipawns: i32,
+    },
+    /// The analysis engine reached a new depth or changed its best line
+    Analysis {
+        depth: u32,
+        score: Score,
+        /// Principal variation in UCI notation
+        pv: Vec<String>,
+    },
+}
+
+/// A piece lifted off or put on the board, the steps a move is made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MicroEvent {
+    /// `piece` left `square`, `at` milliseconds since the Unix epoch
+    Lifted {
+        square: Square,
+        piece: RawPiece,
+        at: u64,
+    },
+    /// `piece` was put on `square`, `at` milliseconds since the Unix epoch
+    Placed {
+        square: Square,
+        piece: RawPiece,
+        at: u64,
+    },
+}
+
+impl MicroEvent {
+    /// The lift or placement behind `change`, on a board set up as `start`
+    pub fn new(change: &SquareChange, start: StartPosition, at: u64) -> Self {
+        let (file, rank) = start.file_rank(change.grid);
+        let square = rules::square(file, rank);
+        match change.after {
+            RawPiece::Empty => MicroEvent::Lifted {
+                square,
+                piece: change.before,
+                at,
+            },
+            piece => MicroEvent::Placed { square, piece, at },
+        }
+    }
+
+    /// Milliseconds since the Unix epoch
+    pub fn at(&self) -> u64 {
+        match self {
+            MicroEvent::Lifted { at, .. } | MicroEvent::Placed { at, .. } => *at,
+        }
+    }
+}
+
+impl Event {
+    /// Convert a decoded board response into an event, if it carries game relevant data
+    pub fn from_response(response: Response) -> Option<Self> {
+        match response {
+            // A game never syncs to a board no legal position matches, so an implausible
+            // dump is followed like any other
+            Response::BoardDump(board) | Response::ImplausibleDump { board, .. } => {
+                Some(Event::BoardDump(board))
+            }
+            Response::FieldUpdate(mv) => Some(Event::FieldUpdate(mv)),
+            Response::BWTime {
+                white_time,
+                black_time,
+                status,
+            } => Some(Event::Clock {
+                white_time,
+                black_time,
+                status,
+            }),
+            Response::ClockAck(ClockAck::Button(button)) => Some(Event::ClockButton(button)),
+            Response::ClockAck(ack @ ClockAck::Buttons(_)) => {
+                ack.pressed().map(Event::ClockButtons)
+            }
+            Response::SerialNumber(serial) => Some(Event::SerialNumber(serial)),
+            Response::Unknown { msg_type, data } => Some(Event::Unknown { msg_type, data }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_micro_events() {
+        // e2 on a board set up with white at the bottom
+        let lift = SquareChange {
+            grid: 52,
+            before: RawPiece::WhitePawn,
+            after: RawPiece::Empty,
+            adjusted: false,
+        };
+        let square = rules::parse_square("e2").unwrap();
+        assert_eq!(
+            MicroEvent::new(&lift, StartPosition::Mirror, 1000),
+            MicroEvent::Lifted {
+                square,
+                piece: RawPiece::WhitePawn,
+                at: 1000
+            }
+        );
+        let place = SquareChange {
+            before: RawPiece::Empty,
+            after: RawPiece::WhitePawn,
+            ..lift
+        };
+        let micro = MicroEvent::new(&place, StartPosition::Mirror, 1500);
+        assert!(matches!(micro, MicroEvent::Placed { square: sq, .. } if sq == square));
+        assert_eq!(micro.at(), 1500);
+    }
+}
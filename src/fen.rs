@@ -0,0 +1,267 @@
+use crate::game::StartPosition;
+use crate::protocol::*;
+use std::fmt;
+use std::str::FromStr;
+
+/// Castling availability for both sides
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Castling {
+    pub white_short: bool,
+    pub white_long: bool,
+    pub black_short: bool,
+    pub black_long: bool,
+}
+
+impl Castling {
+    /// All four castling rights, as in the starting position
+    pub fn all() -> Self {
+        Castling {
+            white_short: true,
+            white_long: true,
+            black_short: true,
+            black_long: true,
+        }
+    }
+}
+
+impl fmt::Display for Castling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = [
+            (self.white_short, 'K'),
+            (self.white_long, 'Q'),
+            (self.black_short, 'k'),
+            (self.black_long, 'q'),
+        ];
+        if flags.iter().all(|(set, _)| !set) {
+            return f.write_str("-");
+        }
+        for (set, c) in flags {
+            if set {
+                write!(f, "{}", c)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Castling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut castling = Castling::default();
+        if s == "-" {
+            return Ok(castling);
+        }
+        for c in s.chars() {
+            match c {
+                'K' => castling.white_short = true,
+                'Q' => castling.white_long = true,
+                'k' => castling.black_short = true,
+                'q' => castling.black_long = true,
+                _ => return Err(format!("invalid castling flag {:?}", c)),
+            }
+        }
+        Ok(castling)
+    }
+}
+
+/// Files (0 = a) of the king and castling rooks in the start position, identical for both colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CastleFiles {
+    pub queen_rook: u8,
+    pub king: u8,
+    pub king_rook: u8,
+}
+
+impl Default for CastleFiles {
+    fn default() -> Self {
+        CastleFiles {
+            queen_rook: 0,
+            king: 4,
+            king_rook: 7,
+        }
+    }
+}
+
+/// Castling field in X-FEN notation for a board indexed from a1. A right is written as
+/// KQkq when its rook is the outermost rook on that side of the king, otherwise as the
+/// file letter of the rook.
+pub fn xfen_castling(squares: &[RawPiece; 64], castling: Castling, files: CastleFiles) -> String {
+    let rights = [
+        (
+            castling.white_short,
+            PieceColor::White,
+            files.king_rook,
+            'K',
+        ),
+        (
+            castling.white_long,
+            PieceColor::White,
+            files.queen_rook,
+            'Q',
+        ),
+        (
+            castling.black_short,
+            PieceColor::Black,
+            files.king_rook,
+            'k',
+        ),
+        (
+            castling.black_long,
+            PieceColor::Black,
+            files.queen_rook,
+            'q',
+        ),
+    ];
+    let mut out = String::new();
+    for (set, colour, rook_file, flag) in rights {
+        if !set {
+            continue;
+        }
+        let (rank, rook) = match colour {
+            PieceColor::Black => (7, RawPiece::BlackRook),
+            _ => (0, RawPiece::WhiteRook),
+        };
+        let mut outer = if rook_file > files.king {
+            rook_file + 1..8
+        } else {
+            0..rook_file
+        };
+        if outer.any(|file| squares[rank * 8 + file as usize] == rook) {
+            let letter = (b'a' + rook_file) as char;
+            out.push(if flag.is_uppercase() {
+                letter.to_ascii_uppercase()
+            } else {
+                letter
+            });
+        } else {
+            out.push(flag);
+        }
+    }
+    if out.is_empty() {
+        out.push('-');
+    }
+    out
+}
+
+/// Pieces of a grid ordered board rearranged so index 0 is a1 and 63 is h8
+pub fn squares(board: &ChessBoard, start: StartPosition) -> [RawPiece; 64] {
+    let mut squares = [RawPiece::Empty; 64];
+    for (grid, piece) in board.board.iter().enumerate() {
+        let (file, rank) = start.file_rank(grid as u8);
+        squares[rank as usize * 8 + file as usize] = *piece;
+    }
+    squares
+}
+
+/// The piece placement field of a FEN, rank 8 first
+pub fn board_fen(board: &ChessBoard, start: StartPosition) -> String {
+    placement(&squares(board, start))
+}
+
+/// The piece placement field of a FEN for a board indexed from a1
+pub fn placement(squares: &[RawPiece; 64]) -> String {
+    let mut out = String::with_capacity(72);
+    for (i, rank) in squares.chunks(8).rev().enumerate() {
+        if i > 0 {
+            out.push('/');
+        }
+        let mut empty = 0;
+        for piece in rank {
+            if *piece == RawPiece::Empty {
+                empty += 1;
+                continue;
+            }
+            if empty > 0 {
+                out.push(char::from(b'0' + empty));
+                empty = 0;
+            }
+            out.push(char::from(*piece));
+        }
+        if empty > 0 {
+            out.push(char::from(b'0' + empty));
+        }
+    }
+    out
+}
+
+/// A complete FEN without en passant target and with fresh move counters
+pub fn to_fen(
+    board: &ChessBoard,
+    start: StartPosition,
+    to_move: PieceColor,
+    castling: Castling,
+) -> String {
+    let side = if to_move == PieceColor::Black {
+        'b'
+    } else {
+        'w'
+    };
+    format!("{} {} {} - 0 1", board_fen(board, start), side, castling)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_start_position_fen() {
+        let text = "rnbqkbnrpppppppp                                PPPPPPPPRNBQKBNR";
+        let board = ChessBoard {
+            board: text
+                .chars()
+                .map(|c| RawPiece::try_from(c).unwrap())
+                .collect::<Vec<_>>()
+                .try_into()
+                .unwrap(),
+        };
+        assert_eq!(
+            to_fen(
+                &board,
+                StartPosition::Mirror,
+                PieceColor::White,
+                Castling::all()
+            ),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        // The same pieces seen by a board rotated 180 degrees
+        let mut rotated = board;
+        rotated.board.reverse();
+        assert_eq!(
+            board_fen(&rotated, StartPosition::Normal),
+            board_fen(&board, StartPosition::Mirror)
+        );
+    }
+
+    #[test]
+    fn test_xfen_castling_shadowed_rook() {
+        // White rooks on b1 and a1 with the king on c1, castling long uses the b1 rook
+        let mut squares = [RawPiece::Empty; 64];
+        squares[0] = RawPiece::WhiteRook;
+        squares[1] = RawPiece::WhiteRook;
+        squares[2] = RawPiece::WhiteKing;
+        let castling = Castling {
+            white_long: true,
+            ..Castling::default()
+        };
+        let files = CastleFiles {
+            queen_rook: 1,
+            king: 2,
+            king_rook: 7,
+        };
+        assert_eq!(xfen_castling(&squares, castling, files), "B");
+        let files = CastleFiles {
+            queen_rook: 0,
+            ..files
+        };
+        assert_eq!(xfen_castling(&squares, castling, files), "Q");
+    }
+
+    #[test]
+    fn test_castling_roundtrip() {
+        for text in ["KQkq", "Kq", "-"] {
+            assert_eq!(text.parse::<Castling>().unwrap().to_string(), text);
+        }
+        assert!("KX".parse::<Castling>().is_err());
+    }
+}
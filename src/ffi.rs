@@ -0,0 +1,341 @@
+//! C interface to the driver, for chess programs written in C or C++. The header is
+//! `include/jackolope.h`, generated from this file alone. Building with the `ffi`
+//! feature writes it to OUT_DIR and warns when the copy in `include` is stale.
+//!
+//! ```c
+//! JackolopeBoard *board = jackolope_connect("/dev/ttyUSB0", 0);
+//! JackolopeEvent event;
+//! while (jackolope_poll_event(board, &event, 100) >= 0) {
+//!     if (event.kind == JACKOLOPE_EVENT_KIND_MOVE)
+//!         printf("%s\n", event.text);
+//! }
+//! jackolope_disconnect(board);
+//! ```
+
+use crate::board::{self, Baud, DgtBoard, ElectronicBoard};
+use crate::game::{GameBoard, SyncState};
+use crate::manager::is_disconnect;
+use crate::protocol::*;
+use std::collections::VecDeque;
+use std::ffi::{c_char, c_int, CStr};
+use std::time::Duration;
+use tracing::warn;
+
+/// A connected board and the game followed on it
+pub struct JackolopeBoard {
+    board: DgtBoard,
+    game: Option<GameBoard>,
+    /// Events decoded but not yet polled
+    pending: VecDeque<JackolopeEvent>,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JackolopeEventKind {
+    /// The whole board, in `board`
+    Board,
+    /// A piece was lifted or placed, see `square` and `piece`
+    FieldUpdate,
+    /// A legal move was completed, in UCI notation in `text`
+    Move,
+    /// Clock times and status
+    Clock,
+    /// The serial number of the board, in `text`
+    SerialNumber,
+    /// The firmware version of the board, in `text`
+    Version,
+}
+
+/// Something the board reported. Only the fields named by `kind` are set, the rest
+/// are zero.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct JackolopeEvent {
+    pub kind: JackolopeEventKind,
+    /// Square of a field update, 0 to 63 with a8 first
+    pub square: u8,
+    /// DGT piece code placed on the square, 0 for a lift
+    pub piece: u8,
+    /// DGT piece codes of the whole board, a8 first
+    pub board: [u8; 64],
+    pub white_seconds: u32,
+    pub black_seconds: u32,
+    /// 0 without a clock, 1 when white is to move, 2 for black
+    pub clock_status: u8,
+    /// Text of the event, NUL terminated
+    pub text: [c_char; 32],
+}
+
+impl JackolopeEvent {
+    fn new(kind: JackolopeEventKind) -> Self {
+        JackolopeEvent {
+            kind,
+            square: 0,
+            piece: 0,
+            board: [0; 64],
+            white_seconds: 0,
+            black_seconds: 0,
+            clock_status: 0,
+            text: [0; 32],
+        }
+    }
+
+    fn with_text(kind: JackolopeEventKind, text: &str) -> Self {
+        let mut event = JackolopeEvent::new(kind);
+        for (to, from) in event.text.iter_mut().zip(text.bytes().take(31)) {
+            *to = from as c_char;
+        }
+        event
+    }
+}
+
+impl JackolopeBoard {
+    /// Turn a response into events, following the game on the way
+    fn handle(&mut self, response: Response) {
+        use JackolopeEventKind::*;
+        match response {
+            Response::BoardDump(board) | Response::ImplausibleDump { board, .. } => {
+                let mut event = JackolopeEvent::new(Board);
+                event.board = board.board.map(|piece| piece as u8);
+                self.pending.push_back(event);
+                // The board read again goes on with the game, a garbled one starts none
+                match (&mut self.game, &response) {
+                    (Some(game), _) => {
+                        let state = game.resync(board);
+                        self.follow(state);
+                    }
+                    (None, Response::BoardDump(_)) => self.game = Some(GameBoard::new(board)),
+                    (None, _) => warn!(board = %board, "implausible board, waiting for another"),
+                }
+            }
+            Response::FieldUpdate(mv) => {
+                let mut event = JackolopeEvent::new(FieldUpdate);
+                event.square = mv.grid;
+                event.piece = mv.piece as u8;
+                self.pending.push_back(event);
+                let Some(game) = &mut self.game else {
+                    return;
+                };
+                if game.apply_move(mv).is_some() {
+                    let state = game.sync();
+                    self.follow(state);
+                }
+            }
+            Response::BWTime {
+                white_time,
+                black_time,
+                status,
+            } => {
+                let mut event = JackolopeEvent::new(Clock);
+                event.white_seconds = white_time.total_seconds();
+                event.black_seconds = black_time.total_seconds();
+                event.clock_status = match status {
+                    ClockStatus::NoCock => 0,
+                    ClockStatus::WhitesTurn => 1,
+                    ClockStatus::BlacksTurn => 2,
+                };
+                self.pending.push_back(event);
+            }
+            Response::SerialNumber(serial) => {
+                self.pending
+                    .push_back(JackolopeEvent::with_text(SerialNumber, &serial));
+            }
+            Response::Version(version) => {
+                self.pending
+                    .push_back(JackolopeEvent::with_text(Version, &version));
+            }
+            _ => {}
+        }
+    }
+
+    /// Report the moves `state` and the syncs after it found, as the reply may be
+    /// complete as well
+    fn follow(&mut self, mut state: SyncState) {
+        let Some(game) = &mut self.game else {
+            return;
+        };
+        while let SyncState::Moved(_) = state {
+            if let Some(ply) = game.moves().last() {
+                self.pending.push_back(JackolopeEvent::with_text(
+                    JackolopeEventKind::Move,
+                    &ply.uci(),
+                ));
+            }
+            state = game.sync();
+        }
+    }
+}
+
+/// Open the DGT board on serial port `port` at `baud`, 0 for the default rate, and
+/// start following it. Returns NULL when the port can not be opened.
+///
+/// # Safety
+///
+/// `port` must be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn jackolope_connect(port: *const c_char, baud: u32) -> *mut JackolopeBoard {
+    if port.is_null() {
+        return std::ptr::null_mut();
+    }
+    let port = CStr::from_ptr(port).to_string_lossy();
+    let baud = (baud != 0).then_some(Baud::Fixed(baud));
+    let result = board::open_dgt(&port, baud, None).and_then(|mut board| {
+        for command in [
+            Command::Reset,
+            Command::RequestBoard,
+            Command::RequestUpdate,
+        ] {
+            board.send(command)?;
+        }
+        Ok(board)
+    });
+    match result {
+        Ok(board) => Box::into_raw(Box::new(JackolopeBoard {
+            board,
+            game: None,
+            pending: VecDeque::new(),
+        })),
+        Err(e) => {
+            warn!(%port, error = %e, "failed to connect");
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Close the board
+///
+/// # Safety
+///
+/// `board` must come from `jackolope_connect` and is not valid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn jackolope_disconnect(board: *mut JackolopeBoard) {
+    if !board.is_null() {
+        let mut board = Box::from_raw(board);
+        let _ = board.board.stop_updates();
+    }
+}
+
+/// Wait up to `timeout_ms` for the next event and store it in `event`. Returns 1 for
+/// an event, 0 when there was none and -1 when the board is gone.
+///
+/// # Safety
+///
+/// `board` must come from `jackolope_connect` and `event` point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn jackolope_poll_event(
+    board: *mut JackolopeBoard,
+    event: *mut JackolopeEvent,
+    timeout_ms: u32,
+) -> c_int {
+    let (Some(board), Some(event)) = (board.as_mut(), event.as_mut()) else {
+        return -1;
+    };
+    if board.pending.is_empty() {
+        match board
+            .board
+            .poll_response(Duration::from_millis(timeout_ms.into()))
+        {
+            Ok(Some(response)) => board.handle(response),
+            Ok(None) => {}
+            Err(e) if is_disconnect(&e) => {
+                warn!(error = %e, "board disconnected");
+                return -1;
+            }
+            Err(e) => warn!(error = %e, "failed to read from the board"),
+        }
+    }
+    match board.pending.pop_front() {
+        Some(next) => {
+            *event = next;
+            1
+        }
+        None => 0,
+    }
+}
+
+/// Send a command byte, e.g. 0x42 to ask for the board. Returns 0 when sent and -1
+/// on failure.
+///
+/// # Safety
+///
+/// `board` must come from `jackolope_connect`.
+#[no_mangle]
+pub unsafe extern "C" fn jackolope_send_command(board: *mut JackolopeBoard, command: u8) -> c_int {
+    let Some(board) = board.as_mut() else {
+        return -1;
+    };
+    match board.board.send_raw(&[command]) {
+        Ok(()) => 0,
+        Err(e) => {
+            warn!(command, error = %e, "failed to send command");
+            -1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Kind;
+    use crate::game::tests::{start_board, update};
+    use crate::transport::{LineControl, Transport};
+    use serialport::SerialPort;
+
+    #[test]
+    fn test_event_text() {
+        let event = JackolopeEvent::with_text(JackolopeEventKind::Move, "e2e4");
+        let text = unsafe { CStr::from_ptr(event.text.as_ptr()) };
+        assert_eq!(text.to_str(), Ok("e2e4"));
+        let long = JackolopeEvent::with_text(JackolopeEventKind::SerialNumber, &"x".repeat(40));
+        let text = unsafe { CStr::from_ptr(long.text.as_ptr()) };
+        assert_eq!(text.to_bytes().len(), 31);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_game() {
+        let (_master, slave) = serialport::TTYPort::pair().unwrap();
+        let line = LineControl::for_board(Kind::Dgt, Transport::Serial);
+        let mut board = JackolopeBoard {
+            board: DgtBoard::open(slave.name().as_deref().unwrap(), 9600, line).unwrap(),
+            game: None,
+            pending: VecDeque::new(),
+        };
+        let moves = |board: &mut JackolopeBoard| {
+            board
+                .pending
+                .drain(..)
+                .filter(|event| event.kind == JackolopeEventKind::Move)
+                .map(|event| {
+                    let text = unsafe { CStr::from_ptr(event.text.as_ptr()) };
+                    text.to_string_lossy().into_owned()
+                })
+                .collect::<Vec<_>>()
+        };
+        // A garbled dump starts no game
+        let mut garbled = start_board();
+        garbled.board[36] = RawPiece::WhiteKing;
+        board.handle(Response::checked_dump(garbled));
+        assert!(board.game.is_none());
+
+        board.handle(Response::BoardDump(start_board()));
+        for mv in [update(52, RawPiece::Empty), update(36, RawPiece::WhitePawn)] {
+            board.handle(Response::FieldUpdate(mv));
+        }
+        assert_eq!(moves(&mut board), ["e2e4"]);
+
+        // Read again with the reply on it, the game goes on
+        let mut replied = start_board();
+        replied.board[52] = RawPiece::Empty;
+        replied.board[36] = RawPiece::WhitePawn;
+        replied.board[12] = RawPiece::Empty;
+        replied.board[28] = RawPiece::BlackPawn;
+        board.handle(Response::BoardDump(replied));
+        assert_eq!(moves(&mut board), ["e7e5"]);
+        let game = board.game.as_ref().unwrap();
+        assert_eq!(game.moves().len(), 2);
+        // And a garbled one keeps it
+        board.handle(Response::checked_dump(garbled));
+        assert_eq!(board.game.as_ref().unwrap().moves().len(), 2);
+    }
+}
@@ -1,44 +1,225 @@
+use crate::chess960;
+use crate::eco::{self, Opening};
+use crate::eval::{self, Material};
+use crate::event::{Event, MicroEvent};
+use crate::fen::{self, CastleFiles, Castling};
+use crate::pgn::{self, GameTags, PgnGame};
 use crate::protocol::*;
-use std::collections::{hash_map::Entry, HashMap, HashSet};
+use crate::rules::{self, Ply, PlyKind, Position};
+use crate::timing::{MoveTiming, TimingStats};
+use crate::variant::{Outcome, Standard, Variant};
+use crate::zobrist;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 
+/// A piece taken off the board by a move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Capture {
     piece: RawPiece,
     grid: u8,
 }
 
+impl Capture {
+    pub fn new(piece: RawPiece, grid: u8) -> Self {
+        Capture { piece, grid }
+    }
+
+    pub fn piece(&self) -> RawPiece {
+        self.piece
+    }
+
+    /// Square the piece was taken from
+    pub fn grid(&self) -> u8 {
+        self.grid
+    }
+}
+
+/// A piece going from one square to another, both in grid numbering
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Move {
     piece: RawPiece,
     from: u8,
     to: u8,
 }
 
+impl Move {
+    pub fn new(piece: RawPiece, from: u8, to: u8) -> Self {
+        Move { piece, from, to }
+    }
+
+    pub fn piece(&self) -> RawPiece {
+        self.piece
+    }
+
+    pub fn from(&self) -> u8 {
+        self.from
+    }
+
+    pub fn to(&self) -> u8 {
+        self.to
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum DetectedMove {
-    ShortCastle,
-    LongCastle,
-    PawnCapture(Capture),
+    /// King side castling, the king move followed by the rook move
+    ShortCastle(Move, Move),
+    /// Queen side castling, the king move followed by the rook move
+    LongCastle(Move, Move),
+    /// En passant capture, the captured pawn is not on the destination square
+    EnPassant(Move, Capture),
     Promotion(Move, RawPiece),
     PromotionCapture(Move, Capture, RawPiece),
     SimpleMove(Move),
     SimpleCapture(Move, Capture),
+    /// A piece placed from the pocket in crazyhouse, `from` equals `to`
+    Drop(Move),
 }
 
-fn detect_move(moves: &[ChessMove]) -> Option<DetectedMove> {
-    let mut added = HashMap::new();
-    let mut removed = HashSet::new();
+impl DetectedMove {
+    /// The primary piece movement, the king for castling
+    pub fn main_move(&self) -> &Move {
+        match self {
+            DetectedMove::ShortCastle(mv, _)
+            | DetectedMove::LongCastle(mv, _)
+            | DetectedMove::EnPassant(mv, _)
+            | DetectedMove::Promotion(mv, _)
+            | DetectedMove::PromotionCapture(mv, _, _)
+            | DetectedMove::SimpleMove(mv)
+            | DetectedMove::SimpleCapture(mv, _)
+            | DetectedMove::Drop(mv) => mv,
+        }
+    }
+
+    /// Color of the side making the move
+    pub fn colour(&self) -> PieceColor {
+        self.main_move().piece.get_colour()
+    }
+}
+
+/// Classify the net effect of a sequence of field updates applied to `before`.
+/// Only classical castling is recognised, the king going two squares towards a rook
+/// in the corner. Chess960 castles need the castling files of the position, which
+/// `GameBoard::sync` has and this function does not.
+pub fn detect_move(before: &ChessBoard, moves: &[ChessMove]) -> Option<DetectedMove> {
+    let mut after = *before;
     for mv in moves {
-        if mv.piece == RawPiece::Empty {
-            if let Entry::Occupied(e) = added.entry(mv.grid) {
-                e.remove();
-            }
-            removed.insert(mv.grid);
+        after.board[mv.grid as usize] = mv.piece;
+    }
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    for grid in 0..64u8 {
+        let (old, new) = (before.board[grid as usize], after.board[grid as usize]);
+        if old == new {
+            continue;
+        }
+        if new == RawPiece::Empty {
+            removed.push(grid);
         } else {
-            if removed.contains(&mv.grid) {
-                removed.remove(&mv.grid);
+            added.push(grid);
+        }
+    }
+
+    let at = |board: &ChessBoard, grid: u8| board.board[grid as usize];
+    match (removed.as_slice(), added.as_slice()) {
+        (&[from], &[to]) => {
+            let piece = at(before, from);
+            let placed = at(&after, to);
+            let captured = at(before, to);
+            if !placed.is_same_colour(&piece) || captured.is_same_colour(&piece) {
+                return None;
+            }
+            let mv = Move { piece, from, to };
+            let capture = Capture {
+                piece: captured,
+                grid: to,
+            };
+            match (placed == piece, captured == RawPiece::Empty) {
+                (true, true) => Some(DetectedMove::SimpleMove(mv)),
+                (true, false) => Some(DetectedMove::SimpleCapture(mv, capture)),
+                _ if !piece.is_pawn() || placed.is_pawn() || placed.is_king() => None,
+                (false, true) => Some(DetectedMove::Promotion(mv, placed)),
+                (false, false) => Some(DetectedMove::PromotionCapture(mv, capture, placed)),
+            }
+        }
+        (&[a, b], &[to]) => {
+            let placed = at(&after, to);
+            let (from, taken) = if at(before, a) == placed {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            let piece = at(before, from);
+            let captured = at(before, taken);
+            if piece != placed
+                || !piece.is_pawn()
+                || !captured.is_pawn()
+                || captured.is_same_colour(&piece)
+                || at(before, to) != RawPiece::Empty
+            {
+                return None;
+            }
+            Some(DetectedMove::EnPassant(
+                Move { piece, from, to },
+                Capture {
+                    piece: captured,
+                    grid: taken,
+                },
+            ))
+        }
+        (&[a, b], &[c, d]) => {
+            let (king_from, rook_from) = if at(before, a).is_king() {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            let king = at(before, king_from);
+            let rook = at(before, rook_from);
+            if !king.is_king() || !rook.is_rook() || !king.is_same_colour(&rook) {
+                return None;
+            }
+            if at(before, c) != RawPiece::Empty || at(before, d) != RawPiece::Empty {
+                return None;
+            }
+            let (king_to, rook_to) = if at(&after, c) == king {
+                (c, d)
+            } else {
+                (d, c)
+            };
+            if at(&after, king_to) != king || at(&after, rook_to) != rook {
+                return None;
+            }
+            // Castling stays on the back rank, rook ends next to the king on the inside
+            let rank = king_from / 8;
+            if [rook_from, king_to, rook_to].iter().any(|g| g / 8 != rank) {
+                return None;
+            }
+            let step = if rook_from > king_from { 1i8 } else { -1 };
+            if king_to as i8 != king_from as i8 + 2 * step || rook_to as i8 != king_to as i8 - step
+            {
+                return None;
+            }
+            let king_move = Move {
+                piece: king,
+                from: king_from,
+                to: king_to,
+            };
+            let rook_move = Move {
+                piece: rook,
+                from: rook_from,
+                to: rook_to,
+            };
+            match rook_from.abs_diff(king_from) {
+                3 => Some(DetectedMove::ShortCastle(king_move, rook_move)),
+                4 => Some(DetectedMove::LongCastle(king_move, rook_move)),
+                _ => None,
             }
-            added.insert(mv.grid, mv.piece);
         }
+        _ => None,
     }
-    None
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -46,31 +227,1375 @@ pub enum StartPosition {
     None,
     Normal,
     Mirror,
+    /// A Chess960 start position other than the classical one, identified by its
+    /// Scharnagl number. `mirror` is set when the board is oriented as for `Mirror`.
+    Chess960 {
+        number: u16,
+        mirror: bool,
+    },
 }
 
+impl StartPosition {
+    /// File and rank (0 based, a1 = (0, 0)) of a grid index. Boards found in the
+    /// `Normal` position have h1 at grid 0, otherwise the DGT documented layout
+    /// with a8 at grid 0 is assumed.
+    pub fn file_rank(self, grid: u8) -> (u8, u8) {
+        if self.is_rotated() {
+            (7 - grid % 8, grid / 8)
+        } else {
+            (grid % 8, 7 - grid / 8)
+        }
+    }
+
+    /// Grid index of a file and rank, the inverse of `file_rank`
+    pub fn grid(self, file: u8, rank: u8) -> u8 {
+        if self.is_rotated() {
+            rank * 8 + 7 - file
+        } else {
+            (7 - rank) * 8 + file
+        }
+    }
+
+    /// Grid index of a square of the rules engine
+    pub fn square_grid(self, sq: rules::Square) -> u8 {
+        self.grid(rules::file_of(sq), rules::rank_of(sq))
+    }
+
+    /// Chess960 number of the start position, 518 for the classical setup
+    pub fn chess960_number(self) -> Option<u16> {
+        match self {
+            StartPosition::None => None,
+            StartPosition::Normal | StartPosition::Mirror => Some(chess960::CLASSICAL),
+            StartPosition::Chess960 { number, .. } => Some(number),
+        }
+    }
+
+    fn is_rotated(self) -> bool {
+        matches!(
+            self,
+            StartPosition::Normal | StartPosition::Chess960 { mirror: false, .. }
+        )
+    }
+
+    /// Algebraic name of the square at a grid index, e.g. "e4"
+    pub fn square_name(self, grid: u8) -> String {
+        let (file, rank) = self.file_rank(grid);
+        format!("{}{}", (b'a' + file) as char, rank + 1)
+    }
+
+    /// The board showing `position` when it is oriented like this
+    pub fn layout(self, position: &Position) -> ChessBoard {
+        let mut board = ChessBoard {
+            board: [RawPiece::Empty; 64],
+        };
+        for (sq, piece) in position.board.iter().enumerate() {
+            board.board[self.square_grid(sq as u8) as usize] = *piece;
+        }
+        board
+    }
+}
+
+/// Corrections beyond which a board is taken to show another game than a saved one
+pub const MAX_RESUME_CORRECTIONS: usize = 4;
+
+/// Milliseconds within which a piece put back where it was lifted from is taken for
+/// adjusted, j'adoube, rather than part of a move
+pub const ADJUST_WINDOW_MS: u64 = 2000;
+
+/// Which moves found on the board are taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Legality {
+    /// Only legal moves, anything else leaves the board out of sync
+    #[default]
+    Strict,
+    /// Also a move that leaves the mover's king in check, as happens in casual games
+    /// nobody stops
+    Lenient,
+}
+
+/// Which way round the board is, deciding the square each grid index stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Orientation {
+    /// Told by the pieces when they stand ready for a game, the DGT documented layout
+    /// otherwise
+    #[default]
+    Detect,
+    /// a8 at grid 0, as DGT documents it, however the pieces stand
+    Documented,
+    /// h1 at grid 0, however the pieces stand
+    Rotated,
+}
+
+impl Orientation {
+    /// How a board on which the pieces look like `detected` is read
+    fn orient(self, detected: StartPosition) -> StartPosition {
+        match self {
+            Orientation::Detect => detected,
+            Orientation::Documented if detected.is_rotated() => StartPosition::None,
+            Orientation::Rotated if !detected.is_rotated() => StartPosition::Normal,
+            Orientation::Documented | Orientation::Rotated => detected,
+        }
+    }
+}
+
+/// How moves are told from what happens on the board. The default suits a tournament
+/// broadcast: only legal moves, made in full. `casual` forgives more.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DetectionPolicy {
+    /// Milliseconds within which a piece put back where it was lifted from was only
+    /// adjusted, see `GameBoard::apply_move_at`
+    pub settle_ms: u64,
+    pub legality: Legality,
+    /// What a pawn left standing on the last rank is promoted to, None to wait for it
+    /// to be swapped for the piece
+    pub promotion: Option<PieceKind>,
+    /// Take the board showing the position before the last move for that move taken
+    /// back, see `SyncState::TakenBack`
+    pub takebacks: bool,
+    pub orientation: Orientation,
+}
+
+impl DetectionPolicy {
+    /// For games between friends: moves leaving the king in check count, a pawn left on
+    /// the last rank is a queen and moves can be taken back
+    pub fn casual() -> Self {
+        DetectionPolicy {
+            legality: Legality::Lenient,
+            promotion: Some(PieceKind::Queen),
+            takebacks: true,
+            ..DetectionPolicy::default()
+        }
+    }
+}
+
+impl Default for DetectionPolicy {
+    fn default() -> Self {
+        DetectionPolicy {
+            settle_ms: ADJUST_WINDOW_MS,
+            legality: Legality::Strict,
+            promotion: None,
+            takebacks: false,
+            orientation: Orientation::Detect,
+        }
+    }
+}
+
+/// Sets up a `GameBoard` with a variant and detection policy other than the defaults
+///
+/// ```
+/// use jackolope::game::{DetectionPolicy, GameBoardBuilder, Legality};
+/// use jackolope::protocol::{ChessBoard, PieceKind, RawPiece};
+///
+/// let board = ChessBoard { board: [RawPiece::Empty; 64] };
+/// let game = GameBoardBuilder::new()
+///     .legality(Legality::Lenient)
+///     .promotion(Some(PieceKind::Queen))
+///     .build(board);
+/// assert!(game.policy().promotion.is_some());
+/// ```
+#[derive(Debug, Clone)]
+pub struct GameBoardBuilder {
+    variant: Arc<dyn Variant>,
+    policy: DetectionPolicy,
+}
+
+impl GameBoardBuilder {
+    pub fn new() -> Self {
+        GameBoardBuilder {
+            variant: Arc::new(Standard),
+            policy: DetectionPolicy::default(),
+        }
+    }
+
+    /// Rules of the game, standard chess by default
+    pub fn variant(mut self, variant: Arc<dyn Variant>) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    /// Every setting at once, the ones below change it further
+    pub fn policy(mut self, policy: DetectionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn settle_ms(mut self, settle_ms: u64) -> Self {
+        self.policy.settle_ms = settle_ms;
+        self
+    }
+
+    pub fn legality(mut self, legality: Legality) -> Self {
+        self.policy.legality = legality;
+        self
+    }
+
+    pub fn promotion(mut self, promotion: Option<PieceKind>) -> Self {
+        self.policy.promotion = promotion;
+        self
+    }
+
+    pub fn takebacks(mut self, takebacks: bool) -> Self {
+        self.policy.takebacks = takebacks;
+        self
+    }
+
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.policy.orientation = orientation;
+        self
+    }
+
+    /// Track the game on the physical `board`
+    pub fn build(self, board: ChessBoard) -> GameBoard {
+        GameBoard::with_policy(board, self.variant, self.policy)
+    }
+}
+
+impl Default for GameBoardBuilder {
+    fn default() -> Self {
+        GameBoardBuilder::new()
+    }
+}
+
+/// A change to a single square caused by a field update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SquareChange {
+    pub grid: u8,
+    pub before: RawPiece,
+    pub after: RawPiece,
+    /// The piece went back to the square it was just lifted from, see
+    /// `GameBoard::apply_move_at`
+    pub adjusted: bool,
+}
+
+/// Result of comparing the physical board against the tracked game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncState {
+    /// The physical board matches the tracked position
+    InSync,
+    /// A move was completed and has been applied to the tracked position
+    Moved(DetectedMove),
+    /// Pieces are being moved, the board does not yet show a complete move
+    Pending,
+    /// The physical board can not be explained by a move, see `GameBoard::recovery_plan`
+    OutOfSync,
+    /// More pieces were displaced than a move displaces, as when a sleeve knocks them
+    /// over. Nothing is taken for a move in progress until the board shows the
+    /// position again or a legal move from it.
+    Disturbed,
+    /// The board shows the position before the last move, which was taken back. Only
+    /// with `DetectionPolicy::takebacks`.
+    TakenBack(DetectedMove),
+}
+
+/// Where move detection stands between field updates, for a UI to tell the players
+/// what it waits for, see `GameBoard::detection_state`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DetectionState {
+    /// The board shows the tracked position
+    Idle,
+    /// Pieces are off their squares as in a move being made
+    PieceLifted,
+    /// A pawn stands on the last rank, to be swapped for the piece it becomes
+    AwaitingPromotionPiece,
+    /// See `SyncState::Disturbed`
+    Disturbed,
+    /// See `SyncState::OutOfSync`
+    OutOfSync,
+}
+
+impl DetectionState {
+    /// Every change between states and what brings it about
+    pub const TRANSITIONS: [(DetectionState, DetectionState, &'static str); 12] = {
+        use DetectionState::*;
+        [
+            (Idle, PieceLifted, "piece lifted"),
+            (PieceLifted, Idle, "move made or piece put back"),
+            (
+                PieceLifted,
+                AwaitingPromotionPiece,
+                "pawn put on the last rank",
+            ),
+            (AwaitingPromotionPiece, Idle, "pawn swapped for a piece"),
+            (AwaitingPromotionPiece, PieceLifted, "pawn lifted again"),
+            (Idle, OutOfSync, "no move explains the board"),
+            (PieceLifted, OutOfSync, "no move explains the board"),
+            (
+                AwaitingPromotionPiece,
+                OutOfSync,
+                "no move explains the board",
+            ),
+            (OutOfSync, Idle, "position restored or move made"),
+            (Idle, Disturbed, "pieces knocked over"),
+            (PieceLifted, Disturbed, "pieces knocked over"),
+            (Disturbed, Idle, "position restored or move made"),
+        ]
+    };
+
+    /// The state machine as a Graphviz graph, to draw with `dot -Tsvg`
+    pub fn diagram() -> String {
+        let mut dot = String::from("digraph detection {\n    Idle [shape=doublecircle];\n");
+        for (from, to, label) in DetectionState::TRANSITIONS {
+            dot.push_str(&format!(
+                "    {:?} -> {:?} [label=\"{}\"];\n",
+                from, to, label
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// A single step needed to bring the physical board back to the tracked position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Correction {
+    /// Move a piece standing on the wrong square to where it belongs
+    Move { piece: RawPiece, from: u8, to: u8 },
+    /// Put a missing piece on a square
+    Place { piece: RawPiece, grid: u8 },
+    /// Take a piece off a square that should be empty or hold another piece
+    Remove { piece: RawPiece, grid: u8 },
+}
+
+/// Advice for a beginner in the middle of a move, see `GameBoard::hint`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Hint {
+    /// A piece of the side to move was lifted and can go to the squares `to`
+    Lifted {
+        piece: RawPiece,
+        from: rules::Square,
+        to: Vec<rules::Square>,
+    },
+    /// The lifted piece was put down on a square it can not move to
+    Illegal {
+        piece: RawPiece,
+        from: rules::Square,
+        to: rules::Square,
+    },
+}
+
+impl fmt::Display for Hint {
+    /// The advice in words, fit to be read out
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |piece: &RawPiece| {
+            piece
+                .kind()
+                .map_or_else(String::new, |kind| format!("{:?}", kind).to_lowercase())
+        };
+        match self {
+            Hint::Lifted { piece, from, to } if to.is_empty() => {
+                write!(
+                    f,
+                    "{} on {} has no legal move",
+                    name(piece),
+                    rules::square_name(*from)
+                )
+            }
+            Hint::Lifted { piece, from, to } => {
+                let squares: Vec<String> = to.iter().map(|sq| rules::square_name(*sq)).collect();
+                write!(
+                    f,
+                    "{} on {} can go to {}",
+                    name(piece),
+                    rules::square_name(*from),
+                    squares.join(", ")
+                )
+            }
+            Hint::Illegal { piece, from, to } => write!(
+                f,
+                "{} can not go from {} to {}",
+                name(piece),
+                rules::square_name(*from),
+                rules::square_name(*to)
+            ),
+        }
+    }
+}
+
+/// Why a game is, or can be declared, drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DrawReason {
+    /// The same position occurred three times, claimable
+    ThreefoldRepetition,
+    /// The same position occurred five times, automatic
+    FivefoldRepetition,
+    /// Fifty moves by each side without a capture or pawn move, claimable
+    FiftyMoves,
+    /// Seventy-five moves by each side without a capture or pawn move, automatic
+    SeventyFiveMoves,
+    /// Neither side can checkmate any more, automatic
+    InsufficientMaterial,
+}
+
+/// A move of the game, kept with what it takes to show it without replaying the game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedMove {
+    pub ply: Ply,
+    /// Standard algebraic notation
+    pub san: String,
+    /// The position after the move
+    pub position: Position,
+    /// Zobrist key of `position`
+    pub key: u64,
+    /// When the move was made on the board, None if it was not played there
+    pub timing: Option<MoveTiming>,
+    /// What a commentator had to say about the move
+    pub notes: Vec<String>,
+}
+
+/// Boards two moves on from a position, with the first move, None when more than one
+/// pair leads there
+type MissedMoves = HashMap<[RawPiece; 64], Option<Ply>>;
+
+#[derive(Debug, Clone)]
 pub struct GameBoard {
+    /// The physical board as last reported
     board: ChessBoard,
+    /// The tracked game position
+    position: Position,
+    /// Field updates received since the tracked position was last updated
+    pending: Vec<ChessMove>,
     start: StartPosition,
+    out_of_sync: bool,
+    variant: Arc<dyn Variant>,
+    /// Position the game started from
+    initial: Position,
+    /// Moves played since the game started
+    moves: Vec<Ply>,
+    /// Moves played since the game started, with their notation and the positions
+    /// they led to
+    history: Vec<RecordedMove>,
+    /// Moves taken back with `undo`, the last one first to `redo`
+    undone: Vec<RecordedMove>,
+    /// Zobrist key of the tracked position
+    key: u64,
+    /// Keys of every position reached since the last capture or pawn move
+    repetition_keys: Vec<u64>,
+    tags: GameTags,
+    /// Why the last rejected field update could not be real, until a board dump
+    /// replaces the physical board
+    suspect: Option<Implausible>,
+    /// Result decided by the players rather than on the board
+    concluded: Option<Outcome>,
+    /// First lift and latest lift or placement of the move being made, milliseconds
+    /// since the Unix epoch
+    move_times: Option<(u64, u64)>,
+    /// Clock times last reported, white first
+    clock: Option<(Remaining, Remaining)>,
+    /// Square and piece of the last lift, and when it was if known
+    lifted: Option<(u8, RawPiece, Option<u64>)>,
+    /// The board was disturbed and has not shown the position since
+    disturbed: bool,
+    /// Boards two moves on from a tracked position, see `missed_move`
+    missed: Option<(Position, MissedMoves)>,
+    policy: DetectionPolicy,
 }
 
 impl GameBoard {
     pub fn new(board: ChessBoard) -> GameBoard {
-        let start = StartPosition::None;
-        let mut game = GameBoard { board, start };
-        game.start = game.is_starting_position();
+        GameBoard::new_with_variant(board, Arc::new(Standard))
+    }
+
+    /// Track a game played under the rules of `variant`, see `GameBoardBuilder` for
+    /// more settings
+    pub fn new_with_variant(board: ChessBoard, variant: Arc<dyn Variant>) -> GameBoard {
+        GameBoard::with_policy(board, variant, DetectionPolicy::default())
+    }
+
+    fn with_policy(
+        board: ChessBoard,
+        variant: Arc<dyn Variant>,
+        policy: DetectionPolicy,
+    ) -> GameBoard {
+        let mut game = GameBoard {
+            board,
+            position: Position::from_squares([RawPiece::Empty; 64]),
+            pending: Vec::new(),
+            start: StartPosition::None,
+            out_of_sync: false,
+            variant,
+            initial: Position::from_squares([RawPiece::Empty; 64]),
+            moves: Vec::new(),
+            key: 0,
+            repetition_keys: Vec::new(),
+            history: Vec::new(),
+            undone: Vec::new(),
+            tags: GameTags::default(),
+            suspect: None,
+            concluded: None,
+            move_times: None,
+            clock: None,
+            lifted: None,
+            disturbed: false,
+            missed: None,
+            policy,
+        };
+        let detected = game.is_starting_position();
+        game.start = policy.orientation.orient(detected);
+        game.position = Position::from_squares(fen::squares(&game.shown(), game.start));
+        // Castling rights only for pieces standing ready the way the board is read
+        if let Some(rank) = (game.start == detected)
+            .then_some(detected)
+            .and_then(StartPosition::chess960_number)
+            .and_then(chess960::back_rank)
+        {
+            let file_of = |piece: RawPiece| rank.iter().position(|p| *p == piece).unwrap() as u8;
+            let king = file_of(RawPiece::WhiteKing);
+            let queen_rook = file_of(RawPiece::WhiteRook);
+            let king_rook = 7 - rank
+                .iter()
+                .rev()
+                .position(|p| *p == RawPiece::WhiteRook)
+                .unwrap() as u8;
+            game.position.castle_files = CastleFiles {
+                queen_rook,
+                king,
+                king_rook,
+            };
+            game.position.castling = Castling::all();
+        }
+        game.initial = game.position.clone();
+        game.key = zobrist::hash(&game.position);
+        game.repetition_keys.push(game.key);
         game
     }
 
-    pub fn apply_move(&mut self, mv: ChessMove) {
-        self.board.board[mv.grid as usize] = mv.piece;
-        for i in 0..8 {
-            let row: Vec<char> = self.board.board[i * 8..(i + 1) * 8]
+    /// Start tracking a new game from `position`, which the physical board may not show
+    /// yet. Until it does the board is out of sync and `recovery_plan` lists the
+    /// pieces to place, move or remove.
+    pub fn reset_to(&mut self, position: Position) {
+        self.initial = position.clone();
+        self.key = zobrist::hash(&position);
+        self.repetition_keys = vec![self.key];
+        self.position = position;
+        self.moves.clear();
+        self.history.clear();
+        self.undone.clear();
+        self.concluded = None;
+        self.pending.clear();
+        self.move_times = None;
+        self.disturbed = false;
+        self.out_of_sync = self.shown() != self.expected_board();
+    }
+
+    /// The main line of `pgn` on a board oriented the way DGT documents it. What the
+    /// board shows is not known yet, see `resumed_on`.
+    pub fn from_pgn(pgn: &PgnGame, variant: Arc<dyn Variant>) -> Result<GameBoard, String> {
+        let board = StartPosition::Mirror.layout(&Position::starting());
+        let mut game = GameBoard::new_with_variant(board, variant);
+        game.reset_to(pgn.start()?);
+        game.set_tags(GameTags::from_pgn(pgn));
+        for (i, san) in pgn.moves.iter().enumerate() {
+            let ply = pgn::parse_san(game.variant.as_ref(), &game.position, san)
+                .ok_or_else(|| format!("move {} {:?} is not legal", i + 1, san))?;
+            game.play(ply);
+        }
+        if let Some((white, black)) = pgn.clocks() {
+            game.note_clock(white, black);
+        }
+        game.out_of_sync = game.board != game.expected_board();
+        Ok(game)
+    }
+
+    /// The current physical board state
+    pub fn board(&self) -> &ChessBoard {
+        &self.board
+    }
+
+    /// The tracked game position
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    /// The tracked game position laid out in grid order, as the board would report it
+    pub fn expected_board(&self) -> ChessBoard {
+        let mut board = ChessBoard {
+            board: [RawPiece::Empty; 64],
+        };
+        for (sq, piece) in self.position.board.iter().enumerate() {
+            let grid = self
+                .start
+                .grid(rules::file_of(sq as u8), rules::rank_of(sq as u8));
+            board.board[grid as usize] = *piece;
+        }
+        board
+    }
+
+    /// The side expected to make the next move
+    pub fn to_move(&self) -> PieceColor {
+        self.position.to_move
+    }
+
+    /// The start position the game was set up in, which also fixes the board orientation
+    pub fn start(&self) -> StartPosition {
+        self.start
+    }
+
+    /// The rules the game is played under
+    pub fn variant(&self) -> &dyn Variant {
+        self.variant.as_ref()
+    }
+
+    /// Remaining castling rights in the tracked game
+    pub fn castling(&self) -> Castling {
+        self.position.castling
+    }
+
+    /// X-FEN of the tracked position, identical to standard FEN for classical games
+    pub fn fen(&self) -> String {
+        self.position.to_fen()
+    }
+
+    /// Result of the game if the tracked position ends it
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.concluded
+            .or_else(|| self.variant.outcome(&self.position))
+    }
+
+    /// End the game with a result the board can not show, a resignation or an agreed
+    /// draw
+    pub fn conclude(&mut self, outcome: Outcome) {
+        self.concluded = Some(outcome);
+    }
+
+    /// The position the game started from
+    pub fn initial_position(&self) -> &Position {
+        &self.initial
+    }
+
+    /// Moves played so far
+    pub fn moves(&self) -> &[Ply] {
+        &self.moves
+    }
+
+    /// Moves played so far with their notation and the positions they led to
+    pub fn history(&self) -> &[RecordedMove] {
+        &self.history
+    }
+
+    /// The moves played so far in standard algebraic notation
+    pub fn san_moves(&self) -> impl Iterator<Item = &str> {
+        self.history.iter().map(|recorded| recorded.san.as_str())
+    }
+
+    /// The position after `ply` half moves, 0 for the initial position
+    pub fn position_at(&self, ply: usize) -> Option<&Position> {
+        match ply {
+            0 => Some(&self.initial),
+            ply => self.history.get(ply - 1).map(|recorded| &recorded.position),
+        }
+    }
+
+    /// Attach `note` to move `number` of `colour`, it has to have been played
+    pub fn annotate(&mut self, number: u16, colour: PieceColor, note: &str) -> Result<(), String> {
+        let ply = (0..self.history.len())
+            .find(|&ply| {
+                self.position_at(ply)
+                    .is_some_and(|before| before.fullmove == number && before.to_move == colour)
+            })
+            .ok_or_else(|| format!("move {} of {:?} has not been played", number, colour))?;
+        self.history[ply].notes.push(note.to_string());
+        Ok(())
+    }
+
+    /// Event, board and players, written to the PGN headers
+    pub fn tags(&self) -> &GameTags {
+        &self.tags
+    }
+
+    pub fn set_tags(&mut self, tags: GameTags) {
+        self.tags = tags;
+    }
+
+    /// White's material minus black's, in pawns
+    pub fn material_balance(&self) -> i32 {
+        Material::of(&self.position).balance()
+    }
+
+    /// Static evaluation of the current position in centipawns from white's point of view
+    pub fn evaluation(&self) -> i32 {
+        eval::evaluate(&self.position)
+    }
+
+    /// ECO classification of the latest position of the game that ends a known line,
+    /// however it was reached. Only for games from the classical start position.
+    pub fn opening(&self) -> Option<Opening> {
+        if self.initial != Position::starting() {
+            return None;
+        }
+        self.history
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, recorded)| {
+                eco::by_key(recorded.key).map(|opening| Opening {
+                    plies: i + 1,
+                    ..opening
+                })
+            })
+    }
+
+    /// Zobrist key of the tracked position
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    /// How often the current position has occurred, counting this occurrence
+    pub fn repetitions(&self) -> usize {
+        self.repetition_keys
+            .iter()
+            .filter(|key| **key == self.key)
+            .count()
+    }
+
+    /// A `DrawClaimable` or `AutoDraw` event if the current position allows or forces a draw.
+    /// Automatic draws take precedence over claimable ones.
+    pub fn draw(&self) -> Option<Event> {
+        let repetitions = self.repetitions();
+        let halfmove = self.position.halfmove;
+        if self.variant.insufficient_material(&self.position) {
+            Some(Event::AutoDraw(DrawReason::InsufficientMaterial))
+        } else if repetitions >= 5 {
+            Some(Event::AutoDraw(DrawReason::FivefoldRepetition))
+        } else if halfmove >= 150 {
+            Some(Event::AutoDraw(DrawReason::SeventyFiveMoves))
+        } else if repetitions >= 3 {
+            Some(Event::DrawClaimable(DrawReason::ThreefoldRepetition))
+        } else if halfmove >= 100 {
+            Some(Event::DrawClaimable(DrawReason::FiftyMoves))
+        } else {
+            None
+        }
+    }
+
+    /// Record a field update on the physical board. Updates that would leave more
+    /// pieces on the board than a set has are taken for garbled and left out, see
+    /// `needs_resync`. A piece put straight back where it was lifted from was only
+    /// adjusted, see `apply_move_at`.
+    pub fn apply_move(&mut self, mv: ChessMove) -> Option<SquareChange> {
+        self.apply_move_at(mv, None)
+    }
+
+    /// Like `apply_move`, for an update that arrived `at` milliseconds since the Unix
+    /// epoch. A piece put back on the square it was last lifted from within
+    /// the policy's `settle_ms` was adjusted: neither the lift nor the placement is taken for
+    /// part of a move.
+    pub fn apply_move_at(&mut self, mv: ChessMove, at: Option<u64>) -> Option<SquareChange> {
+        if self.board.board[mv.grid as usize] == mv.piece {
+            return None;
+        }
+        if let Some(reason) = self.implausible_update(mv) {
+            self.suspect = Some(reason);
+            return None;
+        }
+        let square = &mut self.board.board[mv.grid as usize];
+        let before = std::mem::replace(square, mv.piece);
+        let lifted = self.lifted.take();
+        let adjusted = lifted.is_some_and(|(grid, piece, lifted_at)| {
+            let quick = match (lifted_at, at) {
+                (Some(lifted_at), Some(at)) => {
+                    at.saturating_sub(lifted_at) <= self.policy.settle_ms
+                }
+                _ => true,
+            };
+            grid == mv.grid && piece == mv.piece && quick
+        });
+        if adjusted {
+            if let Some(lift) = self.pending.iter().rposition(|lift| lift.grid == mv.grid) {
+                self.pending.remove(lift);
+            }
+            // Nor does the move start with it
+            if self.pending.is_empty() {
+                self.move_times = None;
+            }
+        } else {
+            if mv.piece == RawPiece::Empty {
+                self.lifted = Some((mv.grid, before, at));
+            }
+            self.pending.push(mv);
+        }
+        Some(SquareChange {
+            grid: mv.grid,
+            before,
+            after: mv.piece,
+            adjusted,
+        })
+    }
+
+    /// Compare the physical board with the tracked position, applying a completed legal
+    /// move if one is found. In blitz the other side may start its reply before the move
+    /// is complete, so the move is also looked for with that side's pieces as they
+    /// were. When it is found that way the board may already show the reply as well:
+    /// call `sync` again while it reports a move.
+    pub fn sync(&mut self) -> SyncState {
+        let expected = self.expected_board();
+        let shown = self.shown();
+        if shown == expected {
+            self.pending.clear();
+            self.lifted = None;
+            self.move_times = None;
+            self.out_of_sync = false;
+            self.disturbed = false;
+            return SyncState::InSync;
+        }
+        let squares = fen::squares(&shown, self.start);
+        if let Some(ply) = self.find_move(&squares) {
+            let mv = self.moved(ply);
+            self.pending.clear();
+            return SyncState::Moved(mv);
+        }
+        if let Some(mv) = self.taken_back(&shown) {
+            return SyncState::TakenBack(mv);
+        }
+        let premove = self
+            .without_reply(&shown, &expected)
+            .filter(|_| !self.disturbed);
+        if let Some(ply) =
+            premove.and_then(|board| self.find_move(&fen::squares(&board, self.start)))
+        {
+            let mv = self.moved(ply);
+            // What is left belongs to the reply
+            let expected = self.expected_board();
+            self.pending
+                .retain(|mv| shown.board[mv.grid as usize] != expected.board[mv.grid as usize]);
+            return SyncState::Moved(mv);
+        }
+        let board = premove.unwrap_or(shown);
+        let plausible = self.is_plausibly_in_progress(&board, &expected);
+        // Updates lost on the way leave the board a move further than it was told, looked
+        // for once it settles with no piece in hand. A reply taking the piece just moved
+        // leaves the board without it plausible, so it is the shown board that counts.
+        let settled = !self.is_plausibly_in_progress(&shown, &expected)
+            && !self.disturbed
+            && self.lifted.is_none();
+        if let Some(first) = settled.then(|| self.missed_move(&squares)).flatten() {
+            let mv = self.moved(first);
+            self.pending.clear();
+            return SyncState::Moved(mv);
+        }
+        // A move takes at most two pieces off their squares, and the board only shows
+        // two off when it shows the move in progress
+        let displaced = (0..64)
+            .filter(|&grid| {
+                expected.board[grid] != RawPiece::Empty && board.board[grid] != expected.board[grid]
+            })
+            .count();
+        if self.disturbed || displaced > 2 || (displaced == 2 && !plausible) {
+            self.disturbed = true;
+            self.out_of_sync = true;
+            return SyncState::Disturbed;
+        }
+        if !self.out_of_sync && plausible {
+            return SyncState::Pending;
+        }
+        self.out_of_sync = true;
+        SyncState::OutOfSync
+    }
+
+    /// Whether the board was disturbed and waits to show the position again, see
+    /// `SyncState::Disturbed`
+    pub fn is_disturbed(&self) -> bool {
+        self.disturbed
+    }
+
+    /// Where move detection stands as of the last `sync`
+    pub fn detection_state(&self) -> DetectionState {
+        if self.disturbed {
+            return DetectionState::Disturbed;
+        }
+        if self.out_of_sync {
+            return DetectionState::OutOfSync;
+        }
+        let shown = self.shown();
+        if shown == self.expected_board() {
+            return DetectionState::Idle;
+        }
+        let last_rank = match self.position.to_move {
+            PieceColor::Black => 0,
+            _ => 7,
+        };
+        let pawn = RawPiece::from_kind(PieceKind::Pawn, self.position.to_move);
+        let promoting =
+            shown.board.iter().enumerate().any(|(grid, piece)| {
+                *piece == pawn && self.start.file_rank(grid as u8).1 == last_rank
+            });
+        if promoting {
+            DetectionState::AwaitingPromotionPiece
+        } else {
+            DetectionState::PieceLifted
+        }
+    }
+
+    /// How moves are told from what happens on the board
+    pub fn policy(&self) -> &DetectionPolicy {
+        &self.policy
+    }
+
+    /// The physical board with the pawns left on the last rank standing for the
+    /// policy's promotion piece
+    fn shown(&self) -> ChessBoard {
+        let mut shown = self.board;
+        let Some(kind) = self.policy.promotion else {
+            return shown;
+        };
+        for (grid, piece) in shown.board.iter_mut().enumerate() {
+            let (_, rank) = self.start.file_rank(grid as u8);
+            match (*piece, rank) {
+                (RawPiece::WhitePawn, 7) | (RawPiece::BlackPawn, 0) => {
+                    *piece = RawPiece::from_kind(kind, piece.get_colour());
+                }
+                _ => {}
+            }
+        }
+        shown
+    }
+
+    /// The move that turns the tracked position into `squares`. Under
+    /// `Legality::Lenient` that may be one leaving the mover's king in check.
+    fn find_move(&self, squares: &[RawPiece; 64]) -> Option<Ply> {
+        if let Some(ply) = self.variant.interpret(&self.position, squares) {
+            return Some(ply);
+        }
+        if self.policy.legality == Legality::Strict {
+            return None;
+        }
+        self.position
+            .pseudo_legal_moves(&rules::PROMOTIONS)
+            .into_iter()
+            .find(|ply| self.variant.play(&self.position, ply).board == *squares)
+    }
+
+    /// Moves from `position` that `find_move` would take, under the legality of the
+    /// policy
+    fn candidate_moves(&self, position: &Position) -> Vec<Ply> {
+        let mut moves = self.variant.legal_moves(position);
+        if self.policy.legality == Legality::Lenient {
+            for ply in position.pseudo_legal_moves(&rules::PROMOTIONS) {
+                if !moves.contains(&ply) {
+                    moves.push(ply);
+                }
+            }
+        }
+        moves
+    }
+
+    /// The first of the only two moves, one by each side, that turn the tracked position
+    /// into `squares`. None when no pair does, or more than one. The pairs are played
+    /// out once for each tracked position.
+    fn missed_move(&mut self, squares: &[RawPiece; 64]) -> Option<Ply> {
+        if self
+            .missed
+            .as_ref()
+            .is_none_or(|(position, _)| *position != self.position)
+        {
+            let mut boards = HashMap::new();
+            for first in self.candidate_moves(&self.position) {
+                let between = self.variant.play(&self.position, &first);
+                for second in self.candidate_moves(&between) {
+                    boards
+                        .entry(self.variant.play(&between, &second).board)
+                        .and_modify(|found: &mut Option<Ply>| *found = None)
+                        .or_insert(Some(first));
+                }
+            }
+            self.missed = Some((self.position.clone(), boards));
+        }
+        self.missed.as_ref()?.1.get(squares).copied().flatten()
+    }
+
+    /// Take back the last move if the policy allows it and `shown` is the position
+    /// before it
+    fn taken_back(&mut self, shown: &ChessBoard) -> Option<DetectedMove> {
+        if !self.policy.takebacks || self.disturbed {
+            return None;
+        }
+        let before = self.position_at(self.history.len().checked_sub(1)?)?;
+        if self.start.layout(before) != *shown {
+            return None;
+        }
+        let ply = self.undo()?.ply;
+        self.lifted = None;
+        self.move_times = None;
+        Some(self.detected(&ply))
+    }
+
+    /// Play `ply`, found on the board, and describe it
+    fn moved(&mut self, ply: Ply) -> DetectedMove {
+        let mv = self.detected(&ply);
+        let mover = self.position.to_move;
+        self.lifted = None;
+        self.disturbed = false;
+        self.play(ply);
+        if let (Some(recorded), Some((lifted, completed))) =
+            (self.history.last_mut(), self.move_times.take())
+        {
+            let clock = self.clock.map(|(white, black)| match mover {
+                PieceColor::Black => black,
+                _ => white,
+            });
+            recorded.timing = Some(MoveTiming {
+                lifted,
+                completed,
+                clock,
+            });
+        }
+        // Playing the move taken back last is the same as redoing it
+        if self.undone.last().is_some_and(|undone| undone.ply == ply) {
+            self.undone.pop();
+        } else {
+            self.undone.clear();
+        }
+        self.out_of_sync = false;
+        mv
+    }
+
+    /// The board with the changes of the side not to move undone: its pieces lifted put
+    /// back and the ones it put down taken off again. None unless the side to move is
+    /// in the middle of a move and the other side touched at most the four squares of
+    /// a move of its own.
+    fn without_reply(&self, shown: &ChessBoard, expected: &ChessBoard) -> Option<ChessBoard> {
+        let other = self.position.to_move.opposite();
+        let mut board = *shown;
+        let mut touched = 0;
+        for grid in 0..64 {
+            let (actual, wanted) = (shown.board[grid], expected.board[grid]);
+            let theirs = match actual {
+                RawPiece::Empty => wanted.get_colour() == other,
+                actual => actual.get_colour() == other,
+            };
+            if actual != wanted && theirs {
+                board.board[grid] = wanted;
+                touched += 1;
+            }
+        }
+        (touched > 0 && touched <= 4 && board != *expected).then_some(board)
+    }
+
+    /// Make a move on the tracked position
+    fn play(&mut self, ply: Ply) {
+        let san = pgn::san(self.variant.as_ref(), &self.position, &ply);
+        let after = self.variant.play(&self.position, &ply);
+        self.key = zobrist::advance(self.key, &self.position, &after);
+        self.position = after;
+        self.moves.push(ply);
+        if self.position.halfmove == 0 {
+            self.repetition_keys.clear();
+        }
+        self.repetition_keys.push(self.key);
+        self.history.push(RecordedMove {
+            ply,
+            san,
+            position: self.position.clone(),
+            key: self.key,
+            timing: None,
+            notes: Vec::new(),
+        });
+    }
+
+    /// Take back the last move. The physical board is out of sync until it shows the
+    /// position before the move, or the same move is played again.
+    pub fn undo(&mut self) -> Option<&RecordedMove> {
+        let undone = self.history.pop()?;
+        self.moves.pop();
+        self.concluded = None;
+        self.position = self.position_at(self.history.len()).cloned()?;
+        self.key = self
+            .history
+            .last()
+            .map_or_else(|| zobrist::hash(&self.initial), |last| last.key);
+        let positions = std::iter::once((&self.initial, zobrist::hash(&self.initial))).chain(
+            self.history
                 .iter()
-                .map(|&x| x.to_char())
-                .collect();
-            println!("{:?}", row);
+                .map(|recorded| (&recorded.position, recorded.key)),
+        );
+        self.repetition_keys.clear();
+        for (position, key) in positions {
+            if position.halfmove == 0 {
+                self.repetition_keys.clear();
+            }
+            self.repetition_keys.push(key);
+        }
+        self.pending.clear();
+        self.out_of_sync = self.shown() != self.expected_board();
+        self.undone.push(undone);
+        self.undone.last()
+    }
+
+    /// Play the move taken back last again
+    pub fn redo(&mut self) -> Option<&RecordedMove> {
+        let redone = self.undone.pop()?;
+        self.play(redone.ply);
+        self.pending.clear();
+        self.out_of_sync = self.shown() != self.expected_board();
+        self.history.last()
+    }
+
+    /// Play `uci` on the tracked position without it being made on the board, as when it
+    /// was made somewhere else. The board is out of sync until it shows the move too.
+    pub fn expect_move(&mut self, uci: &str) -> Result<(), String> {
+        let ply = self
+            .variant
+            .legal_moves(&self.position)
+            .into_iter()
+            .find(|ply| ply.uci() == uci)
+            .ok_or_else(|| format!("move {:?} is not legal", uci))?;
+        self.play(ply);
+        self.undone.clear();
+        self.pending.clear();
+        self.move_times = None;
+        self.out_of_sync = self.shown() != self.expected_board();
+        Ok(())
+    }
+
+    /// Replace the moves played so far with `moves` in UCI notation, replayed from the
+    /// initial position. The game is left as it was when one of them is not legal.
+    pub fn replace_moves(&mut self, moves: &[String]) -> Result<(), String> {
+        let mut corrected = self.clone();
+        corrected.reset_to(self.initial.clone());
+        for (i, uci) in moves.iter().enumerate() {
+            let ply = corrected
+                .variant
+                .legal_moves(&corrected.position)
+                .into_iter()
+                .find(|ply| ply.uci() == *uci)
+                .ok_or_else(|| format!("move {} {:?} is not legal", i + 1, uci))?;
+            corrected.play(ply);
+        }
+        corrected.out_of_sync = corrected.shown() != corrected.expected_board();
+        *self = corrected;
+        Ok(())
+    }
+
+    /// Time a lift or placement on the board, for the move it ends up part of
+    pub fn note_micro_event(&mut self, micro: &MicroEvent) {
+        let at = micro.at();
+        let lifted = self.move_times.map_or(at, |(lifted, _)| lifted);
+        self.move_times = Some((lifted, at));
+    }
+
+    /// Keep the clock times to record with the next move
+    pub fn note_clock(&mut self, white: Remaining, black: Remaining) {
+        self.clock = Some((white, black));
+    }
+
+    /// Time spent on every move played on the board, per side and phase of the game
+    pub fn timing_stats(&self) -> TimingStats {
+        TimingStats::new(
+            self.history
+                .iter()
+                .enumerate()
+                .filter_map(|(i, recorded)| Some((self.position_at(i)?, recorded.timing))),
+        )
+    }
+
+    /// What a beginner should know about the move being made: where the piece of the
+    /// side to move lifted first can go, or that it was put down where it can not
+    pub fn hint(&self) -> Option<Hint> {
+        let square = |grid: u8| {
+            let (file, rank) = self.start.file_rank(grid);
+            rules::square(file, rank)
+        };
+        let lift = self.pending.first()?;
+        let expected = self.expected_board();
+        let piece = expected.board[lift.grid as usize];
+        if lift.piece != RawPiece::Empty || piece.get_colour() != self.position.to_move {
+            return None;
+        }
+        let from = square(lift.grid);
+        let mut to: Vec<rules::Square> = self
+            .variant
+            .legal_moves(&self.position)
+            .into_iter()
+            .filter(|ply| ply.from == from && ply.kind != PlyKind::Drop)
+            .map(|ply| ply.to)
+            .collect();
+        // A promotion is a move to the same square for every piece it can become
+        to.sort_unstable();
+        to.dedup();
+        match self.pending.last() {
+            Some(place) if self.pending.len() > 1 => {
+                let placed = square(place.grid);
+                let illegal = place.piece == piece && placed != from && !to.contains(&placed);
+                illegal.then_some(Hint::Illegal {
+                    piece,
+                    from,
+                    to: placed,
+                })
+            }
+            _ => Some(Hint::Lifted { piece, from, to }),
+        }
+    }
+
+    /// Whether the board has diverged from the tracked position
+    pub fn is_out_of_sync(&self) -> bool {
+        self.out_of_sync
+    }
+
+    /// Why a field update was rejected, if one was since the last `resync`. The
+    /// physical board may have changed without the tracked copy knowing, so it should
+    /// be read again.
+    pub fn needs_resync(&self) -> Option<Implausible> {
+        self.suspect
+    }
+
+    /// Replace the tracked copy of the physical board with a fresh dump and compare it
+    /// with the game again, which keeps the moves played so far
+    pub fn resync(&mut self, board: ChessBoard) -> SyncState {
+        self.board = board;
+        self.pending.clear();
+        self.lifted = None;
+        self.disturbed = false;
+        self.suspect = None;
+        self.sync()
+    }
+
+    /// Whether placing a piece would need a second king, or more pieces of a colour
+    /// than a set has. One piece over is let through, as a promoted piece is often put
+    /// down before the pawn is taken off.
+    fn implausible_update(&self, mv: ChessMove) -> Option<Implausible> {
+        if mv.piece == RawPiece::Empty || !self.variant.standard_material() {
+            return None;
+        }
+        let mut board = self.board;
+        board.board[mv.grid as usize] = mv.piece;
+        let colour = mv.piece.get_colour();
+        let ours = board.board.iter().filter(|p| p.get_colour() == colour);
+        if mv.piece.kind() == Some(PieceKind::King)
+            && ours.clone().filter(|p| **p == mv.piece).count() > 1
+        {
+            return Some(Implausible::Kings(colour));
+        }
+        (ours.count() > 17).then_some(Implausible::Pieces(colour))
+    }
+
+    /// Steps that restore the tracked position on the physical board
+    pub fn recovery_plan(&self) -> Vec<Correction> {
+        let expected = self.expected_board();
+        let shown = self.shown();
+        let mut surplus = Vec::new();
+        let mut missing = Vec::new();
+        for grid in 0..64u8 {
+            let actual = shown.board[grid as usize];
+            let expected = expected.board[grid as usize];
+            if actual == expected {
+                continue;
+            }
+            if actual != RawPiece::Empty {
+                surplus.push((grid, actual));
+            }
+            if expected != RawPiece::Empty {
+                missing.push((grid, expected));
+            }
         }
+        let mut plan = Vec::new();
+        missing.retain(
+            |&(to, piece)| match surplus.iter().position(|&(_, p)| p == piece) {
+                Some(i) => {
+                    let (from, _) = surplus.remove(i);
+                    plan.push(Correction::Move { piece, from, to });
+                    false
+                }
+                None => true,
+            },
+        );
+        plan.extend(
+            surplus
+                .into_iter()
+                .map(|(grid, piece)| Correction::Remove { piece, grid }),
+        );
+        plan.extend(
+            missing
+                .into_iter()
+                .map(|(grid, piece)| Correction::Place { piece, grid }),
+        );
+        plan
+    }
+
+    /// This game, saved before the driver stopped, carried on with `board` as found on
+    /// starting again, and how the two compare. Moves made in between, up to one by each
+    /// side, are played. None when the board shows another game: pieces set up for a new
+    /// one, or more than `MAX_RESUME_CORRECTIONS` pieces out of place.
+    pub fn resumed_on(&self, board: ChessBoard) -> Option<(GameBoard, SyncState)> {
+        let mut game = self.clone();
+        let mut state = game.resync(board);
+        while let SyncState::Moved(_) = state {
+            match game.sync() {
+                next @ SyncState::Moved(_) => state = next,
+                _ => break,
+            }
+        }
+        if game.new_game_set_up() {
+            return None;
+        }
+        match state {
+            SyncState::InSync | SyncState::Moved(_) => Some((game, state)),
+            SyncState::Pending
+            | SyncState::OutOfSync
+            | SyncState::Disturbed
+            | SyncState::TakenBack(_) => {
+                (game.recovery_plan().len() <= MAX_RESUME_CORRECTIONS).then_some((game, state))
+            }
+        }
+    }
+
+    /// Describe a move from the rules engine in grid terms
+    fn detected(&self, ply: &Ply) -> DetectedMove {
+        let grid = |sq: rules::Square| self.start.grid(rules::file_of(sq), rules::rank_of(sq));
+        let mv = Move {
+            piece: ply.piece,
+            from: grid(ply.from),
+            to: grid(ply.to),
+        };
+        let capture = Capture {
+            piece: ply.captured,
+            grid: grid(ply.capture_square()),
+        };
+        match ply.kind {
+            PlyKind::Castle { rook_from, rook_to } => {
+                let rook = Move {
+                    piece: self.position.piece_at(rook_from),
+                    from: grid(rook_from),
+                    to: grid(rook_to),
+                };
+                if rules::file_of(ply.to) == 6 {
+                    DetectedMove::ShortCastle(mv, rook)
+                } else {
+                    DetectedMove::LongCastle(mv, rook)
+                }
+            }
+            PlyKind::EnPassant => DetectedMove::EnPassant(mv, capture),
+            PlyKind::Promotion(piece) if ply.is_capture() => {
+                DetectedMove::PromotionCapture(mv, capture, piece)
+            }
+            PlyKind::Promotion(piece) => DetectedMove::Promotion(mv, piece),
+            PlyKind::Drop => DetectedMove::Drop(mv),
+            PlyKind::Normal if ply.is_capture() => DetectedMove::SimpleCapture(mv, capture),
+            PlyKind::Normal => DetectedMove::SimpleMove(mv),
+        }
+    }
+
+    /// A move in progress on `board` touches at most four squares and only adds pieces
+    /// of the side to move
+    fn is_plausibly_in_progress(&self, board: &ChessBoard, expected: &ChessBoard) -> bool {
+        let mut changed = 0;
+        for (actual, expected) in board.board.iter().zip(expected.board.iter()) {
+            if actual == expected {
+                continue;
+            }
+            changed += 1;
+            if *actual != RawPiece::Empty && actual.get_colour() != self.position.to_move {
+                return false;
+            }
+        }
+        changed <= 4
+    }
+
+    /// Whether the pieces were set up for a fresh game after moves were played in this
+    /// one. The first move taken back shows the starting position as well, so after
+    /// one move only a board disturbed on the way counts, as setting it up does.
+    pub fn new_game_set_up(&self) -> bool {
+        (self.moves.len() > 1 || (!self.moves.is_empty() && self.disturbed))
+            && self.is_starting_position() != StartPosition::None
     }
 
     pub fn is_starting_position(&self) -> StartPosition {
@@ -142,7 +1667,784 @@ impl GameBoard {
         {
             return StartPosition::Mirror;
         }
+        for mirror in [false, true] {
+            let probe = StartPosition::Chess960 { number: 0, mirror };
+            let rank = |rank: u8| -> [RawPiece; 8] {
+                std::array::from_fn(|file| self.board.board[probe.grid(file as u8, rank) as usize])
+            };
+            if rank(1).iter().any(|p| *p != RawPiece::WhitePawn)
+                || rank(6).iter().any(|p| *p != RawPiece::BlackPawn)
+            {
+                continue;
+            }
+            let (white, black) = (rank(0), rank(7));
+            let mirrored = white.iter().zip(black.iter()).all(|(w, b)| {
+                b.get_colour() == PieceColor::Black
+                    && char::from(*w) == char::from(*b).to_ascii_uppercase()
+            });
+            if let (true, Some(number)) = (mirrored, chess960::number(&white)) {
+                return StartPosition::Chess960 { number, mirror };
+            }
+        }
 
         StartPosition::None
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::variant::Termination;
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+
+    /// Board in the DGT documented layout, a8 first
+    fn board_from(rows: &str) -> ChessBoard {
+        let mut board = [RawPiece::Empty; 64];
+        for (i, c) in rows.chars().filter(|c| !c.is_whitespace()).enumerate() {
+            board[i] = if c == '.' {
+                RawPiece::Empty
+            } else {
+                RawPiece::try_from(c).unwrap()
+            };
+        }
+        ChessBoard { board }
+    }
+
+    /// The starting position, shared with the tests of other modules
+    pub fn start_board() -> ChessBoard {
+        board_from("rnbqkbnr pppppppp ........ ........ ........ ........ PPPPPPPP RNBQKBNR")
+    }
+
+    pub fn update(grid: u8, piece: RawPiece) -> ChessMove {
+        ChessMove { grid, piece }
+    }
+
+    fn play(game: &mut GameBoard, updates: &[ChessMove]) -> SyncState {
+        let mut state = SyncState::InSync;
+        for mv in updates {
+            if game.apply_move(*mv).is_some() {
+                state = game.sync();
+            }
+        }
+        state
+    }
+
+    #[test]
+    fn test_detect_simple_move() {
+        // e2-e4
+        let updates = [update(52, RawPiece::Empty), update(36, RawPiece::WhitePawn)];
+        let mv = detect_move(&start_board(), &updates).unwrap();
+        assert_eq!(
+            mv,
+            DetectedMove::SimpleMove(Move::new(RawPiece::WhitePawn, 52, 36))
+        );
+    }
+
+    #[test]
+    fn test_detect_capture_and_castle() {
+        let before =
+            board_from("r...k..r ........ ........ ...p.... ....P... ........ ........ R...K..R");
+        // exd5, lifting the captured pawn first
+        let updates = [
+            update(27, RawPiece::Empty),
+            update(36, RawPiece::Empty),
+            update(27, RawPiece::WhitePawn),
+        ];
+        let Some(DetectedMove::SimpleCapture(mv, capture)) = detect_move(&before, &updates) else {
+            panic!("no capture");
+        };
+        assert_eq!((mv.from(), mv.to()), (36, 27));
+        assert_eq!(capture, Capture::new(RawPiece::BlackPawn, 27));
+        assert_eq!(capture.piece(), RawPiece::BlackPawn);
+        // White O-O
+        let updates = [
+            update(60, RawPiece::Empty),
+            update(62, RawPiece::WhiteKing),
+            update(63, RawPiece::Empty),
+            update(61, RawPiece::WhiteRook),
+        ];
+        assert!(matches!(
+            detect_move(&before, &updates),
+            Some(DetectedMove::ShortCastle(_, _))
+        ));
+        // Black O-O-O
+        let updates = [
+            update(4, RawPiece::Empty),
+            update(2, RawPiece::BlackKing),
+            update(0, RawPiece::Empty),
+            update(3, RawPiece::BlackRook),
+        ];
+        assert!(matches!(
+            detect_move(&before, &updates),
+            Some(DetectedMove::LongCastle(_, _))
+        ));
+        // A Chess960 O-O, king b1 to g1 and rook h1 to f1, is not classical castling
+        let before =
+            board_from("....k... ........ ........ ........ ........ ........ ........ .K.....R");
+        let updates = [
+            update(57, RawPiece::Empty),
+            update(63, RawPiece::Empty),
+            update(62, RawPiece::WhiteKing),
+            update(61, RawPiece::WhiteRook),
+        ];
+        assert_eq!(detect_move(&before, &updates), None);
+    }
+
+    #[test]
+    fn test_detect_en_passant() {
+        let before =
+            board_from("....k... ........ ........ ...pP... ........ ........ ........ ....K...");
+        let updates = [
+            update(28, RawPiece::Empty),
+            update(19, RawPiece::WhitePawn),
+            update(27, RawPiece::Empty),
+        ];
+        assert!(matches!(
+            detect_move(&before, &updates),
+            Some(DetectedMove::EnPassant(_, Capture { grid: 27, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_threefold_repetition() {
+        use RawPiece::*;
+        let mut game = GameBoard::new(start_board());
+        let shuffle = [
+            [update(62, Empty), update(45, WhiteKnight)],
+            [update(6, Empty), update(21, BlackKnight)],
+            [update(45, Empty), update(62, WhiteKnight)],
+            [update(21, Empty), update(6, BlackKnight)],
+        ];
+        for _ in 0..2 {
+            assert_eq!(game.draw(), None);
+            for updates in &shuffle {
+                assert!(matches!(play(&mut game, updates), SyncState::Moved(_)));
+            }
+        }
+        assert_eq!(game.repetitions(), 3);
+        assert_eq!(
+            game.draw(),
+            Some(Event::DrawClaimable(DrawReason::ThreefoldRepetition))
+        );
+    }
+
+    #[test]
+    fn test_sync_tracks_moves_and_turns() {
+        let mut game = GameBoard::new(start_board());
+        assert_eq!(game.start(), StartPosition::Mirror);
+        let state = play(&mut game, &[update(52, RawPiece::Empty)]);
+        assert_eq!(state, SyncState::Pending);
+        let state = play(&mut game, &[update(36, RawPiece::WhitePawn)]);
+        assert!(matches!(state, SyncState::Moved(_)));
+        assert_eq!(game.to_move(), PieceColor::Black);
+        assert_eq!(game.start().square_name(36), "e4");
+        assert_eq!(
+            game.fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert!(!game.new_game_set_up());
+        // The pawn put back is a takeback, not the next game
+        let state = play(
+            &mut game,
+            &[update(36, RawPiece::Empty), update(52, RawPiece::WhitePawn)],
+        );
+        assert_eq!(state, SyncState::OutOfSync);
+        assert!(!game.new_game_set_up());
+        // Pieces lifted on the way, as when setting up again
+        let state = play(&mut game, &[update(51, RawPiece::Empty)]);
+        assert_eq!(state, SyncState::Disturbed);
+        play(&mut game, &[update(51, RawPiece::WhitePawn)]);
+        assert!(game.new_game_set_up());
+    }
+
+    /// Field updates of a blitz game, with the moves each one completes
+    fn blitz(game: &mut GameBoard, updates: &[ChessMove]) -> Vec<Vec<String>> {
+        updates
+            .iter()
+            .map(|mv| {
+                let mut moves = Vec::new();
+                if game.apply_move(*mv).is_some() {
+                    while let SyncState::Moved(_) = game.sync() {
+                        moves.push(game.moves().last().unwrap().uci());
+                    }
+                }
+                assert!(!game.is_out_of_sync(), "out of sync after {:?}", mv);
+                moves
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_interleaved_blitz_moves() {
+        use RawPiece::*;
+        // Black lifts the d-pawn while white's e-pawn is still in the air
+        let mut game = GameBoard::new(start_board());
+        let moves = blitz(
+            &mut game,
+            &[
+                update(52, Empty),
+                update(11, Empty),
+                update(36, WhitePawn),
+                update(27, BlackPawn),
+            ],
+        );
+        assert_eq!(moves, [vec![], vec![], vec!["e2e4"], vec!["d7d5"]]);
+
+        // Black's pawn is down before white's, which completes both moves
+        let mut game = GameBoard::new(start_board());
+        let moves = blitz(
+            &mut game,
+            &[
+                update(52, Empty),
+                update(11, Empty),
+                update(27, BlackPawn),
+                update(36, WhitePawn),
+            ],
+        );
+        assert_eq!(moves[3], ["e2e4", "d7d5"]);
+        assert_eq!(game.to_move(), PieceColor::White);
+
+        // exd5 with the queen lifted to recapture before the pawn is down
+        let moves = blitz(
+            &mut game,
+            &[
+                update(27, Empty),
+                update(36, Empty),
+                update(3, Empty),
+                update(27, WhitePawn),
+                update(27, Empty),
+                update(27, BlackQueen),
+            ],
+        );
+        assert_eq!(moves[3], ["e4d5"]);
+        assert_eq!(moves[5], ["d8d5"]);
+        assert_eq!(
+            game.fen(),
+            "rnb1kbnr/ppp1pppp/8/3q4/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3"
+        );
+
+        // Pieces of the side that just moved are not a reply, that stays out of sync
+        let state = play(&mut game, &[update(35, BlackPawn)]);
+        assert_eq!(state, SyncState::OutOfSync);
+    }
+
+    #[test]
+    fn test_adjusted_piece() {
+        use RawPiece::*;
+        let mut game = GameBoard::new(start_board());
+        // The knight straightened while the pawn is in the air
+        game.apply_move_at(update(52, Empty), Some(1000));
+        game.apply_move_at(update(62, Empty), Some(1200));
+        let change = game
+            .apply_move_at(update(62, WhiteKnight), Some(1500))
+            .unwrap();
+        assert!(change.adjusted);
+        assert_eq!(game.sync(), SyncState::Pending);
+        let hint = game.hint().unwrap();
+        assert_eq!(hint.to_string(), "pawn on e2 can go to e3, e4");
+        game.apply_move_at(update(36, WhitePawn), Some(1800));
+        assert!(matches!(game.sync(), SyncState::Moved(_)));
+
+        // Put back after thinking it over is not an adjustment
+        game.apply_move_at(update(1, Empty), Some(5000));
+        let change = game
+            .apply_move_at(update(1, BlackKnight), Some(9000))
+            .unwrap();
+        assert!(!change.adjusted);
+        // Nor is another piece put where one was lifted, as in a capture
+        play(&mut game, &[update(11, Empty), update(27, BlackPawn)]);
+        game.apply_move_at(update(36, Empty), Some(9_900));
+        game.apply_move_at(update(27, Empty), Some(10_000));
+        let change = game.apply_move_at(update(27, WhitePawn), Some(10_100));
+        assert!(!change.unwrap().adjusted);
+        assert!(matches!(game.sync(), SyncState::Moved(_)));
+    }
+
+    #[test]
+    fn test_knocked_over_pieces() {
+        use RawPiece::*;
+        let mut game = GameBoard::new(start_board());
+        // A sleeve sweeps the pawns off f2, g2 and h2, one of them lands on g4
+        let state = play(
+            &mut game,
+            &[update(53, Empty), update(54, Empty), update(55, Empty)],
+        );
+        assert_eq!(state, SyncState::Disturbed);
+        assert!(game.is_disturbed());
+        assert_eq!(
+            play(&mut game, &[update(38, WhitePawn)]),
+            SyncState::Disturbed
+        );
+        // Half put back looks like no move, nor like one in progress
+        let state = play(&mut game, &[update(38, Empty), update(53, WhitePawn)]);
+        assert_eq!(state, SyncState::Disturbed);
+        assert_eq!(game.recovery_plan().len(), 2);
+        assert_eq!(
+            play(&mut game, &[update(54, WhitePawn)]),
+            SyncState::Disturbed
+        );
+        assert_eq!(play(&mut game, &[update(55, WhitePawn)]), SyncState::InSync);
+        assert!(!game.is_disturbed());
+
+        // Put back with a move made from the position also ends it
+        let state = play(
+            &mut game,
+            &[update(11, Empty), update(12, Empty), update(13, Empty)],
+        );
+        assert_eq!(state, SyncState::Disturbed);
+        play(&mut game, &[update(11, BlackPawn), update(13, BlackPawn)]);
+        assert!(game.is_disturbed());
+        play(&mut game, &[update(52, Empty), update(36, WhitePawn)]);
+        assert!(game.is_disturbed());
+        let state = play(&mut game, &[update(12, BlackPawn)]);
+        assert!(matches!(state, SyncState::Moved(_)));
+        assert!(!game.is_disturbed());
+    }
+
+    #[test]
+    fn test_detection_policy() {
+        use RawPiece::*;
+        let opening = [
+            update(52, Empty),
+            update(36, WhitePawn),
+            update(13, Empty),
+            update(29, BlackPawn),
+            update(59, Empty),
+            update(31, WhiteQueen),
+        ];
+        // a7-a6 ignoring the check from h5
+        let ignored = [update(8, Empty), update(16, BlackPawn)];
+        let mut strict = GameBoard::new(start_board());
+        play(&mut strict, &opening);
+        assert!(!matches!(play(&mut strict, &ignored), SyncState::Moved(_)));
+        let mut casual = GameBoardBuilder::new()
+            .policy(DetectionPolicy::casual())
+            .build(start_board());
+        play(&mut casual, &opening);
+        assert!(matches!(play(&mut casual, &ignored), SyncState::Moved(_)));
+        assert_eq!(casual.san_moves().last(), Some("a6"));
+        // Taken back by putting the pawn back
+        let state = play(&mut casual, &[update(16, Empty), update(8, BlackPawn)]);
+        let SyncState::TakenBack(mv) = state else {
+            panic!("{:?} instead of the move taken back", state);
+        };
+        assert_eq!((mv.main_move().from(), mv.main_move().to()), (8, 16));
+        assert_eq!(casual.moves().len(), 3);
+        assert_eq!(strict.policy(), &DetectionPolicy::default());
+
+        // The pawn left on a8 is a queen until it is swapped for one
+        let board =
+            board_from("....k... P....... ........ ........ ........ ........ ........ ....K...");
+        let mut game = GameBoardBuilder::new()
+            .promotion(Some(PieceKind::Queen))
+            .settle_ms(100)
+            .build(board);
+        assert!(matches!(
+            play(&mut game, &[update(8, Empty), update(0, WhitePawn)]),
+            SyncState::Moved(DetectedMove::Promotion(..))
+        ));
+        assert_eq!(game.sync(), SyncState::InSync);
+        assert!(game.recovery_plan().is_empty());
+        assert!(matches!(
+            play(&mut game, &[update(4, Empty), update(13, BlackKing)]),
+            SyncState::Moved(_)
+        ));
+        assert_eq!(
+            play(&mut game, &[update(0, Empty), update(0, WhiteQueen)]),
+            SyncState::InSync
+        );
+        // Put back after the settle time is not an adjustment
+        game.apply_move_at(update(60, Empty), Some(1000));
+        let change = game
+            .apply_move_at(update(60, WhiteKing), Some(1200))
+            .unwrap();
+        assert!(!change.adjusted);
+
+        // A fixed orientation holds whatever the pieces look like
+        let game = GameBoardBuilder::new()
+            .orientation(Orientation::Rotated)
+            .build(board);
+        assert_eq!(game.start(), StartPosition::Normal);
+        assert_eq!(game.castling(), Castling::default());
+        let game = GameBoardBuilder::new()
+            .orientation(Orientation::Documented)
+            .build(start_board());
+        assert_eq!(game.start(), StartPosition::Mirror);
+    }
+
+    #[test]
+    fn test_training_hints() {
+        let mut game = GameBoard::new(start_board());
+        assert_eq!(game.hint(), None);
+        // The g1 knight lifted, then put down on g3
+        play(&mut game, &[update(62, RawPiece::Empty)]);
+        let hint = game.hint().unwrap();
+        assert_eq!(hint.to_string(), "knight on g1 can go to f3, h3");
+        play(&mut game, &[update(46, RawPiece::WhiteKnight)]);
+        let hint = game.hint().unwrap();
+        assert_eq!(hint.to_string(), "knight can not go from g1 to g3");
+        // A black piece lifted while white is to move is no business of the trainee
+        let mut game = GameBoard::new(start_board());
+        play(&mut game, &[update(1, RawPiece::Empty)]);
+        assert_eq!(game.hint(), None);
+    }
+
+    #[test]
+    fn test_history_undo_redo() {
+        use RawPiece::*;
+        let mut game = GameBoard::new(start_board());
+        play(&mut game, &[update(52, Empty), update(36, WhitePawn)]);
+        play(&mut game, &[update(6, Empty), update(21, BlackKnight)]);
+        assert_eq!(game.san_moves().collect::<Vec<_>>(), ["e4", "Nf6"]);
+        assert_eq!(game.position_at(1), Some(&game.history()[0].position));
+        assert_eq!(game.position_at(3), None);
+
+        assert_eq!(game.undo().map(|undone| undone.san.as_str()), Some("Nf6"));
+        assert!(game.is_out_of_sync());
+        assert_eq!(game.to_move(), PieceColor::Black);
+        assert_eq!(game.redo().map(|redone| redone.san.as_str()), Some("Nf6"));
+        assert!(!game.is_out_of_sync());
+        assert_eq!(game.redo(), None);
+
+        // Taking the knight back on the board after an undo, then playing it again
+        game.undo();
+        play(&mut game, &[update(21, Empty), update(6, BlackKnight)]);
+        assert!(!game.is_out_of_sync());
+        play(&mut game, &[update(6, Empty), update(21, BlackKnight)]);
+        assert_eq!(game.moves().len(), 2);
+        assert_eq!(game.redo(), None);
+
+        // A resignation ends the game until the move before it is taken back
+        let resigned = Outcome {
+            winner: PieceColor::Black,
+            termination: Termination::Resignation,
+        };
+        game.conclude(resigned);
+        assert_eq!(game.outcome(), Some(resigned));
+        game.undo();
+        assert_eq!(game.outcome(), None);
+
+        // The arbiter's correction of the knight move the board still shows
+        let corrected = ["e2e4".to_string(), "e7e5".to_string()];
+        assert!(game.replace_moves(&corrected[..1]).is_ok());
+        assert!(game.replace_moves(&["e2e5".to_string()]).is_err());
+        assert_eq!(game.moves().len(), 1);
+        assert!(game.replace_moves(&corrected).is_ok());
+        assert_eq!(game.san_moves().collect::<Vec<_>>(), ["e4", "e5"]);
+        assert!(game.is_out_of_sync());
+    }
+
+    #[test]
+    fn test_implausible_update_and_resync() {
+        let mut game = GameBoard::new(start_board());
+        // A second white king on e4 is a garbled update
+        assert_eq!(game.apply_move(update(36, RawPiece::WhiteKing)), None);
+        assert_eq!(
+            game.needs_resync(),
+            Some(Implausible::Kings(PieceColor::White))
+        );
+        assert_eq!(game.board(), &start_board());
+
+        // The dump read again shows e4 was played meanwhile
+        let board =
+            board_from("rnbqkbnr pppppppp ........ ........ ....P... ........ PPPP.PPP RNBQKBNR");
+        assert!(matches!(game.resync(board), SyncState::Moved(_)));
+        assert_eq!(game.needs_resync(), None);
+        assert_eq!(game.moves().len(), 1);
+    }
+
+    #[test]
+    fn test_chess960_start_and_castle() {
+        use RawPiece::*;
+        // A start position with the king already on g1 and a non-rook on f1
+        let number = (0..960)
+            .find(|&n| {
+                let r = chess960::back_rank(n).unwrap();
+                r[5] != WhiteRook && r[6] == WhiteKing && r[7] == WhiteRook
+            })
+            .unwrap();
+        let rank = chess960::back_rank(number).unwrap();
+        let mut board = start_board();
+        for (file, piece) in rank.iter().enumerate() {
+            board.board[56 + file] = *piece;
+            board.board[file] =
+                RawPiece::try_from(char::from(*piece).to_ascii_lowercase()).unwrap();
+        }
+        let mut game = GameBoard::new(board);
+        assert_eq!(
+            game.start(),
+            StartPosition::Chess960 {
+                number,
+                mirror: true
+            }
+        );
+
+        // Clear f1 so the king side rook can pass over it
+        let (f1, h1) = (game.start().grid(5, 0), game.start().grid(7, 0));
+        game.position.board[rules::square(5, 0) as usize] = Empty;
+        game.board.board[f1 as usize] = Empty;
+        let state = play(&mut game, &[update(h1, Empty), update(f1, WhiteRook)]);
+        assert!(
+            matches!(state, SyncState::Moved(DetectedMove::ShortCastle(k, _)) if k.from == k.to)
+        );
+        assert!(!game.castling().white_short && !game.castling().white_long);
+        assert!(game.fen().contains(" b kq - "));
+    }
+
+    #[test]
+    fn test_recovery_plan() {
+        let mut game = GameBoard::new(start_board());
+        // Black moves out of turn
+        let state = play(
+            &mut game,
+            &[update(12, RawPiece::Empty), update(28, RawPiece::BlackPawn)],
+        );
+        assert_eq!(state, SyncState::OutOfSync);
+        assert_eq!(
+            game.recovery_plan(),
+            vec![Correction::Move {
+                piece: RawPiece::BlackPawn,
+                from: 28,
+                to: 12
+            }]
+        );
+        // Knock a knight off the board as well
+        play(&mut game, &[update(62, RawPiece::Empty)]);
+        assert_eq!(game.recovery_plan().len(), 2);
+        // Fix both and the board is back in sync
+        let state = play(
+            &mut game,
+            &[
+                update(28, RawPiece::Empty),
+                update(12, RawPiece::BlackPawn),
+                update(62, RawPiece::WhiteKnight),
+            ],
+        );
+        assert_eq!(state, SyncState::InSync);
+        assert!(!game.is_out_of_sync());
+    }
+
+    #[test]
+    fn test_resumed_on() {
+        let pgn = &pgn::parse("1. e4 { [%clk 0:04:58] } 1... e5 { [%clk 0:04:50] } *")[0];
+        let game = GameBoard::from_pgn(pgn, Arc::new(Standard)).unwrap();
+        assert_eq!(game.moves().len(), 2);
+        let shown = StartPosition::Mirror.layout(game.position());
+        let (resumed, state) = game.resumed_on(shown).unwrap();
+        assert_eq!(state, SyncState::InSync);
+        assert_eq!(resumed.fen(), game.fen());
+        // Nf3 was played while the driver was down
+        let after = game.position().play(
+            &game
+                .position()
+                .legal_moves()
+                .into_iter()
+                .find(|ply| ply.uci() == "g1f3")
+                .unwrap(),
+        );
+        let (resumed, state) = game
+            .resumed_on(StartPosition::Mirror.layout(&after))
+            .unwrap();
+        assert!(matches!(state, SyncState::Moved(_)));
+        assert_eq!(resumed.moves().len(), 3);
+        // A knocked over pawn is put right, pieces set up again are a new game
+        let mut knocked = shown;
+        knocked.board
+            [StartPosition::Mirror.square_grid(rules::parse_square("a2").unwrap()) as usize] =
+            RawPiece::Empty;
+        assert_eq!(
+            game.resumed_on(knocked).map(|(_, state)| state),
+            Some(SyncState::OutOfSync)
+        );
+        assert!(game.resumed_on(start_board()).is_none());
+    }
+
+    #[test]
+    fn test_detection_states() {
+        use RawPiece::*;
+        let board =
+            board_from("....k... P....... ........ ........ ........ ........ ........ ....K...");
+        let mut game = GameBoard::new(board);
+        assert_eq!(game.detection_state(), DetectionState::Idle);
+        play(&mut game, &[update(8, Empty)]);
+        assert_eq!(game.detection_state(), DetectionState::PieceLifted);
+        play(&mut game, &[update(0, WhitePawn)]);
+        assert_eq!(
+            game.detection_state(),
+            DetectionState::AwaitingPromotionPiece
+        );
+        play(&mut game, &[update(0, Empty), update(0, WhiteQueen)]);
+        assert_eq!(game.detection_state(), DetectionState::Idle);
+        play(&mut game, &[update(16, WhiteRook)]);
+        assert_eq!(game.detection_state(), DetectionState::OutOfSync);
+
+        let diagram = DetectionState::diagram();
+        assert!(diagram.starts_with("digraph detection {"));
+        assert!(diagram.contains("PieceLifted -> AwaitingPromotionPiece"));
+    }
+
+    #[test]
+    fn test_missed_updates() {
+        let after = |moves: &[&str]| {
+            let mut position = Position::starting();
+            for uci in moves {
+                let ply = position
+                    .pseudo_legal_moves(&rules::PROMOTIONS)
+                    .into_iter()
+                    .find(|ply| ply.uci() == *uci);
+                position = position.play(&ply.unwrap());
+            }
+            grid_board(&position)
+        };
+        // Every update of 1. e4 e5 lost, the board read again shows both moves
+        let mut game = GameBoard::new(start_board());
+        assert!(matches!(
+            game.resync(after(&["e2e4", "e7e5"])),
+            SyncState::Moved(_)
+        ));
+        assert!(matches!(game.sync(), SyncState::Moved(_)));
+        assert_eq!(game.sync(), SyncState::InSync);
+        assert_eq!(game.san_moves().collect::<Vec<_>>(), ["e4", "e5"]);
+        // Three moves on are too many to tell
+        let state = game.resync(after(&["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"]));
+        assert!(!matches!(state, SyncState::Moved(_)));
+        assert_eq!(game.moves().len(), 2);
+
+        // 2... Bb4+ ignored by 3. a3 Bxa3, found only when moves leaving the king in check
+        // count
+        for legality in [Legality::Strict, Legality::Lenient] {
+            let mut game = GameBoardBuilder::new()
+                .legality(legality)
+                .build(start_board());
+            let mut moves = vec![];
+            for pair in [["d2d4", "e7e5"], ["e1d2", "f8b4"], ["a2a3", "b4a3"]] {
+                moves.extend(pair);
+                if !matches!(game.resync(after(&moves)), SyncState::Moved(_)) {
+                    break;
+                }
+                assert!(matches!(game.sync(), SyncState::Moved(_)));
+            }
+            let expected = match legality {
+                Legality::Strict => 4,
+                Legality::Lenient => 6,
+            };
+            assert_eq!(game.moves().len(), expected);
+        }
+    }
+
+    #[test]
+    fn test_apply_move_reports_change() {
+        let mut game = GameBoard::new(ChessBoard {
+            board: [RawPiece::Empty; 64],
+        });
+        let mv = ChessMove {
+            grid: 12,
+            piece: RawPiece::WhiteKnight,
+        };
+        let change = game.apply_move(mv).unwrap();
+        assert_eq!(change.before, RawPiece::Empty);
+        assert_eq!(change.after, RawPiece::WhiteKnight);
+        assert_eq!(game.board().board[12], RawPiece::WhiteKnight);
+        assert_eq!(game.apply_move(mv), None);
+    }
+
+    /// The board a position shows, in the DGT documented layout
+    fn grid_board(position: &Position) -> ChessBoard {
+        let mut board = ChessBoard {
+            board: [RawPiece::Empty; 64],
+        };
+        for (sq, piece) in position.board.iter().enumerate() {
+            board.board[StartPosition::Mirror.square_grid(sq as u8) as usize] = *piece;
+        }
+        board
+    }
+
+    /// Field updates a player could produce making the move from `before` to `after`:
+    /// every piece lifted before anything is put down, optionally in reverse order,
+    /// and optionally with the moving piece set down on a free square on the way
+    fn updates(
+        before: &ChessBoard,
+        after: &ChessBoard,
+        reverse: bool,
+        slide: Option<Index>,
+    ) -> Vec<ChessMove> {
+        let changed: Vec<u8> = (0..64u8)
+            .filter(|&grid| before.board[grid as usize] != after.board[grid as usize])
+            .collect();
+        let mut lifts: Vec<ChessMove> = changed
+            .iter()
+            .filter(|&&grid| before.board[grid as usize] != RawPiece::Empty)
+            .map(|&grid| update(grid, RawPiece::Empty))
+            .collect();
+        let mut places: Vec<ChessMove> = changed
+            .iter()
+            .filter(|&&grid| after.board[grid as usize] != RawPiece::Empty)
+            .map(|&grid| update(grid, after.board[grid as usize]))
+            .collect();
+        if reverse {
+            lifts.reverse();
+            places.reverse();
+        }
+        if let Some(slide) = slide {
+            let free: Vec<u8> = (0..64u8)
+                .filter(|&grid| {
+                    before.board[grid as usize] == RawPiece::Empty
+                        && after.board[grid as usize] == RawPiece::Empty
+                })
+                .collect();
+            let grid = free[slide.index(free.len())];
+            lifts.push(update(grid, places[0].piece));
+            lifts.push(update(grid, RawPiece::Empty));
+        }
+        lifts.extend(places);
+        lifts
+    }
+
+    proptest! {
+        #[test]
+        fn test_detect_move_reconstructs_games(
+            plies in proptest::collection::vec(
+                (
+                    any::<Index>(),
+                    any::<bool>(),
+                    proptest::option::of(any::<Index>()),
+                ),
+                1..80,
+            )
+        ) {
+            let mut position = Position::starting();
+            for (choice, reverse, slide) in plies {
+                let legal = position.legal_moves();
+                if legal.is_empty() {
+                    break;
+                }
+                let ply = legal[choice.index(legal.len())];
+                let next = position.play(&ply);
+                let (before, after) = (grid_board(&position), grid_board(&next));
+                let detected = detect_move(&before, &updates(&before, &after, reverse, slide));
+                let Some(detected) = detected else {
+                    panic!("{} not detected in {}", ply.uci(), position.to_fen());
+                };
+                let grid = |sq| StartPosition::Mirror.square_grid(sq);
+                let main = detected.main_move();
+                prop_assert_eq!((main.from, main.to), (grid(ply.from), grid(ply.to)));
+                prop_assert_eq!(main.piece, ply.piece);
+                let kind_matches = match (ply.kind, detected) {
+                    (PlyKind::Castle { .. }, DetectedMove::ShortCastle(..))
+                    | (PlyKind::Castle { .. }, DetectedMove::LongCastle(..))
+                    | (PlyKind::EnPassant, DetectedMove::EnPassant(..)) => true,
+                    (PlyKind::Promotion(piece), DetectedMove::Promotion(_, placed))
+                    | (PlyKind::Promotion(piece), DetectedMove::PromotionCapture(_, _, placed)) => {
+                        piece == placed
+                    }
+                    (PlyKind::Normal, DetectedMove::SimpleCapture(..)) => ply.is_capture(),
+                    (PlyKind::Normal, DetectedMove::SimpleMove(_)) => !ply.is_capture(),
+                    _ => false,
+                };
+                prop_assert!(kind_matches, "{} detected as {:?}", ply.uci(), detected);
+                position = next;
+            }
+        }
+    }
+}
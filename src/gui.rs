@@ -0,0 +1,446 @@
+//! The board as an engine inside a chess GUI such as Arena or Cute Chess. The GUI talks
+//! XBoard or UCI to jackolope as it would to an engine: the moves it sends are made on
+//! the board by hand, and the moves the player at the board makes go back to it as the
+//! moves of the engine. `Bridge` keeps the game the GUI knows and says what to do,
+//! without doing any IO itself.
+
+use crate::protocol::PieceColor;
+use crate::rules::{square_name, Ply, PlyKind, Position};
+
+/// The engine protocols a GUI can speak
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    XBoard,
+    Uci,
+}
+
+/// What the GUI wants done
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// A line for the GUI
+    Send(String),
+    /// The GUI has another game, from `start` with `moves` in UCI notation, which the
+    /// board has to be set up to show
+    SetUp {
+        start: Position,
+        moves: Vec<String>,
+    },
+    /// The GUI moved, in UCI notation, the move is to be made on the board
+    Copy(String),
+    /// The GUI waits for the player at the board to move
+    Go,
+    Quit,
+}
+
+/// The game as the GUI knows it, and whose move it waits for
+#[derive(Debug, Clone)]
+pub struct Bridge {
+    protocol: Protocol,
+    start: Position,
+    position: Position,
+    /// Moves since `start` in UCI notation, castling as the king's two square move
+    moves: Vec<String>,
+    /// The GUI waits for a move from the board
+    thinking: bool,
+    /// XBoard force mode, the board plays neither side
+    force: bool,
+    /// The side the board plays under XBoard
+    side: PieceColor,
+    /// Castling written the way the protocol has it for Chess960
+    chess960: bool,
+}
+
+impl Default for Bridge {
+    fn default() -> Self {
+        Bridge::new()
+    }
+}
+
+impl Bridge {
+    pub fn new() -> Self {
+        Bridge {
+            protocol: Protocol::XBoard,
+            start: Position::starting(),
+            position: Position::starting(),
+            moves: Vec::new(),
+            thinking: false,
+            force: false,
+            side: PieceColor::Black,
+            chess960: false,
+        }
+    }
+
+    /// The protocol the GUI speaks, XBoard until it says `uci`
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Position the game of the GUI starts from
+    pub fn start(&self) -> &Position {
+        &self.start
+    }
+
+    /// Moves of the game of the GUI in UCI notation
+    pub fn moves(&self) -> &[String] {
+        &self.moves
+    }
+
+    /// Whether the GUI waits for the player at the board to move
+    pub fn is_thinking(&self) -> bool {
+        self.thinking
+    }
+
+    /// Handle a line from the GUI
+    pub fn command(&mut self, line: &str) -> Vec<Action> {
+        let mut tokens = line.split_whitespace();
+        let Some(command) = tokens.next() else {
+            return Vec::new();
+        };
+        let rest: Vec<&str> = tokens.collect();
+        match (self.protocol, command) {
+            (_, "uci") => {
+                self.protocol = Protocol::Uci;
+                [
+                    "id name jackolope",
+                    "id author the jackolope developers",
+                    "option name UCI_Chess960 type check default false",
+                    "uciok",
+                ]
+                .map(|line| Action::Send(line.to_string()))
+                .to_vec()
+            }
+            (_, "xboard") => {
+                self.protocol = Protocol::XBoard;
+                Vec::new()
+            }
+            (_, "quit") => vec![Action::Quit],
+            (Protocol::Uci, "isready") => vec![Action::Send("readyok".to_string())],
+            (Protocol::Uci, "setoption") => {
+                if let [_, name, _, value] = rest[..] {
+                    if name.eq_ignore_ascii_case("UCI_Chess960") {
+                        self.chess960 = value == "true";
+                    }
+                }
+                Vec::new()
+            }
+            (Protocol::Uci, "ucinewgame") => {
+                self.thinking = false;
+                Vec::new()
+            }
+            (Protocol::Uci, "position") => match self.uci_position(&rest) {
+                Ok(actions) => actions,
+                Err(e) => vec![Action::Send(format!("info string {}", e))],
+            },
+            (Protocol::Uci, "go") => {
+                self.thinking = true;
+                vec![Action::Go]
+            }
+            (Protocol::Uci, "stop") if self.thinking => {
+                // The player has not moved, a null move lets the GUI carry on
+                self.thinking = false;
+                vec![Action::Send("bestmove 0000".to_string())]
+            }
+            (Protocol::Uci, _) => Vec::new(),
+            (Protocol::XBoard, "protover") => vec![Action::Send(
+                "feature myname=\"jackolope\" usermove=1 setboard=1 ping=1 sigint=0 \
+                 sigterm=0 colors=0 analyze=0 variants=\"normal,fischerandom\" done=1"
+                    .to_string(),
+            )],
+            (Protocol::XBoard, "new") => {
+                self.force = false;
+                self.thinking = false;
+                self.side = PieceColor::Black;
+                self.chess960 = false;
+                self.set_game(Position::starting(), Vec::new())
+            }
+            (Protocol::XBoard, "variant") => {
+                self.chess960 = rest.first() == Some(&"fischerandom");
+                Vec::new()
+            }
+            (Protocol::XBoard, "setboard") => match Position::from_fen(&rest.join(" ")) {
+                Some(start) => {
+                    self.thinking = false;
+                    self.set_game(start, Vec::new())
+                }
+                None => vec![Action::Send("tellusererror Illegal position".to_string())],
+            },
+            (Protocol::XBoard, "usermove") => match rest.first() {
+                Some(text) => self.xboard_move(text),
+                None => Vec::new(),
+            },
+            (Protocol::XBoard, "go") => {
+                self.force = false;
+                self.side = self.position.to_move;
+                self.think()
+            }
+            (Protocol::XBoard, "playother") => {
+                self.force = false;
+                self.side = self.position.to_move.opposite();
+                self.thinking = false;
+                Vec::new()
+            }
+            (Protocol::XBoard, "force") => {
+                self.force = true;
+                self.thinking = false;
+                Vec::new()
+            }
+            (Protocol::XBoard, "result") => {
+                self.thinking = false;
+                Vec::new()
+            }
+            (Protocol::XBoard, "undo" | "remove") => {
+                let count = if command == "undo" { 1 } else { 2 };
+                let mut moves = self.moves.clone();
+                moves.truncate(moves.len().saturating_sub(count));
+                self.thinking = false;
+                self.set_game(self.start.clone(), moves)
+            }
+            (Protocol::XBoard, "ping") => {
+                vec![Action::Send(format!(
+                    "pong {}",
+                    rest.first().unwrap_or(&"")
+                ))]
+            }
+            (
+                Protocol::XBoard,
+                "accepted" | "rejected" | "random" | "level" | "st" | "sd" | "time" | "otim"
+                | "post" | "nopost" | "hard" | "easy" | "computer" | "name" | "rating" | "?" | "."
+                | "draw",
+            ) => Vec::new(),
+            // GUIs that ignore usermove=1 send moves on their own
+            (Protocol::XBoard, text) if self.find(text).is_some() => self.xboard_move(text),
+            (Protocol::XBoard, _) => {
+                vec![Action::Send(format!(
+                    "Error (unknown command): {}",
+                    command
+                ))]
+            }
+        }
+    }
+
+    /// The player at the board made `ply`. Returns what to tell the GUI, or why the move
+    /// is not one it waits for.
+    pub fn board_move(&mut self, ply: &Ply) -> Result<Vec<Action>, String> {
+        if !self.thinking {
+            return Err("the GUI does not wait for a move from the board".to_string());
+        }
+        if !self.position.legal_moves().contains(ply) {
+            return Err(format!("{} is not legal in the game of the GUI", ply.uci()));
+        }
+        let text = self.text(ply);
+        self.play(ply);
+        self.thinking = false;
+        let line = match self.protocol {
+            Protocol::XBoard => format!("move {}", text),
+            Protocol::Uci => format!("bestmove {}", text),
+        };
+        Ok(vec![Action::Send(line)])
+    }
+
+    /// `position [startpos | fen <fen>] [moves <move>...]`
+    fn uci_position(&mut self, args: &[&str]) -> Result<Vec<Action>, String> {
+        let moves_at = args.iter().position(|&arg| arg == "moves");
+        let (setup, texts) = match moves_at {
+            Some(at) => (&args[..at], &args[at + 1..]),
+            None => (args, &[][..]),
+        };
+        let start = match setup {
+            ["startpos"] => Position::starting(),
+            ["fen", fen @ ..] => Position::from_fen(&fen.join(" "))
+                .ok_or_else(|| format!("invalid FEN {}", fen.join(" ")))?,
+            _ => return Err(format!("invalid position {}", args.join(" "))),
+        };
+        let mut position = start.clone();
+        let mut moves = Vec::new();
+        for text in texts {
+            let ply = self
+                .find_in(&position, text)
+                .ok_or_else(|| format!("illegal move {} in {}", text, position.to_fen()))?;
+            moves.push(ply.uci());
+            position = position.play(&ply);
+        }
+        Ok(self.set_game(start, moves))
+    }
+
+    /// A move the GUI made under XBoard, which the board answers if it plays the side
+    /// to move after it
+    fn xboard_move(&mut self, text: &str) -> Vec<Action> {
+        let Some(ply) = self.find(text) else {
+            return vec![Action::Send(format!("Illegal move: {}", text))];
+        };
+        self.play(&ply);
+        let mut actions = vec![Action::Copy(ply.uci())];
+        actions.extend(self.think());
+        actions
+    }
+
+    /// Wait for the board if it plays the side to move
+    fn think(&mut self) -> Vec<Action> {
+        self.thinking = !self.force && self.position.to_move == self.side;
+        match self.thinking {
+            true => vec![Action::Go],
+            false => Vec::new(),
+        }
+    }
+
+    /// Take over the game of the GUI. One move more than the game so far is to be
+    /// copied, anything else has the board set up again.
+    fn set_game(&mut self, start: Position, moves: Vec<String>) -> Vec<Action> {
+        let mut position = start.clone();
+        for uci in &moves {
+            let Some(ply) = position
+                .legal_moves()
+                .into_iter()
+                .find(|ply| ply.uci() == *uci)
+            else {
+                break;
+            };
+            position = position.play(&ply);
+        }
+        let same_start = start == self.start;
+        let action = match moves.split_last() {
+            _ if same_start && moves == self.moves => None,
+            Some((last, before)) if same_start && before == self.moves => {
+                Some(Action::Copy(last.clone()))
+            }
+            _ => Some(Action::SetUp {
+                start: start.clone(),
+                moves: moves.clone(),
+            }),
+        };
+        self.start = start;
+        self.position = position;
+        self.moves = moves;
+        action.into_iter().collect()
+    }
+
+    fn play(&mut self, ply: &Ply) {
+        self.position = self.position.play(ply);
+        self.moves.push(ply.uci());
+    }
+
+    /// The legal move written as `text` in the protocol of the GUI
+    fn find(&self, text: &str) -> Option<Ply> {
+        self.find_in(&self.position, text)
+    }
+
+    fn find_in(&self, position: &Position, text: &str) -> Option<Ply> {
+        position
+            .legal_moves()
+            .into_iter()
+            .find(|ply| self.text(ply) == text)
+    }
+
+    /// `ply` in the protocol of the GUI. For Chess960 UCI has castling as the king
+    /// taking its own rook and XBoard as O-O, which also tell apart a castling king
+    /// that moves one square or none from an ordinary king move.
+    fn text(&self, ply: &Ply) -> String {
+        match ply.kind {
+            PlyKind::Castle { rook_from, .. } if self.chess960 => match self.protocol {
+                Protocol::Uci => format!("{}{}", square_name(ply.from), square_name(rook_from)),
+                Protocol::XBoard if rook_from > ply.from => "O-O".to_string(),
+                Protocol::XBoard => "O-O-O".to_string(),
+            },
+            _ => ply.uci(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent(actions: &[Action]) -> Vec<&str> {
+        actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::Send(line) => Some(line.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The legal move of `position` written as `uci`, castling the way the board has it
+    fn ply(position: &Position, uci: &str) -> Ply {
+        let legal = position.legal_moves();
+        let mut found = legal.iter().filter(|ply| ply.uci() == uci);
+        let first = *found.next().unwrap();
+        found
+            .find(|ply| matches!(ply.kind, PlyKind::Castle { .. }))
+            .copied()
+            .unwrap_or(first)
+    }
+
+    #[test]
+    fn test_bridge() {
+        // XBoard: the GUI plays white, the board black
+        let mut bridge = Bridge::new();
+        assert!(bridge.command("xboard").is_empty());
+        assert!(sent(&bridge.command("protover 2"))[0].ends_with("done=1"));
+        assert!(bridge.command("new").is_empty());
+        let e5 = ply(
+            &Position::starting().play(&ply(&Position::starting(), "e2e4")),
+            "e7e5",
+        );
+        assert!(bridge.board_move(&e5).is_err());
+        assert_eq!(
+            bridge.command("usermove e2e4"),
+            [Action::Copy("e2e4".to_string()), Action::Go]
+        );
+        assert_eq!(sent(&bridge.board_move(&e5).unwrap()), ["move e7e5"]);
+        assert_eq!(
+            sent(&bridge.command("usermove e2e5")),
+            ["Illegal move: e2e5"]
+        );
+        assert_eq!(sent(&bridge.command("ping 7")), ["pong 7"]);
+        assert_eq!(
+            bridge.command("undo"),
+            [Action::SetUp {
+                start: Position::starting(),
+                moves: vec!["e2e4".to_string()]
+            }]
+        );
+        assert!(!bridge.is_thinking());
+        assert_eq!(bridge.command("go"), [Action::Go]);
+
+        // UCI, with the GUI sending the whole game every move
+        let mut bridge = Bridge::new();
+        assert_eq!(sent(&bridge.command("uci")).last(), Some(&"uciok"));
+        assert_eq!(bridge.protocol(), Protocol::Uci);
+        assert_eq!(sent(&bridge.command("isready")), ["readyok"]);
+        assert!(bridge.command("position startpos").is_empty());
+        assert_eq!(
+            bridge.command("position startpos moves e2e4"),
+            [Action::Copy("e2e4".to_string())]
+        );
+        assert_eq!(bridge.command("go wtime 60000 btime 60000"), [Action::Go]);
+        let c5 = ply(
+            &Position::starting().play(&ply(&Position::starting(), "e2e4")),
+            "c7c5",
+        );
+        assert_eq!(sent(&bridge.board_move(&c5).unwrap()), ["bestmove c7c5"]);
+        assert!(bridge
+            .command("position startpos moves e2e4 c7c5")
+            .is_empty());
+        assert!(matches!(
+            &bridge.command("position startpos moves d2d4")[..],
+            [Action::SetUp { moves, .. }] if moves == &["d2d4".to_string()]
+        ));
+
+        // Chess960 castling is the king taking its rook, not the king stepping aside
+        bridge.command("setoption name UCI_Chess960 value true");
+        let fen = "rk6/8/8/8/8/8/8/RK6 w Aa - 0 1";
+        bridge.command(&format!("position fen {}", fen));
+        bridge.command("go");
+        let castle = ply(&Position::from_fen(fen).unwrap(), "b1c1");
+        assert_eq!(
+            sent(&bridge.board_move(&castle).unwrap()),
+            ["bestmove b1a1"]
+        );
+        assert!(bridge
+            .command(&format!("position fen {} moves b1a1", fen))
+            .is_empty());
+        bridge.command("go");
+        assert_eq!(sent(&bridge.command("stop")), ["bestmove 0000"]);
+    }
+}
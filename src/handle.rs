@@ -0,0 +1,346 @@
+//! A board shared between threads. One thread owns the board, reads it and carries out
+//! what the `BoardHandle`s ask for between reads, so a web server, a relay and a UI can
+//! all talk to the same board without taking turns on the serial port themselves.
+
+use crate::board::{self, BoardSnapshot, ConnectionStatus, ElectronicBoard};
+use crate::clock::ClockCommand;
+use crate::protocol::{RawHandler, Response};
+use crate::queue::{self, Coalesce, Overflow, Publisher, Subscription};
+use std::sync::mpsc::{self, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::{debug, warn};
+
+type Job = Box<dyn FnOnce(&mut dyn ElectronicBoard) + Send>;
+/// Told every message from the board, dropped once it returns false
+type Listener = Box<dyn FnMut(&Response) -> bool + Send>;
+
+/// Only what the board looks like counts: a dump stands in for every change before it,
+/// and the last change of a square or the clock for the earlier ones
+impl Coalesce for Response {
+    fn supersedes(&self, older: &Self) -> bool {
+        match (self, older) {
+            (
+                Response::BoardDump(_) | Response::ImplausibleDump { .. },
+                Response::BoardDump(_)
+                | Response::ImplausibleDump { .. }
+                | Response::PartialDump { .. }
+                | Response::FieldUpdate(_),
+            ) => true,
+            (Response::FieldUpdate(new), Response::FieldUpdate(old)) => new.grid == old.grid,
+            (Response::BWTime { .. }, Response::BWTime { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A snapshot holds everything the ones before it did
+impl Coalesce for BoardSnapshot {
+    fn supersedes(&self, _older: &Self) -> bool {
+        true
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    snapshot: Mutex<BoardSnapshot>,
+    listeners: Mutex<Vec<Listener>>,
+    /// Subscribers to the latest snapshot only
+    watchers: Mutex<Vec<Publisher<BoardSnapshot>>>,
+}
+
+impl Shared {
+    /// Hand the snapshot to the watchers, and let them go once the board is gone
+    fn publish(&self, snapshot: &BoardSnapshot) {
+        let mut watchers = self.watchers.lock().unwrap();
+        watchers.retain(|watcher| watcher.push(snapshot.clone()).is_ok());
+        if snapshot.connection == ConnectionStatus::Disconnected {
+            watchers.clear();
+        }
+    }
+}
+
+/// Cheap to clone, every clone reaches the same board. The board is put back into idle
+/// mode and closed once the last handle is dropped.
+#[derive(Clone)]
+pub struct BoardHandle {
+    jobs: Sender<Job>,
+    shared: Arc<Shared>,
+}
+
+fn gone() -> board::Error {
+    "the board is gone".into()
+}
+
+impl BoardHandle {
+    /// Hand `board`, already sending updates, to a thread named `name` that reads it
+    pub fn spawn(
+        name: impl Into<String>,
+        board: Box<dyn ElectronicBoard>,
+    ) -> std::io::Result<BoardHandle> {
+        BoardHandle::start(name.into(), board, Vec::new())
+    }
+
+    /// Like `spawn`, with `listener` subscribed before the first message is read so it
+    /// misses none
+    pub fn spawn_with(
+        name: impl Into<String>,
+        board: Box<dyn ElectronicBoard>,
+        listener: impl FnMut(&Response) -> bool + Send + 'static,
+    ) -> std::io::Result<BoardHandle> {
+        BoardHandle::start(name.into(), board, vec![Box::new(listener)])
+    }
+
+    fn start(
+        name: String,
+        mut board: Box<dyn ElectronicBoard>,
+        listeners: Vec<Listener>,
+    ) -> std::io::Result<BoardHandle> {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let shared = Arc::new(Shared {
+            listeners: Mutex::new(listeners),
+            ..Shared::default()
+        });
+        let state = shared.clone();
+        thread::Builder::new().name(name.clone()).spawn(move || loop {
+            loop {
+                match queue.try_recv() {
+                    Ok(job) => job(board.as_mut()),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        if let Err(e) = board.stop_updates() {
+                            debug!(board = %name, error = %e, "failed to put the board to idle");
+                        }
+                        state.watchers.lock().unwrap().clear();
+                        return;
+                    }
+                }
+            }
+            match board.next_responses() {
+                Ok(responses) => {
+                    // One snapshot for the whole burst, every message for the listeners
+                    let snapshot = {
+                        let mut snapshot = state.snapshot.lock().unwrap();
+                        responses.iter().for_each(|response| snapshot.take_in(response));
+                        snapshot.clone()
+                    };
+                    state.publish(&snapshot);
+                    let mut listeners = state.listeners.lock().unwrap();
+                    for response in &responses {
+                        listeners.retain_mut(|listener| listener(response));
+                    }
+                }
+                Err(e) if board::is_disconnect(&e) => {
+                    warn!(board = %name, error = %e, "board disconnected");
+                    let snapshot = {
+                        let mut snapshot = state.snapshot.lock().unwrap();
+                        snapshot.connection = ConnectionStatus::Disconnected;
+                        snapshot.clone()
+                    };
+                    state.publish(&snapshot);
+                    return;
+                }
+                Err(e) => debug!(board = %name, error = %e, "no update"),
+            }
+        })?;
+        Ok(BoardHandle { jobs, shared })
+    }
+
+    /// Everything known about the board, taken in one go so the parts agree
+    pub fn snapshot(&self) -> BoardSnapshot {
+        self.shared.snapshot.lock().unwrap().clone()
+    }
+
+    /// Have `listener` told every message from the board from now on, on the thread
+    /// reading it, until it returns false
+    pub fn subscribe(&self, listener: impl FnMut(&Response) -> bool + Send + 'static) {
+        self.shared
+            .listeners
+            .lock()
+            .unwrap()
+            .push(Box::new(listener));
+    }
+
+    /// Have every message from the board from now on queued for reading on another
+    /// thread. Once `capacity` messages are waiting, `overflow` decides what gives, so a
+    /// slow reader never holds up the board.
+    pub fn subscribe_queue(&self, capacity: usize, overflow: Overflow) -> Subscription<Response> {
+        let (publisher, subscription) = queue::bounded(capacity, overflow);
+        self.subscribe(move |response| publisher.push(response.clone()).is_ok());
+        subscription
+    }
+
+    /// Follow the board without caring for every message: reading gives the snapshot as
+    /// it is now, or waits for the next change. Whatever changed in between is folded
+    /// into it, which suits an overlay or a retained MQTT topic. Reading ends once the
+    /// board is gone and its last snapshot was read.
+    pub fn subscribe_latest(&self) -> Subscription<BoardSnapshot> {
+        let (publisher, subscription) = queue::bounded(1, Overflow::Coalesce);
+        let mut watchers = self.shared.watchers.lock().unwrap();
+        let snapshot = self.snapshot();
+        let _ = publisher.push(snapshot.clone());
+        if snapshot.connection != ConnectionStatus::Disconnected {
+            watchers.push(publisher);
+        }
+        subscription
+    }
+
+    /// Do `job` with the board once the message being read is in, without waiting for it
+    pub fn submit(
+        &self,
+        job: impl FnOnce(&mut dyn ElectronicBoard) + Send + 'static,
+    ) -> Result<(), board::Error> {
+        self.jobs.send(Box::new(job)).map_err(|_| gone())
+    }
+
+    /// Do `job` with the board once the message being read is in, and wait for its result
+    pub fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut dyn ElectronicBoard) -> T + Send + 'static,
+    ) -> Result<T, board::Error> {
+        let (tx, rx) = mpsc::channel();
+        self.submit(move |board| {
+            let _ = tx.send(job(board));
+        })?;
+        rx.recv().map_err(|_| gone())
+    }
+
+    /// Forward `command` to the clock, false if the board has no way to
+    pub fn send_clock(&self, command: ClockCommand) -> Result<bool, board::Error> {
+        self.run(move |board| board.send_clock(&command).map_err(|e| e.to_string()))?
+            .map_err(Into::into)
+    }
+
+    /// Hand frames of `msg_type` to `handler` on the thread reading the board instead of
+    /// decoding them, see `Framer::register_raw_handler`
+    pub fn register_raw_handler(
+        &self,
+        msg_type: u8,
+        handler: impl Fn(&[u8]) + Send + Sync + 'static,
+    ) -> Result<(), board::Error> {
+        let handler: RawHandler = Arc::new(handler);
+        self.run(move |board| board.register_raw_handler(msg_type, handler))
+    }
+
+    /// Ask for the position, it arrives as a board dump to the listeners
+    pub fn request_board(&self) -> Result<bool, board::Error> {
+        self.run(|board| board.request_board().map_err(|e| e.to_string()))?
+            .map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{ChessBoard, RawPiece};
+    use std::io;
+    use std::time::Duration;
+
+    const EMPTY: ChessBoard = ChessBoard {
+        board: [RawPiece::Empty; 64],
+    };
+
+    /// Answers board requests with an empty board, quiet otherwise until `unplugged`
+    struct Quiet {
+        dumps: usize,
+        clock_texts: Arc<Mutex<Vec<String>>>,
+        unplugged: Arc<Mutex<bool>>,
+    }
+
+    impl ElectronicBoard for Quiet {
+        fn name(&self) -> &str {
+            "quiet"
+        }
+
+        fn board(&mut self) -> Result<ChessBoard, board::Error> {
+            Ok(EMPTY)
+        }
+
+        fn start_updates(&mut self) -> Result<(), board::Error> {
+            Ok(())
+        }
+
+        fn request_board(&mut self) -> Result<bool, board::Error> {
+            self.dumps += 1;
+            Ok(true)
+        }
+
+        fn next_response(&mut self) -> Result<Response, board::Error> {
+            if *self.unplugged.lock().unwrap() {
+                return Err(io::Error::from(io::ErrorKind::BrokenPipe).into());
+            }
+            if self.dumps > 0 {
+                self.dumps -= 1;
+                return Ok(Response::BoardDump(EMPTY));
+            }
+            thread::sleep(Duration::from_millis(5));
+            Err(io::Error::from(io::ErrorKind::TimedOut).into())
+        }
+
+        fn send_clock(&mut self, command: &ClockCommand) -> Result<bool, board::Error> {
+            if let ClockCommand::Text { text, .. } = command {
+                self.clock_texts.lock().unwrap().push(text.clone());
+            }
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let clock_texts = Arc::default();
+        let unplugged = Arc::new(Mutex::new(false));
+        let board = Quiet {
+            dumps: 0,
+            clock_texts: Arc::clone(&clock_texts),
+            unplugged: unplugged.clone(),
+        };
+        let handle = BoardHandle::spawn("quiet", Box::new(board)).unwrap();
+        // Listeners are told in the order they subscribed, the queue has every message
+        // by the time the channel has it
+        let dumps = handle.subscribe_queue(1, Overflow::Coalesce);
+        let (tx, rx) = mpsc::channel();
+        handle.subscribe(move |response| tx.send(response.clone()).is_ok());
+        assert!(handle.request_board().unwrap());
+        assert!(matches!(rx.recv(), Ok(Response::BoardDump(_))));
+        assert_eq!(handle.snapshot().board, Some(EMPTY));
+        // The second dump takes the place of the first, unread one
+        handle.request_board().unwrap();
+        assert!(matches!(rx.recv(), Ok(Response::BoardDump(_))));
+        assert!(matches!(dumps.recv(), Ok(Response::BoardDump(_))));
+        assert_eq!(dumps.dropped(), 1);
+
+        // Only the snapshot after the last of them is waiting
+        let latest = handle.subscribe_latest();
+        handle.request_board().unwrap();
+        handle.request_board().unwrap();
+        rx.recv().unwrap();
+        rx.recv().unwrap();
+        assert_eq!(latest.recv(), Ok(handle.snapshot()));
+        assert_eq!(latest.recv_timeout(Duration::from_millis(20)), Ok(None));
+
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    handle
+                        .send_clock(ClockCommand::Text {
+                            text: format!("hi {}", i),
+                            beep: false,
+                        })
+                        .unwrap()
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert!(thread.join().unwrap());
+        }
+        assert_eq!(clock_texts.lock().unwrap().len(), 4);
+        assert_eq!(handle.snapshot().connection, ConnectionStatus::Connected);
+
+        *unplugged.lock().unwrap() = true;
+        let last = latest.recv().unwrap();
+        assert_eq!(last.connection, ConnectionStatus::Disconnected);
+        assert_eq!(latest.recv(), Err(queue::QueueError::Closed));
+        assert!(handle.request_board().is_err());
+    }
+}
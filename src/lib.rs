@@ -0,0 +1,76 @@
+//! Driver for DGT and compatible electronic chess boards: `protocol` speaks the wire
+//! format, `board` and `handle` talk to a board, `game` turns what it reports into
+//! moves, and `relay` passes the game on. `prelude` has the types most programs need.
+
+pub mod arbiter;
+pub mod bitboard;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod board;
+pub mod chess960;
+pub mod clock;
+pub mod config;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod daemon;
+#[cfg(all(feature = "database", not(target_arch = "wasm32")))]
+pub mod database;
+pub mod dedup;
+pub mod eco;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod emulator;
+pub mod eval;
+pub mod event;
+pub mod fen;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+pub mod game;
+pub mod gui;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod handle;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod lichess;
+pub mod locale;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod manager;
+pub mod matchplay;
+pub mod metrics;
+#[cfg(all(feature = "millennium", not(target_arch = "wasm32")))]
+pub mod millennium;
+#[cfg(all(feature = "mqtt", not(target_arch = "wasm32")))]
+pub mod mqtt;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod netplay;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod overlay;
+pub mod pacing;
+pub mod pairing;
+pub mod pgn;
+pub mod prelude;
+pub mod protocol;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod puzzle;
+pub mod queue;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod relay;
+pub mod render;
+pub mod repl;
+pub mod report;
+pub mod rotate;
+pub mod rules;
+pub mod session;
+pub mod setup;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod simulator;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod speech;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod status;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod stream;
+pub mod timing;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod transport;
+pub mod uci;
+pub mod variant;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod zobrist;
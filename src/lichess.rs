@@ -0,0 +1,187 @@
+use serde::Deserialize;
+use std::fmt;
+use std::io::Read;
+
+pub const DEFAULT_BASE_URL: &str = "https://lichess.org";
+
+#[derive(Debug)]
+pub enum Error {
+    Http(Box<ureq::Error>),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "lichess request failed: {}", e),
+            Error::Io(e) => write!(f, "lichess response unreadable: {}", e),
+            Error::Json(e) => write!(f, "unexpected lichess response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ureq::Error> for Error {
+    fn from(e: ureq::Error) -> Self {
+        Error::Http(Box::new(e))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// A Lichess training puzzle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Puzzle {
+    pub id: String,
+    pub rating: Option<u32>,
+    pub themes: Vec<String>,
+    /// Moves of the source game in SAN, up to and past the puzzle position
+    pub game_moves: Vec<String>,
+    /// The puzzle position is reached after playing this many plies plus one
+    pub initial_ply: usize,
+    /// Solution in UCI notation, the solver's moves alternating with the replies
+    pub solution: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct PuzzleResponse {
+    game: PuzzleGame,
+    puzzle: PuzzleData,
+}
+
+#[derive(Deserialize)]
+struct PuzzleGame {
+    pgn: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PuzzleData {
+    id: String,
+    rating: Option<u32>,
+    #[serde(default)]
+    themes: Vec<String>,
+    initial_ply: usize,
+    solution: Vec<String>,
+}
+
+/// Minimal blocking client for the public Lichess API
+pub struct Client {
+    base_url: String,
+    token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl Client {
+    /// A client for lichess.org, authenticated with a personal API token if given
+    pub fn new(token: Option<String>) -> Self {
+        Client::with_base_url(DEFAULT_BASE_URL, token)
+    }
+
+    pub fn with_base_url(base_url: &str, token: Option<String>) -> Self {
+        Client {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token,
+            agent: ureq::AgentBuilder::new()
+                .user_agent(concat!("jackolope/", env!("CARGO_PKG_VERSION")))
+                .build(),
+        }
+    }
+
+    fn get(&self, path: &str, accept: &str) -> Result<String, Error> {
+        let mut request = self
+            .agent
+            .get(&format!("{}{}", self.base_url, path))
+            .set("Accept", accept);
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        let mut body = String::new();
+        request.call()?.into_reader().read_to_string(&mut body)?;
+        Ok(body)
+    }
+
+    fn post(&self, path: &str, body: &str) -> Result<String, Error> {
+        let mut request = self.agent.post(&format!("{}{}", self.base_url, path));
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        let mut response = String::new();
+        request
+            .send_string(body)?
+            .into_reader()
+            .read_to_string(&mut response)?;
+        Ok(response)
+    }
+
+    /// Replace the games of a broadcast round with `pgn`, needs a token with the
+    /// study:write scope
+    pub fn push_broadcast(&self, round: &str, pgn: &str) -> Result<(), Error> {
+        self.post(&format!("/api/broadcast/round/{}/push", round), pgn)?;
+        Ok(())
+    }
+
+    /// Fetch a puzzle by id
+    pub fn puzzle(&self, id: &str) -> Result<Puzzle, Error> {
+        parse_puzzle(&self.get(&format!("/api/puzzle/{}", id), "application/json")?)
+    }
+
+    /// Fetch the puzzle of the day
+    pub fn daily_puzzle(&self) -> Result<Puzzle, Error> {
+        parse_puzzle(&self.get("/api/puzzle/daily", "application/json")?)
+    }
+
+    /// Fetch all chapters of a study as PGN
+    pub fn study_pgn(&self, id: &str) -> Result<String, Error> {
+        self.get(&format!("/api/study/{}.pgn", id), "application/x-chess-pgn")
+    }
+}
+
+/// Decode the JSON returned by the puzzle endpoints
+pub fn parse_puzzle(json: &str) -> Result<Puzzle, Error> {
+    let response: PuzzleResponse = serde_json::from_str(json)?;
+    Ok(Puzzle {
+        id: response.puzzle.id,
+        rating: response.puzzle.rating,
+        themes: response.puzzle.themes,
+        game_moves: response
+            .game
+            .pgn
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+        initial_ply: response.puzzle.initial_ply,
+        solution: response.puzzle.solution,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_puzzle() {
+        let json = r#"{
+            "game": {"id": "abc", "pgn": "e4 e5 Qh5 Nc6 Bc4 Nf6"},
+            "puzzle": {"id": "K69di", "rating": 1500, "plays": 10, "initialPly": 5,
+                       "solution": ["h5f7"], "themes": ["mateIn1"]}
+        }"#;
+        let puzzle = parse_puzzle(json).unwrap();
+        assert_eq!(puzzle.id, "K69di");
+        assert_eq!(puzzle.game_moves.len(), 6);
+        assert_eq!(puzzle.initial_ply, 5);
+        assert_eq!(puzzle.solution, ["h5f7"]);
+    }
+}
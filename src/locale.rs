@@ -0,0 +1,304 @@
+//! The words players read and hear, in the language of the club: what the clock shows,
+//! what speech says and the labels of the overlay. Log messages stay in English.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Languages the messages come in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    English,
+    German,
+    French,
+    Spanish,
+    Dutch,
+    Norwegian,
+}
+
+/// A message shown or spoken to the players. Those with `{}` take a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Msg {
+    King,
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+    Pawn,
+    Takes,
+    /// A piece dropped in Crazyhouse, followed by the square
+    DroppedOn,
+    /// Followed by the piece
+    PromotesTo,
+    Check,
+    Checkmate,
+    CastlesKingside,
+    CastlesQueenside,
+    White,
+    Black,
+    NewGame,
+    WhiteWins,
+    BlackWins,
+    Draw,
+    GameOver,
+    OneMinuteLeft,
+    SecondsLeft,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 6] = [
+        Locale::English,
+        Locale::German,
+        Locale::French,
+        Locale::Spanish,
+        Locale::Dutch,
+        Locale::Norwegian,
+    ];
+
+    /// Two letter ISO 639-1 code, Norwegian as Bokmål
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::English => "en",
+            Locale::German => "de",
+            Locale::French => "fr",
+            Locale::Spanish => "es",
+            Locale::Dutch => "nl",
+            Locale::Norwegian => "nb",
+        }
+    }
+
+    /// `msg` in this language
+    pub fn text(self, msg: Msg) -> &'static str {
+        use Locale::*;
+        use Msg::*;
+        match (self, msg) {
+            (English, King) => "king",
+            (English, Queen) => "queen",
+            (English, Rook) => "rook",
+            (English, Bishop) => "bishop",
+            (English, Knight) => "knight",
+            (English, Pawn) => "pawn",
+            (English, Takes) => "takes",
+            (English, DroppedOn) => "dropped on",
+            (English, PromotesTo) => "promotes to",
+            (English, Check) => "check",
+            (English, Checkmate) => "checkmate",
+            (English, CastlesKingside) => "castles kingside",
+            (English, CastlesQueenside) => "castles queenside",
+            (English, White) => "white",
+            (English, Black) => "black",
+            (English, NewGame) => "new game",
+            (English, WhiteWins) => "white wins",
+            (English, BlackWins) => "black wins",
+            (English, Draw) => "draw",
+            (English, GameOver) => "game over",
+            (English, OneMinuteLeft) => "one minute left",
+            (English, SecondsLeft) => "{} seconds left",
+
+            (German, King) => "König",
+            (German, Queen) => "Dame",
+            (German, Rook) => "Turm",
+            (German, Bishop) => "Läufer",
+            (German, Knight) => "Springer",
+            (German, Pawn) => "Bauer",
+            (German, Takes) => "schlägt",
+            (German, DroppedOn) => "eingesetzt auf",
+            (German, PromotesTo) => "wird zur",
+            (German, Check) => "Schach",
+            (German, Checkmate) => "Schachmatt",
+            (German, CastlesKingside) => "kurze Rochade",
+            (German, CastlesQueenside) => "lange Rochade",
+            (German, White) => "Weiß",
+            (German, Black) => "Schwarz",
+            (German, NewGame) => "neue Partie",
+            (German, WhiteWins) => "Weiß gewinnt",
+            (German, BlackWins) => "Schwarz gewinnt",
+            (German, Draw) => "Remis",
+            (German, GameOver) => "Partie beendet",
+            (German, OneMinuteLeft) => "noch eine Minute",
+            (German, SecondsLeft) => "noch {} Sekunden",
+
+            (French, King) => "roi",
+            (French, Queen) => "dame",
+            (French, Rook) => "tour",
+            (French, Bishop) => "fou",
+            (French, Knight) => "cavalier",
+            (French, Pawn) => "pion",
+            (French, Takes) => "prend",
+            (French, DroppedOn) => "parachuté en",
+            (French, PromotesTo) => "promu en",
+            (French, Check) => "échec",
+            (French, Checkmate) => "échec et mat",
+            (French, CastlesKingside) => "petit roque",
+            (French, CastlesQueenside) => "grand roque",
+            (French, White) => "blancs",
+            (French, Black) => "noirs",
+            (French, NewGame) => "nouvelle partie",
+            (French, WhiteWins) => "les blancs gagnent",
+            (French, BlackWins) => "les noirs gagnent",
+            (French, Draw) => "partie nulle",
+            (French, GameOver) => "partie terminée",
+            (French, OneMinuteLeft) => "plus qu'une minute",
+            (French, SecondsLeft) => "plus que {} secondes",
+
+            (Spanish, King) => "rey",
+            (Spanish, Queen) => "dama",
+            (Spanish, Rook) => "torre",
+            (Spanish, Bishop) => "alfil",
+            (Spanish, Knight) => "caballo",
+            (Spanish, Pawn) => "peón",
+            (Spanish, Takes) => "captura",
+            (Spanish, DroppedOn) => "colocado en",
+            (Spanish, PromotesTo) => "corona",
+            (Spanish, Check) => "jaque",
+            (Spanish, Checkmate) => "jaque mate",
+            (Spanish, CastlesKingside) => "enroque corto",
+            (Spanish, CastlesQueenside) => "enroque largo",
+            (Spanish, White) => "blancas",
+            (Spanish, Black) => "negras",
+            (Spanish, NewGame) => "nueva partida",
+            (Spanish, WhiteWins) => "ganan las blancas",
+            (Spanish, BlackWins) => "ganan las negras",
+            (Spanish, Draw) => "tablas",
+            (Spanish, GameOver) => "partida terminada",
+            (Spanish, OneMinuteLeft) => "queda un minuto",
+            (Spanish, SecondsLeft) => "quedan {} segundos",
+
+            (Dutch, King) => "koning",
+            (Dutch, Queen) => "dame",
+            (Dutch, Rook) => "toren",
+            (Dutch, Bishop) => "loper",
+            (Dutch, Knight) => "paard",
+            (Dutch, Pawn) => "pion",
+            (Dutch, Takes) => "slaat",
+            (Dutch, DroppedOn) => "geplaatst op",
+            (Dutch, PromotesTo) => "promoveert tot",
+            (Dutch, Check) => "schaak",
+            (Dutch, Checkmate) => "schaakmat",
+            (Dutch, CastlesKingside) => "korte rokade",
+            (Dutch, CastlesQueenside) => "lange rokade",
+            (Dutch, White) => "wit",
+            (Dutch, Black) => "zwart",
+            (Dutch, NewGame) => "nieuwe partij",
+            (Dutch, WhiteWins) => "wit wint",
+            (Dutch, BlackWins) => "zwart wint",
+            (Dutch, Draw) => "remise",
+            (Dutch, GameOver) => "partij afgelopen",
+            (Dutch, OneMinuteLeft) => "nog één minuut",
+            (Dutch, SecondsLeft) => "nog {} seconden",
+
+            (Norwegian, King) => "konge",
+            (Norwegian, Queen) => "dronning",
+            (Norwegian, Rook) => "tårn",
+            (Norwegian, Bishop) => "løper",
+            (Norwegian, Knight) => "springer",
+            (Norwegian, Pawn) => "bonde",
+            (Norwegian, Takes) => "slår",
+            (Norwegian, DroppedOn) => "satt inn på",
+            (Norwegian, PromotesTo) => "blir til",
+            (Norwegian, Check) => "sjakk",
+            (Norwegian, Checkmate) => "sjakkmatt",
+            (Norwegian, CastlesKingside) => "kort rokade",
+            (Norwegian, CastlesQueenside) => "lang rokade",
+            (Norwegian, White) => "hvit",
+            (Norwegian, Black) => "svart",
+            (Norwegian, NewGame) => "nytt parti",
+            (Norwegian, WhiteWins) => "hvit vinner",
+            (Norwegian, BlackWins) => "svart vinner",
+            (Norwegian, Draw) => "remis",
+            (Norwegian, GameOver) => "partiet er slutt",
+            (Norwegian, OneMinuteLeft) => "ett minutt igjen",
+            (Norwegian, SecondsLeft) => "{} sekunder igjen",
+        }
+    }
+
+    /// `msg` with its `{}` filled in with `value`
+    pub fn format(self, msg: Msg, value: impl fmt::Display) -> String {
+        self.text(msg).replacen("{}", &value.to_string(), 1)
+    }
+
+    /// `msg` with the first letter capitalized, for labels
+    pub fn label(self, msg: Msg) -> String {
+        let text = self.text(msg);
+        let mut chars = text.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    }
+
+    /// A move in standard algebraic notation with the piece letters of the language, as
+    /// printed in its chess books, e.g. "Sf3" in German for "Nf3". The letters stay
+    /// ASCII so the clock can show them.
+    pub fn san(self, san: &str) -> String {
+        let letters: [char; 5] = match self {
+            // King, queen, rook, bishop and knight
+            Locale::English => return san.to_string(),
+            Locale::German => ['K', 'D', 'T', 'L', 'S'],
+            Locale::French => ['R', 'D', 'T', 'F', 'C'],
+            Locale::Spanish => ['R', 'D', 'T', 'A', 'C'],
+            Locale::Dutch => ['K', 'D', 'T', 'L', 'P'],
+            Locale::Norwegian => ['K', 'D', 'T', 'L', 'S'],
+        };
+        san.chars()
+            .map(|c| match "KQRBN".find(c) {
+                Some(index) => letters[index],
+                None => c,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Takes the code and also the language part of a POSIX locale like `de_DE.UTF-8`
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = s
+            .split(['_', '-', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let language = match language.as_str() {
+            "c" | "posix" => "en",
+            "no" | "nn" => "nb",
+            language => language,
+        };
+        Locale::ALL
+            .into_iter()
+            .find(|locale| locale.code() == language)
+            .ok_or_else(|| {
+                let codes: Vec<_> = Locale::ALL.iter().map(|locale| locale.code()).collect();
+                format!("no messages in {:?}, only in {}", s, codes.join(", "))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog() {
+        assert_eq!("de_DE.UTF-8".parse(), Ok(Locale::German));
+        assert_eq!("no".parse(), Ok(Locale::Norwegian));
+        assert_eq!("C".parse(), Ok(Locale::English));
+        assert!("xx".parse::<Locale>().is_err());
+        for locale in Locale::ALL {
+            assert_eq!(locale.to_string().parse(), Ok(locale));
+            assert!(locale.format(Msg::SecondsLeft, 10).contains("10"));
+            assert!(locale.san("Nxf3+").is_ascii());
+        }
+        assert_eq!(Locale::German.san("Nxf3+"), "Sxf3+");
+        assert_eq!(Locale::French.san("e8=Q#"), "e8=D#");
+        assert_eq!(Locale::Spanish.san("O-O-O"), "O-O-O");
+        assert_eq!(Locale::German.label(Msg::White), "Weiß");
+        assert_eq!(Locale::French.label(Msg::Black), "Noirs");
+    }
+}
@@ -1,105 +1,3337 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 
-use serialport::SerialPort;
+use clap::{Parser, Subcommand};
+use tracing::{debug, info, warn};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter};
 
-mod game;
-mod protocol;
+use jackolope::arbiter::Intervention;
+use jackolope::board::{self, ElectronicBoard};
+use jackolope::clock::{
+    Beeps, ButtonMap, ButtonSet, ClockCommand, ClockSide, ClockSignal, DrawOffer, LocalClock,
+    StandaloneClock, TimeControl,
+};
+use jackolope::config::{self, Config};
+use jackolope::daemon;
+use jackolope::dedup::Dedup;
+use jackolope::emulator::Emulator;
+use jackolope::event::{Event, MicroEvent};
+use jackolope::fen::{self, Castling};
+use jackolope::game::*;
+use jackolope::gui::{Action, Bridge};
+use jackolope::handle::BoardHandle;
+use jackolope::lichess;
+use jackolope::locale::Locale;
+use jackolope::manager::{BoardEvent, BoardManager};
+use jackolope::matchplay::Match;
+use jackolope::metrics::{self, METRICS};
+use jackolope::netplay::{self, Link, LinkSender, Reconcile};
+use jackolope::overlay::{self, Overlay};
+use jackolope::pacing::Pacing;
+use jackolope::pairing::{PairUpdate, Pairing};
+use jackolope::pgn::{self, GameTags};
+use jackolope::protocol::*;
+use jackolope::puzzle::{Exercise, Verdict};
+use jackolope::relay::{self, Broadcast, Relays};
+use jackolope::render::{Orientation, PieceStyle, Style};
+use jackolope::rotate::{self, RotatingFile, Rotation};
+use jackolope::session::{self, SessionLog, SessionReader};
+use jackolope::setup::Setup;
+use jackolope::speech;
+use jackolope::status::{self, ExitStatus, Failure};
+use jackolope::stream::EventStream;
+use jackolope::transport::{self, Transport};
+use jackolope::uci::{Engine, Searcher};
+use jackolope::variant::{self, Outcome, Standard, Termination, Variant};
+use jackolope::{render, report, rules};
 
-use game::*;
-use protocol::*;
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Driver for DGT and other electronic chess boards",
+    after_help = "Exit codes: 0 success, 1 other failure, 2 usage, 3 no answer from the board, \
+                  4 no device found, 5 permission denied, 6 handshake failed, 7 parse failure, \
+                  130 interrupted"
+)]
+struct Cli {
+    /// Profile of the config file to take option defaults from
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    /// Serve Prometheus metrics at /metrics on this address, e.g. 127.0.0.1:9187
+    #[arg(long, global = true)]
+    metrics: Option<String>,
+    /// Write the exit status, the error if any and the last known state of the board
+    /// and game to this file as JSON on exit
+    #[arg(long, global = true)]
+    status_file: Option<PathBuf>,
+    /// Also write the log to this file, started afresh as --log-max-size and
+    /// --log-max-age say with the old ones kept as FILE.1, FILE.2 and so on
+    #[arg(long, global = true, env = "JACKOLOPE_LOG_FILE")]
+    log_file: Option<PathBuf>,
+    /// Size the log file is started afresh at, like 50M
+    #[arg(long, global = true, env = "JACKOLOPE_LOG_MAX_SIZE", value_parser = rotate::parse_size)]
+    log_max_size: Option<u64>,
+    /// Age the log file is started afresh at, like 12h or 1d
+    #[arg(long, global = true, env = "JACKOLOPE_LOG_MAX_AGE", value_parser = rotate::parse_age)]
+    log_max_age: Option<Duration>,
+    /// Old log files kept, all of them if not given
+    #[arg(long, global = true, env = "JACKOLOPE_LOG_KEEP")]
+    log_keep: Option<usize>,
+    /// Options for following a game when no subcommand is given
+    #[command(flatten)]
+    watch: WatchArgs,
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+// Parsed once, the size of the options does not matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Subcommand)]
+enum Commands {
+    /// Replay a recorded session log through the event pipeline
+    ReplaySession {
+        /// Session log written with `--record`
+        file: PathBuf,
+        /// Reproduce the original timing between events
+        #[arg(long)]
+        realtime: bool,
+        /// Rules the recorded game was played under
+        #[arg(long, default_value = "standard", value_parser = parse_variant)]
+        variant: Arc<dyn Variant>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Place pieces freely and print the FEN of the composed position
+    Setup(SetupArgs),
+    /// Follow the game while a UCI engine continuously analyses the live position
+    Analyze(AnalyzeArgs),
+    /// Solve Lichess puzzles or replay study chapters on the physical board
+    Puzzle(PuzzleArgs),
+    /// Step through the games of a PGN file on the physical board, showing every move
+    /// to play and checking it
+    Guide(GuideArgs),
+    /// Print the serial numbers, versions and addresses the board reports
+    Info(InfoArgs),
+    /// Ask a DGT board one question, print the answer and exit, for scripts and
+    /// monitoring checks. Exits with 3 when the board does not answer in time.
+    Send(SendArgs),
+    /// Type command names or hex bytes to send to a DGT board and see what it answers
+    Repl(ReplArgs),
+    /// Use a DGT3000 as a clock on its own, say for an arbiter: set a time control,
+    /// show the lines typed on it and print its times and button presses, without
+    /// following a game. The clock is reached through the board it is plugged into.
+    Clock(ClockArgs),
+    /// List the serial ports a board might be connected to
+    Ports,
+    /// Print the states move detection goes through as a Graphviz graph, to draw with
+    /// dot -Tsvg
+    States,
+    /// Follow every board plugged into this computer, each with its own game
+    Boards(BoardsArgs),
+    /// Play one game on two boards, each player copying the other's moves onto their own
+    Pair(PairArgs),
+    /// Play one game against another jackolope over the network, each on their own board
+    Remote(RemoteArgs),
+    /// Be an engine for a chess GUI such as Arena or Cute Chess, speaking XBoard or UCI
+    /// on stdin and stdout: the moves of the GUI are made on the board, and the moves
+    /// made on the board are the moves of the engine
+    Gui(GuiArgs),
+    /// Pretend to be a DGT board on a pseudo-terminal, for testing without hardware
+    #[cfg(unix)]
+    Simulate(SimulateArgs),
+    /// Pause the clock, give time, correct the moves, set the result or comment on the
+    /// moves of a followed game
+    #[cfg(unix)]
+    Arbiter(ArbiterArgs),
+    /// List, search and export the games archived with --database
+    #[cfg(feature = "database")]
+    Games(GamesArgs),
+}
+
+#[cfg(feature = "database")]
+#[derive(clap::Args)]
+struct GamesArgs {
+    /// Archive the games were saved to
+    #[arg(long, env = "JACKOLOPE_DATABASE")]
+    database: PathBuf,
+    /// Only games of a player whose name contains this
+    #[arg(long)]
+    player: Option<String>,
+    /// Only games on the board with this label or serial number
+    #[arg(long)]
+    board: Option<String>,
+    /// Only games with this result, like 1-0
+    #[arg(long)]
+    result: Option<String>,
+    /// At most this many games, the latest first
+    #[arg(long, default_value_t = 20)]
+    limit: u32,
+    /// Write the games found to this file as PGN instead of listing them
+    #[arg(long)]
+    export: Option<PathBuf>,
+    /// Print the PGN of the game with this id
+    id: Option<i64>,
+}
+
+#[cfg(unix)]
+#[derive(clap::Args)]
+struct ArbiterArgs {
+    /// Socket the driver was given with --arbiter-socket
+    #[arg(long, env = "JACKOLOPE_ARBITER_SOCKET")]
+    socket: PathBuf,
+    /// Token the driver was given with --arbiter-token
+    #[arg(long, env = "JACKOLOPE_ARBITER_TOKEN", hide_env_values = true)]
+    token: String,
+    /// Command to send: pause, resume, time <white|black> <+-seconds>, moves <uci>...,
+    /// result <1-0|0-1|1/2-1/2> or note <12.|12...> <text>. Commands are read from
+    /// stdin when none is given.
+    command: Vec<String>,
+}
+
+#[cfg(unix)]
+#[derive(clap::Args)]
+struct SimulateArgs {
+    /// Moves in UCI notation to play once the driver asks for updates
+    moves: Vec<String>,
+    /// File with more moves in UCI notation, separated by whitespace
+    #[arg(long)]
+    script: Option<PathBuf>,
+    /// Replay the main line of the first game in this PGN file after the other moves,
+    /// for showing a recorded game to other software
+    #[arg(long)]
+    pgn: Option<PathBuf>,
+    /// Milliseconds between moves
+    #[arg(long, default_value_t = 1000)]
+    interval: u64,
+}
+
+#[derive(clap::Args)]
+struct PairArgs {
+    /// The board of white, given with --port
+    #[command(flatten)]
+    board: BoardArgs,
+    /// Serial port of the board of black
+    #[arg(long)]
+    black_port: String,
+    /// Rules of the game
+    #[arg(long, env = "JACKOLOPE_VARIANT", default_value = "standard", value_parser = parse_variant)]
+    variant: Arc<dyn Variant>,
+    /// Write the game as PGN to this file after every move
+    #[arg(long, env = "JACKOLOPE_PGN")]
+    pgn: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct RemoteArgs {
+    #[command(flatten)]
+    board: BoardArgs,
+    /// Colour played on this board, white or black
+    #[arg(long, value_parser = parse_colour)]
+    colour: PieceColor,
+    /// Wait for the other player on this address
+    #[arg(long, num_args = 0..=1, default_missing_value = "0.0.0.0:7648", conflicts_with = "connect", required_unless_present = "connect")]
+    listen: Option<String>,
+    /// Address of the other player, who is listening, like example.org:7648
+    #[arg(long)]
+    connect: Option<String>,
+    /// Secret shared with the other player, neither end plays anyone without it
+    #[arg(long, env = "JACKOLOPE_REMOTE_TOKEN", hide_env_values = true)]
+    token: String,
+    /// Rules of the game
+    #[arg(long, env = "JACKOLOPE_VARIANT", default_value = "standard", value_parser = parse_variant)]
+    variant: Arc<dyn Variant>,
+    /// Write the game as PGN to this file after every move
+    #[arg(long, env = "JACKOLOPE_PGN")]
+    pgn: Option<PathBuf>,
+    /// Pretend to be a DGT board showing the game to other software, such as a chess
+    /// GUI, on this serial port, or on a new pseudo-terminal with pty
+    #[arg(long)]
+    virtual_board: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct GuiArgs {
+    #[command(flatten)]
+    board: BoardArgs,
+}
+
+#[derive(clap::Args)]
+struct BoardsArgs {
+    /// Type of the boards: dgt, or millennium when built with that feature
+    #[arg(long, env = "JACKOLOPE_BOARD", default_value = "dgt")]
+    board: board::Kind,
+    /// Serial speed, or auto to probe the usual rates
+    #[arg(long, env = "JACKOLOPE_BAUD")]
+    baud: Option<board::Baud>,
+    /// Rules of the games
+    #[arg(long, env = "JACKOLOPE_VARIANT", default_value = "standard", value_parser = parse_variant)]
+    variant: Arc<dyn Variant>,
+    /// Directory to write the game of every board to, as PGN named after its port
+    #[arg(long)]
+    pgn_dir: Option<PathBuf>,
+    /// Time control to set the clock of each board to whenever a new game is set up,
+    /// in minutes and seconds of increment like 90+30
+    #[arg(long, env = "JACKOLOPE_TIME_CONTROL")]
+    time_control: Option<TimeControl>,
+    /// Milliseconds within which a repeated field update is dropped, 0 to keep all
+    #[arg(long, env = "JACKOLOPE_DEDUP_WINDOW", default_value_t = Dedup::DEFAULT_WINDOW.as_millis() as u64)]
+    dedup_window: u64,
+}
+
+#[derive(clap::Args)]
+struct InfoArgs {
+    #[command(flatten)]
+    board: BoardArgs,
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct SendArgs {
+    #[command(flatten)]
+    board: BoardArgs,
+    /// What to ask the board for
+    #[arg(value_enum)]
+    query: Query,
+    /// Print the answer as JSON
+    #[arg(long)]
+    json: bool,
+    /// Milliseconds to wait for the answer
+    #[arg(long, default_value_t = board::REPLY_TIMEOUT.as_millis() as u64)]
+    timeout: u64,
+}
+
+/// The questions `send` can ask
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Query {
+    Version,
+    Board,
+    Serial,
+    Clock,
+}
+
+#[derive(clap::Args)]
+struct ReplArgs {
+    #[command(flatten)]
+    board: BoardArgs,
+}
+
+#[derive(clap::Args)]
+struct ClockArgs {
+    #[command(flatten)]
+    board: BoardArgs,
+    /// Time control to set the clock to, in minutes and seconds of increment like 90+30,
+    /// or of delay like 90d5 or Bronstein increment like 90b5. The host gives the
+    /// increment after every press of the lever.
+    #[arg(long, env = "JACKOLOPE_TIME_CONTROL")]
+    time_control: Option<TimeControl>,
+    /// Start the time of this side right away instead of leaving the clock stopped
+    #[arg(long, value_enum, requires = "time_control")]
+    run: Option<Side>,
+    /// Text to show on the clock until the first line is typed or a button is pressed
+    #[arg(long)]
+    text: Option<String>,
+    /// Print the times and button presses as JSON lines
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct PuzzleArgs {
+    #[command(flatten)]
+    board: BoardArgs,
+    /// Lichess puzzle ids, solved in order
+    ids: Vec<String>,
+    /// Start with the Lichess puzzle of the day
+    #[arg(long)]
+    daily: bool,
+    /// Lichess study id, every chapter becomes an exercise
+    #[arg(long)]
+    study: Option<String>,
+    /// Lichess API token, needed for private studies
+    #[arg(long, env = "LICHESS_TOKEN")]
+    token: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct GuideArgs {
+    #[command(flatten)]
+    board: BoardArgs,
+    /// PGN file whose games are replayed in order
+    file: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct BoardArgs {
+    /// Serial port the board is connected to, e.g. COM7 or /dev/ttyUSB0, or auto for the
+    /// first port that looks like a board
+    #[arg(long, env = "JACKOLOPE_PORT", default_value = "auto")]
+    port: String,
+    /// Type of board: dgt, or millennium when built with that feature
+    #[arg(long, env = "JACKOLOPE_BOARD", default_value = "dgt")]
+    board: board::Kind,
+    /// Serial speed, or auto to probe the usual rates. Defaults to the rate of the board
+    /// type.
+    #[arg(long, env = "JACKOLOPE_BAUD")]
+    baud: Option<board::Baud>,
+    /// How the board is attached: serial, usb or bluetooth. Picks flow control and the
+    /// DTR/RTS wake-up, detected from the port when not given.
+    #[arg(long, env = "JACKOLOPE_TRANSPORT")]
+    transport: Option<Transport>,
+    /// Accept messages with padding bytes after the data, as some firmware sends,
+    /// instead of dropping them
+    #[arg(long, env = "JACKOLOPE_LENIENT")]
+    lenient: bool,
+    /// Milliseconds to leave between commands to the board
+    #[arg(long, default_value_t = Pacing::default().gap.as_millis() as u64)]
+    command_gap: u64,
+    /// Milliseconds to give the board after a reset before the next command
+    #[arg(long, default_value_t = Pacing::default().settle.as_millis() as u64)]
+    reset_settle: u64,
+}
+
+impl BoardArgs {
+    fn open(&self) -> Result<Box<dyn ElectronicBoard>, board::Error> {
+        let (port, transport) = self.port()?;
+        let mut board = board::open(self.board, &port, self.baud, transport)?;
+        board.set_pacing(self.pacing());
+        board.set_parse_mode(self.parse_mode());
+        info!(board = board.name(), %port, "board opened");
+        Ok(board)
+    }
+
+    /// Open the board as a DGT board, for speaking the protocol directly
+    fn open_dgt(&self) -> Result<board::DgtBoard, board::Error> {
+        if self.board != board::Kind::Dgt {
+            return Err("only DGT boards can be driven directly".into());
+        }
+        let (port, transport) = self.port()?;
+        let mut board = board::open_dgt(&port, self.baud, transport)?;
+        board.set_pacing(self.pacing());
+        board.set_parse_mode(self.parse_mode());
+        info!(board = board.name(), %port, "board opened");
+        Ok(board)
+    }
+
+    fn port(&self) -> Result<(String, Option<Transport>), board::Error> {
+        if self.port == "auto" {
+            let candidate = transport::discover().into_iter().next().ok_or_else(|| {
+                Failure::new(
+                    ExitStatus::NoDevice,
+                    "no serial ports found, give one with --port",
+                )
+            })?;
+            Ok((candidate.port, self.transport.or(Some(candidate.transport))))
+        } else {
+            Ok((transport::port_path(&self.port), self.transport))
+        }
+    }
+
+    fn parse_mode(&self) -> ParseMode {
+        match self.lenient {
+            true => ParseMode::Lenient,
+            false => ParseMode::Strict,
+        }
+    }
+
+    fn pacing(&self) -> Pacing {
+        Pacing {
+            gap: Duration::from_millis(self.command_gap),
+            settle: Duration::from_millis(self.reset_settle),
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct AnalyzeArgs {
+    #[command(flatten)]
+    watch: WatchArgs,
+    /// Path to the UCI engine executable
+    #[arg(long, env = "JACKOLOPE_ENGINE")]
+    engine: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct SetupArgs {
+    #[command(flatten)]
+    board: BoardArgs,
+    /// Side to move in the composed position
+    #[arg(long, value_enum, default_value_t = Side::White)]
+    to_move: Side,
+    /// Castling rights in FEN notation, e.g. "KQkq" or "-"
+    #[arg(long, default_value = "-")]
+    castling: Castling,
+    /// Finish once the board has been left untouched for this many seconds
+    #[arg(long)]
+    stable: Option<u64>,
+    /// The board reports h1 as its first square instead of a8
+    #[arg(long, env = "JACKOLOPE_ROTATED")]
+    rotated: bool,
+}
+
+/// What the clock beeps for
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum BeepOn {
+    Move,
+    Illegal,
+}
+
+/// Whether to carry on with an unfinished game left by an earlier run
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Resume {
+    Never,
+    /// Ask on the terminal first
+    Ask,
+    Auto,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Side {
+    White,
+    Black,
+}
+
+#[derive(clap::Args)]
+struct WatchArgs {
+    #[command(flatten)]
+    board: BoardArgs,
+    /// Record every event to a session log file
+    #[arg(long, env = "JACKOLOPE_RECORD")]
+    record: Option<PathBuf>,
+    /// Keep the session logs of only this many games before the current one. The log
+    /// of a game is never cut short, so it can still be replayed and resumed.
+    #[arg(long, env = "JACKOLOPE_RECORD_KEEP")]
+    record_keep: Option<usize>,
+    /// Stream every event as the JSON lines of a session log to programs on this
+    /// computer: on a Unix domain socket made at this path, or a named pipe made there
+    /// with mkfifo, or on Windows a named pipe like \\.\pipe\jackolope
+    #[arg(long, env = "JACKOLOPE_EVENT_SOCKET")]
+    event_socket: Option<PathBuf>,
+    /// Run unattended under a service manager: log for the journal, keep the PID file,
+    /// the session log and the status file in --state-dir, and after a restart carry on
+    /// with the game of the session log if the board still shows it
+    #[arg(long, env = "JACKOLOPE_DAEMON")]
+    daemon: bool,
+    /// Directory of --daemon, by default the one systemd made for StateDirectory= or
+    /// /var/lib/jackolope
+    #[arg(long, env = "JACKOLOPE_STATE_DIR")]
+    state_dir: Option<PathBuf>,
+    /// Carry on with the unfinished game of an earlier run, from the last session log
+    /// of --record or else the last PGN of --pgn, if the board shows it or is a few
+    /// pieces off. Auto with --daemon, never otherwise.
+    #[arg(long, env = "JACKOLOPE_RESUME", value_enum)]
+    resume: Option<Resume>,
+    /// Rules of the game: standard (including Chess960), atomic, antichess or crazyhouse
+    #[arg(long, env = "JACKOLOPE_VARIANT", default_value = "standard", value_parser = parse_variant)]
+    variant: Arc<dyn Variant>,
+    #[command(flatten)]
+    output: OutputArgs,
+    /// Show every move on the DGT3000 clock, as DGT Pi and PicoChess setups do
+    #[arg(long)]
+    clock_moves: bool,
+    /// Training mode for beginners: list the squares a lifted piece can move to and
+    /// point out a piece put down on a square it can not go to
+    #[arg(long, env = "JACKOLOPE_TRAINING")]
+    training: bool,
+    /// Beep the clock for every move, when the board stops matching the game, or both,
+    /// e.g. move,illegal
+    #[arg(long, env = "JACKOLOPE_BEEP", value_enum, value_delimiter = ',')]
+    beep: Vec<BeepOn>,
+    /// Beep the clock when the time of the player to move drops below this many seconds
+    #[arg(long, env = "JACKOLOPE_LOW_TIME_BEEP")]
+    low_time_beep: Option<u32>,
+    /// Announce moves, results and a clock passing one minute and ten seconds left
+    /// through a speech synthesizer, espeak or say unless another program is given
+    #[arg(long, env = "JACKOLOPE_SPEAK", value_name = "PROGRAM", num_args = 0..=1,
+          default_missing_value = speech::DEFAULT_PROGRAM)]
+    speak: Option<String>,
+    /// Language of the moves on the clock, what speech says and the overlay labels:
+    /// en, de, fr, es, nl or nb
+    #[arg(long, env = "JACKOLOPE_LOCALE", default_value_t)]
+    locale: Locale,
+    /// Serve an overlay for streaming software on this address, like 127.0.0.1:8088,
+    /// to add as a browser source
+    #[arg(long, env = "JACKOLOPE_OVERLAY")]
+    overlay: Option<String>,
+    /// Write the overlay to this PNG file whenever it changes
+    #[cfg(feature = "resvg")]
+    #[arg(long, env = "JACKOLOPE_OVERLAY_PNG")]
+    overlay_png: Option<PathBuf>,
+    /// Light the analysis engine's best move on boards with square LEDs
+    #[arg(long)]
+    leds: bool,
+    /// Put the board back into idle mode when exiting
+    #[arg(long)]
+    idle_on_exit: bool,
+    /// Time control to set the clock to whenever a new game is set up, in minutes and
+    /// seconds of increment like 90+30, or of delay like 90d5 or Bronstein increment like
+    /// 90b5. Time odds give white's and black's apart, like 5+3/3+2.
+    #[arg(long, env = "JACKOLOPE_TIME_CONTROL")]
+    time_control: Option<TimeControl>,
+    /// Keep the time of the time control on the host, for boards without a clock.
+    /// Times a clock reports take over when there is one.
+    #[arg(long, env = "JACKOLOPE_LOCAL_CLOCK", requires = "time_control")]
+    local_clock: bool,
+    /// Only take a move as made once its player pressed the clock, as over the board,
+    /// before it is relayed or analysed. Without a clock, press Enter instead.
+    #[arg(long, env = "JACKOLOPE_CONFIRM_MOVES")]
+    confirm_moves: bool,
+    /// Go easy on the players, as in a game between friends: take a move that leaves the
+    /// king in check, a pawn left on the last rank for a queen and the board showing the
+    /// position before the last move for that move taken back
+    #[arg(long, env = "JACKOLOPE_CASUAL")]
+    casual: bool,
+    #[command(flatten)]
+    contest: MatchArgs,
+    /// Milliseconds within which a repeated field update is taken for an echo and
+    /// dropped, 0 to keep every update
+    #[arg(long, env = "JACKOLOPE_DEDUP_WINDOW", default_value_t = Dedup::DEFAULT_WINDOW.as_millis() as u64)]
+    dedup_window: u64,
+    #[command(flatten)]
+    buttons: ButtonArgs,
+    /// Take arbiter commands on this Unix socket, see the arbiter subcommand
+    #[cfg(unix)]
+    #[arg(long, env = "JACKOLOPE_ARBITER_SOCKET", requires = "arbiter_token")]
+    arbiter_socket: Option<PathBuf>,
+    /// Token the arbiter has to give before any command
+    #[cfg(unix)]
+    #[arg(long, env = "JACKOLOPE_ARBITER_TOKEN", hide_env_values = true)]
+    arbiter_token: Option<String>,
+    #[command(flatten)]
+    relay: RelayArgs,
+}
+
+/// A match of consecutive games between two players
+#[derive(clap::Args)]
+struct MatchArgs {
+    /// Players of a match, the first with white in the first game, e.g. Smith,Jones.
+    /// Colours alternate from game to game and the score goes into the PGN tags.
+    #[arg(long, value_name = "FIRST,SECOND", value_delimiter = ',')]
+    match_players: Vec<String>,
+    /// Round of the first game of the match
+    #[arg(long, default_value_t = 1, requires = "match_players")]
+    first_round: u32,
+}
+
+impl MatchArgs {
+    fn contest(&self) -> Result<Option<Match>, String> {
+        match self.match_players.as_slice() {
+            [] => Ok(None),
+            [first, second] => Ok(Some(Match::new(
+                first.clone(),
+                second.clone(),
+                self.first_round,
+            ))),
+            _ => Err("a match needs two players, like --match-players Smith,Jones".to_string()),
+        }
+    }
+}
+
+/// Clock buttons players signal results with, as button numbers from the left joined
+/// by `+`, or none
+#[derive(clap::Args)]
+struct ButtonArgs {
+    /// Buttons that offer a draw after moving and before pressing the clock, pressed by
+    /// the opponent before their next move to accept it
+    #[arg(long, env = "JACKOLOPE_DRAW_BUTTONS", default_value_t = ButtonMap::default().draw)]
+    draw_buttons: ButtonSet,
+    /// Buttons that resign for the player to move
+    #[arg(long, env = "JACKOLOPE_RESIGN_BUTTONS", default_value_t = ButtonMap::default().resign)]
+    resign_buttons: ButtonSet,
+    /// Buttons that pause the game
+    #[arg(long, env = "JACKOLOPE_PAUSE_BUTTONS", default_value_t = ButtonMap::default().pause)]
+    pause_buttons: ButtonSet,
+}
+
+impl ButtonArgs {
+    fn map(&self) -> ButtonMap {
+        ButtonMap {
+            draw: self.draw_buttons,
+            resign: self.resign_buttons,
+            pause: self.pause_buttons,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct RelayArgs {
+    /// Mirror the game to a broadcast service: lichess or chesscom
+    #[arg(long)]
+    relay: Option<relay::Backend>,
+    /// Lichess broadcast round id to push the game to
+    #[arg(long)]
+    relay_round: Option<String>,
+    /// File kept up to date with the live PGN, for chess.com broadcasts to poll
+    #[arg(long)]
+    relay_pgn: Option<PathBuf>,
+    /// Lichess API token with the study:write scope
+    #[arg(long, env = "LICHESS_TOKEN", hide_env_values = true)]
+    lichess_token: Option<String>,
+    /// Publish FEN, moves and clock times to this MQTT broker, as host or host:port
+    #[cfg(feature = "mqtt")]
+    #[arg(long)]
+    mqtt: Option<String>,
+    /// Topic prefix for the MQTT messages
+    #[cfg(feature = "mqtt")]
+    #[arg(long, default_value = "jackolope")]
+    mqtt_topic: String,
+    /// Archive every finished game in this SQLite file, see the games command
+    #[cfg(feature = "database")]
+    #[arg(long, env = "JACKOLOPE_DATABASE")]
+    database: Option<PathBuf>,
+    /// Pretend to be a DGT board showing the game to other software, such as a chess
+    /// GUI, on this serial port, or on a new pseudo-terminal with pty
+    #[arg(long)]
+    virtual_board: Option<String>,
+    /// Keep fen.txt, last_move.txt, white_clock.txt and black_clock.txt in this
+    /// directory up to date, for overlay tools that watch files
+    #[arg(long, env = "JACKOLOPE_POSITION_FILES")]
+    position_files: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Clone)]
+struct OutputArgs {
+    /// Write the game as PGN to this file after every move
+    #[arg(long, env = "JACKOLOPE_PGN")]
+    pgn: Option<PathBuf>,
+    /// Once the game ends, review it with this UCI engine and write an annotated PGN
+    /// and a summary next to the PGN file
+    #[arg(long, env = "JACKOLOPE_REVIEW_ENGINE", requires = "pgn")]
+    review_engine: Option<PathBuf>,
+    /// Search depth per position when reviewing
+    #[arg(long, env = "JACKOLOPE_REVIEW_DEPTH", default_value_t = 14)]
+    review_depth: u32,
+    /// How the board is drawn: unicode glyphs, letters, or coloured letters
+    #[arg(long, env = "JACKOLOPE_PIECES", default_value_t)]
+    pieces: PieceStyle,
+    /// Side to draw the board from, white or black at the bottom
+    #[arg(long, env = "JACKOLOPE_BOTTOM", default_value_t)]
+    bottom: Orientation,
+}
+
+impl OutputArgs {
+    fn style(&self) -> Style {
+        Style {
+            pieces: self.pieces,
+            bottom: self.bottom,
+        }
+    }
+}
+
+fn parse_variant(name: &str) -> Result<Arc<dyn Variant>, String> {
+    variant::from_name(name).ok_or_else(|| format!("unknown variant {:?}", name))
+}
+
+fn parse_colour(name: &str) -> Result<PieceColor, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "white" => Ok(PieceColor::White),
+        "black" => Ok(PieceColor::Black),
+        _ => Err(format!("no colour {:?}, white or black", name)),
+    }
+}
+
+/// The event pipeline shared by live boards and session replays
+struct App {
+    game: Option<GameBoard>,
+    variant: Arc<dyn Variant>,
+    output: OutputArgs,
+    engine: Option<Engine>,
+    relays: Relays,
+    clock_moves: bool,
+    /// Language of what the players read and hear
+    locale: Locale,
+    /// Tell where a lifted piece can go and when it is put down where it can not
+    training: bool,
+    leds: bool,
+    /// Move currently lit on the board
+    lit: Option<(u8, u8)>,
+    /// Output for the board, written by the event loop
+    outputs: Vec<BoardOutput>,
+    /// Labels and players of known boards by serial number, from the config file
+    known_boards: HashMap<String, GameTags>,
+    /// Tags of the board being followed, given to every game on it
+    tags: GameTags,
+    /// Arrival of the first field update of the move being made
+    move_started: Option<Instant>,
+    /// Wall clock time of the event being handled in milliseconds since the Unix
+    /// epoch, as recorded in the session log
+    now_ms: u64,
+    /// What the clock buttons signal
+    buttons: ButtonMap,
+    /// A draw offered and not yet taken up
+    draw_offer: DrawOffer,
+    /// Times and status the clock reported last
+    clock: Option<(Remaining, Remaining, ClockStatus)>,
+    /// The arbiter stopped the clock
+    paused: bool,
+    /// Clock setting for every new game
+    time_control: Option<TimeControl>,
+    /// Keep the time of every new game on the host, see `LocalClock`
+    simulate_clock: bool,
+    /// Time kept on the host for the current game
+    local_clock: Option<LocalClock>,
+    /// The DGT3000 set again after every press for the increment or delay it does not
+    /// keep itself, when the time is not kept on the host
+    clock_keeper: Option<StandaloneClock>,
+    /// Wait for the clock to be pressed before taking a move as made
+    confirm_moves: bool,
+    /// A move was detected and waits for the clock to be pressed
+    unconfirmed: bool,
+    /// How new games tell moves from what happens on the board
+    policy: DetectionPolicy,
+    /// The board was disturbed and has not been restored yet
+    disturbed: bool,
+    /// Where move detection stood after the last field update
+    detection: DetectionState,
+    /// Games followed so far, counting the current one
+    game_number: u32,
+    /// Match the games belong to, if any
+    contest: Option<Match>,
+    /// What the clock beeps for
+    beeps: Beeps,
+    /// White and black were warned of their low time with a beep
+    low_time_warned: [bool; 2],
+}
+
+/// Something to show on the board or the clock attached to it
+#[derive(Debug)]
+enum BoardOutput {
+    Clock(ClockCommand),
+    Highlight {
+        from: u8,
+        to: u8,
+    },
+    /// Blink the square of a move to take back
+    Flash(u8),
+    ClearLeds,
+    /// Read the position again, after a field update that can not be trusted
+    RequestBoard,
+}
+
+/// Write `outputs` to the board, skipping what it has no hardware for
+fn send_outputs(board: &mut dyn ElectronicBoard, outputs: impl IntoIterator<Item = BoardOutput>) {
+    for output in outputs {
+        let result = match &output {
+            BoardOutput::Clock(command) => board.send_clock(command).map(|_| ()),
+            BoardOutput::Highlight { from, to } => board
+                .leds()
+                .map_or(Ok(()), |leds| leds.highlight_move(*from, *to)),
+            BoardOutput::Flash(grid) => {
+                board.leds().map_or(Ok(()), |leds| leds.flash_square(*grid))
+            }
+            BoardOutput::ClearLeds => board.leds().map_or(Ok(()), |leds| leds.clear()),
+            BoardOutput::RequestBoard => board.request_board().map(|_| ()),
+        };
+        if let Err(e) = result {
+            warn!(error = %e, ?output, "failed to update the board");
+        }
+    }
+}
+
+impl App {
+    fn new(variant: Arc<dyn Variant>, output: OutputArgs) -> Self {
+        App {
+            game: None,
+            variant,
+            output,
+            engine: None,
+            relays: Relays::new(),
+            clock_moves: false,
+            locale: Locale::default(),
+            training: false,
+            leds: false,
+            lit: None,
+            outputs: Vec::new(),
+            known_boards: HashMap::new(),
+            tags: GameTags::default(),
+            move_started: None,
+            now_ms: 0,
+            buttons: ButtonMap::default(),
+            draw_offer: DrawOffer::default(),
+            clock: None,
+            paused: false,
+            time_control: None,
+            simulate_clock: false,
+            local_clock: None,
+            clock_keeper: None,
+            confirm_moves: false,
+            unconfirmed: false,
+            policy: DetectionPolicy::default(),
+            disturbed: false,
+            detection: DetectionState::Idle,
+            game_number: 1,
+            contest: None,
+            beeps: Beeps::default(),
+            low_time_warned: [false; 2],
+        }
+    }
+
+    /// Review the finished game with an engine, writing `<name>.annotated.pgn` and
+    /// `<name>.report.txt` next to the PGN
+    fn review(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let (Some(game), Some(pgn_path), Some(engine)) = (
+            self.game.as_ref(),
+            self.pgn_path(),
+            self.output.review_engine.as_ref(),
+        ) else {
+            return Ok(());
+        };
+        info!(moves = game.moves().len(), "reviewing game");
+        let mut searcher = Searcher::spawn(engine, 2)?;
+        let report = report::analyse(game, &mut searcher, self.output.review_depth)?;
+        let annotated = pgn_path.with_extension("annotated.pgn");
+        std::fs::write(
+            &annotated,
+            pgn::to_annotated_pgn(game, &report.annotations()),
+        )?;
+        let summary = report.summary() + &game.timing_stats().summary();
+        std::fs::write(pgn_path.with_extension("report.txt"), &summary)?;
+        print!("{}", summary);
+        info!(path = %annotated.display(), "review written");
+        Ok(())
+    }
+
+    /// Report the outcome of comparing the board with the game after it changed
+    fn synced(&mut self, state: SyncState, was_out_of_sync: bool) {
+        if let Some(detection) = self
+            .game
+            .as_ref()
+            .map(GameBoard::detection_state)
+            .filter(|detection| *detection != self.detection)
+        {
+            self.handle_event(&Event::Detection(detection));
+        }
+        if self.disturbed && matches!(state, SyncState::InSync | SyncState::Moved(_)) {
+            self.handle_event(&Event::BoardRestored);
+        }
+        let Some(game) = self.game.as_mut() else {
+            return;
+        };
+        match state {
+            SyncState::Moved(mv) => {
+                info!(?mv, fen = %game.fen(), "move detected");
+                if let Some(started) = self.move_started.take() {
+                    METRICS
+                        .move_latency
+                        .observe(started.elapsed().as_secs_f64());
+                }
+                print!("{}", self.output.style().board(game.board(), game.start()));
+                // A move that ends the game needs no clock press
+                if self.confirm_moves && game.outcome().is_none() {
+                    // A reply means the press went unnoticed, the next one takes both
+                    if !self.unconfirmed {
+                        info!("waiting for the clock to be pressed");
+                    }
+                    self.unconfirmed = true;
+                    return;
+                }
+                self.commit_move();
+            }
+            SyncState::InSync if was_out_of_sync => {
+                self.move_started = None;
+                info!("board back in sync");
+                print!("{}", self.output.style().board(game.board(), game.start()));
+            }
+            SyncState::TakenBack(mv) => {
+                info!(?mv, fen = %game.fen(), "move taken back");
+                print!("{}", self.output.style().board(game.board(), game.start()));
+                self.relays.moved(game);
+                self.unconfirmed = false;
+                self.draw_offer.withdraw();
+                self.write_pgn();
+                self.analyze();
+            }
+            SyncState::Disturbed => {
+                if !self.disturbed {
+                    self.handle_event(&Event::BoardDisturbed);
+                }
+                let Some(game) = self.game.as_ref() else {
+                    return;
+                };
+                for step in game.recovery_plan() {
+                    println!("  {}", self.output.style().correction(&step, game.start()));
+                }
+            }
+            SyncState::OutOfSync => {
+                if !was_out_of_sync {
+                    warn!("board out of sync with the game, restore it as follows");
+                    if self.beeps.illegal {
+                        self.outputs
+                            .push(BoardOutput::Clock(ClockCommand::beep(Beeps::ILLEGAL)));
+                    }
+                }
+                for step in game.recovery_plan() {
+                    println!("  {}", self.output.style().correction(&step, game.start()));
+                }
+            }
+            // A piece touched and put back is not the start of a move
+            SyncState::InSync => self.move_started = None,
+            SyncState::Pending => {}
+        }
+    }
+
+    /// Take the moves detected so far as made: run the clock of the side to move, tell
+    /// the relays and the engine, and end the game if they did
+    fn commit_move(&mut self) {
+        let pgn_path = self.pgn_path();
+        let Some(game) = self.game.as_mut() else {
+            return;
+        };
+        self.unconfirmed = false;
+        if let Some(local) = self.local_clock.as_mut() {
+            local.start_turn(game.to_move(), Instant::now());
+        }
+        self.relays.moved(game);
+        if let (true, Some(san)) = (self.clock_moves, pgn::last_san(game)) {
+            self.outputs.push(BoardOutput::Clock(ClockCommand::Text {
+                text: self.locale.san(&san),
+                beep: true,
+            }));
+        }
+        if self.lit.take().is_some() {
+            self.outputs.push(BoardOutput::ClearLeds);
+        }
+        // Showing the move on the clock beeps already
+        if self.beeps.moves && !self.clock_moves {
+            self.outputs
+                .push(BoardOutput::Clock(ClockCommand::beep(Beeps::MOVE)));
+        }
+        if let Some(path) = &pgn_path {
+            if let Err(e) = std::fs::write(path, pgn::to_pgn(game)) {
+                warn!(error = %e, path = %path.display(), "failed to write PGN");
+            }
+        }
+        let opening = game
+            .opening()
+            .filter(|opening| opening.plies == game.moves().len())
+            .map(|opening| Event::Opening {
+                eco: opening.eco.to_string(),
+                name: opening.name.to_string(),
+            });
+        let evaluation = Event::Evaluation {
+            material: game.material_balance(),
+            centipawns: game.evaluation(),
+        };
+        let end = if let Some(outcome) = game.outcome() {
+            info!(?outcome, "game over");
+            None
+        } else {
+            game.draw()
+        };
+        let finished = game.outcome().is_some() || matches!(end, Some(Event::AutoDraw(_)));
+        for event in [evaluation].iter().chain(&opening).chain(&end) {
+            self.handle_event(event);
+        }
+        self.analyze();
+        if finished {
+            self.game_over();
+        }
+    }
+
+    /// Report the result of the game and review it
+    fn game_over(&mut self) {
+        if let Some(game) = self.game.as_ref() {
+            METRICS.game_moves.observe(game.moves().len() as f64);
+            self.relays.result(game);
+            if self.clock_moves {
+                self.outputs.push(BoardOutput::Clock(ClockCommand::Text {
+                    text: pgn::result(game).to_string(),
+                    beep: false,
+                }));
+            }
+        }
+        if let Err(e) = self.review() {
+            warn!(error = %e, "game review failed");
+        }
+    }
+
+    /// End the game with a result the players gave from the clock
+    fn conclude(&mut self, outcome: Outcome) {
+        let Some(game) = self.game.as_mut() else {
+            return;
+        };
+        info!(?outcome, "game over");
+        game.conclude(outcome);
+        self.write_pgn();
+        self.game_over();
+    }
+
+    /// Set the clock to its last reported times plus `white` and `black` seconds,
+    /// running for `run` or stopped
+    fn set_clock(&mut self, white: i32, black: i32, run: PieceColor) {
+        let Some((white_time, black_time, _)) = self.clock else {
+            warn!("no clock times reported yet, leaving the clock alone");
+            return;
+        };
+        let add = |time: Remaining, seconds: i32| {
+            Remaining::from_seconds(time.total_seconds().saturating_add_signed(seconds))
+        };
+        if let Some(local) = self.local_clock.as_mut() {
+            local.set(
+                add(white_time, white),
+                add(black_time, black),
+                run,
+                Instant::now(),
+            );
+        }
+        // White sits on the left in the usual setup
+        let run = match run {
+            PieceColor::White => Some(ClockSide::Left),
+            PieceColor::Black => Some(ClockSide::Right),
+            PieceColor::None => None,
+        };
+        self.outputs
+            .push(BoardOutput::Clock(ClockCommand::SetAndRun {
+                left: add(white_time, white),
+                right: add(black_time, black),
+                run,
+            }));
+    }
+
+    /// Report the times of the local clock once they change, and stop it once the
+    /// game is over
+    fn tick(&mut self) {
+        let Some(local) = self.local_clock.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        if self
+            .game
+            .as_ref()
+            .is_some_and(|game| pgn::result(game) != "*")
+        {
+            local.stop(now);
+        }
+        let (white, black) = local.remaining(now);
+        let status = match local.running() {
+            PieceColor::Black => ClockStatus::BlacksTurn,
+            _ => ClockStatus::WhitesTurn,
+        };
+        if self.clock == Some((white, black, status)) {
+            return;
+        }
+        debug!(%white, %black, ?status, "local clock");
+        self.clock = Some((white, black, status));
+        if let Some(game) = self.game.as_mut() {
+            game.note_clock(white, black);
+        }
+        self.warn_low_time();
+        self.relays.clock(white, black);
+    }
+
+    /// Side whose clock is running, as last reported
+    fn running(&self) -> PieceColor {
+        match self.clock {
+            _ if self.paused => PieceColor::None,
+            Some((_, _, ClockStatus::WhitesTurn)) => PieceColor::White,
+            Some((_, _, ClockStatus::BlacksTurn)) => PieceColor::Black,
+            _ => PieceColor::None,
+        }
+    }
+
+    /// Beep once for each player whose running clock drops below the low time
+    fn warn_low_time(&mut self) {
+        let (Some(limit), Some((white, black, _))) = (self.beeps.low_time, self.clock) else {
+            return;
+        };
+        let (side, time) = match self.running() {
+            PieceColor::White => (0, white),
+            PieceColor::Black => (1, black),
+            PieceColor::None => return,
+        };
+        if time.total_seconds() < limit && !self.low_time_warned[side] {
+            self.low_time_warned[side] = true;
+            info!(%time, "low time");
+            self.outputs
+                .push(BoardOutput::Clock(ClockCommand::beep(Beeps::LOW_TIME)));
+        }
+    }
+
+    /// Carry out what the arbiter asked for
+    fn intervene(&mut self, intervention: &Intervention) {
+        info!(%intervention, "arbiter intervention");
+        match intervention {
+            Intervention::Pause => {
+                self.set_clock(0, 0, PieceColor::None);
+                self.paused = true;
+            }
+            Intervention::Resume => {
+                self.paused = false;
+                let to_move = self
+                    .game
+                    .as_ref()
+                    .map_or(PieceColor::White, |g| g.to_move());
+                self.set_clock(0, 0, to_move);
+            }
+            Intervention::AdjustClock { colour, seconds } => {
+                let (white, black) = match colour {
+                    PieceColor::White => (*seconds, 0),
+                    _ => (0, *seconds),
+                };
+                self.set_clock(white, black, self.running());
+            }
+            Intervention::SetMoves(moves) => {
+                let Some(game) = self.game.as_mut() else {
+                    warn!("no game to correct yet");
+                    return;
+                };
+                if let Err(e) = game.replace_moves(moves) {
+                    warn!(error = %e, "arbiter's moves rejected");
+                    return;
+                }
+                info!(
+                    fen = %game.fen(),
+                    out_of_sync = game.is_out_of_sync(),
+                    "moves corrected"
+                );
+                print!("{}", self.output.style().board(game.board(), game.start()));
+                self.relays.moved(game);
+                self.draw_offer.withdraw();
+                self.write_pgn();
+                self.analyze();
+            }
+            Intervention::SetResult(winner) => self.conclude(Outcome {
+                winner: *winner,
+                termination: Termination::Adjudication,
+            }),
+            Intervention::Note {
+                number,
+                colour,
+                text,
+            } => {
+                let Some(game) = self.game.as_mut() else {
+                    warn!("no game to comment on yet");
+                    return;
+                };
+                if let Err(e) = game.annotate(*number, *colour, text) {
+                    warn!(error = %e, "note rejected");
+                    return;
+                }
+                self.relays.moved(game);
+                self.write_pgn();
+            }
+        }
+    }
+
+    /// Turn clock buttons into the signal they are mapped to, if any
+    fn clock_signal(&mut self, pressed: ButtonSet) {
+        let (Some(signal), Some(game)) = (self.buttons.signal(pressed), self.game.as_ref()) else {
+            return;
+        };
+        let to_move = game.to_move();
+        let event = match signal {
+            // A draw is offered after making a move and before pressing the clock, and
+            // accepted by the opponent on their own time, so it counts for the player
+            // whose clock runs
+            ClockSignal::DrawOffered => Event::DrawOffered(match self.running() {
+                PieceColor::None => to_move.opposite(),
+                running => running,
+            }),
+            ClockSignal::Resigned => Event::Resigned(to_move),
+            ClockSignal::Paused => Event::ClockPaused,
+        };
+        self.handle_event(&event);
+    }
+
+    /// Light the squares of a move in UCI notation, unless they already are
+    fn light_move(&mut self, uci: &str) {
+        let Some(game) = self.game.as_ref() else {
+            return;
+        };
+        let square = |range| uci.get(range).and_then(rules::parse_square);
+        let (Some(from), Some(to)) = (square(0..2), square(2..4)) else {
+            return;
+        };
+        let squares = (game.start().square_grid(from), game.start().square_grid(to));
+        if self.lit != Some(squares) {
+            self.lit = Some(squares);
+            self.outputs.push(BoardOutput::Highlight {
+                from: squares.0,
+                to: squares.1,
+            });
+        }
+    }
+
+    /// The PGN file of the current game, numbered from the second game on
+    fn pgn_path(&self) -> Option<PathBuf> {
+        let path = self.output.pgn.as_ref()?;
+        Some(numbered(path, self.game_number))
+    }
+
+    /// Tags of the current game: those of the board, with the players, round and
+    /// score of the match, or else the game number added to the round from the second
+    /// game on
+    fn game_tags(&self) -> GameTags {
+        let mut tags = match &self.contest {
+            Some(contest) => contest.tags(&self.tags),
+            None => self.tags.clone(),
+        };
+        if self.game_number > 1 && self.contest.is_none() {
+            tags.round = Some(match &tags.round {
+                Some(round) => format!("{}.{}", round, self.game_number),
+                None => self.game_number.to_string(),
+            });
+        }
+        if let Some(time_control) = self.time_control {
+            tags.time_control = time_control.pgn_tag();
+            tags.time_control_timing = time_control.timing_tag();
+            if time_control.is_odds() {
+                tags.white_time_control = Some(time_control.white.pgn_tag());
+                tags.black_time_control = Some(time_control.black.pgn_tag());
+            }
+        }
+        tags
+    }
+
+    /// Follow a game on `board`, setting the clock when the pieces stand ready to
+    /// start
+    fn start_game(&mut self, board: ChessBoard) {
+        let mut game = GameBoardBuilder::new()
+            .variant(self.variant.clone())
+            .policy(self.policy)
+            .build(board);
+        game.set_tags(self.game_tags());
+        let start = game.is_starting_position();
+        info!(
+            ?start,
+            variant = game.variant().name(),
+            number = self.game_number,
+            "received board"
+        );
+        print!("{}", self.output.style().board(game.board(), game.start()));
+        self.relays.new_game(&game);
+        if self.clock_moves {
+            self.outputs.push(BoardOutput::Clock(ClockCommand::EndText));
+        }
+        if let (Some(time_control), false) = (self.time_control, start == StartPosition::None) {
+            self.outputs.push(BoardOutput::Clock(time_control.start()));
+        }
+        self.local_clock = self
+            .time_control
+            .filter(|_| self.simulate_clock && start != StartPosition::None)
+            .map(LocalClock::new);
+        self.clock_keeper = self
+            .time_control
+            .filter(|_| !self.simulate_clock && start != StartPosition::None)
+            .map(StandaloneClock::new);
+        if self.lit.take().is_some() {
+            self.outputs.push(BoardOutput::ClearLeds);
+        }
+        self.game = Some(game);
+        self.move_started = None;
+        self.unconfirmed = false;
+        self.disturbed = false;
+        self.detection = DetectionState::Idle;
+        self.draw_offer.withdraw();
+        self.paused = false;
+        self.low_time_warned = [false; 2];
+        self.analyze();
+    }
+
+    /// Archive the game when the pieces are set up again and start the next one
+    fn next_game(&mut self) {
+        let Some(game) = self.game.as_ref() else {
+            return;
+        };
+        let board = *game.board();
+        // Finished games were reported when they ended
+        if pgn::result(game) == "*" {
+            self.relays.result(game);
+        }
+        self.write_pgn();
+        if let Some(contest) = self.contest.as_mut() {
+            contest.record(pgn::result(game));
+            info!(score = %contest, "match score");
+        }
+        info!(
+            number = self.game_number,
+            moves = game.moves().len(),
+            result = pgn::result(game),
+            "game archived, new game set up"
+        );
+        self.game_number += 1;
+        self.start_game(board);
+    }
+
+    /// Write the game to the PGN file, if there is one
+    fn write_pgn(&self) {
+        if let (Some(game), Some(path)) = (self.game.as_ref(), self.pgn_path()) {
+            if let Err(e) = std::fs::write(&path, pgn::to_pgn(game)) {
+                warn!(error = %e, path = %path.display(), "failed to write PGN");
+            }
+        }
+    }
+
+    /// Save the game and blank the board's displays before exiting
+    fn finish(&mut self) {
+        self.write_pgn();
+        self.outputs.push(BoardOutput::Clock(ClockCommand::EndText));
+        if self.lit.take().is_some() {
+            self.outputs.push(BoardOutput::ClearLeds);
+        }
+    }
+
+    /// Tell a player in training about the move they are making
+    fn hint(&mut self) {
+        let Some(hint) = self.game.as_ref().and_then(GameBoard::hint) else {
+            return;
+        };
+        println!("  {}", hint);
+        self.relays.hint(&hint.to_string());
+        if let (Hint::Illegal { .. }, true) = (&hint, self.beeps.illegal) {
+            self.outputs
+                .push(BoardOutput::Clock(ClockCommand::beep(Beeps::ILLEGAL)));
+        }
+    }
+
+    /// Point the analysis engine, if any, at the current position
+    fn analyze(&mut self) {
+        if let (Some(engine), Some(game)) = (self.engine.as_mut(), self.game.as_ref()) {
+            if let Err(e) = engine.analyze(game.position()) {
+                warn!(error = %e, "failed to send position to engine");
+            }
+        }
+    }
+
+    /// Carry on with the saved game on `board`, as read on starting again
+    fn carry_on(&mut self, board: ChessBoard) {
+        let Some(game) = self.game.as_mut() else {
+            return;
+        };
+        let was_out_of_sync = game.is_out_of_sync();
+        let state = game.resync(board);
+        info!(
+            moves = game.moves().len(),
+            ?state,
+            "carrying on with the saved game"
+        );
+        // The saved times go on from where they were left
+        if let (Some(time_control), Some((white, black, _)), true) =
+            (self.time_control, self.clock, self.simulate_clock)
+        {
+            let mut local = LocalClock::new(time_control);
+            local.set(white, black, game.to_move(), Instant::now());
+            self.local_clock = Some(local);
+        }
+        self.clock_keeper = self
+            .time_control
+            .filter(|_| !self.simulate_clock)
+            .map(StandaloneClock::new);
+        self.synced(state, was_out_of_sync);
+        self.analyze();
+    }
+
+    fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::BoardDump(board) => {
+                if let Some(game) = self
+                    .game
+                    .as_mut()
+                    .filter(|game| game.needs_resync().is_some())
+                {
+                    let was_out_of_sync = game.is_out_of_sync();
+                    let mut state = game.resync(*board);
+                    info!(?state, "board read again");
+                    self.synced(state, was_out_of_sync);
+                    // Updates lost before the board was read may hide a reply as well
+                    while let (SyncState::Moved(_), Some(game)) = (state, self.game.as_mut()) {
+                        state = game.sync();
+                        self.synced(state, false);
+                    }
+                    return;
+                }
+                // A garbled dump is no reason to give up the game followed so far
+                if let (Some(_), Err(reason), true) =
+                    (&self.game, board.check(), self.variant.standard_material())
+                {
+                    warn!(%reason, "implausible board, keeping the game");
+                    return;
+                }
+                self.start_game(*board);
+            }
+            Event::FieldUpdate(mv) => {
+                let Some(game) = self.game.as_mut() else {
+                    warn!(%mv, "field update before board dump, ignoring");
+                    return;
+                };
+                let suspect = game.needs_resync();
+                let Some(change) = game.apply_move_at(*mv, Some(self.now_ms)) else {
+                    if let (None, Some(reason)) = (suspect, game.needs_resync()) {
+                        warn!(%mv, %reason, "field update can not be real, reading the board again");
+                        self.outputs.push(BoardOutput::RequestBoard);
+                    }
+                    return;
+                };
+                debug!(
+                    grid = change.grid,
+                    before = ?change.before,
+                    after = ?change.after,
+                    adjusted = change.adjusted,
+                    "square changed"
+                );
+                let micro = MicroEvent::new(&change, game.start(), self.now_ms);
+                // A piece only adjusted does not time the move
+                if !change.adjusted {
+                    game.note_micro_event(&micro);
+                }
+                let was_out_of_sync = game.is_out_of_sync();
+                self.move_started.get_or_insert_with(Instant::now);
+                let mut state = game.sync();
+                // Knights going home by legal moves are not a new game
+                if !matches!(state, SyncState::Moved(_)) && game.new_game_set_up() {
+                    self.handle_event(&Event::NewGameStarted);
+                    return;
+                }
+                self.handle_event(&Event::Micro(micro));
+                if self.training && !matches!(state, SyncState::Moved(_)) {
+                    self.hint();
+                }
+                self.synced(state, was_out_of_sync);
+                // In blitz the reply may be on the board by the time the move is
+                while let (SyncState::Moved(_), Some(game)) = (state, self.game.as_mut()) {
+                    state = game.sync();
+                    self.synced(state, false);
+                }
+            }
+            Event::Clock {
+                white_time,
+                black_time,
+                status,
+            } => {
+                info!(?white_time, ?black_time, ?status, "clock update");
+                if let Some(local) = self.local_clock.as_mut() {
+                    local.reconcile(*white_time, *black_time, *status, Instant::now());
+                }
+                if let Some(keeper) = self.clock_keeper.as_mut() {
+                    let now = Instant::now();
+                    if let Some(command) = keeper.report(*white_time, *black_time, *status, now) {
+                        self.outputs.push(BoardOutput::Clock(command));
+                    }
+                }
+                self.clock = Some((*white_time, *black_time, *status));
+                if let Some(game) = self.game.as_mut() {
+                    game.note_clock(*white_time, *black_time);
+                }
+                self.warn_low_time();
+                self.relays.clock(*white_time, *black_time);
+                // The clock of the side to move started, so the move before is complete
+                let to_move = self.game.as_ref().map(|game| game.to_move());
+                if self.unconfirmed && to_move.is_some_and(|side| side == self.running()) {
+                    info!("clock pressed");
+                    self.commit_move();
+                }
+            }
+            Event::MoveConfirmed => {
+                if self.unconfirmed {
+                    info!("move confirmed");
+                    self.commit_move();
+                }
+            }
+            Event::ClockButton(button) => {
+                info!(button, "clock button pressed");
+                self.clock_signal(ButtonSet::single(*button));
+            }
+            Event::ClockButtons(pressed) => {
+                info!(%pressed, "clock buttons pressed");
+                self.clock_signal(*pressed);
+            }
+            Event::DrawOffered(colour) => {
+                let Some(plies) = self.game.as_ref().map(|game| game.moves().len()) else {
+                    return;
+                };
+                if self.draw_offer.signal(*colour, plies) {
+                    self.conclude(Outcome {
+                        winner: PieceColor::None,
+                        termination: Termination::Agreement,
+                    });
+                } else {
+                    info!(?colour, "draw offered");
+                }
+            }
+            Event::Resigned(colour) => self.conclude(Outcome {
+                winner: colour.opposite(),
+                termination: Termination::Resignation,
+            }),
+            Event::ClockPaused => info!("clock paused"),
+            Event::Micro(micro) => debug!(?micro, "piece lifted or placed"),
+            Event::NewGameStarted => self.next_game(),
+            Event::BoardDisturbed => {
+                self.disturbed = true;
+                warn!("board disturbed, put the pieces back as follows");
+                if self.beeps.illegal {
+                    self.outputs
+                        .push(BoardOutput::Clock(ClockCommand::beep(Beeps::ILLEGAL)));
+                }
+            }
+            Event::BoardRestored => {
+                self.disturbed = false;
+                info!("board restored");
+            }
+            Event::Detection(detection) => {
+                self.detection = *detection;
+                status::update(|state| state.detection = Some(*detection));
+                match detection {
+                    DetectionState::AwaitingPromotionPiece => {
+                        info!("waiting for the pawn to be swapped for the piece it becomes")
+                    }
+                    _ => debug!(?detection, "move detection"),
+                }
+            }
+            Event::Arbiter(intervention) => self.intervene(intervention),
+            Event::SerialNumber(serial) => {
+                let Some(tags) = self.known_boards.get(serial) else {
+                    info!(%serial, "board serial number");
+                    return;
+                };
+                info!(%serial, label = tags.label(), "board identified");
+                self.tags = tags.clone();
+                let tags = self.game_tags();
+                if let Some(game) = self.game.as_mut() {
+                    game.set_tags(tags);
+                }
+            }
+            Event::Unknown { msg_type, data } => info!(
+                msg_type = format_args!("0x{:02x}", msg_type),
+                data = %jackolope::repl::hex(data),
+                "message of a type not known yet, please report it"
+            ),
+            Event::DrawClaimable(reason) => info!(?reason, "draw can be claimed"),
+            Event::AutoDraw(reason) => info!(?reason, "game drawn"),
+            Event::Opening { eco, name } => info!(%eco, %name, "opening"),
+            Event::Evaluation {
+                material,
+                centipawns,
+            } => {
+                info!(material, centipawns, "evaluation");
+                // The engine's opinion is worth more where there is one
+                if self.engine.is_none() {
+                    self.relays.evaluation(*centipawns);
+                }
+            }
+            Event::Analysis { depth, score, pv } => {
+                info!(depth, ?score, pv = pv.join(" "), "analysis");
+                self.relays.evaluation(score.to_centipawns());
+                if let (true, Some(best)) = (self.leds, pv.first()) {
+                    self.light_move(best);
+                }
+            }
+        }
+    }
+}
+
+fn watch(
+    args: WatchArgs,
+    engine: Option<PathBuf>,
+    known_boards: HashMap<String, GameTags>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let state_dir = args.daemon.then(|| daemon::state_dir(args.state_dir));
+    let _pid_file = state_dir
+        .as_deref()
+        .map(daemon::PidFile::create)
+        .transpose()?;
+    let mut board = args.board.open()?;
+    board.allow_implausible_dumps(!args.variant.standard_material());
+    let dump = board.board()?;
+    let device = board.device_info()?;
+    info!(?device, "board identified");
+    status::update(|state| {
+        state.board = Some(device.board.clone());
+        state.serial_number = device.serial_number.clone();
+    });
+    let record = args
+        .record
+        .or_else(|| state_dir.map(|dir| dir.join(daemon::SESSION_LOG)));
+    let retention = Rotation {
+        keep: args.record_keep,
+        ..Rotation::default()
+    };
+    let mut app = App::new(args.variant, args.output);
+    app.time_control = args.time_control;
+    app.simulate_clock = args.local_clock;
+    app.confirm_moves = args.confirm_moves;
+    if args.casual {
+        app.policy = DetectionPolicy::casual();
+    }
+    app.contest = args.contest.contest()?;
+    app.buttons = args.buttons.map();
+    app.clock_moves = args.clock_moves;
+    app.locale = args.locale;
+    app.training = args.training;
+    app.beeps = Beeps {
+        moves: args.beep.contains(&BeepOn::Move),
+        illegal: args.beep.contains(&BeepOn::Illegal),
+        low_time: args.low_time_beep,
+    };
+    app.leds = args.leds;
+    app.known_boards = known_boards;
+    // A game left unfinished is picked up before there are relays to tell its old
+    // moves again
+    let resume = args.resume.unwrap_or(match args.daemon {
+        true => Resume::Auto,
+        false => Resume::Never,
+    });
+    let resumed = match resume {
+        Resume::Never => None,
+        _ => resume_saved(&mut app, record.as_deref(), dump, resume),
+    };
+    let mut logged_game = app.game_number;
+    let mut log = match (&record, resumed) {
+        (Some(path), Some(Saved::SessionLog(number))) => {
+            logged_game = number;
+            Some(SessionLog::append(numbered(path, number))?)
+        }
+        (Some(path), _) => Some(SessionLog::create(numbered(path, logged_game))?),
+        (None, _) => None,
+    };
+    if let Some(path) = &record {
+        prune_logs(path, logged_game, &retention);
+    }
+    if let Some(backend) = args.relay.relay {
+        let broadcast = Broadcast::for_backend(
+            backend,
+            args.relay.lichess_token,
+            args.relay.relay_round,
+            args.relay.relay_pgn,
+        )?;
+        app.relays.add(Box::new(broadcast))?;
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(host) = &args.relay.mqtt {
+        let mqtt = jackolope::mqtt::MqttRelay::connect(host, &args.relay.mqtt_topic)?;
+        app.relays.add(Box::new(mqtt))?;
+    }
+    app.relays.add(Box::new(status::Tracker))?;
+    if let Some(endpoint) = &args.relay.virtual_board {
+        let (emulator, path) = Emulator::open(endpoint)?;
+        println!("Virtual board on {}", path);
+        app.relays.add(Box::new(emulator))?;
+    }
+    if let Some(dir) = args.relay.position_files {
+        app.relays.add(Box::new(relay::PositionFiles::new(dir)?))?;
+    }
+    if let Some(program) = &args.speak {
+        app.relays.add(Box::new(
+            speech::Speech::new(program.as_str()).with_locale(args.locale),
+        ))?;
+    }
+    #[cfg(feature = "resvg")]
+    let overlay_png = args.overlay_png;
+    #[cfg(not(feature = "resvg"))]
+    let overlay_png: Option<PathBuf> = None;
+    if args.overlay.is_some() || overlay_png.is_some() {
+        let overlay = Overlay::new().with_locale(args.locale);
+        #[cfg(feature = "resvg")]
+        let overlay = match overlay_png {
+            Some(path) => overlay.with_png(path)?,
+            None => overlay,
+        };
+        if let Some(addr) = &args.overlay {
+            overlay::serve(addr.as_str(), overlay.scene())?;
+        }
+        app.relays.add(Box::new(overlay))?;
+    }
+    #[cfg(feature = "database")]
+    if let Some(path) = &args.relay.database {
+        let database = jackolope::database::Database::open(path)?;
+        let store = jackolope::database::GameStore::new(database, device.serial_number.clone());
+        app.relays.add(Box::new(store))?;
+    }
+    // Events produced off the serial thread, such as engine analysis
+    let (events_tx, events_rx) = mpsc::channel();
+    #[cfg(unix)]
+    if let (Some(path), Some(token)) = (&args.arbiter_socket, args.arbiter_token) {
+        let events_tx = events_tx.clone();
+        jackolope::arbiter::serve(path, token, move |intervention| {
+            let _ = events_tx.send(Event::Arbiter(intervention));
+        })?;
+    }
+    // Enter stands in for the clock press on boards without a clock
+    if args.confirm_moves {
+        let events_tx = events_tx.clone();
+        std::thread::Builder::new()
+            .name("confirm".to_string())
+            .spawn(move || {
+                for _ in std::io::stdin().lines().map_while(Result::ok) {
+                    if events_tx.send(Event::MoveConfirmed).is_err() {
+                        return;
+                    }
+                }
+            })?;
+    }
+    if let Some(path) = engine {
+        let engine = Engine::spawn(&path, move |info| {
+            let _ = events_tx.send(Event::Analysis {
+                depth: info.depth,
+                score: info.score,
+                pv: info.pv,
+            });
+        })?;
+        info!(name = engine.name().unwrap_or("unknown"), "engine started");
+        app.engine = Some(engine);
+    }
+    // The relays are told the saved game as it was, then what the board made of it
+    if let Some(game) = &app.game {
+        app.relays.new_game(game);
+        if let Some((white, black, _)) = app.clock {
+            app.relays.clock(white, black);
+        }
+    }
+    if let (Some(saved), Some(game)) = (resumed, app.game.as_ref()) {
+        if let Some(log) = log.as_mut() {
+            // A log started from a PGN begins with the game so far, so it replays to it
+            let mut events = Vec::new();
+            if let Saved::Pgn = saved {
+                let moves = game.moves().iter().map(|ply| ply.uci()).collect();
+                events.push(Event::BoardDump(
+                    game.start().layout(game.initial_position()),
+                ));
+                events.push(Event::Arbiter(Intervention::SetMoves(moves)));
+            }
+            events.push(Event::BoardDump(dump));
+            for event in &events {
+                if let Err(e) = log.record(event) {
+                    warn!(error = %e, "failed to write session log");
+                }
+            }
+        }
+        app.now_ms = session::now_ms();
+        app.carry_on(dump);
+    }
+    let stream = args
+        .event_socket
+        .as_deref()
+        .map(EventStream::open)
+        .transpose()?;
+    // Echoes are dropped before anything sees them, the session log included
+    let mut dedup = Dedup::new(Duration::from_millis(args.dedup_window));
+    let mut dispatch = |app: &mut App, event: Event| {
+        if !dedup.accept(&event, Instant::now()) {
+            return;
+        }
+        app.now_ms = session::now_ms();
+        if let Some(stream) = &stream {
+            stream.publish(app.now_ms, &event);
+        }
+        if let Some(log) = log.as_mut() {
+            if let Err(e) = log.record_at(app.now_ms, &event) {
+                warn!(error = %e, "failed to write session log");
+            }
+        }
+        app.handle_event(&event);
+        // Every game gets a log of its own, starting from the board it was set up on
+        if let (Some(path), Some(game)) = (&record, app.game.as_ref()) {
+            if app.game_number != logged_game {
+                logged_game = app.game_number;
+                prune_logs(path, logged_game, &retention);
+                let path = numbered(path, logged_game);
+                log = SessionLog::create(&path)
+                    .and_then(|mut log| {
+                        log.record(&Event::BoardDump(*game.board()))?;
+                        Ok(log)
+                    })
+                    .map_err(|e| warn!(error = %e, path = %path.display(), "failed to start session log"))
+                    .ok();
+            }
+        }
+    };
+
+    // Known first, so the game starts out with the board's label and players
+    if let Some(serial) = device.serial_number {
+        dispatch(&mut app, Event::SerialNumber(serial));
+    }
+    if resumed.is_none() {
+        dispatch(&mut app, Event::BoardDump(dump));
+    }
+
+    board.start_updates()?;
+    daemon::notify_ready();
+    daemon::notify_status(&format!("following {}", board.name()));
+
+    // Ctrl-C ends the loop, reads time out often enough to notice it quickly
+    let stop = Arc::new(AtomicBool::new(false));
+    let flag = stop.clone();
+    ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed))?;
+
+    while !stop.load(Ordering::Relaxed) {
+        // Everything the board sent in a burst is taken in before the outputs go out
+        match board.next_responses() {
+            Ok(responses) => {
+                for event in responses.into_iter().filter_map(Event::from_response) {
+                    dispatch(&mut app, event);
+                }
+            }
+            Err(e) => {
+                warn!(error = %e, "failed to read response");
+            }
+        }
+        while let Ok(event) = events_rx.try_recv() {
+            dispatch(&mut app, event);
+        }
+        app.tick();
+        send_outputs(board.as_mut(), app.outputs.drain(..));
+    }
+
+    info!("shutting down");
+    daemon::notify_stopping();
+    app.finish();
+    send_outputs(board.as_mut(), app.outputs.drain(..));
+    if args.idle_on_exit {
+        board.stop_updates()?;
+    }
+    // Relays deliver what they have queued before their threads are joined
+    drop(app);
+    Err(Failure::new(ExitStatus::Interrupted, "interrupted").into())
+}
+
+/// `path` for the first game, with the game number added to the name for later ones,
+/// e.g. game-2.pgn
+fn numbered(path: &Path, number: u32) -> PathBuf {
+    if number <= 1 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, number),
+    };
+    path.with_file_name(name)
+}
+
+/// Number of the last file numbered after `path`, past any gaps left by the logs of
+/// older games removed
+fn last_numbered(path: &Path) -> Option<u32> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let stem = path.file_stem()?.to_string_lossy().into_owned() + "-";
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name();
+            if Some(name.as_os_str()) == path.file_name() {
+                return Some(1);
+            }
+            let digits = name.to_str()?.strip_prefix(&stem)?.split('.').next()?;
+            let number = digits.parse().ok()?;
+            (numbered(path, number).file_name() == Some(name.as_os_str())).then_some(number)
+        })
+        .max()
+}
 
-fn get_response(port: &mut Box<dyn SerialPort>) -> Result<Response, Box<dyn std::error::Error>> {
-    let mut buffer = [0; 1];
-    loop {
-        port.read_exact(&mut buffer)?;
-        if buffer[0] & 0x80 == 0 {
-            continue;
+/// Remove the session logs at `path` of the games before `number` beyond those
+/// `retention` keeps
+fn prune_logs(path: &Path, number: u32, retention: &Rotation) {
+    let older: Vec<_> = (1..number).map(|n| numbered(path, n)).collect();
+    retention.prune(&older);
+}
+
+/// Replay the session log of the last game recorded at `path` into `app`. The number of
+/// that game, None when there is no log to go on.
+fn restore(app: &mut App, path: &Path) -> Option<u32> {
+    let number = last_numbered(path)?;
+    let path = numbered(path, number);
+    let records = match SessionReader::open(&path) {
+        Ok(records) => records,
+        Err(e) => {
+            warn!(error = %e, path = %path.display(), "failed to read session log");
+            return None;
         }
-        let resp_type = buffer[0] & 0x7F;
-        port.read_exact(&mut buffer)?;
-        if buffer[0] & 0x80 != 0 {
-            continue;
+    };
+    for record in records {
+        match record {
+            Ok(record) => {
+                app.now_ms = record.timestamp_ms;
+                // The board as read when the game was resumed before
+                match (&record.event, &app.game) {
+                    (Event::BoardDump(board), Some(game)) if game.resumed_on(*board).is_some() => {
+                        app.carry_on(*board)
+                    }
+                    (event, _) => app.handle_event(event),
+                }
+            }
+            // The last line may be cut short by the crash that is being recovered from
+            Err(e) => {
+                warn!(error = %e, path = %path.display(), "session log ends in a bad record");
+                break;
+            }
+        }
+    }
+    // What the board was shown back then is not worth showing again
+    app.outputs.clear();
+    // The log holds one game, or the end of it and the start of the next
+    app.game_number += number - 1;
+    info!(number, path = %path.display(), "game restored from the session log");
+    Some(number)
+}
+
+/// Read the last game of the last PGN written to `path` into `app` if it is unfinished,
+/// with the clock times of its `[%clk]` comments. The number of that game.
+fn restore_pgn(app: &mut App, path: &Path) -> Option<u32> {
+    let number = (1..).take_while(|n| numbered(path, *n).exists()).last()?;
+    let path = numbered(path, number);
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| warn!(error = %e, path = %path.display(), "failed to read PGN"))
+        .ok()?;
+    let saved = pgn::parse(&text).pop()?;
+    if saved.tag("Result") != Some("*") || saved.moves.is_empty() {
+        return None;
+    }
+    let game = GameBoard::from_pgn(&saved, app.variant.clone())
+        .map_err(|e| warn!(error = %e, path = %path.display(), "failed to replay PGN"))
+        .ok()?;
+    if let Some((white, black)) = saved.clocks() {
+        let status = match game.to_move() {
+            PieceColor::Black => ClockStatus::BlacksTurn,
+            _ => ClockStatus::WhitesTurn,
+        };
+        app.clock = Some((white, black, status));
+    }
+    info!(number, moves = game.moves().len(), path = %path.display(), "game restored from the PGN");
+    app.game = Some(game);
+    app.game_number = number;
+    Some(number)
+}
+
+/// Where the game to carry on with was saved, the session log with its number
+#[derive(Clone, Copy)]
+enum Saved {
+    SessionLog(u32),
+    Pgn,
+}
+
+/// Restore the unfinished game an earlier run left in the last session log at `record`,
+/// or else in the last PGN, if `board` shows it or is a few pieces off and `resume`
+/// lets it. Otherwise the next game gets a number of its own so the files of the saved
+/// one are kept.
+fn resume_saved(
+    app: &mut App,
+    record: Option<&Path>,
+    board: ChessBoard,
+    resume: Resume,
+) -> Option<Saved> {
+    let pgn = app.output.pgn.clone();
+    let saved = match record.and_then(|path| restore(app, path)) {
+        Some(number) => Saved::SessionLog(number),
+        None => restore_pgn(app, pgn.as_deref()?).map(|_| Saved::Pgn)?,
+    };
+    let fits = app
+        .game
+        .as_ref()
+        .filter(|game| pgn::result(game) == "*")
+        .and_then(|game| Some((game, game.resumed_on(board)?.0)));
+    let accepted = match fits {
+        Some(_) if resume == Resume::Auto => true,
+        Some((game, resumed)) => {
+            println!(
+                "Game {} was left unfinished after {} moves: {}",
+                app.game_number,
+                game.moves().len(),
+                game.fen()
+            );
+            for step in resumed.recovery_plan() {
+                println!(
+                    "  {}",
+                    app.output.style().correction(&step, resumed.start())
+                );
+            }
+            confirm("Resume it?")
+        }
+        None => false,
+    };
+    if accepted {
+        return Some(saved);
+    }
+    if app.game.is_some() {
+        info!(number = app.game_number, "not resuming the saved game");
+    }
+    app.game = None;
+    app.clock = None;
+    app.draw_offer.withdraw();
+    app.paused = false;
+    app.low_time_warned = [false; 2];
+    app.game_number += 1;
+    None
+}
+
+/// Ask a yes or no question on the terminal, yes unless answered otherwise
+fn confirm(question: &str) -> bool {
+    print!("{} [Y/n] ", question);
+    let _ = std::io::Write::flush(&mut std::io::stdout());
+    let mut answer = String::new();
+    let _ = std::io::stdin().read_line(&mut answer);
+    !answer.trim().to_ascii_lowercase().starts_with('n')
+}
+
+/// Read `board` on a thread of its own behind the returned handle. Its events go to
+/// `events`, wrapped by `wrap`.
+fn spawn_board<T: Send + 'static>(
+    name: String,
+    mut board: Box<dyn ElectronicBoard>,
+    events: mpsc::Sender<T>,
+    wrap: impl Fn(Event) -> T + Send + 'static,
+) -> Result<BoardHandle, Box<dyn std::error::Error>> {
+    let dump = board.board()?;
+    board.start_updates()?;
+    events.send(wrap(Event::BoardDump(dump)))?;
+    let handle = BoardHandle::spawn_with(name, board, move |response| {
+        Event::from_response(response.clone()).is_none_or(|event| events.send(wrap(event)).is_ok())
+    })?;
+    Ok(handle)
+}
+
+/// Have the board behind `handle` show `output` once it is done reading
+fn show_on(handle: &BoardHandle, output: BoardOutput) {
+    if let Err(e) = handle.submit(move |board| send_outputs(board, [output])) {
+        debug!(error = %e, "board gone, not showing");
+    }
+}
+
+fn player_name(colour: PieceColor) -> &'static str {
+    match colour {
+        PieceColor::Black => "Black",
+        _ => "White",
+    }
+}
+
+/// Show the last move of `game`, played elsewhere as `san`, on the board waiting for it
+fn show_move_to_copy(game: &GameBoard, san: String, show: impl Fn(BoardOutput)) {
+    if let Some(ply) = game.moves().last() {
+        show(BoardOutput::Highlight {
+            from: game.start().square_grid(ply.from),
+            to: game.start().square_grid(ply.to),
+        });
+    }
+    show(BoardOutput::Clock(ClockCommand::Text {
+        text: san,
+        beep: true,
+    }));
+}
+
+fn write_pgn(path: Option<&Path>, game: &GameBoard) {
+    if let Some(path) = path {
+        if let Err(e) = std::fs::write(path, pgn::to_pgn(game)) {
+            warn!(error = %e, path = %path.display(), "failed to write PGN");
+        }
+    }
+}
+
+/// Tell the player at `board` that it does not show the game, or that it does again
+fn print_board_state(pairing: &Pairing, update: &PairUpdate) {
+    let (board, corrections, problem) = match update {
+        PairUpdate::NotYours { board, corrections } => (
+            *board,
+            corrections,
+            "that move is for the other board, take it back:",
+        ),
+        PairUpdate::OutOfSync { board, corrections } => (
+            *board,
+            corrections,
+            "the board does not show the game, to continue:",
+        ),
+        PairUpdate::Synced { board } => {
+            println!("{}: board matches the game", player_name(*board));
+            return;
+        }
+        PairUpdate::Played { .. } | PairUpdate::Nothing => return,
+    };
+    println!("{}: {}", player_name(board), problem);
+    let start = pairing
+        .game(board)
+        .map_or(StartPosition::Mirror, GameBoard::start);
+    for correction in corrections {
+        println!("  {}", render::correction(correction, start));
+    }
+}
+
+/// Play one game on two boards. Each board is read on a thread of its own, which also
+/// writes what is shown on it.
+fn pair(args: PairArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let white = args.board.open()?;
+    let black = BoardArgs {
+        port: args.black_port,
+        ..args.board
+    }
+    .open()?;
+    let (events_tx, events_rx) = mpsc::channel();
+    let mut outputs = Vec::new();
+    for (colour, board) in [(PieceColor::White, white), (PieceColor::Black, black)] {
+        let name = format!("{:?} board", colour);
+        outputs.push(spawn_board(name, board, events_tx.clone(), move |event| {
+            (colour, event)
+        })?);
+    }
+    drop(events_tx);
+
+    let show = |colour: PieceColor, output: BoardOutput| {
+        show_on(&outputs[(colour == PieceColor::Black) as usize], output);
+    };
+    let mut pairing = Pairing::new(args.variant);
+    for (colour, event) in events_rx {
+        let update = match event {
+            Event::BoardDump(board) => pairing.board_dump(colour, board),
+            Event::FieldUpdate(mv) => pairing.field_update(colour, mv),
+            _ => continue,
+        };
+        match update {
+            PairUpdate::Played { board, san, .. } => {
+                let other = board.opposite();
+                println!(
+                    "{} played {}, {} copies it",
+                    player_name(board),
+                    san,
+                    player_name(other)
+                );
+                let Some(game) = pairing.game(other) else {
+                    continue;
+                };
+                show_move_to_copy(game, san, |output| show(other, output));
+                write_pgn(args.pgn.as_deref(), game);
+            }
+            PairUpdate::Synced { board } => {
+                print_board_state(&pairing, &update);
+                show(board, BoardOutput::ClearLeds);
+            }
+            _ => print_board_state(&pairing, &update),
+        }
+    }
+    Ok(())
+}
+
+/// What the game against a player at the other end of the network is driven by
+enum Remote {
+    Board(Event),
+    /// Authenticated with the other end, which messages can be sent to now
+    Connected(LinkSender),
+    Peer(netplay::Message),
+    /// The connection was lost
+    Gone,
+}
+
+/// Keep a connection to the other player, made again whenever it is lost
+fn spawn_link(
+    listen: Option<String>,
+    connect: Option<String>,
+    token: String,
+    colour: PieceColor,
+    events: mpsc::Sender<Remote>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = listen.map(TcpListener::bind).transpose()?;
+    if let Some(listener) = &listener {
+        println!("Waiting for the other player on {}", listener.local_addr()?);
+    }
+    std::thread::Builder::new()
+        .name("network".to_string())
+        .spawn(move || loop {
+            let stream = match (&listener, &connect) {
+                (Some(listener), _) => listener.accept().map(|(stream, _)| stream),
+                (None, Some(address)) => TcpStream::connect(address),
+                (None, None) => return,
+            };
+            match stream.and_then(|stream| Link::handshake(stream, &token, colour)) {
+                Ok(mut link) => {
+                    let Ok(sender) = link.sender() else {
+                        continue;
+                    };
+                    if events.send(Remote::Connected(sender)).is_err() {
+                        return;
+                    }
+                    loop {
+                        match link.recv() {
+                            Ok(Some(message)) => {
+                                if events.send(Remote::Peer(message)).is_err() {
+                                    return;
+                                }
+                            }
+                            Ok(None) => break,
+                            Err(e) => {
+                                warn!(error = %e, "connection to the other player failed");
+                                break;
+                            }
+                        }
+                    }
+                    if events.send(Remote::Gone).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => warn!(error = %e, "could not pair with the other player"),
+            }
+            if listener.is_none() {
+                std::thread::sleep(netplay::RECONNECT_DELAY);
+            }
+        })?;
+    Ok(())
+}
+
+/// Play one game against someone at another board, who runs the remote command as well.
+/// The moves of the other player are shown here to be copied over, and after a lost
+/// connection both ends send what they have so the one behind can catch up.
+fn remote(args: RemoteArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let colour = args.colour;
+    let opponent = player_name(colour.opposite());
+    let (events_tx, events_rx) = mpsc::channel();
+    let board = args.board.open()?;
+    let handle = spawn_board("board".to_string(), board, events_tx.clone(), Remote::Board)?;
+    spawn_link(args.listen, args.connect, args.token, colour, events_tx)?;
+    let emulator = match &args.virtual_board {
+        Some(endpoint) => {
+            let (emulator, path) = Emulator::open(endpoint)?;
+            println!("Virtual board on {}", path);
+            Some(emulator)
+        }
+        None => None,
+    };
+
+    let show = |output: BoardOutput| show_on(&handle, output);
+    let mut pairing = Pairing::new(args.variant);
+    let mut link: Option<LinkSender> = None;
+    let send = |link: &mut Option<LinkSender>, message: netplay::Message| {
+        if let Some(sender) = link {
+            if let Err(e) = sender.send(&message) {
+                warn!(error = %e, "failed to send to the other player");
+            }
+        }
+    };
+    let moves = |pairing: &Pairing| -> Vec<String> {
+        pairing.game(colour).map_or_else(Vec::new, |game| {
+            game.moves().iter().map(|ply| ply.uci()).collect()
+        })
+    };
+    for event in events_rx {
+        let updates = match event {
+            Remote::Board(Event::BoardDump(board)) => vec![pairing.board_dump(colour, board)],
+            Remote::Board(Event::FieldUpdate(mv)) => vec![pairing.field_update(colour, mv)],
+            Remote::Board(Event::Clock {
+                white_time,
+                black_time,
+                ..
+            }) => {
+                if let Some(emulator) = &emulator {
+                    emulator.show_clock(white_time, black_time);
+                }
+                let clock = netplay::Message::Clock {
+                    white: white_time,
+                    black: black_time,
+                };
+                send(&mut link, clock);
+                continue;
+            }
+            Remote::Board(_) => continue,
+            Remote::Connected(sender) => {
+                println!("Connected to {}", opponent);
+                link = Some(sender);
+                let moves = moves(&pairing);
+                send(&mut link, netplay::Message::Sync { moves });
+                continue;
+            }
+            Remote::Gone => {
+                println!(
+                    "Lost the connection to {}, waiting for it to come back",
+                    opponent
+                );
+                link = None;
+                continue;
+            }
+            Remote::Peer(netplay::Message::Sync { moves: theirs }) => {
+                match netplay::reconcile(&moves(&pairing), &theirs) {
+                    Reconcile::Behind(missing) => missing
+                        .iter()
+                        .map_while(|uci| {
+                            pairing
+                                .remote_move(uci)
+                                .inspect_err(|e| warn!(error = %e, "can not catch up"))
+                                .ok()
+                        })
+                        .collect(),
+                    Reconcile::Diverged(ply) => {
+                        println!(
+                            "The games differ from move {} on, one of them has to be corrected",
+                            ply / 2 + 1
+                        );
+                        continue;
+                    }
+                    Reconcile::InStep | Reconcile::Ahead => continue,
+                }
+            }
+            Remote::Peer(netplay::Message::Move { ply, uci }) => {
+                let played = moves(&pairing).len();
+                if ply != played {
+                    warn!(ply, played, %uci, "move out of turn, asking to sync");
+                    let moves = moves(&pairing);
+                    send(&mut link, netplay::Message::Sync { moves });
+                    continue;
+                }
+                match pairing.remote_move(&uci) {
+                    Ok(update) => vec![update],
+                    Err(e) => {
+                        warn!(error = %e, %uci, "the other player sent a move that can not be played");
+                        continue;
+                    }
+                }
+            }
+            Remote::Peer(netplay::Message::Clock { white, black }) => {
+                debug!(?white, ?black, "clock of the other player");
+                continue;
+            }
+            Remote::Peer(netplay::Message::Result { result }) => {
+                println!("{} reports the game ended {}", opponent, result);
+                continue;
+            }
+            Remote::Peer(message) => {
+                debug!(?message, "unexpected message");
+                continue;
+            }
+        };
+        for update in updates {
+            match update {
+                PairUpdate::Played { board, uci, san } => {
+                    let Some(game) = pairing.game(colour) else {
+                        continue;
+                    };
+                    if board == colour {
+                        println!("You played {}", san);
+                        let ply = game.moves().len() - 1;
+                        send(&mut link, netplay::Message::Move { ply, uci });
+                    } else {
+                        println!("{} played {}, copy it", opponent, san);
+                        show_move_to_copy(game, san, show);
+                    }
+                    write_pgn(args.pgn.as_deref(), game);
+                    if let Some(emulator) = &emulator {
+                        emulator.show(game);
+                    }
+                    if game.outcome().is_some() {
+                        let result = pgn::result(game).to_string();
+                        println!("Game over: {}", result);
+                        send(&mut link, netplay::Message::Result { result });
+                    }
+                }
+                PairUpdate::Synced { .. } => {
+                    print_board_state(&pairing, &update);
+                    show(BoardOutput::ClearLeds);
+                }
+                _ => print_board_state(&pairing, &update),
+            }
+        }
+    }
+    Ok(())
+}
+
+enum Front {
+    Board(Event),
+    /// A line the GUI sent
+    Line(String),
+    /// The GUI closed its end
+    Gone,
+}
+
+/// Tell the player at the board what to change for it to show `game`
+fn print_corrections(game: &GameBoard, problem: &str) {
+    eprintln!("{}", problem);
+    for correction in game.recovery_plan() {
+        eprintln!("  {}", render::correction(&correction, game.start()));
+    }
+}
+
+/// Have `game` follow the game of the GUI from `start` with `moves`
+fn set_up_for_gui(game: &mut GameBoard, start: rules::Position, moves: &[String]) {
+    game.reset_to(start);
+    if let Err(e) = game.replace_moves(moves) {
+        warn!(error = %e, "can not follow the game of the GUI");
+    }
+    if game.is_out_of_sync() {
+        print_corrections(game, "Set up the board for the game of the GUI:");
+    }
+}
+
+/// Be an engine for a chess GUI on stdin and stdout. What is meant for the player at
+/// the board goes to stderr, which GUIs keep out of the protocol.
+fn gui(args: GuiArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let (events_tx, events_rx) = mpsc::channel();
+    let board = args.board.open()?;
+    let handle = spawn_board("board".to_string(), board, events_tx.clone(), Front::Board)?;
+    std::thread::Builder::new()
+        .name("gui".to_string())
+        .spawn(move || {
+            for line in std::io::stdin().lines().map_while(Result::ok) {
+                if events_tx.send(Front::Line(line)).is_err() {
+                    return;
+                }
+            }
+            let _ = events_tx.send(Front::Gone);
+        })?;
+
+    let show = |output: BoardOutput| show_on(&handle, output);
+    let mut bridge = Bridge::new();
+    let mut game: Option<GameBoard> = None;
+    for event in events_rx {
+        let actions = match event {
+            Front::Board(Event::BoardDump(board)) => {
+                let mut fresh = GameBoard::new(board);
+                set_up_for_gui(&mut fresh, bridge.start().clone(), bridge.moves());
+                game = Some(fresh);
+                continue;
+            }
+            Front::Board(Event::FieldUpdate(mv)) => {
+                let Some(game) = game.as_mut() else {
+                    continue;
+                };
+                if game.apply_move(mv).is_none() {
+                    continue;
+                }
+                let was_out_of_sync = game.is_out_of_sync();
+                match game.sync() {
+                    SyncState::Moved(_) => {
+                        let ply = *game.moves().last().expect("a move was just played");
+                        match bridge.board_move(&ply) {
+                            Ok(actions) => actions,
+                            Err(e) => {
+                                game.undo();
+                                print_corrections(game, &format!("Not now, {}. Take it back:", e));
+                                continue;
+                            }
+                        }
+                    }
+                    SyncState::InSync if was_out_of_sync => {
+                        eprintln!("The board shows the game of the GUI");
+                        show(BoardOutput::ClearLeds);
+                        continue;
+                    }
+                    SyncState::OutOfSync | SyncState::Disturbed if !was_out_of_sync => {
+                        print_corrections(
+                            game,
+                            "The board does not show the game of the GUI, to continue:",
+                        );
+                        continue;
+                    }
+                    _ => continue,
+                }
+            }
+            Front::Board(_) => continue,
+            Front::Line(line) => {
+                debug!(%line, "from the GUI");
+                bridge.command(&line)
+            }
+            Front::Gone => break,
+        };
+        for action in actions {
+            match action {
+                Action::Send(line) => {
+                    debug!(%line, "to the GUI");
+                    println!("{}", line);
+                }
+                Action::SetUp { start, moves } => {
+                    if let Some(game) = game.as_mut() {
+                        set_up_for_gui(game, start, &moves);
+                    }
+                }
+                Action::Copy(uci) => {
+                    let Some(game) = game.as_mut() else {
+                        continue;
+                    };
+                    match game.expect_move(&uci) {
+                        Ok(()) => {
+                            let san = pgn::last_san(game).unwrap_or(uci);
+                            eprintln!("The GUI played {}, make it on the board", san);
+                            show_move_to_copy(game, san, show);
+                        }
+                        Err(e) => warn!(error = %e, "can not follow the move of the GUI"),
+                    }
+                }
+                Action::Go => eprintln!("Your move"),
+                Action::Quit => return Ok(()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Follow all boards the manager finds, each with its own event pipeline
+fn boards(
+    args: BoardsArgs,
+    known_boards: HashMap<String, GameTags>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = &args.pgn_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut apps: HashMap<String, App> = HashMap::new();
+    let mut dedups: HashMap<String, Dedup> = HashMap::new();
+    // Boards are logged by their label where one is configured
+    let mut labels: HashMap<String, String> = HashMap::new();
+    let manager = BoardManager::new(args.board, args.baud)
+        .allow_implausible_dumps(!args.variant.standard_material());
+    let boards = manager.spawn()?;
+    while let Some(event) = boards.recv() {
+        match event {
+            BoardEvent::Connected { port, device } => {
+                let tags = device
+                    .serial_number
+                    .as_ref()
+                    .and_then(|serial| known_boards.get(serial))
+                    .cloned()
+                    .unwrap_or_default();
+                let label = tags.label().unwrap_or_else(|| port.clone());
+                info!(%port, serial = ?device.serial_number, %label, "following board");
+                let pgn = args.pgn_dir.as_ref().map(|dir| {
+                    let name: String = port
+                        .chars()
+                        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                        .collect();
+                    dir.join(name.trim_matches('_')).with_extension("pgn")
+                });
+                let output = OutputArgs {
+                    pgn,
+                    review_engine: None,
+                    review_depth: 0,
+                    pieces: PieceStyle::default(),
+                    bottom: Orientation::default(),
+                };
+                let mut app = App::new(args.variant.clone(), output);
+                app.tags = tags;
+                app.time_control = args.time_control;
+                labels.insert(port.clone(), label);
+                dedups.insert(
+                    port.clone(),
+                    Dedup::new(Duration::from_millis(args.dedup_window)),
+                );
+                apps.insert(port, app);
+            }
+            BoardEvent::Event { port, event } => {
+                if let Some(dedup) = dedups.get_mut(&port) {
+                    if !dedup.accept(&event, Instant::now()) {
+                        continue;
+                    }
+                }
+                if let (Some(app), Some(label)) = (apps.get_mut(&port), labels.get(&port)) {
+                    let _span = tracing::info_span!("board", %label).entered();
+                    app.now_ms = session::now_ms();
+                    app.handle_event(&event);
+                    // The boards belong to their driver threads, which are handed the
+                    // output to send
+                    let outputs = std::mem::take(&mut app.outputs);
+                    if outputs.is_empty() {
+                        continue;
+                    }
+                    if let Err(e) = boards.submit(&port, move |board| send_outputs(board, outputs))
+                    {
+                        warn!(error = %e, "failed to update the board");
+                    }
+                }
+            }
+            BoardEvent::Disconnected { port } => {
+                info!(%port, "board gone");
+                apps.remove(&port);
+                labels.remove(&port);
+                dedups.remove(&port);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Serve a simulated board on a new pseudo-terminal, whose path is printed for the
+/// driver to open with `--port`
+#[cfg(unix)]
+fn simulate(args: SimulateArgs) -> Result<(), Box<dyn std::error::Error>> {
+    use jackolope::simulator::Simulator;
+    use serialport::SerialPort;
+
+    let mut moves = args.moves;
+    if let Some(path) = &args.script {
+        let text = std::fs::read_to_string(path)?;
+        moves.extend(text.split_whitespace().map(str::to_string));
+    }
+    if let Some(path) = &args.pgn {
+        let games = pgn::parse(&std::fs::read_to_string(path)?);
+        let first = games.first().ok_or("no game in the PGN file")?;
+        if first.tag("FEN").is_some() {
+            return Err(
+                "the simulated board can only replay games from the starting position".into(),
+            );
+        }
+        let game = GameBoard::from_pgn(first, Arc::new(Standard))?;
+        moves.extend(game.moves().iter().map(|ply| ply.uci()));
+    }
+    let (mut master, slave) = serialport::TTYPort::pair()?;
+    master.set_timeout(Duration::from_millis(50))?;
+    let path = slave.name().ok_or("pseudo-terminal has no name")?;
+    let mut simulator = Simulator::new(master);
+    simulator.script(&moves)?;
+    println!("{}", path);
+    info!(%path, moves = moves.len(), "simulated board ready");
+    // The slave end stays open so the terminal survives the driver closing it
+    let _slave = slave;
+    simulator.run(Duration::from_millis(args.interval))?;
+    Ok(())
+}
+
+/// List archived games, or export them or one of them as PGN
+#[cfg(feature = "database")]
+fn games(args: GamesArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let database = jackolope::database::Database::open(&args.database)?;
+    if let Some(id) = args.id {
+        let game = database.get(id)?.ok_or_else(|| format!("no game {}", id))?;
+        print!("{}", game.pgn);
+        return Ok(());
+    }
+    let query = jackolope::database::Query {
+        player: args.player,
+        board: args.board,
+        result: args.result,
+        limit: Some(args.limit),
+    };
+    let games = database.search(&query)?;
+    match args.export {
+        Some(path) => {
+            let pgn: Vec<&str> = games.iter().rev().map(|game| game.pgn.as_str()).collect();
+            std::fs::write(&path, pgn.join("\n"))?;
+            info!(games = games.len(), path = %path.display(), "games exported");
+        }
+        None => {
+            for game in &games {
+                println!("{}", game);
+            }
         }
-        let mut length = (buffer[0] as usize) << 7;
-        port.read_exact(&mut buffer)?;
-        if buffer[0] & 0x80 != 0 {
+    }
+    Ok(())
+}
+
+/// Send commands to the arbiter console of a running driver
+#[cfg(unix)]
+fn arbiter(args: ArbiterArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = jackolope::arbiter::Client::connect(&args.socket, &args.token)?;
+    if !args.command.is_empty() {
+        client.send(&args.command.join(" "))?;
+        return Ok(());
+    }
+    for line in std::io::stdin().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
             continue;
         }
-        length |= buffer[0] as usize;
-        if length < 3 {
-            return Err("Invalid response length".into());
+        match client.send(&line) {
+            Ok(()) => println!("ok"),
+            Err(e) => println!("{}", e),
+        }
+    }
+    Ok(())
+}
+
+fn replay_session(
+    file: PathBuf,
+    realtime: bool,
+    variant: Arc<dyn Variant>,
+    output: OutputArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut app = App::new(variant, output);
+    let mut last = None;
+    for record in SessionReader::open(file)? {
+        let record = record?;
+        if let (true, Some(last)) = (realtime, last) {
+            std::thread::sleep(Duration::from_millis(
+                record.timestamp_ms.saturating_sub(last),
+            ));
+        }
+        last = Some(record.timestamp_ms);
+        debug!(timestamp_ms = record.timestamp_ms, "replaying event");
+        app.now_ms = record.timestamp_ms;
+        app.handle_event(&record.event);
+    }
+    Ok(())
+}
+
+fn setup(args: SetupArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut board = args.board.open()?;
+    let dump = board.board()?;
+    let start = if args.rotated {
+        StartPosition::Normal
+    } else {
+        StartPosition::Mirror
+    };
+    let mut setup = Setup::new(dump, start, Instant::now());
+    print!("{}", render::unicode(setup.board()));
+    println!("Place the pieces and press Enter when done");
+
+    let (done_tx, done_rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut line = String::new();
+        let _ = std::io::stdin().read_line(&mut line);
+        let _ = done_tx.send(());
+    });
+
+    board.start_updates()?;
+    let settle = args.stable.map(Duration::from_secs);
+    loop {
+        match board.next_response() {
+            Ok(Response::FieldUpdate(mv)) => {
+                if setup.apply_move(mv, Instant::now()) {
+                    print!("{}", render::unicode(setup.board()));
+                }
+            }
+            Ok(response) => debug!(%response, "ignoring response during setup"),
+            Err(e) => debug!(error = %e, "no update"),
+        }
+        if done_rx.try_recv().is_ok() {
+            break;
+        }
+        if let Some(settle) = settle {
+            if setup.is_stable(Instant::now(), settle) {
+                info!(
+                    seconds = settle.as_secs(),
+                    "position stable, finishing setup"
+                );
+                break;
+            }
         }
-        length -= 3;
-        println!("Response type: {}, length: {}", resp_type, length);
-        let mut data = Vec::with_capacity(length);
-        for _ in 0..length {
-            port.read_exact(&mut buffer)?;
-            data.push(buffer[0]);
+    }
+
+    let to_move = match args.to_move {
+        Side::White => PieceColor::White,
+        Side::Black => PieceColor::Black,
+    };
+    println!("{}", setup.fen(to_move, args.castling));
+    Ok(())
+}
+
+fn fetch_exercises(args: &PuzzleArgs) -> Result<Vec<Exercise>, Box<dyn std::error::Error>> {
+    let client = lichess::Client::new(args.token.clone());
+    let mut exercises = Vec::new();
+    if args.daily {
+        exercises.push(Exercise::from_puzzle(&client.daily_puzzle()?)?);
+    }
+    for id in &args.ids {
+        exercises.push(Exercise::from_puzzle(&client.puzzle(id)?)?);
+    }
+    if let Some(study) = &args.study {
+        exercises.extend(Exercise::from_study(&client.study_pgn(study)?)?);
+    }
+    Ok(exercises)
+}
+
+fn print_plan(game: &GameBoard) {
+    for correction in game.recovery_plan() {
+        println!("  {}", render::correction(&correction, game.start()));
+    }
+}
+
+/// Tell the user what to do at `step`, lighting the move when it is given rather than
+/// to be found
+fn prompt(board: &mut dyn ElectronicBoard, game: &GameBoard, exercise: &Exercise, step: usize) {
+    let output = match exercise.solution.get(step) {
+        Some(ply) if !exercise.is_player_move(step) => BoardOutput::Highlight {
+            from: game.start().square_grid(ply.from),
+            to: game.start().square_grid(ply.to),
+        },
+        _ => BoardOutput::ClearLeds,
+    };
+    send_outputs(board, [output]);
+    let position = exercise.position_at(step);
+    let side = if position.to_move == PieceColor::Black {
+        "Black"
+    } else {
+        "White"
+    };
+    let Some(ply) = exercise.solution.get(step) else {
+        return;
+    };
+    if exercise.is_player_move(step) {
+        println!("{} to move, find the best move", side);
+    } else {
+        let verb = if exercise.player == Some(PieceColor::None) {
+            "plays"
+        } else {
+            "replies"
+        };
+        let san = pgn::san(&Standard, &position, ply);
+        println!("{} {} {}, play it on the board", side, verb, san);
+    }
+}
+
+fn puzzle(args: PuzzleArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let exercises = fetch_exercises(&args)?;
+    if exercises.is_empty() {
+        return Err("no puzzles or study given".into());
+    }
+    let mut board = args.board.open()?;
+    work_through(board.as_mut(), &exercises)?;
+    println!("All exercises done");
+    Ok(())
+}
+
+/// Show the games of a PGN file move by move on the board
+fn guide(args: GuideArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(&args.file)?;
+    let games = Exercise::from_games(&text)?;
+    if games.is_empty() {
+        return Err(format!("no games in {}", args.file.display()).into());
+    }
+    let mut board = args.board.open()?;
+    work_through(board.as_mut(), &games)?;
+    println!("All games replayed");
+    Ok(())
+}
+
+/// Have the moves of every exercise played on the board in turn, checking each one
+fn work_through(
+    board: &mut dyn ElectronicBoard,
+    exercises: &[Exercise],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut game = GameBoard::new(board.board()?);
+    board.start_updates()?;
+
+    for (n, exercise) in exercises.iter().enumerate() {
+        println!(
+            "Exercise {}/{}: {} ({})",
+            n + 1,
+            exercises.len(),
+            exercise.name,
+            exercise.start.to_fen()
+        );
+        game.reset_to(exercise.start.clone());
+        let mut ready = !game.is_out_of_sync();
+        if ready {
+            prompt(board, &game, exercise, 0);
+        } else {
+            println!("Set up the position:");
+            print_plan(&game);
         }
-        if let Some(rtype) = MessageType::try_from_byte(resp_type) {
-            let response = match Response::try_from_raw(rtype, &data) {
-                Ok(r) => r,
+        let mut step = 0;
+        while step < exercise.solution.len() {
+            let mv = match board.next_response() {
+                Ok(Response::FieldUpdate(mv)) => mv,
+                Ok(response) => {
+                    debug!(%response, "ignoring response during puzzle");
+                    continue;
+                }
                 Err(e) => {
-                    println!(
-                        "Received response: {:?}({}) but failed to parse: {:?}",
-                        rtype, resp_type, e
-                    );
-                    return Err("Parse error".into());
+                    debug!(error = %e, "no update");
+                    continue;
                 }
             };
-            return Ok(response);
-        } else {
-            println!("Received response: Unknown({})", resp_type);
-            return Err("Invalid response type".into());
+            if game.apply_move(mv).is_none() {
+                continue;
+            }
+            match game.sync() {
+                SyncState::InSync if !ready => {
+                    ready = true;
+                    print!("{}", render::unicode(game.board()));
+                    prompt(board, &game, exercise, step);
+                }
+                SyncState::InSync | SyncState::TakenBack(_) => {}
+                SyncState::Moved(_) => {
+                    let ply = *game.moves().last().expect("a move was just played");
+                    match exercise.check(step, &ply) {
+                        Verdict::Correct => {
+                            step += 1;
+                            if exercise.is_player_move(step - 1) {
+                                println!("Correct!");
+                            }
+                            print_comments(exercise, step);
+                            prompt(board, &game, exercise, step);
+                        }
+                        Verdict::Solved => {
+                            step += 1;
+                            send_outputs(board, [BoardOutput::ClearLeds]);
+                            print_comments(exercise, step);
+                            println!("Solved!");
+                        }
+                        Verdict::Wrong(expected) => {
+                            let grid = game.start().square_grid(ply.to);
+                            send_outputs(board, [BoardOutput::Flash(grid)]);
+                            let position = exercise.position_at(step);
+                            if exercise.is_player_move(step) {
+                                println!("That is not the solution, take it back:");
+                            } else {
+                                let san = pgn::san(&Standard, &position, &expected);
+                                println!("Play {} instead, take the move back:", san);
+                            }
+                            game.reset_to(position);
+                            ready = false;
+                            print_plan(&game);
+                        }
+                    }
+                }
+                // A piece put down where it can not go is pointed out before the
+                // move is complete
+                SyncState::Pending => {
+                    if let Some(hint @ Hint::Illegal { .. }) = game.hint() {
+                        println!("  {}", hint);
+                    }
+                }
+                SyncState::OutOfSync | SyncState::Disturbed => {
+                    println!("Board does not match, to continue:");
+                    print_plan(&game);
+                }
+            }
         }
     }
+    Ok(())
 }
 
-fn main() {
-    println!("Hello, world!");
-
-    let port_name = "/dev/tty.usbserial-1120";
+fn print_comments(exercise: &Exercise, step: usize) {
+    for comment in exercise.comments_at(step) {
+        println!("  {}", comment);
+    }
+}
 
-    let mut port = serialport::new(port_name, 9600)
-        .data_bits(serialport::DataBits::Eight)
-        .parity(serialport::Parity::None)
-        .stop_bits(serialport::StopBits::One)
-        .flow_control(serialport::FlowControl::Hardware)
-        .timeout(Duration::from_millis(1000))
-        .open()
-        .unwrap();
+fn device_info(args: InfoArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut board = args.board.open()?;
+    board.board()?;
+    let device = board.device_info()?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&device)?);
+        return Ok(());
+    }
+    let rows = [
+        ("Board", Some(device.board)),
+        ("Serial number", device.serial_number),
+        ("Long serial number", device.long_serial_number),
+        ("Firmware version", device.version),
+        ("Trademark", device.trademark),
+        ("Bus address", device.bus_address.map(|a| a.to_string())),
+        ("Clock version", device.clock_version),
+    ];
+    for (name, value) in rows {
+        println!("{:<20}{}", name, value.as_deref().unwrap_or("-"));
+    }
+    Ok(())
+}
 
-    port.write_all(&Command::Reset.as_byte()).unwrap(); // Reset the device
-    port.write_all(&Command::RequestBoard.as_byte()).unwrap(); // Reset the device
-    let pos = get_response(&mut port).unwrap();
-    println!("{:?}", pos);
-    let mut game_board = match pos {
-        Response::BoardDump(board) => {
-            println!("{:?}", board);
-            GameBoard::new(board)
+/// Ask the board the question of `args` and print the answer
+fn send(args: SendArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut board = args.board.open_dgt()?;
+    let query = args.query;
+    let command = match query {
+        Query::Version => Command::RequestVersion,
+        Query::Board => Command::RequestBoard,
+        Query::Serial => Command::RequestSerialNumber,
+        Query::Clock => Command::RequestClock,
+    };
+    let answer = move |response: &Response| match (query, response) {
+        (Query::Version, Response::Version(version)) => {
+            Some((version.clone(), serde_json::json!({ "version": version })))
         }
-        _ => {
-            panic!("Unexpected response");
+        (
+            Query::Board,
+            Response::BoardDump(dump) | Response::ImplausibleDump { board: dump, .. },
+        ) => {
+            let fen = fen::board_fen(dump, StartPosition::Mirror);
+            let text = format!("{}{}", render::unicode(dump), fen);
+            Some((text, serde_json::json!({ "fen": fen, "board": dump })))
         }
+        (Query::Serial, Response::SerialNumber(serial)) => Some((
+            serial.clone(),
+            serde_json::json!({ "serial_number": serial }),
+        )),
+        (
+            Query::Clock,
+            Response::BWTime {
+                white_time,
+                black_time,
+                status,
+            },
+        ) => Some((
+            format!("{} {} {:?}", white_time, black_time, status),
+            serde_json::json!({
+                "white": white_time.total_seconds(),
+                "black": black_time.total_seconds(),
+                "status": status,
+            }),
+        )),
+        _ => None,
     };
-    port.write_all(&Command::RequestSerialNumber.as_byte())
-        .unwrap(); // Reset the device
-    println!("{:?}", get_response(&mut port).unwrap());
+    let timeout = Duration::from_millis(args.timeout);
+    let board::Reply::Answer((text, json)) =
+        board.request(command, answer, timeout, &board::Cancel::new())?
+    else {
+        let message = format!("no answer within {} ms", args.timeout);
+        return Err(Failure::new(ExitStatus::NoAnswer, message).into());
+    };
+    if args.json {
+        println!("{}", json);
+    } else {
+        println!("{}", text);
+    }
+    Ok(())
+}
 
-    port.write_all(&Command::RequestUpdate.as_byte()).unwrap(); // Reset the device
+/// Drive a DGT3000 on its own: set it, show what is typed and print what it reports
+fn clock(args: ClockArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut board = args.board.open()?;
+    board.start_updates()?;
+    let mut standalone = None;
+    if let Some(time_control) = args.time_control {
+        let command = match (time_control.start(), args.run) {
+            (ClockCommand::SetAndRun { left, right, .. }, Some(side)) => ClockCommand::SetAndRun {
+                left,
+                right,
+                run: Some(match side {
+                    Side::White => ClockSide::Left,
+                    Side::Black => ClockSide::Right,
+                }),
+            },
+            (command, _) => command,
+        };
+        if !board.send_clock(&command)? {
+            return Err(format!("{} can not pass messages to a clock", board.name()).into());
+        }
+        info!(%time_control, "clock set");
+        standalone = Some(StandaloneClock::new(time_control));
+    }
+    let mut showing = false;
+    if let Some(text) = args.text {
+        showing = board.send_clock(&ClockCommand::Text { text, beep: false })?;
+    }
 
+    // Every line typed goes to the clock, an empty one brings the times back
+    let (lines_tx, lines) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines().map_while(Result::ok) {
+            if lines_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
     loop {
-        match get_response(&mut port) {
-            Ok(response) => {
-                println!("Received response: {:?}", response);
-                if let Response::FieldUpdate(mv) = response {
-                    game_board.apply_move(mv);
-                    println!("{:?}", game_board.is_starting_position());
+        while let Ok(line) = lines.try_recv() {
+            let text = line.trim();
+            showing = !text.is_empty();
+            let command = match showing {
+                true => ClockCommand::Text {
+                    text: text.to_string(),
+                    beep: false,
+                },
+                false => ClockCommand::EndText,
+            };
+            board.send_clock(&command)?;
+        }
+        let responses = match board.next_responses() {
+            Ok(responses) => responses,
+            Err(e) if board::is_disconnect(&e) => return Err(e),
+            Err(e) => {
+                debug!(error = %e, "no update");
+                continue;
+            }
+        };
+        for event in responses.into_iter().filter_map(Event::from_response) {
+            let text = match &event {
+                Event::Clock {
+                    white_time,
+                    black_time,
+                    status,
+                } => {
+                    let report = standalone.as_mut().and_then(|standalone| {
+                        standalone.report(*white_time, *black_time, *status, Instant::now())
+                    });
+                    if let Some(command) = report {
+                        board.send_clock(&command)?;
+                    }
+                    format!("{} {} {:?}", white_time, black_time, status)
                 }
+                Event::ClockButton(button) => format!("button {}", button),
+                Event::ClockButtons(pressed) => format!("buttons {}", pressed),
+                _ => continue,
+            };
+            if showing && matches!(event, Event::ClockButton(_) | Event::ClockButtons(_)) {
+                showing = false;
+                board.send_clock(&ClockCommand::EndText)?;
             }
-            Err(e) => {
-                println!("Error: {:?}", e);
+            match args.json {
+                true => println!("{}", serde_json::to_string(&event)?),
+                false => println!("{}", text),
+            }
+        }
+    }
+}
+
+/// Send what is typed to the board and print every frame that comes back, including
+/// those the board sends on its own
+fn repl(args: ReplArgs) -> Result<(), Box<dyn std::error::Error>> {
+    use jackolope::repl::{self, Input};
+
+    let mut board = args.board.open_dgt()?;
+    let (lines_tx, lines) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines().map_while(Result::ok) {
+            if lines_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    println!("Type a command name like RequestVersion or hex bytes like 4d, help or quit");
+    loop {
+        match lines.try_recv() {
+            Ok(line) if line.trim().is_empty() => {}
+            Ok(line) => match repl::parse_input(&line) {
+                Ok(Input::Send(bytes)) => {
+                    board.send_raw(&bytes)?;
+                    println!("> {}", repl::hex(&bytes));
+                }
+                Ok(Input::Help) => println!("Commands: {}", repl::command_names().join(" ")),
+                Ok(Input::Quit) => break,
+                Err(e) => println!("{}", e),
+            },
+            Err(mpsc::TryRecvError::Empty) => {}
+            Err(mpsc::TryRecvError::Disconnected) => break,
+        }
+        match board.read_raw(Duration::from_millis(50)) {
+            Ok(Some(frame)) => println!("{}", repl::describe(&frame)),
+            Ok(None) => {}
+            Err(e) => println!("! {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Make the settings of the config file the fallback of the command line options by
+/// exporting them as the environment variables those options read. The profile has to
+/// be known before the command line is parsed, so it is picked out of the raw arguments.
+fn apply_config() -> Result<Config, config::Error> {
+    let Some(path) = config::default_path() else {
+        return Ok(Config::default());
+    };
+    let config = Config::load(&path)?;
+    let args: Vec<String> = std::env::args().collect();
+    let profile = args
+        .iter()
+        .position(|arg| arg == "--profile")
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+        .or_else(|| args.iter().find_map(|arg| arg.strip_prefix("--profile=")));
+    for (name, value) in config.settings(profile)?.env_vars() {
+        if std::env::var_os(name).is_none() {
+            std::env::set_var(name, value);
+        }
+    }
+    Ok(config)
+}
+
+fn main() {
+    // The config file comes first as it can turn on the daemon and with it the log
+    // format, its errors are reported once logging is set up
+    let config = apply_config();
+    let cli = Cli::parse();
+    let watching = match &cli.command {
+        None => Some(&cli.watch),
+        Some(Commands::Analyze(args)) => Some(&args.watch),
+        _ => None,
+    };
+    let state_dir = watching
+        .filter(|args| args.daemon)
+        .map(|args| daemon::state_dir(args.state_dir.clone()));
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let stderr = fmt::layer().with_writer(std::io::stderr);
+    let stderr = match state_dir {
+        Some(_) => stderr
+            .with_ansi(false)
+            .event_format(daemon::Journal)
+            .boxed(),
+        None => stderr.boxed(),
+    };
+    let rotation = Rotation {
+        max_size: cli.log_max_size,
+        max_age: cli.log_max_age,
+        keep: cli.log_keep,
+    };
+    let log_file = cli
+        .log_file
+        .as_ref()
+        .map(|path| RotatingFile::open(path, rotation))
+        .transpose();
+    let (log_file, log_file_error) = match log_file {
+        Ok(file) => (file, None),
+        Err(e) => (None, Some(e)),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(stderr)
+        .with(log_file.map(|file| {
+            fmt::layer()
+                .with_ansi(false)
+                .with_writer(std::sync::Mutex::new(file))
+        }))
+        .init();
+
+    let config = config.unwrap_or_else(|e| {
+        tracing::error!(error = %e, "exiting");
+        std::process::exit(ExitStatus::of(&e).code());
+    });
+    if let Some(e) = log_file_error {
+        tracing::error!(error = %e, "cannot open the log file, exiting");
+        std::process::exit(1);
+    }
+    let status_file = cli
+        .status_file
+        .clone()
+        .or_else(|| state_dir.map(|dir| dir.join(daemon::STATUS_FILE)));
+    if let Some(addr) = &cli.metrics {
+        if let Err(e) = metrics::serve(addr.as_str()) {
+            tracing::error!(error = %e, "exiting");
+            std::process::exit(1);
+        }
+    }
+    let result = match cli.command {
+        Some(Commands::ReplaySession {
+            file,
+            realtime,
+            variant,
+            output,
+        }) => replay_session(file, realtime, variant, output),
+        Some(Commands::Setup(args)) => setup(args),
+        Some(Commands::Puzzle(args)) => puzzle(args),
+        Some(Commands::Guide(args)) => guide(args),
+        Some(Commands::Info(args)) => device_info(args),
+        Some(Commands::Send(args)) => send(args),
+        Some(Commands::Repl(args)) => repl(args),
+        Some(Commands::Clock(args)) => clock(args),
+        Some(Commands::Boards(args)) => boards(args, config.boards),
+        Some(Commands::Pair(args)) => pair(args),
+        Some(Commands::Remote(args)) => remote(args),
+        Some(Commands::Gui(args)) => gui(args),
+        #[cfg(unix)]
+        Some(Commands::Simulate(args)) => simulate(args),
+        #[cfg(unix)]
+        Some(Commands::Arbiter(args)) => arbiter(args),
+        #[cfg(feature = "database")]
+        Some(Commands::Games(args)) => games(args),
+        Some(Commands::Ports) => {
+            for candidate in transport::discover() {
+                println!(
+                    "{:<30}{:<12}{}",
+                    candidate.port,
+                    format!("{:?}", candidate.transport).to_lowercase(),
+                    candidate.description.unwrap_or_default()
+                );
             }
+            Ok(())
+        }
+        Some(Commands::States) => {
+            print!("{}", DetectionState::diagram());
+            Ok(())
+        }
+        Some(Commands::Analyze(args)) => watch(args.watch, Some(args.engine), config.boards),
+        None => watch(cli.watch, None, config.boards),
+    };
+    let status = match &result {
+        Ok(()) => ExitStatus::Success,
+        Err(e) => ExitStatus::of(e.as_ref()),
+    };
+    match &result {
+        Err(_) if status == ExitStatus::Interrupted => info!("interrupted"),
+        Err(e) => tracing::error!(error = %e, "exiting"),
+        Ok(()) => {}
+    }
+    if let Some(path) = &status_file {
+        let error = result.as_ref().err().map(|e| e.as_ref());
+        if let Err(e) = status::write_status_file(path, status, error) {
+            warn!(error = %e, path = %path.display(), "failed to write the status file");
         }
     }
+    std::process::exit(status.code());
 }
@@ -0,0 +1,333 @@
+use crate::board::{self, Baud, DeviceInfo, ElectronicBoard, Kind};
+use crate::event::Event;
+use crate::metrics::METRICS;
+use crate::transport::{self, Candidate};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+pub use crate::board::is_disconnect;
+
+/// Something to do with a board on the thread driving it
+type Job = Box<dyn FnOnce(&mut dyn ElectronicBoard) + Send>;
+
+/// Something that happened on one of the boards followed by a `BoardManager`, tagged
+/// with the port of the board
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoardEvent {
+    /// A board was found and answered, its events follow
+    Connected {
+        port: String,
+        device: DeviceInfo,
+    },
+    Event {
+        port: String,
+        event: Event,
+    },
+    /// The board was unplugged or stopped answering
+    Disconnected {
+        port: String,
+    },
+}
+
+/// Watches the serial ports for boards being plugged in and out, and runs a driver
+/// thread per board. Ports are polled, which works the same on every platform.
+pub struct BoardManager {
+    kind: Kind,
+    baud: Option<Baud>,
+    poll_interval: Duration,
+    accept: Box<dyn Fn(&Candidate) -> bool + Send>,
+    allow_implausible: bool,
+}
+
+impl BoardManager {
+    /// Follow boards of type `kind` on USB and Bluetooth ports
+    pub fn new(kind: Kind, baud: Option<Baud>) -> Self {
+        BoardManager {
+            kind,
+            baud,
+            poll_interval: Duration::from_secs(2),
+            accept: Box::new(|candidate| candidate.description.is_some()),
+            allow_implausible: false,
+        }
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Only try ports for which `accept` returns true
+    pub fn accept(mut self, accept: impl Fn(&Candidate) -> bool + Send + 'static) -> Self {
+        self.accept = Box::new(accept);
+        self
+    }
+
+    /// Pass on board dumps that need more than one set as plain dumps, see
+    /// `ElectronicBoard::allow_implausible_dumps`
+    pub fn allow_implausible_dumps(mut self, allow: bool) -> Self {
+        self.allow_implausible = allow;
+        self
+    }
+
+    /// Start watching. The manager and its drivers stop once the returned stream of
+    /// events is dropped.
+    pub fn spawn(self) -> io::Result<Boards> {
+        let (tx, rx) = mpsc::channel();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shared = Arc::new(Shared::default());
+        let (flag, drivers) = (shutdown.clone(), shared.clone());
+        thread::Builder::new()
+            .name("board manager".to_string())
+            .spawn(move || self.run(tx, flag, drivers))?;
+        Ok(Boards {
+            events: rx,
+            shutdown,
+            shared,
+        })
+    }
+
+    fn run(self, tx: Sender<BoardEvent>, shutdown: Arc<AtomicBool>, shared: Arc<Shared>) {
+        // Ports with a driver, including ones whose board failed or went quiet. Those
+        // are only tried again once the port disappears and comes back.
+        let mut running: HashMap<String, Arc<AtomicBool>> = HashMap::new();
+        while !shutdown.load(Ordering::Relaxed) {
+            let candidates: Vec<Candidate> = transport::discover()
+                .into_iter()
+                .filter(|candidate| (self.accept)(candidate))
+                .collect();
+            running.retain(|port, stop| {
+                let present = candidates.iter().any(|candidate| &candidate.port == port);
+                if !present {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                present
+            });
+            for candidate in candidates {
+                if running.contains_key(&candidate.port) {
+                    continue;
+                }
+                let stop = Arc::new(AtomicBool::new(false));
+                running.insert(candidate.port.clone(), stop.clone());
+                let (kind, baud, tx, shared) = (self.kind, self.baud, tx.clone(), shared.clone());
+                let allow_implausible = self.allow_implausible;
+                let spawned = thread::Builder::new()
+                    .name(format!("board {}", candidate.port))
+                    .spawn(move || {
+                        drive(kind, baud, allow_implausible, candidate, stop, tx, shared)
+                    });
+                if let Err(e) = spawned {
+                    warn!(error = %e, "failed to start board driver");
+                }
+            }
+            thread::sleep(self.poll_interval);
+        }
+        for stop in running.values() {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// What the driver threads share with the manager and the stream of their events
+#[derive(Default)]
+struct Shared {
+    /// Ports a board has been seen on, coming back there counts as a reconnect
+    seen: Mutex<HashSet<String>>,
+    /// Jobs for the driver of each connected board
+    jobs: Mutex<HashMap<String, Sender<Job>>>,
+}
+
+/// Merged events of the boards followed by a `BoardManager`
+pub struct Boards {
+    events: Receiver<BoardEvent>,
+    shutdown: Arc<AtomicBool>,
+    shared: Arc<Shared>,
+}
+
+impl Boards {
+    /// Do `job` with the board on `port` once the message being read is in, without
+    /// waiting for it
+    pub fn submit(
+        &self,
+        port: &str,
+        job: impl FnOnce(&mut dyn ElectronicBoard) + Send + 'static,
+    ) -> Result<(), board::Error> {
+        let jobs = self.shared.jobs.lock().unwrap();
+        let sent = jobs.get(port).map(|jobs| jobs.send(Box::new(job)));
+        match sent {
+            Some(Ok(())) => Ok(()),
+            _ => Err(format!("no board on {}", port).into()),
+        }
+    }
+
+    /// Wait for the next event of any board
+    pub fn recv(&self) -> Option<BoardEvent> {
+        self.events.recv().ok()
+    }
+
+    pub fn try_recv(&self) -> Option<BoardEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Iterator for Boards {
+    type Item = BoardEvent;
+
+    fn next(&mut self) -> Option<BoardEvent> {
+        self.recv()
+    }
+}
+
+impl Drop for Boards {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+}
+
+fn connect(
+    kind: Kind,
+    baud: Option<Baud>,
+    allow_implausible: bool,
+    candidate: &Candidate,
+) -> Result<(Box<dyn ElectronicBoard>, DeviceInfo, Event), board::Error> {
+    let mut board = board::open(kind, &candidate.port, baud, Some(candidate.transport))?;
+    board.allow_implausible_dumps(allow_implausible);
+    let dump = board.board()?;
+    let device = board.device_info()?;
+    board.start_updates()?;
+    Ok((board, device, Event::BoardDump(dump)))
+}
+
+/// Run one board until it disappears, `stop` is set or nobody listens
+fn drive(
+    kind: Kind,
+    baud: Option<Baud>,
+    allow_implausible: bool,
+    candidate: Candidate,
+    stop: Arc<AtomicBool>,
+    tx: Sender<BoardEvent>,
+    shared: Arc<Shared>,
+) {
+    let port = candidate.port.clone();
+    let (mut board, device, dump) = match connect(kind, baud, allow_implausible, &candidate) {
+        Ok(connected) => connected,
+        Err(e) => {
+            debug!(%port, error = %e, "no board on port");
+            return;
+        }
+    };
+    info!(%port, ?device, "board connected");
+    if !shared.seen.lock().unwrap().insert(port.clone()) {
+        METRICS.reconnects.inc();
+    }
+    let (jobs, queue) = mpsc::channel::<Job>();
+    shared.jobs.lock().unwrap().insert(port.clone(), jobs);
+    let port_event = |event| BoardEvent::Event {
+        port: port.clone(),
+        event,
+    };
+    let connected = BoardEvent::Connected {
+        port: port.clone(),
+        device,
+    };
+    if tx.send(connected).is_err() || tx.send(port_event(dump)).is_err() {
+        return;
+    }
+    while !stop.load(Ordering::Relaxed) {
+        match board.next_responses() {
+            Ok(responses) => {
+                for event in responses.into_iter().filter_map(Event::from_response) {
+                    if tx.send(port_event(event)).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(e) if is_disconnect(&e) => {
+                warn!(%port, error = %e, "board disconnected");
+                break;
+            }
+            Err(e) => debug!(%port, error = %e, "no update"),
+        }
+        for job in queue.try_iter() {
+            job(board.as_mut());
+        }
+    }
+    shared.jobs.lock().unwrap().remove(&port);
+    let _ = tx.send(BoardEvent::Disconnected { port });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_disconnect() {
+        let gone: board::Error = io::Error::from(io::ErrorKind::BrokenPipe).into();
+        let quiet: board::Error = io::Error::from(io::ErrorKind::TimedOut).into();
+        let garbled: board::Error = "invalid status message".into();
+        assert!(is_disconnect(&gone));
+        assert!(!is_disconnect(&quiet));
+        assert!(!is_disconnect(&garbled));
+    }
+
+    /// A board asked to be read again from outside, as `boards` does with a board whose
+    /// updates can not be real, answers through its driver thread
+    #[cfg(unix)]
+    #[test]
+    fn test_submit_request_board() {
+        use crate::simulator::Simulator;
+        use crate::transport::Transport;
+        use serialport::SerialPort;
+
+        let (mut master, slave) = serialport::TTYPort::pair().unwrap();
+        master.set_timeout(Duration::from_millis(50)).unwrap();
+        thread::spawn(move || Simulator::new(master).run(Duration::from_secs(1)));
+        let candidate = Candidate {
+            port: slave.name().unwrap(),
+            transport: Transport::Serial,
+            description: None,
+        };
+        let port = candidate.port.clone();
+        let (tx, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let shared = Arc::new(Shared::default());
+        let (flag, drivers) = (stop.clone(), shared.clone());
+        let driver =
+            thread::spawn(move || drive(Kind::Dgt, None, false, candidate, flag, tx, drivers));
+        let boards = Boards {
+            events,
+            shutdown: Arc::default(),
+            shared,
+        };
+        let next = || boards.events.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(next(), BoardEvent::Connected { .. }));
+        assert!(matches!(
+            next(),
+            BoardEvent::Event {
+                event: Event::BoardDump(_),
+                ..
+            }
+        ));
+        assert!(boards.submit("elsewhere", |_| {}).is_err());
+        boards
+            .submit(&port, |board| {
+                board.request_board().unwrap();
+            })
+            .unwrap();
+        assert!(matches!(
+            next(),
+            BoardEvent::Event {
+                event: Event::BoardDump(_),
+                ..
+            }
+        ));
+        stop.store(true, Ordering::Relaxed);
+        driver.join().unwrap();
+        assert!(boards.submit(&port, |_| {}).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! Matches of several games between two players followed from one machine, as clubs
+//! run them: the round of every game, who has white in it and the score so far.
+
+use crate::pgn::GameTags;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// The first player has white in the first game
+    players: [String; 2],
+    first_round: u32,
+    /// Half points of each player
+    half_points: [u32; 2],
+    /// Games finished so far
+    played: u32,
+}
+
+/// Points from half points, "1.5" rather than "3/2"
+fn points(half_points: u32) -> String {
+    match half_points % 2 {
+        0 => (half_points / 2).to_string(),
+        _ => format!("{}.5", half_points / 2),
+    }
+}
+
+impl Match {
+    /// A match whose first game is `first_round`, with white for `first` in it
+    pub fn new(first: String, second: String, first_round: u32) -> Self {
+        Match {
+            players: [first, second],
+            first_round,
+            half_points: [0, 0],
+            played: 0,
+        }
+    }
+
+    /// Index of the player with white in the current game, colours alternate
+    fn white(&self) -> usize {
+        (self.played % 2) as usize
+    }
+
+    pub fn round(&self) -> u32 {
+        self.first_round + self.played
+    }
+
+    /// Count the PGN result of the current game and go on to the next. An unfinished
+    /// game scores nothing but still swaps the colours.
+    pub fn record(&mut self, result: &str) {
+        let white = self.white();
+        let (white_points, black_points) = match result {
+            "1-0" => (2, 0),
+            "0-1" => (0, 2),
+            "1/2-1/2" => (1, 1),
+            _ => (0, 0),
+        };
+        self.half_points[white] += white_points;
+        self.half_points[1 - white] += black_points;
+        self.played += 1;
+    }
+
+    /// `tags` with the round, the players and their scores before the current game
+    pub fn tags(&self, tags: &GameTags) -> GameTags {
+        let white = self.white();
+        GameTags {
+            round: Some(self.round().to_string()),
+            white: Some(self.players[white].clone()),
+            black: Some(self.players[1 - white].clone()),
+            white_score: Some(points(self.half_points[white])),
+            black_score: Some(points(self.half_points[1 - white])),
+            ..tags.clone()
+        }
+    }
+}
+
+impl fmt::Display for Match {
+    /// The score like "Smith 1.5 - 0.5 Jones"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} - {} {}",
+            self.players[0],
+            points(self.half_points[0]),
+            points(self.half_points[1]),
+            self.players[1]
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_colours_and_score() {
+        let mut club = Match::new("Smith".to_string(), "Jones".to_string(), 3);
+        let board = GameTags {
+            board: Some("Board 1".to_string()),
+            ..GameTags::default()
+        };
+        club.record("1-0");
+        club.record("1/2-1/2");
+        let tags = club.tags(&board);
+        assert_eq!(tags.round.as_deref(), Some("5"));
+        assert_eq!(tags.white.as_deref(), Some("Smith"));
+        assert_eq!(tags.white_score.as_deref(), Some("1.5"));
+        assert_eq!(tags.black_score.as_deref(), Some("0.5"));
+        assert_eq!(tags.board, board.board);
+        club.record("1-0");
+        assert_eq!(club.to_string(), "Smith 2.5 - 0.5 Jones");
+        club.record("*");
+        assert_eq!(club.tags(&board).white.as_deref(), Some("Smith"));
+    }
+}
@@ -0,0 +1,207 @@
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Counters and histograms of the running driver, shared by every board it follows
+pub static METRICS: Metrics = Metrics::new();
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub const fn new() -> Self {
+        Counter(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Observations counted into buckets with fixed upper bounds
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    /// Count per bound plus one for the rest, the sum and the total count
+    state: Mutex<(Vec<u64>, f64, u64)>,
+}
+
+impl Histogram {
+    pub const fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            state: Mutex::new((Vec::new(), 0.0, 0)),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let mut state = self.state.lock().unwrap();
+        state.0.resize(self.bounds.len() + 1, 0);
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        state.0[bucket] += 1;
+        state.1 += value;
+        state.2 += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.state.lock().unwrap().2
+    }
+
+    /// Cumulative buckets, sum and count in the Prometheus text format
+    fn render(&self, name: &str, out: &mut String) {
+        let state = self.state.lock().unwrap();
+        let mut cumulative = 0;
+        for (i, bound) in self.bounds.iter().enumerate() {
+            cumulative += state.0.get(i).copied().unwrap_or(0);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, state.2);
+        let _ = writeln!(out, "{}_sum {}", name, state.1);
+        let _ = writeln!(out, "{}_count {}", name, state.2);
+    }
+}
+
+#[derive(Debug)]
+pub struct Metrics {
+    /// Frames decoded, their rate is the throughput of the serial link
+    pub frames: Counter,
+    /// Frames dropped as unknown or malformed
+    pub parse_errors: Counter,
+    /// Boards that came back after being unplugged or going silent
+    pub reconnects: Counter,
+    /// Seconds from the first field update of a move until it is recognised
+    pub move_latency: Histogram,
+    /// Moves played in each finished game
+    pub game_moves: Histogram,
+}
+
+impl Metrics {
+    pub const fn new() -> Self {
+        Metrics {
+            frames: Counter::new(),
+            parse_errors: Counter::new(),
+            reconnects: Counter::new(),
+            move_latency: Histogram::new(&[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]),
+            game_moves: Histogram::new(&[20.0, 40.0, 60.0, 80.0, 120.0, 160.0]),
+        }
+    }
+
+    /// All metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "jackolope_frames_total",
+                "Frames received from boards",
+                &self.frames,
+            ),
+            (
+                "jackolope_parse_errors_total",
+                "Frames that could not be decoded",
+                &self.parse_errors,
+            ),
+            (
+                "jackolope_reconnects_total",
+                "Boards connected again after disappearing",
+                &self.reconnects,
+            ),
+        ];
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, counter.get());
+        }
+        let histograms = [
+            (
+                "jackolope_move_latency_seconds",
+                "Time from the first lift of a move until it is detected",
+                &self.move_latency,
+            ),
+            (
+                "jackolope_game_moves",
+                "Moves played per finished game",
+                &self.game_moves,
+            ),
+        ];
+        for (name, help, histogram) in histograms {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} histogram", name);
+            histogram.render(name, &mut out);
+        }
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+/// Serve `METRICS` over HTTP on `addr` for Prometheus to scrape at `/metrics`
+pub fn serve(addr: impl ToSocketAddrs) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    info!(addr = %listener.local_addr()?, "serving metrics");
+    thread::Builder::new()
+        .name("metrics".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(|mut stream| {
+                    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+                    let mut request = String::new();
+                    BufReader::new(&stream).read_line(&mut request)?;
+                    debug!(request = request.trim_end(), "metrics request");
+                    let (status, body) = match request.split_whitespace().nth(1) {
+                        Some("/metrics") => ("200 OK", METRICS.render()),
+                        _ => ("404 Not Found", "not found\n".to_string()),
+                    };
+                    write!(
+                        stream,
+                        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    )
+                });
+                if let Err(e) = result {
+                    warn!(error = %e, "failed to answer metrics request");
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.frames.inc();
+        metrics.frames.inc();
+        metrics.move_latency.observe(0.3);
+        metrics.move_latency.observe(20.0);
+        let text = metrics.render();
+        assert!(text.contains("jackolope_frames_total 2\n"));
+        assert!(text.contains("jackolope_parse_errors_total 0\n"));
+        assert!(text.contains("jackolope_move_latency_seconds_bucket{le=\"0.25\"} 0\n"));
+        assert!(text.contains("jackolope_move_latency_seconds_bucket{le=\"0.5\"} 1\n"));
+        assert!(text.contains("jackolope_move_latency_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("jackolope_move_latency_seconds_count 2\n"));
+        assert!(text.contains("jackolope_game_moves_count 0\n"));
+    }
+}
@@ -0,0 +1,235 @@
+use crate::board::{changes, ElectronicBoard, Error, LedControl};
+use crate::metrics::METRICS;
+use crate::pacing::{Pacer, Pacing};
+use crate::protocol::*;
+use crate::transport::LineControl;
+use serialport::SerialPort;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, trace};
+
+/// How often the board is asked for its position while waiting for a change
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Quiet period after which `next_response` gives up, like a DGT read timeout
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Millennium ChessLink board. It speaks 7 bit ASCII with odd parity, every message
+/// ends in two hex digits holding the XOR of its characters. The board only reports
+/// whole positions, changes are turned into DGT style field updates by comparing them.
+pub struct MillenniumBoard {
+    port: Box<dyn SerialPort>,
+    last: Option<ChessBoard>,
+    pending: VecDeque<ChessMove>,
+    pacer: Pacer,
+}
+
+/// LED patterns are 8 bits, shown one after the other in a loop
+const LED_STEADY: u8 = 0xff;
+const LED_BLINK: u8 = 0xcc;
+/// Length of a pattern slot in units of about 4 ms
+const LED_SLOT_TIME: u8 = 0x20;
+
+/// Two hex digits of the XOR of `message`
+fn block_parity(message: &[u8]) -> [u8; 2] {
+    let parity = message.iter().fold(0, |acc, b| acc ^ b);
+    let hex = format!("{:02X}", parity);
+    [hex.as_bytes()[0], hex.as_bytes()[1]]
+}
+
+/// Decode the 64 piece letters of a status reply, a8 first and '.' for empty squares
+fn parse_status(message: &[u8]) -> Option<ChessBoard> {
+    let (body, parity) = message.split_at_checked(message.len().checked_sub(2)?)?;
+    if body.len() != 65 || body[0] != b's' || block_parity(body) != parity {
+        return None;
+    }
+    let mut board = [RawPiece::Empty; 64];
+    for (square, c) in board.iter_mut().zip(&body[1..]) {
+        *square = match c {
+            b'.' => RawPiece::Empty,
+            c => RawPiece::try_from(*c as char).ok()?,
+        };
+    }
+    Some(ChessBoard { board })
+}
+
+/// The board has a 9 by 9 grid of LEDs on the corners of the squares, a8 side first.
+/// Returns the "L" command lighting the corners of `squares` with `pattern`.
+fn led_command(squares: &[u8], pattern: u8) -> String {
+    let mut leds = [0u8; 81];
+    for &grid in squares {
+        let (row, col) = (grid as usize / 8, grid as usize % 8);
+        for corner in [
+            row * 9 + col,
+            row * 9 + col + 1,
+            (row + 1) * 9 + col,
+            (row + 1) * 9 + col + 1,
+        ] {
+            leds[corner] = pattern;
+        }
+    }
+    let mut command = format!("L{:02X}", LED_SLOT_TIME);
+    for led in leds {
+        command.push_str(&format!("{:02X}", led));
+    }
+    command
+}
+
+impl MillenniumBoard {
+    pub const DEFAULT_BAUD: u32 = 38400;
+
+    pub fn open(name: &str, baud: u32, line: LineControl) -> Result<Self, serialport::Error> {
+        let port = line.open(
+            serialport::new(name, baud)
+                .data_bits(serialport::DataBits::Seven)
+                .parity(serialport::Parity::Odd)
+                .stop_bits(serialport::StopBits::One)
+                .timeout(Duration::from_millis(1000)),
+        )?;
+        Ok(MillenniumBoard {
+            port,
+            last: None,
+            pending: VecDeque::new(),
+            pacer: Pacer::default(),
+        })
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        let mut message = command.as_bytes().to_vec();
+        message.extend(block_parity(&message));
+        self.pacer.wait();
+        let result = self.port.write_all(&message);
+        self.pacer.sent(false);
+        result
+    }
+
+    /// Ask for the position and wait for the status reply
+    fn status(&mut self) -> Result<ChessBoard, Error> {
+        self.send("S")?;
+        let mut byte = [0; 1];
+        loop {
+            self.port.read_exact(&mut byte)?;
+            if byte[0] & 0x7f == b's' {
+                break;
+            }
+            trace!(byte = byte[0], "skipping byte before status");
+        }
+        let mut message = vec![b's'; 67];
+        self.port.read_exact(&mut message[1..])?;
+        message.iter_mut().for_each(|b| *b &= 0x7f);
+        let Some(board) = parse_status(&message) else {
+            METRICS.parse_errors.inc();
+            return Err("invalid status message".into());
+        };
+        METRICS.frames.inc();
+        Ok(board)
+    }
+}
+
+impl ElectronicBoard for MillenniumBoard {
+    fn name(&self) -> &str {
+        "Millennium"
+    }
+
+    fn board(&mut self) -> Result<ChessBoard, Error> {
+        let board = self.status()?;
+        self.last = Some(board);
+        self.pending.clear();
+        Ok(board)
+    }
+
+    /// The board has no update mode, `next_response` polls it instead
+    fn start_updates(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn set_pacing(&mut self, pacing: Pacing) {
+        self.pacer.set_pacing(pacing);
+    }
+
+    fn leds(&mut self) -> Option<&mut dyn LedControl> {
+        Some(self)
+    }
+
+    fn next_response(&mut self) -> Result<Response, Error> {
+        let started = Instant::now();
+        loop {
+            if let Some(mv) = self.pending.pop_front() {
+                return Ok(Response::FieldUpdate(mv));
+            }
+            let board = self.status()?;
+            if let Some(last) = self.last.replace(board) {
+                self.pending.extend(changes(&last, &board));
+                debug!(changes = self.pending.len(), "position changed");
+            }
+            if self.pending.is_empty() {
+                if started.elapsed() >= IDLE_TIMEOUT {
+                    return Err("no update".into());
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    /// All changes found by one poll of the board at once
+    fn next_responses(&mut self) -> Result<Vec<Response>, Error> {
+        let first = self.next_response()?;
+        Ok(std::iter::once(first)
+            .chain(self.pending.drain(..).map(Response::FieldUpdate))
+            .collect())
+    }
+}
+
+impl LedControl for MillenniumBoard {
+    fn highlight_move(&mut self, from: u8, to: u8) -> Result<(), Error> {
+        Ok(self.send(&led_command(&[from, to], LED_STEADY))?)
+    }
+
+    fn flash_square(&mut self, grid: u8) -> Result<(), Error> {
+        Ok(self.send(&led_command(&[grid], LED_BLINK))?)
+    }
+
+    fn clear(&mut self) -> Result<(), Error> {
+        Ok(self.send("X")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_and_diff() {
+        let start = b"srnbqkbnrpppppppp................................PPPPPPPPRNBQKBNR";
+        let mut message = start.to_vec();
+        message.extend(block_parity(start));
+        let before = parse_status(&message).unwrap();
+        assert_eq!(before.board[0], RawPiece::BlackRook);
+        assert_eq!(before.board[63], RawPiece::WhiteRook);
+        message[60] = b'.';
+        assert_eq!(parse_status(&message), None);
+
+        let leds = led_command(&[0], LED_STEADY);
+        assert_eq!(leds.len(), 3 + 2 * 81);
+        assert_eq!(&leds[..7], "L20FFFF");
+        assert_eq!(&leds[21..25], "FFFF");
+
+        let mut after = before;
+        after.board[52] = RawPiece::Empty;
+        after.board[36] = RawPiece::WhitePawn;
+        assert_eq!(
+            changes(&before, &after),
+            [
+                ChessMove {
+                    grid: 52,
+                    piece: RawPiece::Empty
+                },
+                ChessMove {
+                    grid: 36,
+                    piece: RawPiece::WhitePawn
+                }
+            ]
+        );
+    }
+}
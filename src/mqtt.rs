@@ -0,0 +1,101 @@
+use crate::game::GameBoard;
+use crate::pgn;
+use crate::protocol::*;
+use crate::relay::{Error, Relay};
+use rumqttc::{Client, MqttOptions, QoS};
+use serde_json::json;
+use std::thread;
+use std::time::Duration;
+use tracing::warn;
+
+pub const DEFAULT_PORT: u16 = 1883;
+
+/// Publishes the game to an MQTT broker under `<prefix>/fen`, `<prefix>/move`,
+/// `<prefix>/clock`, `<prefix>/result` and `<prefix>/tags`, the latter naming the board
+/// and players. All but the moves are retained so signage that subscribes mid-game
+/// shows the current state straight away.
+pub struct MqttRelay {
+    client: Client,
+    prefix: String,
+}
+
+impl MqttRelay {
+    /// Connect to the broker at `host`, given as `name` or `name:port`
+    pub fn connect(host: &str, prefix: &str) -> Result<Self, String> {
+        let (name, port) = match host.rsplit_once(':') {
+            Some((name, port)) => (
+                name,
+                port.parse()
+                    .map_err(|_| format!("invalid MQTT port in {}", host))?,
+            ),
+            None => (host, DEFAULT_PORT),
+        };
+        let mut options = MqttOptions::new(format!("jackolope-{}", std::process::id()), name, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, mut connection) = Client::new(options, 64);
+        // The connection has to be polled for anything to be sent, it reconnects on the
+        // next poll after an error
+        thread::Builder::new()
+            .name("mqtt".to_string())
+            .spawn(move || {
+                for notification in connection.iter() {
+                    if let Err(e) = notification {
+                        warn!(error = %e, "MQTT connection error");
+                        thread::sleep(Duration::from_secs(5));
+                    }
+                }
+            })
+            .map_err(|e| e.to_string())?;
+        Ok(MqttRelay {
+            client,
+            prefix: prefix.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn publish(&self, topic: &str, retain: bool, payload: String) -> Result<(), Error> {
+        self.client.publish(
+            format!("{}/{}", self.prefix, topic),
+            QoS::AtLeastOnce,
+            retain,
+            payload,
+        )?;
+        Ok(())
+    }
+}
+
+impl Relay for MqttRelay {
+    fn name(&self) -> &str {
+        "mqtt"
+    }
+
+    fn on_new_game(&mut self, game: &GameBoard) -> Result<(), Error> {
+        let tags = serde_json::to_string(game.tags())?;
+        self.publish("tags", true, tags)?;
+        self.publish("fen", true, game.fen())
+    }
+
+    fn on_move(&mut self, game: &GameBoard) -> Result<(), Error> {
+        let (Some(last), Some(san)) = (game.moves().last(), pgn::last_san(game)) else {
+            return Ok(());
+        };
+        let payload = json!({
+            "uci": last.uci(),
+            "san": san,
+            "ply": game.moves().len(),
+        });
+        self.publish("fen", true, game.fen())?;
+        self.publish("move", false, payload.to_string())
+    }
+
+    fn on_clock(&mut self, white: Remaining, black: Remaining) -> Result<(), Error> {
+        let payload = json!({
+            "white": white.total_seconds(),
+            "black": black.total_seconds(),
+        });
+        self.publish("clock", true, payload.to_string())
+    }
+
+    fn on_result(&mut self, _game: &GameBoard, result: &str) -> Result<(), Error> {
+        self.publish("result", true, result.to_string())
+    }
+}
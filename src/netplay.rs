@@ -0,0 +1,377 @@
+//! Play over the network: two instances, each following the board of one player, send
+//! each other the moves made on their board. Messages are lines of JSON over TCP.
+//!
+//! Both ends start with a `hello` carrying the colour they play and a random nonce, and
+//! answer the nonce of the other with an HMAC-SHA256 of it keyed with the shared token,
+//! so neither takes moves from someone who does not know the token. Every message after
+//! that starts with an HMAC of it, keyed with the token and taken over both nonces, the
+//! colour of the sender and the number of the message, so nothing can be slipped in,
+//! changed, replayed or reordered on the way. Then both send the moves they have, which
+//! lets a player who lost the connection catch up.
+//!
+//! ```text
+//! {"type":"hello","version":2,"colour":"White","nonce":"9f86d081884c7d65"}
+//! {"type":"auth","proof":"5d41402abc4b2a76b9719d911017c592..."}
+//! 0c1f7e4d52b1a3e8... {"type":"sync","moves":["e2e4"]}
+//! 8d9a6b07c3e2f514... {"type":"move","ply":1,"uci":"e7e5"}
+//! ```
+
+use crate::protocol::{PieceColor, Remaining};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Version of the messages, both ends have to speak the same
+pub const PROTOCOL_VERSION: u32 = 2;
+/// How long to wait before connecting again after the connection was lost
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// How long the other end has to get through the handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest line taken from the other end before it has proven it knows the token
+const MAX_HANDSHAKE_LINE: u64 = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    /// First message of either end
+    Hello {
+        version: u32,
+        /// Colour played at the end sending it
+        colour: PieceColor,
+        /// Hex, to be answered with a proof
+        nonce: String,
+    },
+    /// HMAC of the nonce of the other end and the own colour, keyed with the token
+    Auth { proof: String },
+    /// All moves of the game so far, in UCI notation
+    Sync { moves: Vec<String> },
+    /// Move number `ply`, counted from 0, was made on the board of the sender
+    Move { ply: usize, uci: String },
+    /// What the clock of the sender shows
+    Clock { white: Remaining, black: Remaining },
+    /// The game ended, as a PGN result
+    Result { result: String },
+}
+
+/// What the moves of the other end mean for the game here
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reconcile {
+    /// Both have the same moves
+    InStep,
+    /// The other end has these moves on top of ours
+    Behind(Vec<String>),
+    /// We have moves the other end has yet to catch up with
+    Ahead,
+    /// The games differ from this ply on
+    Diverged(usize),
+}
+
+/// Compare `ours` with the moves the other end has
+pub fn reconcile(ours: &[String], theirs: &[String]) -> Reconcile {
+    if let Some(ply) = ours.iter().zip(theirs).position(|(a, b)| a != b) {
+        return Reconcile::Diverged(ply);
+    }
+    match ours.len().cmp(&theirs.len()) {
+        std::cmp::Ordering::Equal => Reconcile::InStep,
+        std::cmp::Ordering::Less => Reconcile::Behind(theirs[ours.len()..].to_vec()),
+        std::cmp::Ordering::Greater => Reconcile::Ahead,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// The colour goes into the proof, so one end can not pass off a proof the other end
+/// made for its own nonce
+fn signed(nonce: &str, colour: PieceColor) -> Vec<u8> {
+    format!("{}:{:?}", nonce, colour).into_bytes()
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Signs or checks the messages one end sends after the handshake
+struct Seal {
+    key: hmac::Key,
+    /// Both nonces and the colour of the sending end
+    session: String,
+    /// Number of the next message
+    count: u64,
+}
+
+impl Seal {
+    fn new(token: &str, nonces: [&str; 2], sender: PieceColor) -> Seal {
+        Seal {
+            key: hmac::Key::new(hmac::HMAC_SHA256, token.as_bytes()),
+            session: format!("{}:{}:{:?}", nonces[0], nonces[1], sender),
+            count: 0,
+        }
+    }
+
+    fn signed(&self, json: &str) -> Vec<u8> {
+        format!("{}:{}:{}", self.session, self.count, json).into_bytes()
+    }
+
+    /// The MAC of the next message
+    fn sign(&mut self, json: &str) -> String {
+        let tag = hmac::sign(&self.key, &self.signed(json));
+        self.count += 1;
+        hex(tag.as_ref())
+    }
+
+    /// Make sure `mac` belongs to the next message
+    fn check(&mut self, mac: &str, json: &str) -> io::Result<()> {
+        let mac = unhex(mac).ok_or_else(|| invalid("MAC is not hex"))?;
+        hmac::verify(&self.key, &self.signed(json), &mac).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "message not signed with the token",
+            )
+        })?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+/// Sends messages to the other end, can be cloned off a `Link` for another thread
+pub struct LinkSender {
+    stream: TcpStream,
+    /// Shared by all senders, so the messages are numbered in the order they go out
+    seal: Option<Arc<Mutex<Seal>>>,
+}
+
+impl LinkSender {
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        let json = serde_json::to_string(message).map_err(io::Error::other)?;
+        match &self.seal {
+            Some(seal) => {
+                let mut seal = seal.lock().unwrap();
+                let line = format!("{} {}\n", seal.sign(&json), json);
+                self.stream.write_all(line.as_bytes())
+            }
+            None => self.stream.write_all(format!("{}\n", json).as_bytes()),
+        }
+    }
+}
+
+/// An authenticated connection to the other end
+pub struct Link {
+    sender: LinkSender,
+    reader: BufReader<TcpStream>,
+    /// Checks the messages of the other end, once through the handshake
+    seal: Option<Seal>,
+    /// Colour the other end plays
+    pub peer: PieceColor,
+}
+
+impl Link {
+    /// Greet the other end on `stream` as the player of `colour`, and make sure both
+    /// ends know `token` and play different colours
+    pub fn handshake(stream: TcpStream, token: &str, colour: PieceColor) -> io::Result<Link> {
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let mut link = Link {
+            sender: LinkSender {
+                stream: stream.try_clone()?,
+                seal: None,
+            },
+            reader: BufReader::new(stream),
+            seal: None,
+            peer: PieceColor::None,
+        };
+        let mut nonce = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| io::Error::other("no random numbers for a nonce"))?;
+        let nonce = hex(&nonce);
+        link.send(&Message::Hello {
+            version: PROTOCOL_VERSION,
+            colour,
+            nonce: nonce.clone(),
+        })?;
+        let (peer, peer_nonce) = match link.recv()? {
+            Some(Message::Hello {
+                version: PROTOCOL_VERSION,
+                colour,
+                nonce,
+            }) => (colour, nonce),
+            Some(Message::Hello { version, .. }) => {
+                return Err(invalid(format!(
+                    "the other end speaks version {}, not {}",
+                    version, PROTOCOL_VERSION
+                )))
+            }
+            _ => return Err(invalid("the other end did not say hello")),
+        };
+        if peer == colour || peer == PieceColor::None {
+            return Err(invalid(format!("the other end plays {:?} as well", peer)));
+        }
+        let key = hmac::Key::new(hmac::HMAC_SHA256, token.as_bytes());
+        let proof = hmac::sign(&key, &signed(&peer_nonce, colour));
+        link.send(&Message::Auth {
+            proof: hex(proof.as_ref()),
+        })?;
+        let Some(Message::Auth { proof }) = link.recv()? else {
+            return Err(invalid("the other end did not authenticate"));
+        };
+        let proof = unhex(&proof).ok_or_else(|| invalid("proof is not hex"))?;
+        hmac::verify(&key, &signed(&nonce, peer), &proof)
+            .map_err(|_| io::Error::new(io::ErrorKind::PermissionDenied, "wrong token"))?;
+        link.reader.get_ref().set_read_timeout(None)?;
+        let nonces = match colour {
+            PieceColor::White => [nonce.as_str(), peer_nonce.as_str()],
+            _ => [peer_nonce.as_str(), nonce.as_str()],
+        };
+        link.sender.seal = Some(Arc::new(Mutex::new(Seal::new(token, nonces, colour))));
+        link.seal = Some(Seal::new(token, nonces, peer));
+        link.peer = peer;
+        Ok(link)
+    }
+
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        self.sender.send(message)
+    }
+
+    /// The next message, None once the other end hung up
+    pub fn recv(&mut self) -> io::Result<Option<Message>> {
+        let mut line = String::new();
+        let read = match self.seal {
+            Some(_) => self.reader.read_line(&mut line)?,
+            // Nobody unauthenticated gets to fill the memory with one endless line
+            None => (&mut self.reader)
+                .take(MAX_HANDSHAKE_LINE)
+                .read_line(&mut line)?,
+        };
+        if read == 0 {
+            return Ok(None);
+        }
+        if self.seal.is_none() && !line.ends_with('\n') {
+            return Err(invalid("handshake line too long"));
+        }
+        let json = match self.seal.as_mut() {
+            Some(seal) => {
+                let (mac, json) = line
+                    .trim_end()
+                    .split_once(' ')
+                    .ok_or_else(|| invalid(format!("unsigned message {:?}", line.trim())))?;
+                seal.check(mac, json)?;
+                json
+            }
+            None => line.as_str(),
+        };
+        serde_json::from_str(json)
+            .map(Some)
+            .map_err(|e| invalid(format!("bad message {:?}: {}", json.trim(), e)))
+    }
+
+    /// Another sender on the same connection
+    pub fn sender(&self) -> io::Result<LinkSender> {
+        Ok(LinkSender {
+            stream: self.sender.stream.try_clone()?,
+            seal: self.sender.seal.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn connect(
+        host_token: &'static str,
+        guest_token: &'static str,
+    ) -> (io::Result<Link>, io::Result<Link>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let guest = thread::spawn(move || {
+            let stream = TcpStream::connect(addr)?;
+            Link::handshake(stream, guest_token, PieceColor::Black)
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let host = Link::handshake(stream, host_token, PieceColor::White);
+        (host, guest.join().unwrap())
+    }
+
+    #[test]
+    fn test_handshake_and_moves() {
+        let (host, guest) = connect("s3cret", "s3cret");
+        let (host, mut guest) = (host.unwrap(), guest.unwrap());
+        assert_eq!(host.peer, PieceColor::Black);
+        let e4 = Message::Move {
+            ply: 0,
+            uci: "e2e4".to_string(),
+        };
+        host.sender().unwrap().send(&e4).unwrap();
+        assert_eq!(guest.recv().unwrap(), Some(e4.clone()));
+        // A message slipped in without the token is turned away
+        let mut stream = host.sender.stream.try_clone().unwrap();
+        let forged = format!(
+            "{} {}\n",
+            hex(&[0; 32]),
+            serde_json::to_string(&e4).unwrap()
+        );
+        stream.write_all(forged.as_bytes()).unwrap();
+        assert_eq!(
+            guest.recv().err().map(|e| e.kind()),
+            Some(io::ErrorKind::PermissionDenied)
+        );
+        drop((host, stream));
+        assert_eq!(guest.recv().unwrap(), None);
+
+        let (host, guest) = connect("s3cret", "guess");
+        assert_eq!(
+            host.err().map(|e| e.kind()),
+            Some(io::ErrorKind::PermissionDenied)
+        );
+        assert!(guest.is_err());
+
+        // An endless line before the handshake is cut off
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (done, hold) = std::sync::mpsc::channel::<()>();
+        let flood = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let _ = stream.write_all(&[b'x'; 4096]);
+            let _ = hold.recv();
+        });
+        let (stream, _) = listener.accept().unwrap();
+        assert_eq!(
+            Link::handshake(stream, "s3cret", PieceColor::White)
+                .err()
+                .map(|e| e.kind()),
+            Some(io::ErrorKind::InvalidData)
+        );
+        drop(done);
+        flood.join().unwrap();
+
+        let moves = |ucis: &[&str]| -> Vec<String> { ucis.iter().map(|s| s.to_string()).collect() };
+        let ours = moves(&["e2e4", "e7e5"]);
+        assert_eq!(reconcile(&ours, &ours), Reconcile::InStep);
+        assert_eq!(
+            reconcile(&ours, &moves(&["e2e4", "e7e5", "g1f3"])),
+            Reconcile::Behind(moves(&["g1f3"]))
+        );
+        assert_eq!(reconcile(&ours, &moves(&["e2e4"])), Reconcile::Ahead);
+        assert_eq!(
+            reconcile(&ours, &moves(&["e2e4", "c7c5"])),
+            Reconcile::Diverged(1)
+        );
+    }
+}
@@ -0,0 +1,446 @@
+//! Overlay for streaming software such as OBS: the position, the clocks, the players and
+//! an evaluation bar on a transparent background. It is served as a page to add as a
+//! browser source, and with the `resvg` feature also written to a PNG after every change
+//! for layouts that take an image, a ticking clock at most once a second.
+
+use crate::game::GameBoard;
+use crate::locale::{Locale, Msg};
+use crate::protocol::{PieceColor, RawPiece, Remaining};
+use crate::relay::{Error, Relay};
+use crate::render;
+use crate::rules::Position;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Side of a square in pixels, the board is eight of them wide
+const SQUARE: u32 = 64;
+/// Width of the evaluation bar left of the board
+const BAR: u32 = 24;
+/// Space between the bar and the board
+const GAP: u32 = 8;
+/// Height of the name and clock rows above and below the board
+const ROW: u32 = 48;
+/// Advantage in centipawns that fills the bar
+const FULL_BAR: i32 = 1000;
+/// Least time between two PNGs written for a ticking clock
+#[cfg(feature = "resvg")]
+const PNG_CLOCK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What the overlay shows
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    pub position: Option<Position>,
+    pub white: Option<String>,
+    pub black: Option<String>,
+    pub clock: Option<(Remaining, Remaining)>,
+    /// Advantage of white in centipawns
+    pub centipawns: Option<i32>,
+    /// PGN result once the game is over
+    pub result: Option<String>,
+    /// Language of the labels standing in for missing names
+    pub locale: Locale,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Scene {
+    pub fn width() -> u32 {
+        BAR + GAP + 8 * SQUARE
+    }
+
+    pub fn height() -> u32 {
+        2 * ROW + 8 * SQUARE
+    }
+
+    /// The overlay as an SVG image
+    pub fn svg(&self) -> String {
+        let (width, height, board) = (Scene::width(), Scene::height(), 8 * SQUARE);
+        let left = BAR + GAP;
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+             viewBox=\"0 0 {0} {1}\" font-family=\"DejaVu Sans, sans-serif\">\n",
+            width, height
+        );
+        // Filled from the bottom by white's share, even while there is no evaluation
+        let share = 0.5
+            + self.centipawns.unwrap_or(0).clamp(-FULL_BAR, FULL_BAR) as f64
+                / (2 * FULL_BAR) as f64;
+        let white_height = (share * board as f64).round() as u32;
+        let _ = writeln!(
+            out,
+            "<rect x=\"0\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#333\"/>\n\
+             <rect x=\"0\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#eee\"/>",
+            ROW,
+            BAR,
+            board,
+            ROW + board - white_height,
+            BAR,
+            white_height
+        );
+        let position = self.position.clone().unwrap_or_else(Position::starting);
+        for (square, piece) in position.board.iter().enumerate() {
+            let (file, rank) = (square as u32 % 8, square as u32 / 8);
+            let (x, y) = (left + file * SQUARE, ROW + (7 - rank) * SQUARE);
+            let fill = if (file + rank) % 2 == 0 {
+                "#b58863"
+            } else {
+                "#f0d9b5"
+            };
+            let _ = writeln!(
+                out,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                x, y, SQUARE, SQUARE, fill
+            );
+            if *piece != RawPiece::Empty {
+                let _ = writeln!(
+                    out,
+                    "<text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\">{}</text>",
+                    x + SQUARE / 2,
+                    y + SQUARE * 4 / 5,
+                    SQUARE * 4 / 5,
+                    render::unicode_glyph(*piece)
+                );
+            }
+        }
+        let players = [
+            (PieceColor::Black, &self.black, ROW * 2 / 3),
+            (PieceColor::White, &self.white, height - ROW / 3),
+        ];
+        for (colour, name, y) in players {
+            let clock = self.clock.map(|(white, black)| match colour {
+                PieceColor::Black => black,
+                _ => white,
+            });
+            let label = self.locale.label(match colour {
+                PieceColor::Black => Msg::Black,
+                _ => Msg::White,
+            });
+            let name = name.as_deref().unwrap_or(&label);
+            let _ = writeln!(
+                out,
+                "<text x=\"{}\" y=\"{}\" font-size=\"24\" fill=\"#fff\" stroke=\"#000\" \
+                 stroke-width=\"0.5\">{}</text>",
+                left,
+                y,
+                escape(name)
+            );
+            let right = match (clock, &self.result) {
+                (_, Some(result)) if colour == PieceColor::White => result.clone(),
+                (Some(clock), _) => clock.to_string(),
+                (None, _) => continue,
+            };
+            let _ = writeln!(
+                out,
+                "<text x=\"{}\" y=\"{}\" font-size=\"24\" fill=\"#fff\" stroke=\"#000\" \
+                 stroke-width=\"0.5\" text-anchor=\"end\">{}</text>",
+                width,
+                y,
+                escape(&right)
+            );
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    /// A page showing the overlay on a transparent background, fetching it again every
+    /// second
+    pub fn html(&self) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <style>html, body {{ margin: 0; background: transparent; }}</style>\n\
+             </head>\n<body>\n<div id=\"overlay\">{}</div>\n<script>\n\
+             setInterval(async () => {{\n  \
+             const response = await fetch(\"overlay.svg\");\n  \
+             if (response.ok) document.getElementById(\"overlay\").innerHTML = await response.text();\n\
+             }}, 1000);\n</script>\n</body>\n</html>\n",
+            self.svg()
+        )
+    }
+}
+
+/// Keeps the scene up to date with the game, and the PNG when one is written
+pub struct Overlay {
+    scene: Arc<Mutex<Scene>>,
+    /// Scenes for the PNG writer, each with whether it is written at once
+    #[cfg(feature = "resvg")]
+    png: Option<std::sync::mpsc::Sender<(Scene, bool)>>,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Overlay {
+            scene: Arc::default(),
+            #[cfg(feature = "resvg")]
+            png: None,
+        }
+    }
+
+    /// Label the sides in `locale`
+    pub fn with_locale(self, locale: Locale) -> Self {
+        self.scene.lock().unwrap().locale = locale;
+        self
+    }
+
+    /// Also write the overlay to `path` as a PNG whenever it changes
+    #[cfg(feature = "resvg")]
+    pub fn with_png(mut self, path: std::path::PathBuf) -> io::Result<Self> {
+        self.png = Some(PngWriter::new(path).spawn()?);
+        Ok(self)
+    }
+
+    /// The scene, to be served with `serve`
+    pub fn scene(&self) -> Arc<Mutex<Scene>> {
+        self.scene.clone()
+    }
+
+    fn update(&mut self, change: impl FnOnce(&mut Scene)) -> Result<(), Error> {
+        self.change(change, true)
+    }
+
+    /// Apply `change` to the scene, and have the PNG written at once when `now` is set
+    /// or after `PNG_CLOCK_INTERVAL` otherwise
+    fn change(&mut self, change: impl FnOnce(&mut Scene), now: bool) -> Result<(), Error> {
+        let mut scene = self.scene.lock().unwrap();
+        change(&mut scene);
+        #[cfg(feature = "resvg")]
+        if let Some(png) = &self.png {
+            png.send((scene.clone(), now))
+                .map_err(|_| "overlay PNG writer stopped")?;
+        }
+        #[cfg(not(feature = "resvg"))]
+        let _ = now;
+        Ok(())
+    }
+
+    fn show_game(&mut self, game: &GameBoard) -> Result<(), Error> {
+        let tags = game.tags();
+        let (position, white, black) = (game.position(), &tags.white, &tags.black);
+        self.update(|scene| {
+            scene.position = Some(position.clone());
+            scene.white = white.clone();
+            scene.black = black.clone();
+        })
+    }
+}
+
+impl Default for Overlay {
+    fn default() -> Self {
+        Overlay::new()
+    }
+}
+
+impl Relay for Overlay {
+    fn name(&self) -> &str {
+        "overlay"
+    }
+
+    fn on_new_game(&mut self, game: &GameBoard) -> Result<(), Error> {
+        self.update(|scene| {
+            scene.centipawns = None;
+            scene.result = None;
+        })?;
+        self.show_game(game)
+    }
+
+    fn on_move(&mut self, game: &GameBoard) -> Result<(), Error> {
+        self.show_game(game)
+    }
+
+    fn on_clock(&mut self, white: Remaining, black: Remaining) -> Result<(), Error> {
+        // A ticking clock is written to the PNG no more than once a second
+        self.change(|scene| scene.clock = Some((white, black)), false)
+    }
+
+    fn on_result(&mut self, game: &GameBoard, result: &str) -> Result<(), Error> {
+        self.update(|scene| scene.result = Some(result.to_string()))?;
+        self.show_game(game)
+    }
+
+    fn on_evaluation(&mut self, centipawns: i32) -> Result<(), Error> {
+        self.update(|scene| scene.centipawns = Some(centipawns))
+    }
+}
+
+/// Writes the overlay to a PNG, the system fonts loaded once for all of them
+#[cfg(feature = "resvg")]
+struct PngWriter {
+    path: std::path::PathBuf,
+    fonts: Arc<resvg::usvg::fontdb::Database>,
+    /// When the last one was written
+    written: Option<std::time::Instant>,
+}
+
+#[cfg(feature = "resvg")]
+impl PngWriter {
+    fn new(path: std::path::PathBuf) -> Self {
+        let mut fonts = resvg::usvg::fontdb::Database::new();
+        fonts.load_system_fonts();
+        PngWriter {
+            path,
+            fonts: Arc::new(fonts),
+            written: None,
+        }
+    }
+
+    /// How long until `PNG_CLOCK_INTERVAL` passed since the last one was written
+    fn wait(&self) -> Duration {
+        self.written.map_or(Duration::ZERO, |written| {
+            PNG_CLOCK_INTERVAL.saturating_sub(written.elapsed())
+        })
+    }
+
+    /// Write the scenes sent to the returned channel on a thread of its own. One not to
+    /// be written at once waits out the interval, and is dropped for a later scene.
+    fn spawn(mut self) -> io::Result<std::sync::mpsc::Sender<(Scene, bool)>> {
+        use std::sync::mpsc::{self, RecvTimeoutError};
+        let (tx, rx) = mpsc::channel::<(Scene, bool)>();
+        thread::Builder::new()
+            .name("overlay png".to_string())
+            .spawn(move || {
+                let mut deferred = None;
+                loop {
+                    let received = match deferred {
+                        Some(_) => rx.recv_timeout(self.wait()),
+                        None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                    };
+                    let scene = match received {
+                        Ok((scene, false)) if !self.wait().is_zero() => {
+                            deferred = Some(scene);
+                            continue;
+                        }
+                        Ok((scene, _)) => scene,
+                        // Written once the interval is over, even when the clock stopped
+                        Err(RecvTimeoutError::Timeout) => match deferred.take() {
+                            Some(scene) => scene,
+                            None => continue,
+                        },
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    };
+                    deferred = None;
+                    if let Err(e) = self.write(&scene) {
+                        warn!(error = %e, "overlay PNG not written");
+                    }
+                }
+            })?;
+        Ok(tx)
+    }
+
+    fn write(&mut self, scene: &Scene) -> Result<(), Error> {
+        use resvg::{tiny_skia, usvg};
+        let options = usvg::Options {
+            fontdb: self.fonts.clone(),
+            ..usvg::Options::default()
+        };
+        let tree = usvg::Tree::from_str(&scene.svg(), &options)?;
+        let mut pixmap =
+            tiny_skia::Pixmap::new(Scene::width(), Scene::height()).ok_or("overlay has no size")?;
+        resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+        // Written next to the file and moved over it, so the streaming software never
+        // reads half an image
+        let tmp = self.path.with_extension("tmp");
+        pixmap.save_png(&tmp)?;
+        std::fs::rename(tmp, &self.path)?;
+        self.written = Some(std::time::Instant::now());
+        Ok(())
+    }
+}
+
+/// Serve `scene` over HTTP on `addr`: the page at `/` and the image at `/overlay.svg`
+pub fn serve(addr: impl ToSocketAddrs, scene: Arc<Mutex<Scene>>) -> io::Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    info!(addr = %listener.local_addr()?, "serving overlay");
+    thread::Builder::new()
+        .name("overlay".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(|mut stream| {
+                    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+                    let mut request = String::new();
+                    BufReader::new(&stream).read_line(&mut request)?;
+                    debug!(request = request.trim_end(), "overlay request");
+                    let scene = scene.lock().unwrap().clone();
+                    let (status, content_type, body) = match request.split_whitespace().nth(1) {
+                        Some("/") => ("200 OK", "text/html; charset=utf-8", scene.html()),
+                        Some("/overlay.svg") => ("200 OK", "image/svg+xml", scene.svg()),
+                        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+                    };
+                    write!(
+                        stream,
+                        "HTTP/1.1 {}\r\nContent-Type: {}\r\nCache-Control: no-store\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        content_type,
+                        body.len(),
+                        body
+                    )
+                });
+                if let Err(e) = result {
+                    warn!(error = %e, "failed to answer overlay request");
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_svg() {
+        let mut overlay = Overlay::new();
+        overlay
+            .on_clock(Remaining::from_seconds(300), Remaining::from_seconds(59))
+            .unwrap();
+        overlay.on_evaluation(FULL_BAR).unwrap();
+        let scene = overlay.scene().lock().unwrap().clone();
+        let svg = scene.svg();
+        assert!(svg.contains(">White</text>"));
+        assert!(svg.contains(">0:05:00</text>"));
+        assert!(svg.contains(">♚</text>"));
+        // All white once the advantage fills the bar
+        assert!(svg.contains(&format!(
+            "<rect x=\"0\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#eee\"/>",
+            ROW,
+            BAR,
+            8 * SQUARE
+        )));
+        assert!(scene.html().contains(&svg));
+    }
+
+    #[cfg(feature = "resvg")]
+    #[test]
+    fn test_png_clock_written_once_stopped() {
+        let path = std::env::temp_dir().join(format!("jackolope-{}.png", std::process::id()));
+        let modified = || std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        let mut overlay = Overlay::new().with_png(path.clone()).unwrap();
+        overlay
+            .on_clock(Remaining::from_seconds(300), Remaining::from_seconds(300))
+            .unwrap();
+        let mut first = None;
+        for _ in 0..50 {
+            first = modified();
+            if first.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(first.is_some());
+        // The clock stops within the interval, its last time still ends up in the PNG
+        overlay
+            .on_clock(Remaining::from_seconds(299), Remaining::from_seconds(300))
+            .unwrap();
+        thread::sleep(PNG_CLOCK_INTERVAL + Duration::from_millis(500));
+        assert!(modified() > first);
+        let _ = std::fs::remove_file(path);
+    }
+}
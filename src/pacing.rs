@@ -0,0 +1,104 @@
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::trace;
+
+/// How far apart commands to a board must be. DGT firmware drops commands that
+/// follow each other too closely, and needs a while to come back from a reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pacing {
+    /// Minimum time between two commands
+    pub gap: Duration,
+    /// Time to wait after a reset before the next command
+    pub settle: Duration,
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Pacing {
+            gap: Duration::from_millis(20),
+            settle: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Holds back each command until the board is ready for it. Every write goes
+/// through `wait` and `sent`, so the spacing also holds across callers.
+#[derive(Debug, Clone, Default)]
+pub struct Pacer {
+    pacing: Pacing,
+    /// Earliest time the next command may go out
+    ready_at: Option<Instant>,
+}
+
+impl Pacer {
+    pub fn new(pacing: Pacing) -> Self {
+        Pacer {
+            pacing,
+            ready_at: None,
+        }
+    }
+
+    pub fn pacing(&self) -> Pacing {
+        self.pacing
+    }
+
+    pub fn set_pacing(&mut self, pacing: Pacing) {
+        self.pacing = pacing;
+    }
+
+    /// How long to wait at `now` before the next command
+    fn delay(&self, now: Instant) -> Duration {
+        self.ready_at
+            .map_or(Duration::ZERO, |ready| ready.saturating_duration_since(now))
+    }
+
+    /// Block until the next command may be sent
+    pub fn wait(&self) {
+        let delay = self.delay(Instant::now());
+        if !delay.is_zero() {
+            trace!(?delay, "holding back command");
+            thread::sleep(delay);
+        }
+    }
+
+    /// Note that a command went out, `reset` if it was one that needs settling
+    pub fn sent(&mut self, reset: bool) {
+        self.sent_at(Instant::now(), reset);
+    }
+
+    fn sent_at(&mut self, now: Instant, reset: bool) {
+        let pause = if reset {
+            self.pacing.settle.max(self.pacing.gap)
+        } else {
+            self.pacing.gap
+        };
+        self.ready_at = Some(now + pause);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pacer_delays() {
+        let mut pacer = Pacer::new(Pacing {
+            gap: Duration::from_millis(20),
+            settle: Duration::from_millis(100),
+        });
+        let start = Instant::now();
+        assert_eq!(pacer.delay(start), Duration::ZERO);
+        pacer.sent_at(start, false);
+        assert_eq!(pacer.delay(start), Duration::from_millis(20));
+        assert_eq!(
+            pacer.delay(start + Duration::from_millis(15)),
+            Duration::from_millis(5)
+        );
+        assert_eq!(
+            pacer.delay(start + Duration::from_millis(30)),
+            Duration::ZERO
+        );
+        pacer.sent_at(start, true);
+        assert_eq!(pacer.delay(start), Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,181 @@
+//! One game played on two boards, each player at their own board. A move made on one
+//! board becomes the move the other board expects, and its player copies it over.
+
+use crate::game::{Correction, GameBoard, SyncState};
+use crate::protocol::*;
+use crate::variant::Variant;
+use std::sync::Arc;
+use tracing::warn;
+
+/// What a change on one of the boards means for the players
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PairUpdate {
+    /// Nothing to tell
+    Nothing,
+    /// The player at the `board` of their colour moved, the other board waits for the
+    /// move to be copied
+    Played {
+        board: PieceColor,
+        uci: String,
+        san: String,
+    },
+    /// A move of the other player was made on `board` before it was their turn, it was
+    /// taken back and the board has to be restored
+    NotYours {
+        board: PieceColor,
+        corrections: Vec<Correction>,
+    },
+    /// `board` shows the game again
+    Synced { board: PieceColor },
+    /// `board` does not show the game, the corrections restore it
+    OutOfSync {
+        board: PieceColor,
+        corrections: Vec<Correction>,
+    },
+}
+
+/// The game as seen on the board of white and on the board of black
+pub struct Pairing {
+    variant: Arc<dyn Variant>,
+    games: [Option<GameBoard>; 2],
+}
+
+fn index(colour: PieceColor) -> usize {
+    match colour {
+        PieceColor::Black => 1,
+        _ => 0,
+    }
+}
+
+impl Pairing {
+    pub fn new(variant: Arc<dyn Variant>) -> Self {
+        Pairing {
+            variant,
+            games: [None, None],
+        }
+    }
+
+    /// The game on the board of `colour`, once it reported its pieces
+    pub fn game(&self, colour: PieceColor) -> Option<&GameBoard> {
+        self.games[index(colour)].as_ref()
+    }
+
+    /// The board of `colour` reported all its pieces. It starts out with the moves
+    /// already made on the other board, if any, to be copied over.
+    pub fn board_dump(&mut self, colour: PieceColor, board: ChessBoard) -> PairUpdate {
+        let mut game = GameBoard::new_with_variant(board, self.variant.clone());
+        if let Some(other) = &self.games[index(colour.opposite())] {
+            game.reset_to(other.initial_position().clone());
+            let moves: Vec<String> = other.moves().iter().map(|ply| ply.uci()).collect();
+            if let Err(e) = game.replace_moves(&moves) {
+                warn!(error = %e, "boards do not start from the same position");
+            }
+        }
+        let update = if game.is_out_of_sync() {
+            PairUpdate::OutOfSync {
+                board: colour,
+                corrections: game.recovery_plan(),
+            }
+        } else {
+            PairUpdate::Nothing
+        };
+        self.games[index(colour)] = Some(game);
+        update
+    }
+
+    /// A square changed on the board of `colour`
+    pub fn field_update(&mut self, colour: PieceColor, mv: ChessMove) -> PairUpdate {
+        let Some(game) = self.games[index(colour)].as_mut() else {
+            return PairUpdate::Nothing;
+        };
+        if game.apply_move(mv).is_none() {
+            return PairUpdate::Nothing;
+        }
+        let was_out_of_sync = game.is_out_of_sync();
+        match game.sync() {
+            SyncState::Moved(mv) if mv.colour() != colour => {
+                game.undo();
+                PairUpdate::NotYours {
+                    board: colour,
+                    corrections: game.recovery_plan(),
+                }
+            }
+            SyncState::Moved(_) => {
+                let recorded = game.history().last().expect("a move was just played");
+                let (uci, san) = (recorded.ply.uci(), recorded.san.clone());
+                if let Some(other) = self.games[index(colour.opposite())].as_mut() {
+                    if let Err(e) = other.expect_move(&uci) {
+                        warn!(error = %e, "boards have gone apart");
+                    }
+                }
+                PairUpdate::Played {
+                    board: colour,
+                    uci,
+                    san,
+                }
+            }
+            SyncState::InSync if was_out_of_sync => PairUpdate::Synced { board: colour },
+            // Told once, not for every piece moved while copying a move over
+            SyncState::OutOfSync | SyncState::Disturbed if !was_out_of_sync => {
+                PairUpdate::OutOfSync {
+                    board: colour,
+                    corrections: game.recovery_plan(),
+                }
+            }
+            SyncState::InSync
+            | SyncState::Pending
+            | SyncState::OutOfSync
+            | SyncState::Disturbed
+            | SyncState::TakenBack(_) => PairUpdate::Nothing,
+        }
+    }
+
+    /// `uci` was played on a board this pairing does not follow, such as one at the
+    /// other end of a network connection. The boards followed here wait for it.
+    pub fn remote_move(&mut self, uci: &str) -> Result<PairUpdate, String> {
+        let mut played = None;
+        for game in self.games.iter_mut().flatten() {
+            let mover = game.to_move();
+            game.expect_move(uci)?;
+            let recorded = game.history().last().expect("a move was just played");
+            played = Some(PairUpdate::Played {
+                board: mover,
+                uci: uci.to_string(),
+                san: recorded.san.clone(),
+            });
+        }
+        played.ok_or_else(|| "no board follows the game yet".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::{start_board, update};
+    use crate::variant::Standard;
+
+    #[test]
+    fn test_moves_cross_over() {
+        use PieceColor::*;
+        let mut pairing = Pairing::new(Arc::new(Standard));
+        pairing.board_dump(White, start_board());
+        pairing.board_dump(Black, start_board());
+        // e2e4 on white's board
+        pairing.field_update(White, update(52, RawPiece::Empty));
+        let played = pairing.field_update(White, update(36, RawPiece::WhitePawn));
+        assert!(
+            matches!(played, PairUpdate::Played { board: White, ref uci, .. } if uci == "e2e4")
+        );
+        assert!(pairing.game(Black).unwrap().is_out_of_sync());
+        // Copied on black's board
+        pairing.field_update(Black, update(52, RawPiece::Empty));
+        let synced = pairing.field_update(Black, update(36, RawPiece::WhitePawn));
+        assert_eq!(synced, PairUpdate::Synced { board: Black });
+        // White answering for black on their own board is not on
+        pairing.field_update(White, update(12, RawPiece::Empty));
+        let taken = pairing.field_update(White, update(28, RawPiece::BlackPawn));
+        assert!(matches!(taken, PairUpdate::NotYours { board: White, .. }));
+        assert_eq!(pairing.game(White).unwrap().moves().len(), 1);
+        assert_eq!(pairing.game(Black).unwrap().moves().len(), 1);
+    }
+}
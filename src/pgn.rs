@@ -0,0 +1,548 @@
+use crate::event::Event;
+use crate::game::GameBoard;
+use crate::protocol::*;
+use crate::rules::{self, Ply, PlyKind, Position};
+use crate::variant::{Termination, Variant};
+use serde::{Deserialize, Serialize};
+
+/// Tags naming the game and its players, "?" is written for the ones not known
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameTags {
+    pub event: Option<String>,
+    pub site: Option<String>,
+    pub round: Option<String>,
+    /// Label of the board, e.g. "Board 3"
+    pub board: Option<String>,
+    pub white: Option<String>,
+    pub black: Option<String>,
+    /// PGN time control, seconds and increment like "5400+30"
+    pub time_control: Option<String>,
+    /// Time controls of each player in the same form, for time odds
+    pub white_time_control: Option<String>,
+    pub black_time_control: Option<String>,
+    /// What the seconds per move of the time controls are, "delay" or "bronstein",
+    /// which the TimeControl tag has no way to say
+    pub time_control_timing: Option<String>,
+    /// Points of the players in their match before this game, like "1.5"
+    pub white_score: Option<String>,
+    pub black_score: Option<String>,
+}
+
+impl GameTags {
+    /// "Board 3: Smith - Jones", or whichever parts are known
+    pub fn label(&self) -> Option<String> {
+        let players = match (&self.white, &self.black) {
+            (None, None) => None,
+            (white, black) => Some(format!(
+                "{} - {}",
+                white.as_deref().unwrap_or("?"),
+                black.as_deref().unwrap_or("?")
+            )),
+        };
+        match (&self.board, players) {
+            (Some(board), Some(players)) => Some(format!("{}: {}", board, players)),
+            (board, players) => board.clone().or(players),
+        }
+    }
+
+    /// The tags of a game read from PGN, leaving out the ones written as "?"
+    pub fn from_pgn(game: &PgnGame) -> Self {
+        let tag = |name| game.tag(name).filter(|v| *v != "?").map(str::to_string);
+        GameTags {
+            event: tag("Event"),
+            site: tag("Site"),
+            round: tag("Round"),
+            board: tag("Board"),
+            white: tag("White"),
+            black: tag("Black"),
+            time_control: tag("TimeControl"),
+            white_time_control: tag("WhiteTimeControl"),
+            black_time_control: tag("BlackTimeControl"),
+            time_control_timing: tag("TimeControlTiming"),
+            white_score: tag("WhiteScore"),
+            black_score: tag("BlackScore"),
+        }
+    }
+}
+
+/// Standard algebraic notation of a legal move in `position`
+pub fn san(variant: &dyn Variant, position: &Position, ply: &Ply) -> String {
+    let mut out = match ply.kind {
+        PlyKind::Castle { .. } if rules::file_of(ply.to) == 6 => "O-O".to_string(),
+        PlyKind::Castle { .. } => "O-O-O".to_string(),
+        PlyKind::Drop => format!(
+            "{}@{}",
+            char::from(ply.piece).to_ascii_uppercase(),
+            rules::square_name(ply.to)
+        ),
+        _ if ply.piece.is_pawn() => {
+            let mut out = String::new();
+            if ply.is_capture() {
+                out.push((b'a' + rules::file_of(ply.from)) as char);
+                out.push('x');
+            }
+            out.push_str(&rules::square_name(ply.to));
+            if let PlyKind::Promotion(piece) = ply.kind {
+                out.push('=');
+                out.push(char::from(piece).to_ascii_uppercase());
+            }
+            out
+        }
+        _ => {
+            let mut out = String::new();
+            out.push(char::from(ply.piece).to_ascii_uppercase());
+            let rivals: Vec<Ply> = variant
+                .legal_moves(position)
+                .into_iter()
+                .filter(|other| {
+                    other.piece == ply.piece
+                        && other.to == ply.to
+                        && other.from != ply.from
+                        && other.kind != PlyKind::Drop
+                })
+                .collect();
+            if !rivals.is_empty() {
+                let name = rules::square_name(ply.from);
+                if rivals
+                    .iter()
+                    .all(|o| rules::file_of(o.from) != rules::file_of(ply.from))
+                {
+                    out.push_str(&name[..1]);
+                } else if rivals
+                    .iter()
+                    .all(|o| rules::rank_of(o.from) != rules::rank_of(ply.from))
+                {
+                    out.push_str(&name[1..]);
+                } else {
+                    out.push_str(&name);
+                }
+            }
+            if ply.is_capture() {
+                out.push('x');
+            }
+            out.push_str(&rules::square_name(ply.to));
+            out
+        }
+    };
+    let next = variant.play(position, ply);
+    match variant.outcome(&next) {
+        Some(outcome) if outcome.termination == Termination::Checkmate => out.push('#'),
+        _ if next.in_check() => out.push('+'),
+        _ => {}
+    }
+    out
+}
+
+/// The last move of the game in standard algebraic notation
+pub fn last_san(game: &GameBoard) -> Option<String> {
+    game.san_moves().last().map(str::to_string)
+}
+
+/// Find the legal move written as `text` in standard algebraic notation. Check marks
+/// and annotation symbols are optional.
+pub fn parse_san(variant: &dyn Variant, position: &Position, text: &str) -> Option<Ply> {
+    let bare = |s: &str| {
+        s.trim_end_matches(['+', '#', '!', '?'])
+            .replace('0', "O")
+            .to_string()
+    };
+    let wanted = bare(text);
+    variant
+        .legal_moves(position)
+        .into_iter()
+        .find(|ply| bare(&san(variant, position, ply)) == wanted)
+}
+
+/// A game read from PGN: its tags, the main line in SAN and the comments on it
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    /// Comments of the main line, each with the number of moves played before it
+    pub comments: Vec<(usize, String)>,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The position the game starts from, given by its FEN tag if it has one
+    pub fn start(&self) -> Result<Position, String> {
+        match self.tag("FEN") {
+            Some(fen) => Position::from_fen(fen).ok_or_else(|| format!("invalid FEN {}", fen)),
+            None => Ok(Position::starting()),
+        }
+    }
+
+    /// Time left to white and black in the last `[%clk]` comment after a move of each
+    pub fn clocks(&self) -> Option<(Remaining, Remaining)> {
+        let to_move = self.start().ok()?.to_move;
+        let (mut white, mut black) = (None, None);
+        for (played, comment) in &self.comments {
+            let (Some(clock), true) = (parse_clk(comment), *played > 0) else {
+                continue;
+            };
+            // Odd numbered moves are made by the side to move at the start
+            if (played % 2 == 1) == (to_move == PieceColor::White) {
+                white = Some(clock);
+            } else {
+                black = Some(clock);
+            }
+        }
+        Some((white?, black?))
+    }
+}
+
+/// The time of a `[%clk 1:05:30]` command in a comment, fractions of a second dropped
+fn parse_clk(comment: &str) -> Option<Remaining> {
+    let (_, rest) = comment.split_once("[%clk ")?;
+    let (time, _) = rest.split_once(']')?;
+    let mut seconds = 0;
+    for part in time.trim().split(':') {
+        let whole = part.split('.').next()?;
+        seconds = seconds * 60 + whole.parse::<u32>().ok()?;
+    }
+    Some(Remaining::from_seconds(seconds))
+}
+
+/// Read every game in a PGN file. Variations, glyphs and move numbers are skipped, as
+/// are comments inside variations.
+pub fn parse(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    let mut in_movetext = false;
+    let mut depth = 0;
+    let mut chars = text.chars().peekable();
+    let mut token = String::new();
+    let finish_token = |token: &mut String, game: &mut PgnGame, depth: i32| {
+        let word = std::mem::take(token);
+        // Move numbers, possibly glued to the move as in "1.e4"
+        let word = match word.rfind('.') {
+            Some(i) if word[..i].chars().all(|c| c.is_ascii_digit() || c == '.') => &word[i + 1..],
+            _ => &word,
+        };
+        if depth == 0 && !word.is_empty() && !word.starts_with('$') {
+            game.moves.push(word.to_string());
+        }
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            '[' if depth == 0 && token.is_empty() => {
+                if in_movetext {
+                    games.push(std::mem::take(&mut game));
+                    in_movetext = false;
+                }
+                let line: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if let Some((name, value)) = line.split_once(' ') {
+                    let value = value.trim().trim_matches('"').replace("\\\"", "\"");
+                    game.tags.push((name.to_string(), value));
+                }
+            }
+            '{' => {
+                finish_token(&mut token, &mut game, depth);
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                let comment = comment.split_whitespace().collect::<Vec<_>>().join(" ");
+                if depth == 0 && !comment.is_empty() {
+                    game.comments.push((game.moves.len(), comment));
+                }
+            }
+            ';' => {
+                finish_token(&mut token, &mut game, depth);
+                chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+            }
+            '(' => {
+                finish_token(&mut token, &mut game, depth);
+                depth += 1;
+            }
+            ')' => {
+                finish_token(&mut token, &mut game, depth);
+                depth -= 1;
+            }
+            c if c.is_whitespace() => finish_token(&mut token, &mut game, depth),
+            c => {
+                in_movetext = true;
+                token.push(c);
+            }
+        }
+        if depth == 0 && take_result(&mut game) {
+            games.push(std::mem::take(&mut game));
+            in_movetext = false;
+        }
+    }
+    finish_token(&mut token, &mut game, depth);
+    take_result(&mut game);
+    if in_movetext || !game.tags.is_empty() {
+        games.push(game);
+    }
+    games
+}
+
+/// Move the result ending the movetext read so far into the tags, false if there is none
+fn take_result(game: &mut PgnGame) -> bool {
+    let Some(result) = game
+        .moves
+        .pop_if(|m| ["1-0", "0-1", "1/2-1/2", "*"].contains(&m.as_str()))
+    else {
+        return false;
+    };
+    game.tags.retain(|(n, _)| n != "Result");
+    game.tags.push(("Result".to_string(), result));
+    true
+}
+
+/// Numeric annotation glyphs used when reviewing a game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nag {
+    /// `!`
+    Good,
+    /// `?`
+    Mistake,
+    /// `??`
+    Blunder,
+    /// `?!`
+    Dubious,
+}
+
+impl Nag {
+    /// The `$n` code written to PGN
+    pub fn code(self) -> u8 {
+        match self {
+            Nag::Good => 1,
+            Nag::Mistake => 2,
+            Nag::Blunder => 4,
+            Nag::Dubious => 6,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Nag::Good => "!",
+            Nag::Mistake => "?",
+            Nag::Blunder => "??",
+            Nag::Dubious => "?!",
+        }
+    }
+}
+
+/// Glyph and comment attached to a single move
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Annotation {
+    pub nag: Option<Nag>,
+    pub comment: Option<String>,
+}
+
+/// Result tag of a game: 1-0, 0-1, 1/2-1/2 or * while it is still going on
+pub fn result(game: &GameBoard) -> &'static str {
+    match game.outcome() {
+        Some(outcome) => match outcome.winner {
+            PieceColor::White => "1-0",
+            PieceColor::Black => "0-1",
+            PieceColor::None => "1/2-1/2",
+        },
+        None if matches!(game.draw(), Some(Event::AutoDraw(_))) => "1/2-1/2",
+        None => "*",
+    }
+}
+
+/// The game so far as PGN, with the seven tag roster followed by variant, setup and opening tags
+pub fn to_pgn(game: &GameBoard) -> String {
+    to_annotated_pgn(game, &[])
+}
+
+/// Like `to_pgn`, with `annotations[i]` attached to the i-th move played. Notes of a
+/// commentator follow the annotation of their move.
+pub fn to_annotated_pgn(game: &GameBoard, annotations: &[Annotation]) -> String {
+    let result = result(game);
+    let known = game.tags();
+    let tag = |value: &Option<String>| value.clone().unwrap_or_else(|| "?".to_string());
+    let mut tags = vec![
+        ("Event", tag(&known.event)),
+        ("Site", tag(&known.site)),
+        ("Date", "????.??.??".to_string()),
+        ("Round", tag(&known.round)),
+        ("White", tag(&known.white)),
+        ("Black", tag(&known.black)),
+        ("Result", result.to_string()),
+    ];
+    if let Some(board) = &known.board {
+        tags.push(("Board", board.clone()));
+    }
+    if let Some(time_control) = &known.time_control {
+        tags.push(("TimeControl", time_control.clone()));
+    }
+    if let (Some(white), Some(black)) = (&known.white_time_control, &known.black_time_control) {
+        tags.push(("WhiteTimeControl", white.clone()));
+        tags.push(("BlackTimeControl", black.clone()));
+    }
+    if let Some(timing) = &known.time_control_timing {
+        tags.push(("TimeControlTiming", timing.clone()));
+    }
+    if let (Some(white), Some(black)) = (&known.white_score, &known.black_score) {
+        tags.push(("WhiteScore", white.clone()));
+        tags.push(("BlackScore", black.clone()));
+    }
+    let variant = game.variant();
+    if variant.name() != "standard" {
+        tags.push(("Variant", variant.name().to_string()));
+    }
+    let initial = game.initial_position();
+    if *initial != Position::starting() {
+        tags.push(("SetUp", "1".to_string()));
+        tags.push(("FEN", initial.to_fen()));
+    }
+    if let Some(opening) = game.opening() {
+        tags.push(("ECO", opening.eco.to_string()));
+        tags.push(("Opening", opening.name.to_string()));
+    }
+
+    let mut out = String::new();
+    for (name, value) in tags {
+        out.push_str(&format!("[{} \"{}\"]\n", name, value.replace('"', "\\\"")));
+    }
+    out.push('\n');
+
+    let mut tokens = Vec::new();
+    let mut position = initial;
+    // Black's move number is repeated at the start and after a comment
+    let mut resume = true;
+    for (i, recorded) in game.history().iter().enumerate() {
+        if position.to_move == PieceColor::White {
+            tokens.push(format!("{}.", position.fullmove));
+        } else if resume {
+            tokens.push(format!("{}...", position.fullmove));
+        }
+        resume = false;
+        tokens.push(recorded.san.clone());
+        if let Some(annotation) = annotations.get(i) {
+            if let Some(nag) = annotation.nag {
+                tokens.push(format!("${}", nag.code()));
+            }
+            if let Some(comment) = &annotation.comment {
+                tokens.push(format!("{{ {} }}", comment.replace('}', ")")));
+                resume = true;
+            }
+        }
+        for note in &recorded.notes {
+            tokens.push(format!("{{ {} }}", note.replace('}', ")")));
+            resume = true;
+        }
+        position = &recorded.position;
+    }
+    tokens.push(result.to_string());
+
+    // Movetext lines are kept below 80 characters
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > 79 {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    out.push_str(&line);
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::Standard;
+
+    fn find(position: &Position, uci: &str) -> Ply {
+        position
+            .legal_moves()
+            .into_iter()
+            .find(|ply| ply.uci() == uci)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_pgn() {
+        let text = r#"[Event "Casual"]
+[FEN "7k/8/6K1/8/8/8/8/R7 w - - 0 1"]
+
+1. Ra8+ { mate } (1. Rh1+?? Kg8) 1-0
+
+[Event "Second"]
+
+1. e4 e5 2. Nf3 $1 Nc6 *
+"#;
+        let games = parse(text);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tag("Event"), Some("Casual"));
+        assert_eq!(games[0].tag("Result"), Some("1-0"));
+        assert_eq!(games[0].moves, ["Ra8+"]);
+        assert_eq!(games[0].comments, [(1, "mate".to_string())]);
+        assert_eq!(games[1].moves, ["e4", "e5", "Nf3", "Nc6"]);
+
+        let position = Position::from_fen(games[0].tag("FEN").unwrap()).unwrap();
+        let ply = parse_san(&Standard, &position, "Ra8").unwrap();
+        assert_eq!(ply.uci(), "a1a8");
+        assert_eq!(parse_san(&Standard, &position, "Rb9"), None);
+
+        let clocked = parse(
+            "1. e4 { [%clk 0:04:58] } 1... e5 { [%clk 0:04:55.3] } 2. Nf3 { [%clk 0:04:41] } *",
+        );
+        assert_eq!(
+            clocked[0].clocks(),
+            Some((Remaining::from_seconds(281), Remaining::from_seconds(295)))
+        );
+        assert_eq!(games[1].clocks(), None);
+        assert_eq!(
+            GameTags::from_pgn(&games[1]).event.as_deref(),
+            Some("Second")
+        );
+    }
+
+    #[test]
+    fn test_san() {
+        let position = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(san(&Standard, &position, &find(&position, "e1c1")), "O-O-O");
+        let position = Position::from_fen("r3k3/8/8/6N1/8/5N2/8/RN2K1N1 w - - 0 1").unwrap();
+        assert_eq!(san(&Standard, &position, &find(&position, "g1h3")), "N1h3");
+        assert_eq!(san(&Standard, &position, &find(&position, "b1d2")), "Nbd2");
+        assert_eq!(san(&Standard, &position, &find(&position, "a1a8")), "Rxa8+");
+        let position = Position::from_fen("7k/4P3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            san(&Standard, &position, &find(&position, "e7e8q")),
+            "e8=Q+"
+        );
+        let position = Position::from_fen("7k/8/6K1/8/8/8/8/R7 w - - 0 1").unwrap();
+        assert_eq!(san(&Standard, &position, &find(&position, "a1a8")), "Ra8#");
+    }
+
+    #[test]
+    fn test_notes_become_comments() {
+        let mut game = GameBoard::new(crate::protocol::ChessBoard {
+            board: [crate::protocol::RawPiece::Empty; 64],
+        });
+        game.reset_to(Position::starting());
+        game.replace_moves(&["e2e4".to_string(), "e7e5".to_string()])
+            .unwrap();
+        game.annotate(1, PieceColor::White, "Best by test").unwrap();
+        assert!(game.annotate(2, PieceColor::White, "Too soon").is_err());
+        assert!(to_pgn(&game).contains("1. e4 { Best by test } 1... e5 *"));
+
+        game.set_tags(GameTags {
+            white_time_control: Some("300+3".to_string()),
+            black_time_control: Some("180".to_string()),
+            time_control_timing: Some("delay/increment".to_string()),
+            ..GameTags::default()
+        });
+        let text = to_pgn(&game);
+        assert!(!text.contains("[TimeControl "));
+        let tags = GameTags::from_pgn(&parse(&text)[0]);
+        assert_eq!(tags.white_time_control.as_deref(), Some("300+3"));
+        assert_eq!(tags.black_time_control.as_deref(), Some("180"));
+        assert_eq!(tags.time_control_timing.as_deref(), Some("delay/increment"));
+    }
+}
@@ -0,0 +1,25 @@
+//! The types most programs driving a board need, for a single glob import:
+//!
+//! ```no_run
+//! use jackolope::prelude::*;
+//! ```
+//!
+//! Everything else stays reachable through its own module.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::board::{BoardSnapshot, ConnectionStatus, DgtBoard, ElectronicBoard, Kind};
+pub use crate::clock::{Allowance, ClockCommand, TimeControl, Timing};
+pub use crate::game::{
+    Capture, DetectedMove, DetectionPolicy, DetectionState, GameBoard, GameBoardBuilder, Move,
+    StartPosition, SyncState,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::handle::BoardHandle;
+pub use crate::pgn::{GameTags, PgnGame};
+pub use crate::protocol::{
+    ChessBoard, ChessMove, ClockStatus, Command, Framer, MessageType, ParseError, ParseMode,
+    PieceColor, RawPiece, Remaining, Response,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::relay::Relay;
+pub use crate::rules::Position;
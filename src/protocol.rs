@@ -1,5 +1,11 @@
 #![allow(dead_code)]
 
+use crate::clock::ClockAck;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, trace};
+
 /// Commands that can be sent to a DGT board
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Command {
@@ -23,37 +29,105 @@ pub enum Command {
     RequestNiceUpdate = 0x4b,
     /// Request EE moves
     RequestEEMoves = 0x49,
+    /// Request the 10 character serial number of newer boards
+    RequestLongSerialNumber = 0x55,
+    /// Request complete board state as one 0x69 dump, for firmware that splits the
+    /// answer to `RequestBoard`
+    RequestDump93 = 0x69,
     /// Reset board
     Reset = 0x40,
 }
 
 impl Command {
+    pub const ALL: [Command; 13] = [
+        Command::Reset,
+        Command::RequestClock,
+        Command::RequestBoard,
+        Command::EnableUpdate,
+        Command::RequestUpdate,
+        Command::RequestSerialNumber,
+        Command::RequestBusAddress,
+        Command::RequestTrademark,
+        Command::RequestEEMoves,
+        Command::RequestNiceUpdate,
+        Command::RequestVersion,
+        Command::RequestLongSerialNumber,
+        Command::RequestDump93,
+    ];
+
     /// Convert the command to a byte for sending over serial
     pub fn as_byte(self) -> [u8; 1] {
         [self as u8]
     }
 
     /// Try to convert a byte into a Command
+    #[deprecated(note = "use `Command::try_from(byte)`")]
     pub fn try_from_byte(byte: u8) -> Option<Self> {
+        Command::try_from(byte).ok()
+    }
+}
+
+impl TryFrom<u8> for Command {
+    type Error = InvalidByte;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         use Command::*;
         match byte {
-            0x41 => Some(RequestClock),
-            0x42 => Some(RequestBoard),
-            0x43 => Some(EnableUpdate),
-            0x44 => Some(RequestUpdate),
-            0x45 => Some(RequestSerialNumber),
-            0x46 => Some(RequestBusAddress),
-            0x47 => Some(RequestTrademark),
-            0x4d => Some(RequestVersion),
-            0x4b => Some(RequestNiceUpdate),
-            0x49 => Some(RequestEEMoves),
-            0x40 => Some(Reset),
-            _ => None,
+            0x41 => Ok(RequestClock),
+            0x42 => Ok(RequestBoard),
+            0x43 => Ok(EnableUpdate),
+            0x44 => Ok(RequestUpdate),
+            0x45 => Ok(RequestSerialNumber),
+            0x46 => Ok(RequestBusAddress),
+            0x47 => Ok(RequestTrademark),
+            0x4d => Ok(RequestVersion),
+            0x4b => Ok(RequestNiceUpdate),
+            0x49 => Ok(RequestEEMoves),
+            0x55 => Ok(RequestLongSerialNumber),
+            0x69 => Ok(RequestDump93),
+            0x40 => Ok(Reset),
+            _ => Err(InvalidByte(byte)),
         }
     }
 }
 
+impl std::str::FromStr for Command {
+    type Err = String;
+
+    /// Command by its name, e.g. "RequestVersion", in any case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Command::ALL
+            .into_iter()
+            .find(|command| format!("{:?}", command).eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown command {:?}", s))
+    }
+}
+
+/// A byte that stands for no value of the type it was converted to
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidByte(pub u8);
+
+impl std::fmt::Display for InvalidByte {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid byte 0x{:02x}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidByte {}
+
+/// A character that stands for no piece
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidChar(pub char);
+
+impl std::fmt::Display for InvalidChar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid piece character {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidChar {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Remaining {
     hours: u8,
     minutes: u8,
@@ -79,9 +153,45 @@ impl Remaining {
             seconds: 10 * (seconds >> 4) + (seconds & 0x0f),
         }
     }
+
+    /// A time of `seconds`, with the hours capped at what fits
+    pub fn from_seconds(seconds: u32) -> Self {
+        Remaining::new(
+            (seconds / 3600).min(u8::MAX as u32) as u8,
+            (seconds / 60 % 60) as u8,
+            (seconds % 60) as u8,
+        )
+    }
+
+    pub fn hours(&self) -> u8 {
+        self.hours
+    }
+
+    pub fn minutes(&self) -> u8 {
+        self.minutes
+    }
+
+    pub fn seconds(&self) -> u8 {
+        self.seconds
+    }
+
+    pub fn total_seconds(&self) -> u32 {
+        self.hours as u32 * 3600 + self.minutes as u32 * 60 + self.seconds as u32
+    }
+
+    /// The time as a board sends it, hours capped at 99
+    pub fn to_bcd(&self) -> [u8; 3] {
+        [self.hours.min(99), self.minutes, self.seconds].map(|n| ((n / 10) << 4) | (n % 10))
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl std::fmt::Display for Remaining {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{:02}:{:02}", self.hours, self.minutes, self.seconds)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ClockStatus {
     NoCock,
     WhitesTurn,
@@ -98,6 +208,15 @@ impl ClockStatus {
             ClockStatus::WhitesTurn
         }
     }
+
+    /// The status byte a board sends after the times
+    pub fn to_byte(self) -> u8 {
+        match self {
+            ClockStatus::NoCock => 0x01,
+            ClockStatus::WhitesTurn => 0x00,
+            ClockStatus::BlacksTurn => 0x08,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -107,21 +226,140 @@ pub struct ChessBoard {
 
 impl ChessBoard {
     fn new(raw: &[u8; 64]) -> Option<Self> {
-        let mut board = Vec::new();
-        for s in raw.iter() {
-            if let Some(piece) = RawPiece::try_from_byte(*s) {
-                board.push(piece);
-            } else {
-                return None;
+        let mut board = [RawPiece::Empty; 64];
+        for (square, byte) in board.iter_mut().zip(raw) {
+            *square = RawPiece::try_from(*byte).ok()?;
+        }
+        Some(ChessBoard { board })
+    }
+
+    /// Check that the board could come from a standard set: at most one king and 16
+    /// pieces a side, no more pawns and promoted pieces than the 8 pawns a side starts
+    /// with, and no pawns on the first or last rank. A dump that fails this had bytes
+    /// garbled on the way, or caught the pieces mid-move, like a promoted piece put
+    /// down before the pawn is taken off.
+    pub fn check(&self) -> Result<(), Implausible> {
+        for colour in [PieceColor::White, PieceColor::Black] {
+            let count = |kind| {
+                self.board
+                    .iter()
+                    .filter(|piece| piece.get_colour() == colour && piece.kind() == Some(kind))
+                    .count()
+            };
+            if count(PieceKind::King) > 1 {
+                return Err(Implausible::Kings(colour));
+            }
+            if self
+                .board
+                .iter()
+                .filter(|p| p.get_colour() == colour)
+                .count()
+                > 16
+            {
+                return Err(Implausible::Pieces(colour));
+            }
+            let promoted = count(PieceKind::Queen).saturating_sub(1)
+                + count(PieceKind::Rook).saturating_sub(2)
+                + count(PieceKind::Bishop).saturating_sub(2)
+                + count(PieceKind::Knight).saturating_sub(2);
+            if count(PieceKind::Pawn) + promoted > 8 {
+                return Err(Implausible::Pawns(colour));
             }
         }
-        Some(ChessBoard {
-            board: board.try_into().unwrap(),
-        })
+        // The first and last rank are the outer rows in either orientation
+        match (0..8)
+            .chain(56..64)
+            .find(|&grid| self.board[grid].is_pawn())
+        {
+            Some(grid) => Err(Implausible::PawnOnBackRank(grid as u8)),
+            None => Ok(()),
+        }
     }
 }
 
+/// Why a board dump can not show a real position
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Implausible {
+    /// More than one king of a color
+    Kings(PieceColor),
+    /// More than 16 pieces of a color
+    Pieces(PieceColor),
+    /// More pawns of a color than promotions leave room for
+    Pawns(PieceColor),
+    /// A pawn at this grid index, on the first or last rank
+    PawnOnBackRank(u8),
+}
+
+impl std::fmt::Display for Implausible {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = |colour: &PieceColor| format!("{:?}", colour).to_lowercase();
+        match self {
+            Implausible::Kings(colour) => write!(f, "more than one {} king", name(colour)),
+            Implausible::Pieces(colour) => write!(f, "more than 16 {} pieces", name(colour)),
+            Implausible::Pawns(colour) => {
+                write!(f, "too many {} pawns and promoted pieces", name(colour))
+            }
+            Implausible::PawnOnBackRank(grid) => write!(f, "pawn on a back rank at {}", grid),
+        }
+    }
+}
+
+/// FEN style piece placement in grid order, one rank per 8 squares from grid 0
+impl std::fmt::Display for ChessBoard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, row) in self.board.chunks(8).enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            let mut empty = 0;
+            for piece in row {
+                if *piece == RawPiece::Empty {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    write!(f, "{}", empty)?;
+                    empty = 0;
+                }
+                write!(f, "{}", char::from(*piece))?;
+            }
+            if empty > 0 {
+                write!(f, "{}", empty)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Boards serialize as a 64 character string of FEN piece letters in grid order
+impl Serialize for ChessBoard {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let text: String = self.board.iter().map(|p| char::from(*p)).collect();
+        serializer.serialize_str(&text)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChessBoard {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        let text = String::deserialize(deserializer)?;
+        let mut board = [RawPiece::Empty; 64];
+        let mut count = 0;
+        for (i, c) in text.chars().enumerate() {
+            if i >= 64 {
+                return Err(D::Error::invalid_length(i + 1, &"64 squares"));
+            }
+            board[i] = RawPiece::try_from(c).map_err(D::Error::custom)?;
+            count += 1;
+        }
+        if count != 64 {
+            return Err(D::Error::invalid_length(count, &"64 squares"));
+        }
+        Ok(ChessBoard { board })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChessMove {
     pub grid: u8,
     pub piece: RawPiece,
@@ -133,15 +371,50 @@ impl ChessMove {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// "lift from e2" or "place WhiteKnight on f3", squares named as in the DGT
+/// documented layout with a8 at grid 0
+impl std::fmt::Display for ChessMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let square =
+            crate::rules::square_name(crate::rules::square(self.grid % 8, 7 - self.grid / 8));
+        match self.piece {
+            RawPiece::Empty => write!(f, "lift from {}", square),
+            piece => write!(f, "place {:?} on {}", piece, square),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PieceColor {
     None,
     White,
     Black,
 }
 
+impl PieceColor {
+    /// The other side, None stays None
+    pub fn opposite(self) -> Self {
+        match self {
+            PieceColor::None => PieceColor::None,
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+        }
+    }
+}
+
+/// Piece type independent of color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PieceKind {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
 /// Raw piece representation as sent by DGT board
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum RawPiece {
     #[default]
@@ -160,32 +433,61 @@ pub enum RawPiece {
     BlackQueen = 0x0c,
 }
 
-impl RawPiece {
-    /// Convert a byte into a RawPiece, returning None for invalid values
-    pub fn try_from_byte(byte: u8) -> Option<Self> {
+/// The piece a byte of the protocol stands for
+impl TryFrom<u8> for RawPiece {
+    type Error = InvalidByte;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
         use RawPiece::*;
         match byte {
-            0x00 => Some(Empty),
-            0x01 => Some(WhitePawn),
-            0x02 => Some(WhiteRook),
-            0x03 => Some(WhiteKnight),
-            0x04 => Some(WhiteBishop),
-            0x05 => Some(WhiteKing),
-            0x06 => Some(WhiteQueen),
-            0x07 => Some(BlackPawn),
-            0x08 => Some(BlackRook),
-            0x09 => Some(BlackKnight),
-            0x0a => Some(BlackBishop),
-            0x0b => Some(BlackKing),
-            0x0c => Some(BlackQueen),
-            _ => None,
+            0x00 => Ok(Empty),
+            0x01 => Ok(WhitePawn),
+            0x02 => Ok(WhiteRook),
+            0x03 => Ok(WhiteKnight),
+            0x04 => Ok(WhiteBishop),
+            0x05 => Ok(WhiteKing),
+            0x06 => Ok(WhiteQueen),
+            0x07 => Ok(BlackPawn),
+            0x08 => Ok(BlackRook),
+            0x09 => Ok(BlackKnight),
+            0x0a => Ok(BlackBishop),
+            0x0b => Ok(BlackKing),
+            0x0c => Ok(BlackQueen),
+            _ => Err(InvalidByte(byte)),
         }
     }
+}
 
-    /// Convert the piece to a FEN character representation
-    pub fn to_char(self) -> char {
+/// The piece of a FEN character, or ' ' for an empty square
+impl TryFrom<char> for RawPiece {
+    type Error = InvalidChar;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
         use RawPiece::*;
-        match self {
+        match c {
+            ' ' => Ok(Empty),
+            'P' => Ok(WhitePawn),
+            'R' => Ok(WhiteRook),
+            'N' => Ok(WhiteKnight),
+            'B' => Ok(WhiteBishop),
+            'K' => Ok(WhiteKing),
+            'Q' => Ok(WhiteQueen),
+            'p' => Ok(BlackPawn),
+            'r' => Ok(BlackRook),
+            'n' => Ok(BlackKnight),
+            'b' => Ok(BlackBishop),
+            'k' => Ok(BlackKing),
+            'q' => Ok(BlackQueen),
+            _ => Err(InvalidChar(c)),
+        }
+    }
+}
+
+/// The FEN character of a piece, ' ' for an empty square
+impl From<RawPiece> for char {
+    fn from(piece: RawPiece) -> char {
+        use RawPiece::*;
+        match piece {
             Empty => ' ',
             WhitePawn => 'P',
             WhiteRook => 'R',
@@ -201,9 +503,29 @@ impl RawPiece {
             BlackQueen => 'q',
         }
     }
+}
+
+impl RawPiece {
+    /// Convert a byte into a RawPiece, returning None for invalid values
+    #[deprecated(note = "use `RawPiece::try_from(byte)`")]
+    pub fn try_from_byte(byte: u8) -> Option<Self> {
+        RawPiece::try_from(byte).ok()
+    }
+
+    /// Convert a FEN character (or ' ' for an empty square) into a RawPiece
+    #[deprecated(note = "use `RawPiece::try_from(c)`")]
+    pub fn try_from_char(c: char) -> Option<Self> {
+        RawPiece::try_from(c).ok()
+    }
+
+    /// Convert the piece to a FEN character representation
+    #[deprecated(note = "use `char::from(piece)`")]
+    pub fn to_char(self) -> char {
+        self.into()
+    }
 
     /// Get the color of the piece
-    fn get_colour(&self) -> PieceColor {
+    pub fn get_colour(&self) -> PieceColor {
         match self {
             RawPiece::Empty => PieceColor::None,
             RawPiece::WhitePawn
@@ -222,13 +544,60 @@ impl RawPiece {
     }
 
     /// Check if two pieces are the same color
-    fn is_same_colour(&self, other: &RawPiece) -> bool {
+    pub fn is_same_colour(&self, other: &RawPiece) -> bool {
         *self != RawPiece::Empty && self.get_colour() == other.get_colour()
     }
+
+    /// Get the piece type, None for an empty square
+    pub fn kind(&self) -> Option<PieceKind> {
+        use RawPiece::*;
+        match self {
+            Empty => None,
+            WhitePawn | BlackPawn => Some(PieceKind::Pawn),
+            WhiteKnight | BlackKnight => Some(PieceKind::Knight),
+            WhiteBishop | BlackBishop => Some(PieceKind::Bishop),
+            WhiteRook | BlackRook => Some(PieceKind::Rook),
+            WhiteQueen | BlackQueen => Some(PieceKind::Queen),
+            WhiteKing | BlackKing => Some(PieceKind::King),
+        }
+    }
+
+    /// Build a piece from its type and color, Empty for PieceColor::None
+    pub fn from_kind(kind: PieceKind, colour: PieceColor) -> Self {
+        use RawPiece::*;
+        match (colour, kind) {
+            (PieceColor::None, _) => Empty,
+            (PieceColor::White, PieceKind::Pawn) => WhitePawn,
+            (PieceColor::White, PieceKind::Knight) => WhiteKnight,
+            (PieceColor::White, PieceKind::Bishop) => WhiteBishop,
+            (PieceColor::White, PieceKind::Rook) => WhiteRook,
+            (PieceColor::White, PieceKind::Queen) => WhiteQueen,
+            (PieceColor::White, PieceKind::King) => WhiteKing,
+            (PieceColor::Black, PieceKind::Pawn) => BlackPawn,
+            (PieceColor::Black, PieceKind::Knight) => BlackKnight,
+            (PieceColor::Black, PieceKind::Bishop) => BlackBishop,
+            (PieceColor::Black, PieceKind::Rook) => BlackRook,
+            (PieceColor::Black, PieceKind::Queen) => BlackQueen,
+            (PieceColor::Black, PieceKind::King) => BlackKing,
+        }
+    }
+
+    pub fn is_pawn(&self) -> bool {
+        matches!(self, RawPiece::WhitePawn | RawPiece::BlackPawn)
+    }
+
+    pub fn is_king(&self) -> bool {
+        matches!(self, RawPiece::WhiteKing | RawPiece::BlackKing)
+    }
+
+    pub fn is_rook(&self) -> bool {
+        matches!(self, RawPiece::WhiteRook | RawPiece::BlackRook)
+    }
 }
 
 /// Message types that can be received from a DGT board
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum MessageType {
     BoardDump = 0x06,
     BWTime = 0x0d,
@@ -238,70 +607,695 @@ pub enum MessageType {
     SerialNumber = 0x11,
     Trademark = 0x12,
     Version = 0x13,
+    LongSerialNumber = 0x22,
+    /// Board dump of newer firmware, the squares followed by status bytes
+    BoardDump93 = 0x69,
+    /// Part of a board dump, for firmware that splits them
+    PartialDump = 0x6a,
+}
+
+impl std::fmt::Display for MessageType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} (0x{:02x})", self, *self as u8)
+    }
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = InvalidByte;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        match byte {
+            0x06 => Ok(MessageType::BoardDump),
+            0x0d => Ok(MessageType::BWTime),
+            0x0e => Ok(MessageType::FieldUpdate),
+            0x0f => Ok(MessageType::EEMoves),
+            0x10 => Ok(MessageType::BusAddress),
+            0x11 => Ok(MessageType::SerialNumber),
+            0x12 => Ok(MessageType::Trademark),
+            0x13 => Ok(MessageType::Version),
+            0x22 => Ok(MessageType::LongSerialNumber),
+            0x69 => Ok(MessageType::BoardDump93),
+            0x6a => Ok(MessageType::PartialDump),
+            _ => Err(InvalidByte(byte)),
+        }
+    }
 }
 
 impl MessageType {
+    #[deprecated(note = "use `MessageType::try_from(byte)`")]
     pub fn try_from_byte(byte: u8) -> Option<Self> {
-        match byte {
-            0x06 => Some(MessageType::BoardDump),
-            0x0d => Some(MessageType::BWTime),
-            0x0e => Some(MessageType::FieldUpdate),
-            0x0f => Some(MessageType::EEMoves),
-            0x10 => Some(MessageType::BusAddress),
-            0x11 => Some(MessageType::SerialNumber),
-            0x12 => Some(MessageType::Trademark),
-            0x13 => Some(MessageType::Version),
-            _ => None,
+        MessageType::try_from(byte).ok()
+    }
+
+    /// How much data a frame of this type carries
+    pub fn data_length(self) -> DataLength {
+        DATA_LENGTHS
+            .iter()
+            .find(|(message_type, _)| *message_type == self)
+            .map(|(_, length)| *length)
+            .unwrap()
+    }
+
+    /// How much of `length` bytes of data to parse, or why `mode` turns them down
+    pub fn check_length(self, length: usize, mode: ParseMode) -> Result<usize, ParseError> {
+        let lengths = self.data_length();
+        lengths.accept(length, mode).ok_or_else(|| {
+            let expected = lengths.expected.first().copied().unwrap_or(lengths.max);
+            ParseError::invalid_length(self, expected, length)
+        })
+    }
+}
+
+/// Largest length a frame header can give
+pub const MAX_FRAME_LENGTH: usize = 0x3fff;
+/// Most data a frame of a type not known here may carry before it is taken for noise
+pub const MAX_UNKNOWN_DATA_LENGTH: usize = 256;
+
+/// How strictly the length of a frame is held to `DATA_LENGTHS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Only the lengths the protocol gives
+    #[default]
+    Strict,
+    /// Also frames longer than that, up to the most their type may carry, as some
+    /// firmware pads them. The bytes past the expected length are ignored.
+    Lenient,
+}
+
+/// How much data a message type carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataLength {
+    /// Lengths the protocol gives, shortest first. Empty when any length up to `max`
+    /// will do.
+    pub expected: &'static [usize],
+    /// Most data a frame of the type may carry, padding included
+    pub max: usize,
+}
+
+impl DataLength {
+    /// How much of `length` bytes of data to parse, None when `mode` turns them down
+    pub fn accept(&self, length: usize, mode: ParseMode) -> Option<usize> {
+        if length > self.max {
+            return None;
+        }
+        if self.expected.is_empty() || self.expected.contains(&length) {
+            return Some(length);
+        }
+        match mode {
+            ParseMode::Strict => None,
+            ParseMode::Lenient => self.expected.iter().rev().find(|e| **e < length).copied(),
         }
     }
 }
 
-/// Decoded responses from the DGT board
+/// Data lengths of every message type, for the framer to turn a frame down on its
+/// header and for the parser to know what to read. Strings get room for what any
+/// firmware was seen to send, the move memory the whole 14 bit length.
+pub const DATA_LENGTHS: [(MessageType, DataLength); 11] = [
+    (
+        MessageType::BoardDump,
+        DataLength {
+            expected: &[64],
+            max: 72,
+        },
+    ),
+    (
+        MessageType::BWTime,
+        // Seven bytes of times and status, ten from clocks that add more after them
+        DataLength {
+            expected: &[7, 10],
+            max: 16,
+        },
+    ),
+    (
+        MessageType::FieldUpdate,
+        DataLength {
+            expected: &[2],
+            max: 8,
+        },
+    ),
+    (
+        MessageType::EEMoves,
+        DataLength {
+            expected: &[],
+            max: MAX_FRAME_LENGTH - 3,
+        },
+    ),
+    (
+        MessageType::BusAddress,
+        DataLength {
+            expected: &[2],
+            max: 8,
+        },
+    ),
+    (
+        MessageType::SerialNumber,
+        DataLength {
+            expected: &[],
+            max: 32,
+        },
+    ),
+    (
+        MessageType::Trademark,
+        DataLength {
+            expected: &[],
+            max: 256,
+        },
+    ),
+    (
+        MessageType::Version,
+        DataLength {
+            expected: &[2],
+            max: 8,
+        },
+    ),
+    (
+        MessageType::LongSerialNumber,
+        DataLength {
+            expected: &[],
+            max: 32,
+        },
+    ),
+    (
+        MessageType::BoardDump93,
+        // The squares, then status bytes that differ between firmware versions
+        DataLength {
+            expected: &[],
+            max: 80,
+        },
+    ),
+    (
+        MessageType::PartialDump,
+        // The first square and up to all of them
+        DataLength {
+            expected: &[],
+            max: 65,
+        },
+    ),
+];
+
+/// A frame as it came off the wire, before it is decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFrame {
+    /// Message type with the high bit cleared
+    pub message_type: u8,
+    pub data: Vec<u8>,
+}
+
+impl RawFrame {
+    /// The frame as the board sends it: type with the high bit set, 14 bit length
+    /// including the header, then the data
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let length = self.data.len() + 3;
+        let mut bytes = vec![
+            0x80 | self.message_type,
+            (length >> 7) as u8 & 0x7f,
+            length as u8 & 0x7f,
+        ];
+        bytes.extend(&self.data);
+        bytes
+    }
+
+    /// Decode the frame into a response
+    pub fn decode(&self) -> Result<Response, FrameError> {
+        self.decode_with(ParseMode::Strict)
+    }
+
+    /// Decode the frame into a response, holding its length to `mode`
+    pub fn decode_with(&self, mode: ParseMode) -> Result<Response, FrameError> {
+        decode(self.message_type, &self.data, mode)
+    }
+}
+
+fn decode(message_type: u8, data: &[u8], mode: ParseMode) -> Result<Response, FrameError> {
+    let Ok(known) = MessageType::try_from(message_type) else {
+        return Ok(Response::Unknown {
+            msg_type: message_type,
+            data: data.to_vec(),
+        });
+    };
+    Response::parse(known, data, mode).map_err(FrameError::Parse)
+}
+
+/// Why a frame was dropped
 #[derive(Debug)]
+#[non_exhaustive]
+pub enum FrameError {
+    /// The length in the header is shorter than the header itself
+    InvalidLength(usize),
+    /// The length in the header is more than a frame of its type can be. Only the
+    /// header is dropped, the bytes after it are searched for the next frame.
+    TooLarge {
+        message_type: u8,
+        length: usize,
+    },
+    /// No longer returned, frames of unknown types come through as `Response::Unknown`
+    #[deprecated(note = "unknown frame types are passed on as Response::Unknown")]
+    UnknownType(u8),
+    Parse(ParseError),
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::InvalidLength(_) => write!(f, "Invalid response length"),
+            FrameError::TooLarge {
+                message_type,
+                length,
+            } => write!(
+                f,
+                "Response of type 0x{:02x} too long: {} bytes",
+                message_type, length
+            ),
+            FrameError::Parse(_) => write!(f, "Parse error"),
+            #[allow(deprecated)]
+            FrameError::UnknownType(_) => write!(f, "Invalid response type"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Puts together the board from the partial dumps of firmware that splits them
+#[derive(Debug, Clone)]
+pub struct DumpAssembler {
+    squares: [Option<RawPiece>; 64],
+}
+
+impl Default for DumpAssembler {
+    fn default() -> Self {
+        DumpAssembler {
+            squares: [None; 64],
+        }
+    }
+}
+
+impl DumpAssembler {
+    /// Take the squares of a partial dump, the whole board once every square was
+    /// received. Parts may come in any order, a square received again replaces the
+    /// earlier one.
+    pub fn add(&mut self, first: u8, pieces: &[RawPiece]) -> Option<ChessBoard> {
+        for (square, piece) in self.squares.iter_mut().skip(first as usize).zip(pieces) {
+            *square = Some(*piece);
+        }
+        let mut board = [RawPiece::Empty; 64];
+        for (square, piece) in board.iter_mut().zip(&self.squares) {
+            *square = (*piece)?;
+        }
+        *self = DumpAssembler::default();
+        Some(ChessBoard { board })
+    }
+}
+
+/// Takes the data of frames of one message type in place of the parser, see
+/// `Framer::register_raw_handler`
+pub type RawHandler = Arc<dyn Fn(&[u8]) + Send + Sync>;
+
+/// Handlers by message type, with the high bit cleared
+#[derive(Clone, Default)]
+struct RawHandlers(HashMap<u8, RawHandler>);
+
+impl std::fmt::Debug for RawHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut types: Vec<_> = self.0.keys().collect();
+        types.sort();
+        f.debug_set()
+            .entries(types.iter().map(|t| format!("0x{:02x}", t)))
+            .finish()
+    }
+}
+
+/// Splits the byte stream of a board into frames, without doing any IO itself: bytes
+/// go in with `push` or `feed` however they arrive, and frames come out once complete.
+/// Bytes outside a frame are skipped, and a header byte with the high bit set starts
+/// the next frame.
+#[derive(Debug, Clone, Default)]
+pub struct Framer {
+    /// Received bytes, the frame being received starts at `start`. The buffer is kept
+    /// from one frame to the next, so once it fits the largest frame the board sends,
+    /// framing allocates nothing.
+    buffer: Vec<u8>,
+    start: usize,
+    dumps: DumpAssembler,
+    /// Pass on board dumps that fail `ChessBoard::check` as plain dumps
+    allow_implausible: bool,
+    mode: ParseMode,
+    handlers: RawHandlers,
+}
+
+impl Framer {
+    pub fn new() -> Self {
+        Framer::default()
+    }
+
+    /// Pass on board dumps that can not come from a standard set as plain dumps, for
+    /// variants where they are real positions
+    pub fn allow_implausible_dumps(&mut self, allow: bool) {
+        self.allow_implausible = allow;
+    }
+
+    /// How strictly frame lengths are checked, strict unless set
+    pub fn set_parse_mode(&mut self, mode: ParseMode) {
+        self.mode = mode;
+    }
+
+    /// Hand the data of every frame of `msg_type` to `handler` instead of decoding it,
+    /// so experimental or vendor messages can be taken in without changing the parser.
+    /// A handler for a type known here takes its place, one registered again replaces
+    /// the earlier one. Frames handled this way are not returned by `next_response` or
+    /// `feed`, `next_frame` still returns them.
+    pub fn register_raw_handler(
+        &mut self,
+        msg_type: u8,
+        handler: impl Fn(&[u8]) + Send + Sync + 'static,
+    ) {
+        self.handlers.0.insert(msg_type & 0x7f, Arc::new(handler));
+    }
+
+    /// Add received bytes, take the frames out with `next_frame` or `next_response`
+    pub fn push(&mut self, bytes: &[u8]) {
+        // Frames taken out are dropped here, once per read rather than once per frame
+        self.buffer.drain(..self.start);
+        self.start = 0;
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Add received bytes and decode every frame they complete. Frames that do not
+    /// decode are dropped, use `push` and `next_frame` to see them. Partial dumps come
+    /// out as one board dump once complete.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Response> {
+        let mut responses = Vec::new();
+        self.feed_into(bytes, &mut responses);
+        responses
+    }
+
+    /// Like `feed`, adding the responses to `responses`, so a caller reusing it
+    /// allocates nothing for frames without strings in them
+    pub fn feed_into(&mut self, bytes: &[u8], responses: &mut Vec<Response>) {
+        self.push(bytes);
+        while let Some(response) = self.next_response() {
+            let assembled = response.and_then(|response| {
+                match response {
+                    Response::PartialDump { first, pieces } => {
+                        match self.dumps.add(first, &pieces) {
+                            Some(board) => Ok(Some(Response::checked_dump(board))),
+                            None => Ok(None),
+                        }
+                    }
+                    response => Ok(Some(response)),
+                }
+                .map_err(FrameError::Parse)
+            });
+            match assembled {
+                Ok(response) => responses.extend(
+                    response.map(|response| response.allowing_implausible(self.allow_implausible)),
+                ),
+                Err(e) => debug!(error = %e, "dropping frame"),
+            }
+        }
+    }
+
+    /// The next complete frame, or None until more bytes are pushed
+    pub fn next_frame(&mut self) -> Option<Result<RawFrame, FrameError>> {
+        let length = match self.next_length()? {
+            Ok(length) => length,
+            Err(e) => return Some(Err(e)),
+        };
+        let frame = &self.buffer[self.start..self.start + length];
+        let raw = RawFrame {
+            message_type: frame[0] & 0x7f,
+            data: frame[3..].to_vec(),
+        };
+        self.start += length;
+        Some(Ok(raw))
+    }
+
+    /// The next complete frame decoded where it lies in the buffer, without copying
+    /// its data, or None until more bytes are pushed. Partial dumps come out as they
+    /// are, `feed` puts them together.
+    pub fn next_response(&mut self) -> Option<Result<Response, FrameError>> {
+        loop {
+            let length = match self.next_length()? {
+                Ok(length) => length,
+                Err(e) => return Some(Err(e)),
+            };
+            let frame = &self.buffer[self.start..self.start + length];
+            let message_type = frame[0] & 0x7f;
+            if let Some(handler) = self.handlers.0.get(&message_type) {
+                handler(&frame[3..]);
+                self.start += length;
+                continue;
+            }
+            let response = decode(message_type, &frame[3..], self.mode);
+            self.start += length;
+            return Some(response);
+        }
+    }
+
+    /// Length of the complete frame the pending bytes start with, once noise before it
+    /// was skipped. A header that is turned down is dropped with its error.
+    fn next_length(&mut self) -> Option<Result<usize, FrameError>> {
+        loop {
+            let pending = &self.buffer[self.start..];
+            let Some(start) = pending.iter().position(|byte| byte & 0x80 != 0) else {
+                if !pending.is_empty() {
+                    trace!(skipped = pending.len(), "skipping bytes outside frame");
+                }
+                self.buffer.clear();
+                self.start = 0;
+                return None;
+            };
+            if start > 0 {
+                trace!(skipped = start, "skipping bytes outside frame");
+                self.start += start;
+            }
+            let pending = &self.buffer[self.start..];
+            let header = &pending[..pending.len().min(3)];
+            if let Some(next) = header.iter().skip(1).position(|byte| byte & 0x80 != 0) {
+                trace!("unexpected high bit in length, resyncing");
+                self.start += next + 1;
+                continue;
+            }
+            if pending.len() < 3 {
+                return None;
+            }
+            let length = ((pending[1] as usize) << 7) | pending[2] as usize;
+            if length < 3 {
+                self.start += 3;
+                return Some(Err(FrameError::InvalidLength(length)));
+            }
+            // A garbled length is turned down before waiting for kilobytes of data
+            let message_type = pending[0] & 0x7f;
+            let handled = self.handlers.0.contains_key(&message_type);
+            let checked = match MessageType::try_from(message_type) {
+                // A handler decides what its frames hold
+                Ok(known)
+                    if handled
+                        && length - 3 <= known.data_length().max.max(MAX_UNKNOWN_DATA_LENGTH) =>
+                {
+                    Ok(())
+                }
+                Ok(known) if length - 3 <= known.data_length().max => known
+                    .check_length(length - 3, self.mode)
+                    .map(drop)
+                    .map_err(FrameError::Parse),
+                Err(_) if length - 3 <= MAX_UNKNOWN_DATA_LENGTH => Ok(()),
+                _ => Err(FrameError::TooLarge {
+                    message_type,
+                    length,
+                }),
+            };
+            if let Err(e) = checked {
+                self.start += 3;
+                return Some(Err(e));
+            }
+            if pending.len() < length {
+                return None;
+            }
+            return Some(Ok(length));
+        }
+    }
+
+    /// How many more bytes the frame being received needs at least, once
+    /// `next_frame` has returned None. Reading no more than this never takes bytes
+    /// past the end of a frame.
+    pub fn needed(&self) -> usize {
+        let pending = &self.buffer[self.start..];
+        if pending.len() < 3 {
+            3 - pending.len()
+        } else {
+            let length = ((pending[1] as usize) << 7) | pending[2] as usize;
+            length.saturating_sub(pending.len()).max(1)
+        }
+    }
+}
+
+/// Decoded responses from the DGT board
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Response {
     /// Complete board state
     BoardDump(ChessBoard),
+    /// Complete board state that fails `ChessBoard::check`, passed on for the caller
+    /// to judge: it may be garbled, or show pieces in the middle of a move
+    ImplausibleDump {
+        board: ChessBoard,
+        reason: Implausible,
+    },
+    /// Consecutive squares of the board from `first` on, see `DumpAssembler`
+    PartialDump { first: u8, pieces: Vec<RawPiece> },
     /// Clock data for both players and active color
     BWTime {
         white_time: Remaining,
         black_time: Remaining,
         status: ClockStatus,
     },
+    /// Reply of a DGT3000 clock, sent in place of a clock time message
+    ClockAck(ClockAck),
     /// Single piece movement
     FieldUpdate(ChessMove),
+    /// Contents of the move memory of the board, undecoded
+    EEMoves(Vec<u8>),
     /// Board serial number
     SerialNumber(String),
-    /// Bus address information
-    BusAddress(String),
+    /// Serial number in the long format of newer boards
+    LongSerialNumber(String),
+    /// Address of the board on a DGT bus
+    BusAddress(u16),
     /// Board trademark information
     Trademark(String),
     /// Board version information
     Version(String),
+    /// A frame of a type the driver does not know, as newer firmware may send, passed
+    /// on as it came so it can be reported
+    Unknown { msg_type: u8, data: Vec<u8> },
+}
+
+impl std::fmt::Display for Response {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Response::BoardDump(board) => write!(f, "board {}", board),
+            Response::ImplausibleDump { board, reason } => {
+                write!(f, "board {} with {}", board, reason)
+            }
+            Response::PartialDump { first, pieces } if pieces.is_empty() => {
+                write!(f, "no squares of the board from {}", first)
+            }
+            Response::PartialDump { first, pieces } => {
+                write!(
+                    f,
+                    "squares {} to {} of the board",
+                    first,
+                    *first as usize + pieces.len() - 1
+                )
+            }
+            Response::BWTime {
+                white_time,
+                black_time,
+                status,
+            } => {
+                write!(f, "white {} black {}", white_time, black_time)?;
+                match status {
+                    ClockStatus::NoCock => write!(f, ", no clock"),
+                    ClockStatus::WhitesTurn => write!(f, ", white to move"),
+                    ClockStatus::BlacksTurn => write!(f, ", black to move"),
+                }
+            }
+            Response::ClockAck(ack) => write!(f, "clock ack {:?}", ack),
+            Response::FieldUpdate(mv) => write!(f, "{}", mv),
+            Response::EEMoves(data) => write!(f, "{} bytes of stored moves", data.len()),
+            Response::SerialNumber(serial) => write!(f, "serial number {}", serial),
+            Response::LongSerialNumber(serial) => write!(f, "long serial number {}", serial),
+            Response::BusAddress(address) => write!(f, "bus address {}", address),
+            Response::Trademark(trademark) => write!(f, "trademark {}", trademark.trim_end()),
+            Response::Version(version) => write!(f, "version {}", version),
+            Response::Unknown { msg_type, data } => {
+                write!(
+                    f,
+                    "unknown message 0x{:02x} of {} bytes",
+                    msg_type,
+                    data.len()
+                )
+            }
+        }
+    }
 }
 
 impl Response {
+    /// A board dump, implausible if the board fails `ChessBoard::check`
+    pub fn checked_dump(board: ChessBoard) -> Self {
+        match board.check() {
+            Ok(()) => Response::BoardDump(board),
+            Err(reason) => Response::ImplausibleDump { board, reason },
+        }
+    }
+
+    /// An implausible dump taken as a plain board dump when `allow` is set, for
+    /// variants where positions failing the check are real
+    pub fn allowing_implausible(self, allow: bool) -> Self {
+        match self {
+            Response::ImplausibleDump { board, .. } if allow => Response::BoardDump(board),
+            response => response,
+        }
+    }
+
+    /// The board of a dump, plausible or not
+    pub fn dump(&self) -> Option<ChessBoard> {
+        match self {
+            Response::BoardDump(board) | Response::ImplausibleDump { board, .. } => Some(*board),
+            _ => None,
+        }
+    }
+
     /// Attempt to parse a raw message into a decoded response
     pub fn try_from_raw(message_type: MessageType, data: &[u8]) -> Result<Self, ParseError> {
+        Response::parse(message_type, data, ParseMode::Strict)
+    }
+
+    /// Parse a raw message, holding its length to `mode`
+    pub fn parse(
+        message_type: MessageType,
+        data: &[u8],
+        mode: ParseMode,
+    ) -> Result<Self, ParseError> {
+        let data = &data[..message_type.check_length(data.len(), mode)?];
         match message_type {
-            MessageType::BoardDump => {
-                if data.len() == 64 {
-                    match ChessBoard::new(data.try_into().unwrap()) {
-                        Some(board) => Ok(Response::BoardDump(board)),
-                        None => Err(ParseError::InvalidPiece),
-                    }
-                } else {
-                    Err(ParseError::invalid_length(message_type, 64, data.len()))
+            MessageType::BoardDump => match <&[u8; 64]>::try_from(data) {
+                Ok(raw) => ChessBoard::new(raw)
+                    .map(Response::checked_dump)
+                    .ok_or(ParseError::InvalidPiece),
+                Err(_) => Err(ParseError::invalid_length(message_type, 64, data.len())),
+            },
+            MessageType::BoardDump93 => match data.first_chunk::<64>() {
+                Some(raw) => ChessBoard::new(raw)
+                    .map(Response::checked_dump)
+                    .ok_or(ParseError::InvalidPiece),
+                None => Err(ParseError::invalid_length(message_type, 64, data.len())),
+            },
+            MessageType::PartialDump => match data.split_first() {
+                Some((&first, squares))
+                    if !squares.is_empty() && first as usize + squares.len() <= 64 =>
+                {
+                    squares
+                        .iter()
+                        .map(|byte| RawPiece::try_from(*byte))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map(|pieces| Response::PartialDump { first, pieces })
+                        .map_err(|_| ParseError::InvalidPiece)
                 }
-            }
+                Some((&first, _)) if first >= 64 => Err(ParseError::InvalidMove),
+                _ => Err(ParseError::invalid_length(message_type, 2, data.len())),
+            },
             MessageType::BWTime => {
-                if data.len() == 7 {
-                    let white_time = Remaining::from_bcd(data[..3].try_into().unwrap());
-                    let black_time = Remaining::from_bcd(data[3..6].try_into().unwrap());
-                    let status = ClockStatus::from_byte(data[6]);
+                if ClockAck::is_ack(data) {
+                    ClockAck::parse(data)
+                        .map(Response::ClockAck)
+                        .ok_or(ParseError::InvalidClockAck)
+                } else if let Some(&[w0, w1, w2, b0, b1, b2, status]) = data.first_chunk() {
                     Ok(Response::BWTime {
-                        white_time,
-                        black_time,
-                        status,
+                        white_time: Remaining::from_bcd([w0, w1, w2]),
+                        black_time: Remaining::from_bcd([b0, b1, b2]),
+                        status: ClockStatus::from_byte(status),
                     })
                 } else {
                     Err(ParseError::invalid_length(message_type, 7, data.len()))
@@ -311,7 +1305,7 @@ impl Response {
                 if data.len() == 2 {
                     let grid = data[0];
                     if grid < 64 {
-                        if let Some(piece) = RawPiece::try_from_byte(data[1]) {
+                        if let Ok(piece) = RawPiece::try_from(data[1]) {
                             Ok(Response::FieldUpdate(ChessMove::new(grid, piece)))
                         } else {
                             Err(ParseError::InvalidPiece)
@@ -326,12 +1320,19 @@ impl Response {
             MessageType::SerialNumber => Ok(Response::SerialNumber(
                 String::from_utf8_lossy(data).into_owned(),
             )),
-            MessageType::EEMoves => {
-                todo!("Implement EEMoves parsing")
-            }
-            MessageType::BusAddress => Ok(Response::BusAddress(
+            MessageType::EEMoves => Ok(Response::EEMoves(data.to_vec())),
+            MessageType::LongSerialNumber => Ok(Response::LongSerialNumber(
                 String::from_utf8_lossy(data).into_owned(),
             )),
+            MessageType::BusAddress => {
+                if data.len() == 2 {
+                    Ok(Response::BusAddress(
+                        ((data[0] as u16) << 7) | data[1] as u16,
+                    ))
+                } else {
+                    Err(ParseError::invalid_length(message_type, 2, data.len()))
+                }
+            }
             MessageType::Trademark => Ok(Response::Trademark(
                 String::from_utf8_lossy(data).into_owned(),
             )),
@@ -348,6 +1349,7 @@ impl Response {
 }
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ParseError {
     InvalidLength {
         message_type: MessageType,
@@ -356,6 +1358,7 @@ pub enum ParseError {
     },
     InvalidPiece,
     InvalidMove,
+    InvalidClockAck,
 }
 
 impl ParseError {
@@ -380,6 +1383,258 @@ mod tests {
     }
 
     #[test]
+    fn test_parse_bus_address() {
+        let response = Response::try_from_raw(MessageType::BusAddress, &[0x01, 0x02]).unwrap();
+        assert!(matches!(response, Response::BusAddress(130)));
+    }
+
+    #[test]
+    fn test_framer() {
+        let mut framer = Framer::new();
+        // A version split over three reads, after noise
+        assert!(framer.feed(&[0x00, 0x93]).is_empty());
+        assert_eq!(framer.needed(), 2);
+        assert!(framer.feed(&[0x00, 0x05, 0x01]).is_empty());
+        let responses = framer.feed(&[0x02, 0x8e, 0x00, 0x05, 0x24, 0x01]);
+        assert!(
+            matches!(&responses[..], [Response::Version(v), Response::FieldUpdate(_)] if v == "1.2")
+        );
+        // A high bit in the length starts over at that byte
+        framer.push(&[0x8e, 0x93, 0x00, 0x05, 0x01, 0x02, 0x8e, 0x00, 0x01]);
+        assert!(matches!(
+            framer.next_frame(),
+            Some(Ok(RawFrame {
+                message_type: 0x13,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            framer.next_frame(),
+            Some(Err(FrameError::InvalidLength(1)))
+        ));
+        assert!(framer.next_frame().is_none());
+        // A field update claiming 16 kB is turned down on its header, and the frame
+        // after it still comes through
+        framer.push(&[0x8e, 0x7f, 0x7f, 0x24, 0x01]);
+        assert!(matches!(
+            framer.next_frame(),
+            Some(Err(FrameError::TooLarge {
+                message_type: 0x0e,
+                length: MAX_FRAME_LENGTH
+            }))
+        ));
+        assert!(framer.next_frame().is_none());
+        assert!(matches!(
+            &framer.feed(&[0x93, 0x00, 0x05, 0x01, 0x02])[..],
+            [Response::Version(v)] if v == "1.2"
+        ));
+        // A type of newer firmware comes through as it was sent
+        assert!(matches!(
+            &framer.feed(&[0xb0, 0x00, 0x05, 0x2a, 0x01])[..],
+            [Response::Unknown { msg_type: 0x30, data }] if data == &[0x2a, 0x01]
+        ));
+    }
+
+    #[test]
+    fn test_framer_reuses_buffers() {
+        let mut framer = Framer::new();
+        let mut responses = Vec::new();
+        // A dump split over two reads, then updates that fit in what it left
+        let dump = RawFrame {
+            message_type: MessageType::BoardDump as u8,
+            data: vec![0; 64],
+        }
+        .to_bytes();
+        framer.feed_into(&dump[..40], &mut responses);
+        assert!(responses.is_empty());
+        framer.feed_into(&dump[40..], &mut responses);
+        assert!(matches!(&responses[..], [Response::BoardDump(_)]));
+        let (capacity, kept) = (framer.buffer.capacity(), responses.capacity());
+        for grid in 0..200u8 {
+            responses.clear();
+            framer.feed_into(&[0x8e, 0x00, 0x05, grid % 64, 0x01], &mut responses);
+            assert_eq!(responses.len(), 1);
+        }
+        assert_eq!(framer.buffer.capacity(), capacity);
+        assert_eq!(responses.capacity(), kept);
+        // Frames come out decoded straight from the buffer too
+        framer.push(&[0x93, 0x00, 0x05, 0x01, 0x02]);
+        assert!(matches!(
+            framer.next_response(),
+            Some(Ok(Response::Version(v))) if v == "1.2"
+        ));
+        assert!(framer.next_response().is_none());
+    }
+
+    #[test]
+    fn test_raw_handlers() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut framer = Framer::new();
+        for msg_type in [MessageType::LongSerialNumber as u8, 0x30] {
+            let seen = seen.clone();
+            framer.register_raw_handler(msg_type, move |data| {
+                seen.lock().unwrap().push((msg_type, data.to_vec()));
+            });
+        }
+        // A vendor serial number longer than the parser allows, then an update and a
+        // message of a type the parser does not know
+        let serial = RawFrame {
+            message_type: MessageType::LongSerialNumber as u8,
+            data: b"VENDOR-0000000042".to_vec(),
+        }
+        .to_bytes();
+        let mut bytes = serial;
+        bytes.extend([0x8e, 0x00, 0x05, 0x24, 0x01, 0xb0, 0x00, 0x04, 0x2a]);
+        let responses = framer.feed(&bytes);
+        assert!(matches!(&responses[..], [Response::FieldUpdate(_)]));
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [(0x22, b"VENDOR-0000000042".to_vec()), (0x30, vec![0x2a])]
+        );
+    }
+
+    #[test]
+    fn test_data_lengths() {
+        // A field update with a padding byte after its two bytes of data
+        let padded = [0x8e, 0x00, 0x06, 0x24, 0x01, 0x00];
+        let mut framer = Framer::new();
+        framer.push(&padded);
+        assert!(matches!(
+            framer.next_frame(),
+            Some(Err(FrameError::Parse(ParseError::InvalidLength {
+                message_type: MessageType::FieldUpdate,
+                expected: 2,
+                actual: 3
+            })))
+        ));
+        assert!(framer.next_frame().is_none());
+        framer.set_parse_mode(ParseMode::Lenient);
+        assert!(matches!(
+            &framer.feed(&padded)[..],
+            [Response::FieldUpdate(update)] if update.grid == 0x24
+        ));
+        // More padding than the type may carry is noise either way
+        framer.push(&[0x8e, 0x00, 0x0c, 0x24, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(matches!(
+            framer.next_frame(),
+            Some(Err(FrameError::TooLarge {
+                message_type: 0x0e,
+                length: 12
+            }))
+        ));
+
+        let version = [1, 2, 0, 0];
+        assert!(Response::parse(MessageType::Version, &version, ParseMode::Strict).is_err());
+        assert!(matches!(
+            Response::parse(MessageType::Version, &version, ParseMode::Lenient),
+            Ok(Response::Version(v)) if v == "1.2"
+        ));
+        // Both clock lengths are expected, padding after seven bytes is dropped
+        let time = [0x00, 0x05, 0x00, 0x00, 0x04, 0x59, 0x01, 0, 0, 0];
+        for data in [&time[..7], &time[..], &time[..8]] {
+            let mode = match data.len() {
+                8 => ParseMode::Lenient,
+                _ => ParseMode::Strict,
+            };
+            assert!(matches!(
+                Response::parse(MessageType::BWTime, data, mode),
+                Ok(Response::BWTime { .. })
+            ));
+        }
+        assert!(Response::parse(MessageType::BWTime, &time[..8], ParseMode::Strict).is_err());
+        assert_eq!(MessageType::BoardDump.data_length().expected, &[64]);
+    }
+
+    #[test]
+    fn test_dump_variants() {
+        let mut squares = vec![0u8; 64];
+        squares[4] = 0x06;
+        squares[60] = 0x0c;
+        let mut dump93 = squares.clone();
+        dump93.extend([0, 0]);
+        let Response::BoardDump(board) =
+            Response::try_from_raw(MessageType::BoardDump93, &dump93).unwrap()
+        else {
+            panic!("no board dump");
+        };
+        assert_eq!(board.board[60], RawPiece::BlackQueen);
+
+        // The same board in two parts, the second first
+        let mut framer = Framer::new();
+        for (first, part) in [(32u8, &squares[32..]), (0, &squares[..32])] {
+            let data = [&[first], part].concat();
+            let frame = RawFrame {
+                message_type: MessageType::PartialDump as u8,
+                data,
+            };
+            let responses = framer.feed(&frame.to_bytes());
+            if first == 32 {
+                assert!(responses.is_empty());
+            } else {
+                assert!(matches!(&responses[..], [Response::BoardDump(b)] if *b == board));
+            }
+        }
+        assert!(Response::try_from_raw(MessageType::PartialDump, &[60, 0, 0, 0, 0, 0]).is_err());
+        let part = Response::PartialDump {
+            first: 8,
+            pieces: vec![RawPiece::Empty; 4],
+        };
+        assert_eq!(part.to_string(), "squares 8 to 11 of the board");
+        let empty = Response::PartialDump {
+            first: 0,
+            pieces: Vec::new(),
+        };
+        assert_eq!(empty.to_string(), "no squares of the board from 0");
+    }
+
+    #[test]
+    fn test_implausible_dump() {
+        let mut start = [0u8; 64];
+        start[..8].copy_from_slice(&[0x08, 0x09, 0x0a, 0x0c, 0x0b, 0x0a, 0x09, 0x08]);
+        start[8..16].fill(0x07);
+        start[48..56].fill(0x01);
+        start[56..].copy_from_slice(&[0x02, 0x03, 0x04, 0x06, 0x05, 0x04, 0x03, 0x02]);
+        assert_eq!(ChessBoard::new(&start).unwrap().check(), Ok(()));
+
+        // A second white king where a pawn should be
+        let mut squares = start;
+        squares[52] = 0x05;
+        let result = Response::try_from_raw(MessageType::BoardDump, &squares);
+        assert!(matches!(
+            result,
+            Ok(Response::ImplausibleDump {
+                reason: Implausible::Kings(PieceColor::White),
+                ..
+            })
+        ));
+
+        // Nine black pawns, one of them in place of a knight
+        let mut squares = start;
+        squares[1] = 0x00;
+        squares[35] = 0x07;
+        let board = ChessBoard::new(&squares).unwrap();
+        assert_eq!(board.check(), Err(Implausible::Pawns(PieceColor::Black)));
+
+        // Passed on with the reason, or as a plain dump where it is allowed
+        let frame = RawFrame {
+            message_type: MessageType::BoardDump as u8,
+            data: squares.to_vec(),
+        };
+        let responses = Framer::new().feed(&frame.to_bytes());
+        assert!(matches!(
+            &responses[..],
+            [Response::ImplausibleDump { board: b, reason: Implausible::Pawns(PieceColor::Black) }]
+                if *b == board
+        ));
+        let mut framer = Framer::new();
+        framer.allow_implausible_dumps(true);
+        assert!(
+            matches!(&framer.feed(&frame.to_bytes())[..], [Response::BoardDump(b)] if *b == board)
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn test_command_roundtrip() {
         let cmd = Command::RequestBoard;
         let byte = cmd.as_byte();
@@ -388,11 +1643,62 @@ mod tests {
     }
 
     #[test]
+    fn test_command_try_from() {
+        let cmd = Command::RequestBoard;
+        let byte = cmd.as_byte();
+        let cmd2 = Command::try_from(byte[0]).unwrap();
+        assert_eq!(cmd, cmd2);
+    }
+
+    #[test]
+    fn test_display_and_parse() {
+        assert_eq!(
+            "requestversion".parse::<Command>(),
+            Ok(Command::RequestVersion)
+        );
+        assert!("RequestNothing".parse::<Command>().is_err());
+        let update = Response::try_from_raw(MessageType::FieldUpdate, &[45, 0x03]).unwrap();
+        assert_eq!(update.to_string(), "place WhiteKnight on f3");
+        assert_eq!(
+            ChessMove::new(52, RawPiece::Empty).to_string(),
+            "lift from e2"
+        );
+        let clock =
+            Response::try_from_raw(MessageType::BWTime, &[0x01, 0x30, 0x05, 0, 0x59, 0, 0x08])
+                .unwrap();
+        assert_eq!(
+            clock.to_string(),
+            "white 1:30:05 black 0:59:00, black to move"
+        );
+        let Response::BWTime { white_time, .. } = clock else {
+            panic!("no clock");
+        };
+        assert_eq!(
+            (
+                white_time.hours(),
+                white_time.minutes(),
+                white_time.seconds()
+            ),
+            (1, 30, 5)
+        );
+        assert_eq!(MessageType::Version.to_string(), "Version (0x13)");
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn test_invalid_command() {
         assert_eq!(Command::try_from_byte(0x00), None);
     }
 
     #[test]
+    fn test_invalid_byte() {
+        assert_eq!(Command::try_from(0x00), Err(InvalidByte(0x00)));
+        assert_eq!(MessageType::try_from(0x13), Ok(MessageType::Version));
+        assert!(MessageType::try_from(0x14).is_err());
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn test_piece_conversion() {
         // Test valid pieces
         assert_eq!(RawPiece::try_from_byte(0x00), Some(RawPiece::Empty));
@@ -404,9 +1710,49 @@ mod tests {
     }
 
     #[test]
+    fn test_piece_try_from() {
+        // Test valid pieces
+        assert_eq!(RawPiece::try_from(0x00), Ok(RawPiece::Empty));
+        assert_eq!(RawPiece::try_from(0x01), Ok(RawPiece::WhitePawn));
+        assert_eq!(RawPiece::try_from(0x0c), Ok(RawPiece::BlackQueen));
+
+        // Test invalid piece
+        assert_eq!(RawPiece::try_from(0x0du8), Err(InvalidByte(0x0d)));
+
+        // Generic code gets the same through iterator adapters
+        let pieces: Result<Vec<RawPiece>, _> = "Kq ".chars().map(RawPiece::try_from).collect();
+        assert_eq!(
+            pieces,
+            Ok(vec![
+                RawPiece::WhiteKing,
+                RawPiece::BlackQueen,
+                RawPiece::Empty
+            ])
+        );
+        assert_eq!(RawPiece::try_from('x'), Err(InvalidChar('x')));
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn test_piece_to_char() {
         assert_eq!(RawPiece::Empty.to_char(), ' ');
         assert_eq!(RawPiece::WhiteKing.to_char(), 'K');
         assert_eq!(RawPiece::BlackPawn.to_char(), 'p');
     }
+
+    #[test]
+    fn test_char_from_piece() {
+        assert_eq!(char::from(RawPiece::Empty), ' ');
+        assert_eq!(char::from(RawPiece::WhiteKing), 'K');
+        assert_eq!(char::from(RawPiece::BlackPawn), 'p');
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_conversions() {
+        assert_eq!(RawPiece::try_from_char('q'), Some(RawPiece::BlackQueen));
+        assert_eq!(RawPiece::try_from_char('x'), None);
+        assert_eq!(MessageType::try_from_byte(0x13), Some(MessageType::Version));
+        assert_eq!(MessageType::try_from_byte(0x14), None);
+    }
 }
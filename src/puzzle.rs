@@ -0,0 +1,188 @@
+use crate::lichess::Puzzle;
+use crate::pgn;
+use crate::protocol::*;
+use crate::rules::{Ply, Position};
+use crate::variant::{Standard, Variant};
+
+/// A position with a line of moves to reproduce on the physical board
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exercise {
+    pub name: String,
+    pub start: Position,
+    pub solution: Vec<Ply>,
+    /// Side the user has to find the moves for, the other side's moves are shown as
+    /// instructions. None when every move has to be found, as in study chapters, and
+    /// PieceColor::None when every move is shown, as when replaying a game.
+    pub player: Option<PieceColor>,
+    /// Comments on the solution, each with the number of moves played before it
+    pub comments: Vec<(usize, String)>,
+}
+
+/// Result of checking a move against the solution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The expected move, more moves follow
+    Correct,
+    /// The last move of the solution
+    Solved,
+    /// Not the move of the solution, which is given
+    Wrong(Ply),
+}
+
+impl Exercise {
+    /// Build the exercise of a Lichess puzzle by replaying its source game
+    pub fn from_puzzle(puzzle: &Puzzle) -> Result<Exercise, String> {
+        let mut position = Position::starting();
+        for san in puzzle.game_moves.iter().take(puzzle.initial_ply + 1) {
+            let ply = pgn::parse_san(&Standard, &position, san)
+                .ok_or_else(|| format!("illegal move {} in puzzle {}", san, puzzle.id))?;
+            position = position.play(&ply);
+        }
+        let start = position.clone();
+        let mut solution = Vec::new();
+        for uci in &puzzle.solution {
+            let ply = Standard
+                .legal_moves(&position)
+                .into_iter()
+                .find(|ply| ply.uci() == *uci)
+                .ok_or_else(|| format!("illegal solution move {} in puzzle {}", uci, puzzle.id))?;
+            position = position.play(&ply);
+            solution.push(ply);
+        }
+        Ok(Exercise {
+            name: format!("puzzle {}", puzzle.id),
+            player: Some(start.to_move),
+            start,
+            solution,
+            comments: Vec::new(),
+        })
+    }
+
+    /// One exercise per chapter of a study, following the main line of each
+    pub fn from_study(pgn_text: &str) -> Result<Vec<Exercise>, String> {
+        pgn::parse(pgn_text)
+            .into_iter()
+            .enumerate()
+            .map(|(i, game)| {
+                let name = game
+                    .tag("Event")
+                    .map_or_else(|| format!("chapter {}", i + 1), str::to_string);
+                let start = match game.tag("FEN") {
+                    Some(fen) => Position::from_fen(fen)
+                        .ok_or_else(|| format!("invalid FEN in {}: {}", name, fen))?,
+                    None => Position::starting(),
+                };
+                let mut position = start.clone();
+                let mut solution = Vec::new();
+                for san in &game.moves {
+                    let ply = pgn::parse_san(&Standard, &position, san)
+                        .ok_or_else(|| format!("illegal move {} in {}", san, name))?;
+                    position = position.play(&ply);
+                    solution.push(ply);
+                }
+                Ok(Exercise {
+                    name,
+                    start,
+                    solution,
+                    player: None,
+                    comments: game.comments,
+                })
+            })
+            .collect()
+    }
+
+    /// The games of a PGN file to replay on the board, every move shown
+    pub fn from_games(pgn_text: &str) -> Result<Vec<Exercise>, String> {
+        let mut games = Exercise::from_study(pgn_text)?;
+        for game in &mut games {
+            game.player = Some(PieceColor::None);
+        }
+        Ok(games)
+    }
+
+    /// Whether the user has to find move number `step` rather than being told it
+    pub fn is_player_move(&self, step: usize) -> bool {
+        match self.player {
+            None => true,
+            Some(PieceColor::None) => false,
+            Some(player) => (self.start.to_move == player) == step.is_multiple_of(2),
+        }
+    }
+
+    /// Compare the move played at `step` with the solution
+    pub fn check(&self, step: usize, ply: &Ply) -> Verdict {
+        match self.solution.get(step) {
+            Some(expected) if expected == ply => {
+                if step + 1 == self.solution.len() {
+                    Verdict::Solved
+                } else {
+                    Verdict::Correct
+                }
+            }
+            Some(expected) => Verdict::Wrong(*expected),
+            None => Verdict::Solved,
+        }
+    }
+
+    /// Comments made once `step` moves were played
+    pub fn comments_at(&self, step: usize) -> impl Iterator<Item = &str> {
+        self.comments
+            .iter()
+            .filter(move |(moves, _)| *moves == step)
+            .map(|(_, comment)| comment.as_str())
+    }
+
+    /// The position before move number `step`
+    pub fn position_at(&self, step: usize) -> Position {
+        self.solution
+            .iter()
+            .take(step)
+            .fold(self.start.clone(), |position, ply| position.play(ply))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_puzzle_exercise() {
+        let puzzle = Puzzle {
+            id: "test".to_string(),
+            rating: None,
+            themes: Vec::new(),
+            game_moves: "e4 e5 Qh5 Nc6 Bc4 Nf6 Qxf7#"
+                .split(' ')
+                .map(str::to_string)
+                .collect(),
+            initial_ply: 5,
+            solution: vec!["h5f7".to_string()],
+        };
+        let exercise = Exercise::from_puzzle(&puzzle).unwrap();
+        assert_eq!(exercise.player, Some(PieceColor::White));
+        assert!(exercise.is_player_move(0));
+        assert!(!exercise.is_player_move(1));
+        let wrong = exercise
+            .start
+            .legal_moves()
+            .into_iter()
+            .find(|ply| ply.uci() == "h5h7")
+            .unwrap();
+        assert_eq!(
+            exercise.check(0, &wrong),
+            Verdict::Wrong(exercise.solution[0])
+        );
+        assert_eq!(
+            exercise.check(0, &exercise.solution[0].clone()),
+            Verdict::Solved
+        );
+
+        let games = Exercise::from_games("1. e4 { best by test } e5 2. Nf3 *").unwrap();
+        assert!(!games[0].is_player_move(0));
+        assert!(!games[0].is_player_move(1));
+        assert_eq!(
+            games[0].comments_at(1).collect::<Vec<_>>(),
+            ["best by test"]
+        );
+    }
+}
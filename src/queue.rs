@@ -0,0 +1,252 @@
+//! Bounded queues between a producer that must never wait, such as the thread reading
+//! the board, and a consumer that may fall behind, such as a relay on a slow network.
+//! What happens to a full queue is up to its `Overflow` policy, so memory stays bounded
+//! and the producer carries on whatever the consumer does.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// What a full queue does with one more item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Make room by dropping the oldest item
+    #[default]
+    DropOldest,
+    /// Drop the items the new one makes pointless, see `Coalesce`, and the oldest if
+    /// that frees nothing
+    Coalesce,
+    /// Give up on the consumer, which is told once it has read what was queued
+    Error,
+}
+
+/// Items of which a later one can stand in for an earlier one, like a board dump for
+/// the field updates before it
+pub trait Coalesce {
+    /// Whether delivering `self` makes delivering `older` pointless
+    fn supersedes(&self, older: &Self) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueError {
+    /// The other end is gone
+    Closed,
+    /// The queue filled up under `Overflow::Error`
+    Overflowed,
+}
+
+impl fmt::Display for QueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            QueueError::Closed => "queue closed",
+            QueueError::Overflowed => "consumer fell too far behind",
+        })
+    }
+}
+
+impl std::error::Error for QueueError {}
+
+struct State<T> {
+    items: VecDeque<T>,
+    /// Items dropped or coalesced away so far
+    dropped: u64,
+    overflowed: bool,
+    publisher_gone: bool,
+    subscription_gone: bool,
+}
+
+struct Queue<T> {
+    state: Mutex<State<T>>,
+    ready: Condvar,
+    capacity: usize,
+    overflow: Overflow,
+}
+
+/// A queue holding at most `capacity` items, at least one
+pub fn bounded<T>(capacity: usize, overflow: Overflow) -> (Publisher<T>, Subscription<T>) {
+    let queue = Arc::new(Queue {
+        state: Mutex::new(State {
+            items: VecDeque::new(),
+            dropped: 0,
+            overflowed: false,
+            publisher_gone: false,
+            subscription_gone: false,
+        }),
+        ready: Condvar::new(),
+        capacity: capacity.max(1),
+        overflow,
+    });
+    (
+        Publisher {
+            queue: queue.clone(),
+        },
+        Subscription { queue },
+    )
+}
+
+/// The producing end
+pub struct Publisher<T> {
+    queue: Arc<Queue<T>>,
+}
+
+impl<T: Coalesce> Publisher<T> {
+    /// Queue `item`, never waiting. An error means there is no point in pushing more.
+    pub fn push(&self, item: T) -> Result<(), QueueError> {
+        let queue = &self.queue;
+        let mut state = queue.state.lock().unwrap();
+        if state.subscription_gone {
+            return Err(QueueError::Closed);
+        }
+        if state.overflowed {
+            return Err(QueueError::Overflowed);
+        }
+        if state.items.len() >= queue.capacity {
+            match queue.overflow {
+                Overflow::DropOldest => {}
+                Overflow::Coalesce => {
+                    let before = state.items.len();
+                    state.items.retain(|older| !item.supersedes(older));
+                    state.dropped += (before - state.items.len()) as u64;
+                }
+                Overflow::Error => {
+                    state.overflowed = true;
+                    queue.ready.notify_all();
+                    return Err(QueueError::Overflowed);
+                }
+            }
+            if state.items.len() >= queue.capacity {
+                state.items.pop_front();
+                state.dropped += 1;
+            }
+        }
+        state.items.push_back(item);
+        queue.ready.notify_all();
+        Ok(())
+    }
+}
+
+impl<T> Drop for Publisher<T> {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().publisher_gone = true;
+        self.queue.ready.notify_all();
+    }
+}
+
+/// The consuming end, iterating over it ends once the publisher is gone or the queue
+/// overflowed
+pub struct Subscription<T> {
+    queue: Arc<Queue<T>>,
+}
+
+impl<T> Subscription<T> {
+    /// The next item, waiting for one to arrive
+    pub fn recv(&self) -> Result<T, QueueError> {
+        self.recv_until(None)
+            .and_then(|item| item.ok_or(QueueError::Closed))
+    }
+
+    /// The next item, None if none arrives within `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<T>, QueueError> {
+        self.recv_until(Some(Instant::now() + timeout))
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<Option<T>, QueueError> {
+        let mut state = self.queue.state.lock().unwrap();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                return Ok(Some(item));
+            }
+            if state.overflowed {
+                return Err(QueueError::Overflowed);
+            }
+            if state.publisher_gone {
+                return Err(QueueError::Closed);
+            }
+            state = match deadline {
+                None => self.queue.ready.wait(state).unwrap(),
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Ok(None);
+                    }
+                    self.queue.ready.wait_timeout(state, left).unwrap().0
+                }
+            };
+        }
+    }
+
+    /// Items dropped or coalesced away because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.queue.state.lock().unwrap().dropped
+    }
+}
+
+impl<T> Iterator for Subscription<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.recv().ok()
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().subscription_gone = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Readings of a sensor, a later reading of the same sensor replaces an earlier one
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Reading(char, u32);
+
+    impl Coalesce for Reading {
+        fn supersedes(&self, older: &Self) -> bool {
+            self.0 == older.0
+        }
+    }
+
+    fn fill(overflow: Overflow) -> (Subscription<Reading>, Result<(), QueueError>) {
+        let (publisher, subscription) = bounded(3, overflow);
+        let mut result = Ok(());
+        for reading in [('a', 1), ('b', 1), ('a', 2), ('c', 1)] {
+            result = publisher.push(Reading(reading.0, reading.1));
+        }
+        (subscription, result)
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let (subscription, result) = fill(Overflow::DropOldest);
+        assert_eq!(result, Ok(()));
+        assert_eq!(subscription.dropped(), 1);
+        let readings: Vec<_> = subscription.collect();
+        assert_eq!(
+            readings,
+            [Reading('b', 1), Reading('a', 2), Reading('c', 1)]
+        );
+
+        let (publisher, subscription) = bounded(2, Overflow::Coalesce);
+        for reading in [('a', 1), ('b', 1), ('b', 2), ('a', 2)] {
+            publisher.push(Reading(reading.0, reading.1)).unwrap();
+        }
+        drop(publisher);
+        assert_eq!(subscription.dropped(), 2);
+        let readings: Vec<_> = subscription.collect();
+        assert_eq!(readings, [Reading('b', 2), Reading('a', 2)]);
+
+        let (subscription, result) = fill(Overflow::Error);
+        assert_eq!(result, Err(QueueError::Overflowed));
+        assert_eq!(subscription.recv(), Ok(Reading('a', 1)));
+        assert_eq!(
+            subscription.recv_timeout(Duration::from_millis(1)),
+            Ok(Some(Reading('b', 1)))
+        );
+        assert_eq!(subscription.recv(), Ok(Reading('a', 2)));
+        assert_eq!(subscription.recv(), Err(QueueError::Overflowed));
+    }
+}
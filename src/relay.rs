@@ -0,0 +1,444 @@
+use crate::game::GameBoard;
+use crate::lichess;
+use crate::pgn::{self, Annotation};
+use crate::protocol::*;
+use crate::queue::{self, Coalesce, Overflow, Publisher, QueueError};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use tracing::warn;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Messages queued for a relay before the oldest are let go
+pub const QUEUE_CAPACITY: usize = 256;
+
+/// A sink the game is mirrored to, such as a broadcast service, a database or a chat bot.
+/// Every relay runs on its own thread, so a slow one holds up neither the board nor the
+/// other relays.
+pub trait Relay: Send {
+    /// Name used in log messages
+    fn name(&self) -> &str;
+
+    /// A fresh game started on the board
+    fn on_new_game(&mut self, _game: &GameBoard) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// A move was played, it is the last one of `game.moves()`
+    fn on_move(&mut self, game: &GameBoard) -> Result<(), Error>;
+
+    /// The clock reported new times
+    fn on_clock(&mut self, _white: Remaining, _black: Remaining) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The game ended, `result` is the PGN result
+    fn on_result(&mut self, _game: &GameBoard, _result: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Advice for a player in training, such as where a lifted piece can go
+    fn on_hint(&mut self, _hint: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The position was evaluated, `centipawns` is the advantage of white
+    fn on_evaluation(&mut self, _centipawns: i32) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+enum Message {
+    NewGame(GameBoard),
+    Move(GameBoard),
+    Clock(Remaining, Remaining),
+    Result(GameBoard, &'static str),
+    Hint(String),
+    Evaluation(i32),
+}
+
+impl Coalesce for Message {
+    /// Each move, clock and evaluation tells all there is to know, the ones before it
+    /// can be skipped by a relay that fell behind
+    fn supersedes(&self, older: &Self) -> bool {
+        matches!(
+            (self, older),
+            (Message::Move(_), Message::Move(_))
+                | (Message::Clock(..), Message::Clock(..))
+                | (Message::Evaluation(_), Message::Evaluation(_))
+        )
+    }
+}
+
+/// The configured relays, each fed through a bounded queue by a worker thread
+#[derive(Default)]
+pub struct Relays {
+    workers: Vec<(Publisher<Message>, JoinHandle<()>)>,
+}
+
+impl Relays {
+    pub fn new() -> Self {
+        Relays::default()
+    }
+
+    /// Start a worker thread for `relay`. Once it falls `QUEUE_CAPACITY` messages
+    /// behind, it skips to the latest moves and clock times.
+    pub fn add(&mut self, relay: Box<dyn Relay>) -> io::Result<()> {
+        self.add_with_queue(relay, QUEUE_CAPACITY, Overflow::Coalesce)
+    }
+
+    /// Like `add`, with `capacity` messages queued before `overflow` kicks in
+    pub fn add_with_queue(
+        &mut self,
+        mut relay: Box<dyn Relay>,
+        capacity: usize,
+        overflow: Overflow,
+    ) -> io::Result<()> {
+        let (tx, rx) = queue::bounded(capacity, overflow);
+        let handle = thread::Builder::new()
+            .name(format!("relay {}", relay.name()))
+            .spawn(move || loop {
+                let message = match rx.recv() {
+                    Ok(message) => message,
+                    Err(QueueError::Overflowed) => {
+                        warn!(relay = relay.name(), "relay fell behind, giving up on it");
+                        return;
+                    }
+                    Err(QueueError::Closed) => return,
+                };
+                let result = match &message {
+                    Message::NewGame(game) => relay.on_new_game(game),
+                    Message::Move(game) => relay.on_move(game),
+                    Message::Clock(white, black) => relay.on_clock(*white, *black),
+                    Message::Result(game, result) => relay.on_result(game, result),
+                    Message::Hint(hint) => relay.on_hint(hint),
+                    Message::Evaluation(centipawns) => relay.on_evaluation(*centipawns),
+                };
+                if let Err(e) = result {
+                    warn!(relay = relay.name(), error = %e, "relay failed");
+                }
+            })?;
+        self.workers.push((tx, handle));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    fn send(&self, message: impl Fn() -> Message) {
+        for (tx, _) in &self.workers {
+            let _ = tx.push(message());
+        }
+    }
+
+    pub fn new_game(&self, game: &GameBoard) {
+        self.send(|| Message::NewGame(game.clone()));
+    }
+
+    pub fn moved(&self, game: &GameBoard) {
+        self.send(|| Message::Move(game.clone()));
+    }
+
+    pub fn clock(&self, white: Remaining, black: Remaining) {
+        self.send(|| Message::Clock(white, black));
+    }
+
+    pub fn result(&self, game: &GameBoard) {
+        let result = pgn::result(game);
+        self.send(|| Message::Result(game.clone(), result));
+    }
+
+    pub fn hint(&self, hint: &str) {
+        self.send(|| Message::Hint(hint.to_string()));
+    }
+
+    pub fn evaluation(&self, centipawns: i32) {
+        self.send(|| Message::Evaluation(centipawns));
+    }
+}
+
+impl Drop for Relays {
+    /// Let every relay finish the messages already queued
+    fn drop(&mut self) {
+        for (tx, handle) in self.workers.drain(..) {
+            drop(tx);
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Broadcast services the live game can be relayed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Push the PGN to a Lichess broadcast round
+    Lichess,
+    /// Chess.com only offers a read-only published-data API, so its broadcast tooling
+    /// polls a PGN URL. The PGN is kept up to date in a file to be served from there.
+    ChessCom,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lichess" => Ok(Backend::Lichess),
+            "chesscom" | "chess.com" => Ok(Backend::ChessCom),
+            _ => Err(format!("unknown relay backend {:?}", s)),
+        }
+    }
+}
+
+/// Where the relayed PGN goes
+pub enum Destination {
+    Lichess {
+        client: lichess::Client,
+        round: String,
+    },
+    File(PathBuf),
+}
+
+/// Mirrors the moves and clock times of the game to a broadcast service
+pub struct Broadcast {
+    destination: Destination,
+    /// Clock of the side that moved, recorded after every move
+    clocks: Vec<Option<Remaining>>,
+    last_clock: Option<(Remaining, Remaining)>,
+}
+
+impl Broadcast {
+    /// Relay through `backend`, which needs a broadcast round id for Lichess and an
+    /// output file for chess.com
+    pub fn for_backend(
+        backend: Backend,
+        token: Option<String>,
+        round: Option<String>,
+        file: Option<PathBuf>,
+    ) -> Result<Self, String> {
+        let destination = match backend {
+            Backend::Lichess => Destination::Lichess {
+                client: lichess::Client::new(token),
+                round: round.ok_or("relaying to Lichess needs a broadcast round id")?,
+            },
+            Backend::ChessCom => {
+                Destination::File(file.ok_or("relaying to chess.com needs a PGN output file")?)
+            }
+        };
+        Ok(Broadcast::new(destination))
+    }
+
+    pub fn new(destination: Destination) -> Self {
+        Broadcast {
+            destination,
+            clocks: Vec::new(),
+            last_clock: None,
+        }
+    }
+
+    fn publish(&self, pgn: &str) -> Result<(), Error> {
+        match &self.destination {
+            Destination::Lichess { client, round } => client.push_broadcast(round, pgn)?,
+            Destination::File(path) => write_atomically(path, pgn)?,
+        }
+        Ok(())
+    }
+}
+
+impl Relay for Broadcast {
+    fn name(&self) -> &str {
+        match self.destination {
+            Destination::Lichess { .. } => "lichess",
+            Destination::File(_) => "pgn file",
+        }
+    }
+
+    fn on_new_game(&mut self, _game: &GameBoard) -> Result<(), Error> {
+        self.clocks.clear();
+        Ok(())
+    }
+
+    /// Remember the latest clock times to attach them to the next move
+    fn on_clock(&mut self, white: Remaining, black: Remaining) -> Result<(), Error> {
+        self.last_clock = Some((white, black));
+        Ok(())
+    }
+
+    fn on_move(&mut self, game: &GameBoard) -> Result<(), Error> {
+        let moves = game.moves().len();
+        // The side that just moved is the one not on move now
+        let mover = game.to_move().opposite();
+        let clock = self.last_clock.map(|(white, black)| match mover {
+            PieceColor::Black => black,
+            _ => white,
+        });
+        self.clocks.resize(moves.saturating_sub(1), None);
+        self.clocks.push(clock);
+        self.publish(&live_pgn(game, &self.clocks))
+    }
+}
+
+/// The game as PGN with `[%clk]` comments, the form broadcast services read clock times from
+pub fn live_pgn(game: &GameBoard, clocks: &[Option<Remaining>]) -> String {
+    let annotations: Vec<Annotation> = clocks
+        .iter()
+        .map(|clock| Annotation {
+            nag: None,
+            comment: clock.map(|c| format!("[%clk {}]", c)),
+        })
+        .collect();
+    pgn::to_annotated_pgn(game, &annotations)
+}
+
+/// Files in `PositionFiles::dir`
+pub const FEN_FILE: &str = "fen.txt";
+pub const LAST_MOVE_FILE: &str = "last_move.txt";
+pub const WHITE_CLOCK_FILE: &str = "white_clock.txt";
+pub const BLACK_CLOCK_FILE: &str = "black_clock.txt";
+
+/// Keeps a text file per value in a directory for overlay tools that watch files rather
+/// than take messages: the FEN, the last move in SAN and each clock. A file is only
+/// written when its value changed, and replaced in one step.
+pub struct PositionFiles {
+    dir: PathBuf,
+    written: HashMap<&'static str, String>,
+}
+
+impl PositionFiles {
+    /// Write to `dir`, which is created if missing
+    pub fn new(dir: PathBuf) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(PositionFiles {
+            dir,
+            written: HashMap::new(),
+        })
+    }
+
+    fn write(&mut self, name: &'static str, value: String) -> io::Result<()> {
+        if self.written.get(name) == Some(&value) {
+            return Ok(());
+        }
+        write_atomically(&self.dir.join(name), &format!("{}\n", value))?;
+        self.written.insert(name, value);
+        Ok(())
+    }
+
+    fn show_game(&mut self, game: &GameBoard) -> Result<(), Error> {
+        self.write(FEN_FILE, game.fen())?;
+        self.write(LAST_MOVE_FILE, pgn::last_san(game).unwrap_or_default())?;
+        Ok(())
+    }
+}
+
+impl Relay for PositionFiles {
+    fn name(&self) -> &str {
+        "position files"
+    }
+
+    fn on_new_game(&mut self, game: &GameBoard) -> Result<(), Error> {
+        self.show_game(game)
+    }
+
+    fn on_move(&mut self, game: &GameBoard) -> Result<(), Error> {
+        self.show_game(game)
+    }
+
+    fn on_clock(&mut self, white: Remaining, black: Remaining) -> Result<(), Error> {
+        self.write(WHITE_CLOCK_FILE, white.to_string())?;
+        self.write(BLACK_CLOCK_FILE, black.to_string())?;
+        Ok(())
+    }
+
+    fn on_result(&mut self, game: &GameBoard, _result: &str) -> Result<(), Error> {
+        self.show_game(game)
+    }
+}
+
+/// Replace the file in one step so a poller never reads half of it
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Relay for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn on_new_game(&mut self, _game: &GameBoard) -> Result<(), Error> {
+            self.0.lock().unwrap().push("new game".to_string());
+            Ok(())
+        }
+
+        fn on_move(&mut self, game: &GameBoard) -> Result<(), Error> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("move {}", game.moves().len()));
+            Err("unreachable sink".into())
+        }
+
+        fn on_result(&mut self, _game: &GameBoard, result: &str) -> Result<(), Error> {
+            self.0.lock().unwrap().push(result.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_relays_deliver_in_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let game = GameBoard::new(ChessBoard {
+            board: [RawPiece::Empty; 64],
+        });
+        let mut relays = Relays::new();
+        relays.add(Box::new(Recorder(seen.clone()))).unwrap();
+        relays.new_game(&game);
+        relays.moved(&game);
+        relays.result(&game);
+        drop(relays);
+        assert_eq!(*seen.lock().unwrap(), ["new game", "move 0", "1/2-1/2"]);
+    }
+
+    #[test]
+    fn test_position_files() {
+        use crate::game::StartPosition;
+        use crate::rules::Position;
+
+        let dir = std::env::temp_dir().join(format!("jackolope-files-{}", std::process::id()));
+        let mut files = PositionFiles::new(dir.clone()).unwrap();
+        let mut game = GameBoard::new(StartPosition::Mirror.layout(&Position::starting()));
+        let read = |name| std::fs::read_to_string(dir.join(name)).unwrap();
+        files.on_new_game(&game).unwrap();
+        assert_eq!(read(LAST_MOVE_FILE), "\n");
+        game.replace_moves(&["g1f3".to_string()]).unwrap();
+        files.on_move(&game).unwrap();
+        assert_eq!(read(LAST_MOVE_FILE), "Nf3\n");
+        assert_eq!(
+            read(FEN_FILE),
+            "rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R b KQkq - 1 1\n"
+        );
+        files
+            .on_clock(Remaining::from_seconds(300), Remaining::from_seconds(59))
+            .unwrap();
+        assert_eq!(read(WHITE_CLOCK_FILE), "0:05:00\n");
+        assert_eq!(read(BLACK_CLOCK_FILE), "0:00:59\n");
+        // Nothing is left half written
+        assert!(std::fs::read_dir(&dir).unwrap().all(|entry| entry
+            .unwrap()
+            .path()
+            .extension()
+            .unwrap()
+            == "txt"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
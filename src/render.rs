@@ -0,0 +1,276 @@
+use crate::game::{Correction, StartPosition};
+use crate::protocol::*;
+use std::fmt;
+use std::str::FromStr;
+
+const BOLD_WHITE: &str = "\x1b[1;97m";
+const BOLD_RED: &str = "\x1b[1;91m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// How pieces are drawn on the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PieceStyle {
+    /// Chess glyphs, `·` for empty squares
+    #[default]
+    Unicode,
+    /// FEN letters, `.` for empty squares, for terminals without the glyphs
+    Letters,
+    /// FEN letters in colour, white pieces bright and black pieces red, for terminals
+    /// whose font makes the glyphs of both sides look alike
+    Coloured,
+}
+
+/// The side drawn at the bottom of the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Orientation {
+    #[default]
+    White,
+    Black,
+}
+
+/// How the console shows the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub pieces: PieceStyle,
+    pub bottom: Orientation,
+}
+
+impl Style {
+    /// The board as 8 rows from the side at the bottom's point of view, whichever way
+    /// round it stands as found at `start`
+    pub fn board(&self, board: &ChessBoard, start: StartPosition) -> String {
+        let mut out = String::with_capacity(8 * 17);
+        for row in 0..8 {
+            for column in 0..8 {
+                let (file, rank) = match self.bottom {
+                    Orientation::White => (column, 7 - row),
+                    Orientation::Black => (7 - column, row),
+                };
+                if column > 0 {
+                    out.push(' ');
+                }
+                out.push_str(&self.glyph(board.board[start.grid(file, rank) as usize]));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// `piece` as drawn in this style
+    pub fn glyph(&self, piece: RawPiece) -> String {
+        match (self.pieces, piece.get_colour()) {
+            (PieceStyle::Unicode, _) => unicode_glyph(piece).to_string(),
+            (PieceStyle::Letters, PieceColor::None) => ".".to_string(),
+            (PieceStyle::Letters, _) => char::from(piece).to_string(),
+            (PieceStyle::Coloured, PieceColor::None) => format!("{}.{}", DIM, RESET),
+            (PieceStyle::Coloured, colour) => {
+                let colour = match colour {
+                    PieceColor::White => BOLD_WHITE,
+                    _ => BOLD_RED,
+                };
+                format!("{}{}{}", colour, char::from(piece), RESET)
+            }
+        }
+    }
+
+    /// Describe a recovery step in words with the pieces in this style
+    pub fn correction(&self, correction: &Correction, start: StartPosition) -> String {
+        let (verb, piece, squares) = match *correction {
+            Correction::Move { piece, from, to } => (
+                "move",
+                piece,
+                format!(
+                    "from {} to {}",
+                    start.square_name(from),
+                    start.square_name(to)
+                ),
+            ),
+            Correction::Place { piece, grid } => {
+                ("place", piece, format!("on {}", start.square_name(grid)))
+            }
+            Correction::Remove { piece, grid } => {
+                ("remove", piece, format!("from {}", start.square_name(grid)))
+            }
+        };
+        format!("{} {} {}", verb, self.glyph(piece), squares)
+    }
+}
+
+impl fmt::Display for PieceStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PieceStyle::Unicode => "unicode",
+            PieceStyle::Letters => "letters",
+            PieceStyle::Coloured => "coloured",
+        })
+    }
+}
+
+impl FromStr for PieceStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "unicode" => Ok(PieceStyle::Unicode),
+            "letters" | "ascii" => Ok(PieceStyle::Letters),
+            "coloured" | "colored" => Ok(PieceStyle::Coloured),
+            _ => Err(format!(
+                "no piece style {:?}, unicode, letters or coloured",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Orientation::White => "white",
+            Orientation::Black => "black",
+        })
+    }
+}
+
+impl FromStr for Orientation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "white" => Ok(Orientation::White),
+            "black" => Ok(Orientation::Black),
+            _ => Err(format!(
+                "no side {:?} to put at the bottom, white or black",
+                s
+            )),
+        }
+    }
+}
+
+/// Render the board as 8 rows of ASCII characters in grid order, with `.` for empty squares
+pub fn ascii(board: &ChessBoard) -> String {
+    render_with(board, |piece| match piece {
+        RawPiece::Empty => '.',
+        p => char::from(p),
+    })
+}
+
+/// Render the board as 8 rows of Unicode chess glyphs in grid order, with `·` for empty squares
+pub fn unicode(board: &ChessBoard) -> String {
+    render_with(board, unicode_glyph)
+}
+
+/// Get the Unicode chess glyph for a piece
+pub fn unicode_glyph(piece: RawPiece) -> char {
+    use RawPiece::*;
+    match piece {
+        Empty => '·',
+        WhitePawn => '♙',
+        WhiteRook => '♖',
+        WhiteKnight => '♘',
+        WhiteBishop => '♗',
+        WhiteKing => '♔',
+        WhiteQueen => '♕',
+        BlackPawn => '♟',
+        BlackRook => '♜',
+        BlackKnight => '♞',
+        BlackBishop => '♝',
+        BlackKing => '♚',
+        BlackQueen => '♛',
+    }
+}
+
+/// Describe a recovery step in words, e.g. "move ♘ from g1 to f3"
+pub fn correction(correction: &Correction, start: StartPosition) -> String {
+    Style::default().correction(correction, start)
+}
+
+fn render_with(board: &ChessBoard, glyph: impl Fn(RawPiece) -> char) -> String {
+    let mut out = String::with_capacity(8 * 17);
+    for row in board.board.chunks(8) {
+        for (i, piece) in row.iter().enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            out.push(glyph(*piece));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_empty_board() {
+        let board = ChessBoard {
+            board: [RawPiece::Empty; 64],
+        };
+        let text = ascii(&board);
+        assert_eq!(text.lines().count(), 8);
+        assert!(text.lines().all(|l| l == ". . . . . . . ."));
+    }
+
+    #[test]
+    fn test_unicode_piece() {
+        let mut board = ChessBoard {
+            board: [RawPiece::Empty; 64],
+        };
+        board.board[3] = RawPiece::WhiteKing;
+        let first = unicode(&board).lines().next().unwrap().to_string();
+        assert_eq!(first, "· · · ♔ · · · ·");
+    }
+
+    #[test]
+    fn test_styles() {
+        let mut board = ChessBoard {
+            board: [RawPiece::Empty; 64],
+        };
+        // White king on e1, black pawn on a7, with a8 at grid 0
+        board.board[60] = RawPiece::WhiteKing;
+        board.board[8] = RawPiece::BlackPawn;
+        let letters = Style {
+            pieces: PieceStyle::Letters,
+            bottom: Orientation::White,
+        };
+        let text = letters.board(&board, StartPosition::Mirror);
+        assert_eq!(text, ascii(&board));
+        assert_eq!(text.lines().last(), Some(". . . . K . . ."));
+        let flipped = Style {
+            bottom: Orientation::Black,
+            ..letters
+        };
+        let text = flipped.board(&board, StartPosition::Mirror);
+        assert_eq!(text.lines().next(), Some(". . . K . . . ."));
+        assert_eq!(text.lines().nth(6), Some(". . . . . . . p"));
+        // A board turned round shows the same from white's side
+        let mut turned = ChessBoard {
+            board: [RawPiece::Empty; 64],
+        };
+        turned.board[3] = RawPiece::WhiteKing;
+        assert_eq!(
+            letters.board(&turned, StartPosition::Normal).lines().last(),
+            Some(". . . . K . . .")
+        );
+
+        let coloured = Style {
+            pieces: PieceStyle::Coloured,
+            ..letters
+        };
+        assert_eq!(coloured.glyph(RawPiece::BlackPawn), "\x1b[1;91mp\x1b[0m");
+        assert_eq!(
+            letters.correction(
+                &Correction::Place {
+                    piece: RawPiece::WhiteKing,
+                    grid: 60
+                },
+                StartPosition::Mirror
+            ),
+            "place K on e1"
+        );
+        assert_eq!("Colored".parse(), Ok(PieceStyle::Coloured));
+        assert!("black".parse::<Orientation>().is_ok());
+    }
+}
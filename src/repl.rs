@@ -0,0 +1,97 @@
+use crate::protocol::*;
+
+/// A line typed at the protocol prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// Bytes to write to the board as they are
+    Send(Vec<u8>),
+    Help,
+    Quit,
+}
+
+/// Read a line as a command name like RequestVersion, or as hex bytes like "45",
+/// "0x45" or "2b 04 03"
+pub fn parse_input(line: &str) -> Result<Input, String> {
+    let line = line.trim();
+    match line.to_ascii_lowercase().as_str() {
+        "help" | "?" => return Ok(Input::Help),
+        "quit" | "exit" => return Ok(Input::Quit),
+        _ => {}
+    }
+    if let Ok(command) = line.parse::<Command>() {
+        return Ok(Input::Send(command.as_byte().to_vec()));
+    }
+    let mut bytes = Vec::new();
+    for word in line.split_whitespace() {
+        let digits = word
+            .strip_prefix("0x")
+            .or_else(|| word.strip_prefix("0X"))
+            .unwrap_or(word);
+        if digits.is_empty() || digits.len() % 2 != 0 {
+            return Err(format!("neither a command nor hex bytes: {:?}", word));
+        }
+        for pair in digits.as_bytes().chunks(2) {
+            let pair = std::str::from_utf8(pair).unwrap_or_default();
+            let byte = u8::from_str_radix(pair, 16)
+                .map_err(|_| format!("neither a command nor hex bytes: {:?}", word))?;
+            bytes.push(byte);
+        }
+    }
+    if bytes.is_empty() {
+        return Err("nothing to send".to_string());
+    }
+    Ok(Input::Send(bytes))
+}
+
+/// Names of all commands, for the help text
+pub fn command_names() -> Vec<String> {
+    Command::ALL
+        .iter()
+        .map(|command| format!("{:?}", command))
+        .collect()
+}
+
+/// Bytes as space separated hex pairs
+pub fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A received frame in hex, followed by what it decodes to
+pub fn describe(frame: &RawFrame) -> String {
+    let decoded = match MessageType::try_from(frame.message_type) {
+        Ok(message_type) => match Response::try_from_raw(message_type, &frame.data) {
+            Ok(response) => response.to_string(),
+            Err(e) => format!("undecodable {}: {:?}", message_type, e),
+        },
+        Err(_) => format!("unknown message type 0x{:02x}", frame.message_type),
+    };
+    format!("< {}  {}", hex(&frame.to_bytes()), decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(parse_input("RequestVersion"), Ok(Input::Send(vec![0x4d])));
+        assert_eq!(parse_input(" 0x45 "), Ok(Input::Send(vec![0x45])));
+        assert_eq!(
+            parse_input("2b 0403"),
+            Ok(Input::Send(vec![0x2b, 0x04, 0x03]))
+        );
+        assert_eq!(parse_input("quit"), Ok(Input::Quit));
+        assert!(parse_input("4").is_err());
+        assert!(parse_input("RequestNothing").is_err());
+        assert!(parse_input("").is_err());
+        let frame = RawFrame {
+            message_type: 0x13,
+            data: vec![1, 2],
+        };
+        assert_eq!(describe(&frame), "< 93 00 05 01 02  version 1.2");
+    }
+}
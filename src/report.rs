@@ -0,0 +1,246 @@
+use crate::game::GameBoard;
+use crate::pgn::{self, Annotation, Nag};
+use crate::protocol::*;
+use crate::rules::{Ply, Position};
+use crate::uci::{Info, Score, Searcher};
+use crate::variant::Variant;
+use std::fmt::Write;
+use std::io;
+
+/// Engine verdict on a single move
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveReview {
+    pub colour: PieceColor,
+    pub fullmove: u16,
+    pub san: String,
+    /// Evaluation after the move, white's point of view
+    pub score: Option<Score>,
+    /// Centipawns the mover gave away compared to the engine's best move
+    pub loss: i32,
+    pub nag: Option<Nag>,
+    /// Lichess style accuracy of the move, 0 to 100
+    pub accuracy: f64,
+}
+
+/// Post-game review of every move
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub moves: Vec<MoveReview>,
+}
+
+/// Winning chances in percent for white at `cp` centipawns
+fn win_percent(cp: i32) -> f64 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * cp as f64).exp()) - 1.0)
+}
+
+/// Review `moves` played from `initial`, given the engine's best lines for the position
+/// before every move and after the last one
+pub fn review(
+    variant: &dyn Variant,
+    initial: &Position,
+    moves: &[Ply],
+    searches: &[Vec<Info>],
+) -> Report {
+    let mut position = initial.clone();
+    let mut reviews = Vec::with_capacity(moves.len());
+    for (i, ply) in moves.iter().enumerate() {
+        let next = variant.play(&position, ply);
+        let sign = if position.to_move == PieceColor::Black {
+            -1
+        } else {
+            1
+        };
+        let lines = searches.get(i).map(Vec::as_slice).unwrap_or_default();
+        let after_lines = searches.get(i + 1).map(Vec::as_slice).unwrap_or_default();
+        let score = after_lines.first().map(|info| info.score);
+        // A finished game has no lines left, its result is the evaluation
+        let after = match (score, variant.outcome(&next)) {
+            (Some(score), _) => score.to_centipawns(),
+            (None, Some(outcome)) => match outcome.winner {
+                PieceColor::White => 1000,
+                PieceColor::Black => -1000,
+                PieceColor::None => 0,
+            },
+            (None, None) => lines.first().map_or(0, |info| info.score.to_centipawns()),
+        };
+        let before = lines
+            .first()
+            .map_or(after, |info| info.score.to_centipawns());
+        let loss = (sign * (before - after)).max(0);
+
+        let only_move = match lines {
+            [best, second, ..] => {
+                best.pv.first() == Some(&ply.uci())
+                    && sign * (best.score.to_centipawns() - second.score.to_centipawns()) >= 150
+            }
+            _ => false,
+        };
+        let nag = match loss {
+            300.. => Some(Nag::Blunder),
+            100.. => Some(Nag::Mistake),
+            50.. => Some(Nag::Dubious),
+            _ if only_move => Some(Nag::Good),
+            _ => None,
+        };
+        let drop = (win_percent(sign * before) - win_percent(sign * after)).max(0.0);
+        let accuracy = (103.1668 * (-0.04354 * drop).exp() - 3.1669).clamp(0.0, 100.0);
+
+        reviews.push(MoveReview {
+            colour: position.to_move,
+            fullmove: position.fullmove,
+            san: pgn::san(variant, &position, ply),
+            score,
+            loss,
+            nag,
+            accuracy,
+        });
+        position = next;
+    }
+    Report { moves: reviews }
+}
+
+/// Search every position of the game to `depth` and review the moves
+pub fn analyse(game: &GameBoard, searcher: &mut Searcher, depth: u32) -> io::Result<Report> {
+    let variant = game.variant();
+    let mut position = game.initial_position().clone();
+    let mut searches = Vec::with_capacity(game.moves().len() + 1);
+    searches.push(searcher.search(&position, depth)?);
+    for ply in game.moves() {
+        position = variant.play(&position, ply);
+        let lines = if variant.outcome(&position).is_some() {
+            Vec::new()
+        } else {
+            searcher.search(&position, depth)?
+        };
+        searches.push(lines);
+    }
+    Ok(review(
+        variant,
+        game.initial_position(),
+        game.moves(),
+        &searches,
+    ))
+}
+
+impl Report {
+    /// Average accuracy of the moves played by `colour`, None if it made no move
+    pub fn accuracy(&self, colour: PieceColor) -> Option<f64> {
+        let moves: Vec<f64> = self
+            .moves
+            .iter()
+            .filter(|m| m.colour == colour)
+            .map(|m| m.accuracy)
+            .collect();
+        (!moves.is_empty()).then(|| moves.iter().sum::<f64>() / moves.len() as f64)
+    }
+
+    /// The `n` moves that lost the most, worst first, counting only mistakes and blunders
+    pub fn worst_moves(&self, n: usize) -> Vec<&MoveReview> {
+        let mut moves: Vec<&MoveReview> = self.moves.iter().filter(|m| m.loss >= 100).collect();
+        moves.sort_by_key(|m| std::cmp::Reverse(m.loss));
+        moves.truncate(n);
+        moves
+    }
+
+    /// Glyphs and `[%eval]` comments for every move, to pass to `pgn::to_annotated_pgn`
+    pub fn annotations(&self) -> Vec<Annotation> {
+        self.moves
+            .iter()
+            .map(|m| Annotation {
+                nag: m.nag,
+                comment: m.score.map(|score| match score {
+                    Score::Centipawns(cp) => format!("[%eval {:.2}]", cp as f64 / 100.0),
+                    Score::Mate(n) => format!("[%eval #{}]", n),
+                }),
+            })
+            .collect()
+    }
+
+    /// Human readable summary: accuracy per side and the biggest blunders
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        for (colour, name) in [(PieceColor::White, "White"), (PieceColor::Black, "Black")] {
+            if let Some(accuracy) = self.accuracy(colour) {
+                let count = |nag| {
+                    self.moves
+                        .iter()
+                        .filter(|m| m.colour == colour && m.nag == Some(nag))
+                        .count()
+                };
+                let _ = writeln!(
+                    out,
+                    "{}: accuracy {:.1}%, {} inaccuracies, {} mistakes, {} blunders",
+                    name,
+                    accuracy,
+                    count(Nag::Dubious),
+                    count(Nag::Mistake),
+                    count(Nag::Blunder)
+                );
+            }
+        }
+        let worst = self.worst_moves(3);
+        if !worst.is_empty() {
+            out.push_str("Biggest errors:\n");
+            for m in worst {
+                let dots = if m.colour == PieceColor::Black {
+                    "..."
+                } else {
+                    "."
+                };
+                let _ = writeln!(
+                    out,
+                    "  {}{} {}{} lost {:.2}",
+                    m.fullmove,
+                    dots,
+                    m.san,
+                    m.nag.map_or("", Nag::symbol),
+                    m.loss as f64 / 100.0
+                );
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::Standard;
+
+    fn info(cp: i32, pv: &str) -> Info {
+        Info {
+            depth: 10,
+            score: Score::Centipawns(cp),
+            pv: vec![pv.to_string()],
+        }
+    }
+
+    fn find(position: &Position, uci: &str) -> Ply {
+        position
+            .legal_moves()
+            .into_iter()
+            .find(|ply| ply.uci() == uci)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_blunder_is_flagged() {
+        let initial = Position::starting();
+        let e4 = find(&initial, "e2e4");
+        let after = initial.play(&e4);
+        let f6 = find(&after, "f7f6");
+        let searches = vec![
+            vec![info(30, "e2e4"), info(20, "d2d4")],
+            vec![info(30, "e7e5")],
+            vec![info(450, "d2d4")],
+        ];
+        let report = review(&Standard, &initial, &[e4, f6], &searches);
+        assert_eq!(report.moves[0].loss, 0);
+        assert_eq!(report.moves[0].nag, None);
+        assert_eq!(report.moves[1].loss, 420);
+        assert_eq!(report.moves[1].nag, Some(Nag::Blunder));
+        assert!(report.accuracy(PieceColor::White).unwrap() > 99.0);
+        assert!(report.accuracy(PieceColor::Black).unwrap() < 50.0);
+        assert!(report.summary().contains("1... f6?? lost 4.20"));
+    }
+}
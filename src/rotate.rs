@@ -0,0 +1,215 @@
+//! Logs that do not outgrow the disk over an all-day tournament: a file cut into pieces
+//! by size or age, the old ones renamed `trace.log.1`, `trace.log.2` and so on, newest
+//! first, with only so many of them kept.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+/// When a log is started afresh and how many old ones are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rotation {
+    /// Start afresh once the file holds this many bytes
+    pub max_size: Option<u64>,
+    /// Start afresh once the file is this old
+    pub max_age: Option<Duration>,
+    /// Old files kept, all of them if None
+    pub keep: Option<usize>,
+}
+
+impl Rotation {
+    /// Whether a file of `size` bytes started `age` ago is done with once `more` bytes
+    /// are added. An empty file always takes them, however long the line.
+    fn due(&self, size: u64, age: Duration, more: usize) -> bool {
+        size > 0
+            && (self.max_size.is_some_and(|max| size + more as u64 > max)
+                || self.max_age.is_some_and(|max| age >= max))
+    }
+
+    /// Remove the files of `older`, oldest first, beyond the newest `keep` of them
+    pub fn prune(&self, older: &[PathBuf]) {
+        let Some(keep) = self.keep else {
+            return;
+        };
+        let present: Vec<_> = older.iter().filter(|path| path.exists()).collect();
+        for path in &present[..present.len().saturating_sub(keep)] {
+            if let Err(e) = fs::remove_file(path) {
+                warn!(error = %e, path = %path.display(), "old log not removed");
+            }
+        }
+    }
+}
+
+/// `path` with `.n` added, the nth newest of the old files
+pub fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// A file appended to that moves out of the way when due. A write never straddles two
+/// files, so whole lines written at once stay whole.
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    size: u64,
+    started: SystemTime,
+}
+
+impl RotatingFile {
+    /// Append to `path`, created if need be, its age taken from when it was created
+    pub fn open(path: impl Into<PathBuf>, rotation: Rotation) -> io::Result<RotatingFile> {
+        let path = path.into();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        Ok(RotatingFile {
+            path,
+            rotation,
+            file,
+            size: metadata.len(),
+            started: metadata.created().unwrap_or_else(|_| SystemTime::now()),
+        })
+    }
+
+    /// Move the file to `.1`, the older ones one further along, and start afresh
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let last = match self.rotation.keep {
+            Some(keep) => keep,
+            None => {
+                (1..)
+                    .take_while(|n| rotated(&self.path, *n).exists())
+                    .count()
+                    + 1
+            }
+        };
+        if last == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            match fs::remove_file(rotated(&self.path, last)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            for n in (1..last).rev() {
+                let older = rotated(&self.path, n);
+                if older.exists() {
+                    fs::rename(&older, rotated(&self.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        self.started = SystemTime::now();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let age = self.started.elapsed().unwrap_or_default();
+        if self.rotation.due(self.size, age, buf.len()) {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// A size like "500K", "50M" or "1G", in bytes of 1024, or a plain number of bytes
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, unit) = s.split_at(s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len()));
+    let scale: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return Err(format!("invalid size {:?}, like 500K, 50M or 1G", s)),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(scale))
+        .ok_or_else(|| format!("invalid size {:?}, like 500K, 50M or 1G", s))
+}
+
+/// An age like "30m", "12h" or "1d", or a plain number of seconds
+pub fn parse_age(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, unit) = s.split_at(s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len()));
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("invalid age {:?}, like 30m, 12h or 1d", s)),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(scale))
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid age {:?}, like 30m, 12h or 1d", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation() {
+        assert_eq!(parse_size("50M"), Ok(50 << 20));
+        assert_eq!(parse_size("1000"), Ok(1000));
+        assert!(parse_size("5X").is_err());
+        assert_eq!(parse_age("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert!(parse_age("d").is_err());
+
+        let dir = std::env::temp_dir().join(format!("jackolope-rotate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("trace.log");
+        let rotation = Rotation {
+            max_size: Some(10),
+            keep: Some(2),
+            ..Rotation::default()
+        };
+        let mut file = RotatingFile::open(&path, rotation).unwrap();
+        for line in [
+            "one\n",
+            "two\n",
+            "three\n",
+            "a line past the limit\n",
+            "four\n",
+        ] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        // Lines stay whole, the oldest file is gone
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), "four\n");
+        assert_eq!(read(&rotated(&path, 1)), "a line past the limit\n");
+        assert_eq!(read(&rotated(&path, 2)), "three\n");
+        assert!(!rotated(&path, 3).exists());
+
+        // Appended to where it left off
+        drop(file);
+        let mut file = RotatingFile::open(&path, rotation).unwrap();
+        file.write_all(b"five\n").unwrap();
+        assert_eq!(read(&path), "four\nfive\n");
+
+        rotation.prune(&[rotated(&path, 2), rotated(&path, 1), path.clone()]);
+        assert!(!rotated(&path, 2).exists());
+        assert!(rotated(&path, 1).exists() && path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
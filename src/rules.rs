@@ -0,0 +1,733 @@
+use crate::bitboard::Bitboards;
+use crate::fen::{self, CastleFiles, Castling};
+use crate::protocol::*;
+use crate::zobrist;
+
+/// Square index with a1 = 0, b1 = 1, ..., h8 = 63
+pub type Square = u8;
+
+pub fn square(file: u8, rank: u8) -> Square {
+    rank * 8 + file
+}
+
+pub fn file_of(sq: Square) -> u8 {
+    sq % 8
+}
+
+pub fn rank_of(sq: Square) -> u8 {
+    sq / 8
+}
+
+/// Algebraic name of a square, e.g. "e4"
+pub fn square_name(sq: Square) -> String {
+    format!("{}{}", (b'a' + file_of(sq)) as char, rank_of(sq) + 1)
+}
+
+/// Parse an algebraic square name
+pub fn parse_square(name: &str) -> Option<Square> {
+    match name.as_bytes() {
+        &[f @ b'a'..=b'h', r @ b'1'..=b'8'] => Some(square(f - b'a', r - b'1')),
+        _ => None,
+    }
+}
+
+fn offset(sq: Square, df: i8, dr: i8) -> Option<Square> {
+    let f = file_of(sq) as i8 + df;
+    let r = rank_of(sq) as i8 + dr;
+    ((0..8).contains(&f) && (0..8).contains(&r)).then(|| square(f as u8, r as u8))
+}
+
+const KNIGHT_STEPS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [(i8, i8); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+const ROOK_DIRS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// Pieces a pawn may promote to in standard chess
+pub const PROMOTIONS: [PieceKind; 4] = [
+    PieceKind::Queen,
+    PieceKind::Rook,
+    PieceKind::Bishop,
+    PieceKind::Knight,
+];
+
+/// Squares adjacent to `sq`
+pub fn neighbours(sq: Square) -> impl Iterator<Item = Square> {
+    KING_STEPS
+        .iter()
+        .filter_map(move |&(df, dr)| offset(sq, df, dr))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlyKind {
+    Normal,
+    EnPassant,
+    /// The king moves to `to`, the rook from `rook_from` to `rook_to`
+    Castle {
+        rook_from: Square,
+        rook_to: Square,
+    },
+    Promotion(RawPiece),
+    /// A piece placed from the pocket (crazyhouse), `from` equals `to`
+    Drop,
+}
+
+/// A move in terms of squares, as produced by the move generator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ply {
+    pub piece: RawPiece,
+    pub from: Square,
+    pub to: Square,
+    pub captured: RawPiece,
+    pub kind: PlyKind,
+}
+
+impl Ply {
+    pub fn is_capture(&self) -> bool {
+        self.captured != RawPiece::Empty
+    }
+
+    /// Square the captured piece stood on, differs from `to` for en passant
+    pub fn capture_square(&self) -> Square {
+        match self.kind {
+            PlyKind::EnPassant => square(file_of(self.to), rank_of(self.from)),
+            _ => self.to,
+        }
+    }
+
+    /// UCI notation, castling written as the king's two square move
+    pub fn uci(&self) -> String {
+        match self.kind {
+            PlyKind::Drop => format!(
+                "{}@{}",
+                char::from(self.piece).to_ascii_uppercase(),
+                square_name(self.to)
+            ),
+            PlyKind::Promotion(p) => format!(
+                "{}{}{}",
+                square_name(self.from),
+                square_name(self.to),
+                char::from(p).to_ascii_lowercase()
+            ),
+            _ => format!("{}{}", square_name(self.from), square_name(self.to)),
+        }
+    }
+}
+
+/// Pieces in hand for crazyhouse, counts indexed as pawn, knight, bishop, rook, queen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Pockets {
+    pub white: [u8; 5],
+    pub black: [u8; 5],
+}
+
+impl Pockets {
+    pub fn get_mut(&mut self, colour: PieceColor) -> &mut [u8; 5] {
+        match colour {
+            PieceColor::Black => &mut self.black,
+            _ => &mut self.white,
+        }
+    }
+
+    pub fn get(&self, colour: PieceColor) -> &[u8; 5] {
+        match colour {
+            PieceColor::Black => &self.black,
+            _ => &self.white,
+        }
+    }
+
+    /// Pocket slot of a piece type, None for kings
+    pub fn slot(kind: PieceKind) -> Option<usize> {
+        match kind {
+            PieceKind::Pawn => Some(0),
+            PieceKind::Knight => Some(1),
+            PieceKind::Bishop => Some(2),
+            PieceKind::Rook => Some(3),
+            PieceKind::Queen => Some(4),
+            PieceKind::King => None,
+        }
+    }
+}
+
+/// A chess position with the state needed for move legality
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    /// Pieces indexed by square, a1 = 0
+    pub board: [RawPiece; 64],
+    pub to_move: PieceColor,
+    pub castling: Castling,
+    pub castle_files: CastleFiles,
+    /// Square passed over by a pawn double step, only set when a capture there is possible
+    pub en_passant: Option<Square>,
+    pub halfmove: u16,
+    pub fullmove: u16,
+    pub pockets: Pockets,
+    /// Squares holding promoted pieces, bit per square
+    pub promoted: u64,
+}
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+impl Position {
+    /// A position with the given pieces, white to move and no castling rights
+    pub fn from_squares(board: [RawPiece; 64]) -> Self {
+        Position {
+            board,
+            to_move: PieceColor::White,
+            castling: Castling::default(),
+            castle_files: CastleFiles::default(),
+            en_passant: None,
+            halfmove: 0,
+            fullmove: 1,
+            pockets: Pockets::default(),
+            promoted: 0,
+        }
+    }
+
+    /// The classical start position
+    pub fn starting() -> Self {
+        Position::from_fen(START_FEN).unwrap()
+    }
+
+    /// Parse a FEN, accepting X-FEN and Shredder-FEN castling fields
+    pub fn from_fen(text: &str) -> Option<Self> {
+        let mut fields = text.split_whitespace();
+        let mut board = [RawPiece::Empty; 64];
+        let ranks: Vec<&str> = fields.next()?.split('/').collect();
+        if ranks.len() != 8 {
+            return None;
+        }
+        for (i, rank) in ranks.iter().enumerate() {
+            let mut file = 0u8;
+            for c in rank.chars() {
+                if let Some(n) = c.to_digit(10) {
+                    file = file.checked_add(n as u8).filter(|file| *file <= 8)?;
+                } else {
+                    if file >= 8 {
+                        return None;
+                    }
+                    board[square(file, 7 - i as u8) as usize] = match RawPiece::try_from(c) {
+                        Ok(RawPiece::Empty) | Err(_) => return None,
+                        Ok(p) => p,
+                    };
+                    file += 1;
+                }
+            }
+            if file != 8 {
+                return None;
+            }
+        }
+        let mut position = Position::from_squares(board);
+        position.to_move = match fields.next().unwrap_or("w") {
+            "w" => PieceColor::White,
+            "b" => PieceColor::Black,
+            _ => return None,
+        };
+        position.set_castling(fields.next().unwrap_or("-"))?;
+        position.en_passant = match fields.next().unwrap_or("-") {
+            "-" => None,
+            name => Some(parse_square(name)?),
+        };
+        position.halfmove = fields.next().map_or(Some(0), |f| f.parse().ok())?;
+        position.fullmove = fields.next().map_or(Some(1), |f| f.parse().ok())?;
+        Some(position)
+    }
+
+    fn set_castling(&mut self, field: &str) -> Option<()> {
+        if field == "-" {
+            return Some(());
+        }
+        for c in field.chars() {
+            let (colour, rank) = if c.is_ascii_uppercase() {
+                (PieceColor::White, 0)
+            } else {
+                (PieceColor::Black, 7)
+            };
+            let king = RawPiece::from_kind(PieceKind::King, colour);
+            let rook = RawPiece::from_kind(PieceKind::Rook, colour);
+            let king_file = (0..8).find(|&f| self.board[square(f, rank) as usize] == king)?;
+            self.castle_files.king = king_file;
+            let rook_file = match c.to_ascii_lowercase() {
+                'k' => (king_file + 1..8)
+                    .rev()
+                    .find(|&f| self.board[square(f, rank) as usize] == rook)?,
+                'q' => (0..king_file).find(|&f| self.board[square(f, rank) as usize] == rook)?,
+                f @ 'a'..='h' => f as u8 - b'a',
+                _ => return None,
+            };
+            let short = rook_file > king_file;
+            if short {
+                self.castle_files.king_rook = rook_file;
+            } else {
+                self.castle_files.queen_rook = rook_file;
+            }
+            match (colour, short) {
+                (PieceColor::White, true) => self.castling.white_short = true,
+                (PieceColor::White, false) => self.castling.white_long = true,
+                (_, true) => self.castling.black_short = true,
+                (_, false) => self.castling.black_long = true,
+            }
+        }
+        Some(())
+    }
+
+    /// FEN of the position, with X-FEN castling notation
+    pub fn to_fen(&self) -> String {
+        let side = if self.to_move == PieceColor::Black {
+            'b'
+        } else {
+            'w'
+        };
+        format!(
+            "{} {} {} {} {} {}",
+            fen::placement(&self.board),
+            side,
+            fen::xfen_castling(&self.board, self.castling, self.castle_files),
+            self.en_passant.map_or("-".to_string(), square_name),
+            self.halfmove,
+            self.fullmove
+        )
+    }
+
+    pub fn piece_at(&self, sq: Square) -> RawPiece {
+        self.board[sq as usize]
+    }
+
+    /// Square of the king of `colour`, if it is on the board
+    pub fn king(&self, colour: PieceColor) -> Option<Square> {
+        let king = RawPiece::from_kind(PieceKind::King, colour);
+        (0..64).find(|&sq| self.board[sq as usize] == king)
+    }
+
+    /// The placement as bitboards, to be built once for many attack queries
+    pub fn bitboards(&self) -> Bitboards {
+        Bitboards::new(&self.board)
+    }
+
+    /// Whether any piece of `by` attacks `sq`. Builds the bitboards, so a caller
+    /// asking more than once is better off with `bitboards()`.
+    pub fn is_attacked(&self, sq: Square, by: PieceColor) -> bool {
+        self.bitboards().is_attacked(sq, by)
+    }
+
+    /// Whether the side to move is in check
+    pub fn in_check(&self) -> bool {
+        let bitboards = self.bitboards();
+        bitboards
+            .king(self.to_move)
+            .is_some_and(|k| bitboards.is_attacked(k, self.to_move.opposite()))
+    }
+
+    /// Moves obeying piece movement rules without regard to check, excluding castling
+    pub fn pseudo_legal_moves(&self, promotions: &[PieceKind]) -> Vec<Ply> {
+        let us = self.to_move;
+        let mut moves = Vec::with_capacity(48);
+        for from in 0..64u8 {
+            let piece = self.piece_at(from);
+            if piece == RawPiece::Empty || piece.get_colour() != us {
+                continue;
+            }
+            match piece.kind() {
+                Some(PieceKind::Pawn) => self.pawn_moves(from, piece, promotions, &mut moves),
+                Some(PieceKind::Knight) => self.step_moves(from, piece, &KNIGHT_STEPS, &mut moves),
+                Some(PieceKind::King) => self.step_moves(from, piece, &KING_STEPS, &mut moves),
+                Some(PieceKind::Bishop) => self.slide_moves(from, piece, &BISHOP_DIRS, &mut moves),
+                Some(PieceKind::Rook) => self.slide_moves(from, piece, &ROOK_DIRS, &mut moves),
+                Some(PieceKind::Queen) => {
+                    self.slide_moves(from, piece, &ROOK_DIRS, &mut moves);
+                    self.slide_moves(from, piece, &BISHOP_DIRS, &mut moves);
+                }
+                None => {}
+            }
+        }
+        moves
+    }
+
+    fn push(&self, moves: &mut Vec<Ply>, piece: RawPiece, from: Square, to: Square, kind: PlyKind) {
+        let captured = match kind {
+            PlyKind::EnPassant => {
+                RawPiece::from_kind(PieceKind::Pawn, piece.get_colour().opposite())
+            }
+            _ => self.piece_at(to),
+        };
+        moves.push(Ply {
+            piece,
+            from,
+            to,
+            captured,
+            kind,
+        });
+    }
+
+    fn pawn_moves(
+        &self,
+        from: Square,
+        piece: RawPiece,
+        promotions: &[PieceKind],
+        moves: &mut Vec<Ply>,
+    ) {
+        let colour = piece.get_colour();
+        let (dir, start_rank, last_rank) = match colour {
+            PieceColor::White => (1, 1, 7),
+            _ => (-1, 6, 0),
+        };
+        let advance = |to: Square, moves: &mut Vec<Ply>| {
+            if rank_of(to) == last_rank {
+                for kind in promotions {
+                    let promoted = RawPiece::from_kind(*kind, colour);
+                    self.push(moves, piece, from, to, PlyKind::Promotion(promoted));
+                }
+            } else {
+                self.push(moves, piece, from, to, PlyKind::Normal);
+            }
+        };
+        if let Some(one) = offset(from, 0, dir) {
+            if self.piece_at(one) == RawPiece::Empty {
+                advance(one, moves);
+                if rank_of(from) == start_rank {
+                    if let Some(two) = offset(from, 0, 2 * dir) {
+                        if self.piece_at(two) == RawPiece::Empty {
+                            self.push(moves, piece, from, two, PlyKind::Normal);
+                        }
+                    }
+                }
+            }
+        }
+        for df in [-1, 1] {
+            let Some(to) = offset(from, df, dir) else {
+                continue;
+            };
+            let target = self.piece_at(to);
+            if target != RawPiece::Empty && target.get_colour() != colour {
+                advance(to, moves);
+            } else if target == RawPiece::Empty && self.en_passant == Some(to) {
+                self.push(moves, piece, from, to, PlyKind::EnPassant);
+            }
+        }
+    }
+
+    fn step_moves(&self, from: Square, piece: RawPiece, steps: &[(i8, i8)], moves: &mut Vec<Ply>) {
+        for &(df, dr) in steps {
+            if let Some(to) = offset(from, df, dr) {
+                if !self.piece_at(to).is_same_colour(&piece) {
+                    self.push(moves, piece, from, to, PlyKind::Normal);
+                }
+            }
+        }
+    }
+
+    fn slide_moves(&self, from: Square, piece: RawPiece, dirs: &[(i8, i8)], moves: &mut Vec<Ply>) {
+        for &(df, dr) in dirs {
+            let mut cur = from;
+            while let Some(to) = offset(cur, df, dr) {
+                let target = self.piece_at(to);
+                if target.is_same_colour(&piece) {
+                    break;
+                }
+                self.push(moves, piece, from, to, PlyKind::Normal);
+                if target != RawPiece::Empty {
+                    break;
+                }
+                cur = to;
+            }
+        }
+    }
+
+    /// Castling moves under Chess960 rules, which include the classical ones: the king ends on
+    /// the g or c file and the rook next to it, every square passed over apart from the king and
+    /// rook themselves must be empty, and the king may not be in or pass through check.
+    pub fn castling_moves(&self) -> Vec<Ply> {
+        let us = self.to_move;
+        let (rank, short, long) = match us {
+            PieceColor::White => (0, self.castling.white_short, self.castling.white_long),
+            PieceColor::Black => (7, self.castling.black_short, self.castling.black_long),
+            PieceColor::None => return Vec::new(),
+        };
+        let king = RawPiece::from_kind(PieceKind::King, us);
+        let rook = RawPiece::from_kind(PieceKind::Rook, us);
+        let files = self.castle_files;
+        let king_from = square(files.king, rank);
+        let mut moves = Vec::new();
+        if self.piece_at(king_from) != king || self.in_check() {
+            return moves;
+        }
+        for (allowed, rook_file, king_file_to, rook_file_to) in [
+            (short, files.king_rook, 6, 5),
+            (long, files.queen_rook, 2, 3),
+        ] {
+            let rook_from = square(rook_file, rank);
+            if !allowed || self.piece_at(rook_from) != rook {
+                continue;
+            }
+            let lo = files
+                .king
+                .min(rook_file)
+                .min(king_file_to)
+                .min(rook_file_to);
+            let hi = files
+                .king
+                .max(rook_file)
+                .max(king_file_to)
+                .max(rook_file_to);
+            let blocked = (lo..=hi).map(|f| square(f, rank)).any(|sq| {
+                sq != king_from && sq != rook_from && self.piece_at(sq) != RawPiece::Empty
+            });
+            if blocked {
+                continue;
+            }
+            let (a, b) = (files.king.min(king_file_to), files.king.max(king_file_to));
+            // With the castling rook removed, a rook behind it could be giving check on the path
+            let mut without_rook = self.board;
+            without_rook[rook_from as usize] = RawPiece::Empty;
+            let without_rook = Bitboards::new(&without_rook);
+            if (a..=b).any(|f| without_rook.is_attacked(square(f, rank), us.opposite())) {
+                continue;
+            }
+            moves.push(Ply {
+                piece: king,
+                from: king_from,
+                to: square(king_file_to, rank),
+                captured: RawPiece::Empty,
+                kind: PlyKind::Castle {
+                    rook_from,
+                    rook_to: square(rook_file_to, rank),
+                },
+            });
+        }
+        moves
+    }
+
+    /// All legal moves under standard rules
+    pub fn legal_moves(&self) -> Vec<Ply> {
+        let us = self.to_move;
+        let mut moves = self.pseudo_legal_moves(&PROMOTIONS);
+        moves.extend(self.castling_moves());
+        // Only the placement matters for check, so the move is made on the bitboards
+        let bitboards = self.bitboards();
+        moves.retain(|ply| {
+            let next = bitboards.play(ply);
+            next.king(us)
+                .is_none_or(|k| !next.is_attacked(k, us.opposite()))
+        });
+        moves
+    }
+
+    /// The position after `ply`, which is assumed to be playable
+    pub fn play(&self, ply: &Ply) -> Position {
+        let mut next = self.clone();
+        let us = self.to_move;
+        let (from, to) = (ply.from as usize, ply.to as usize);
+        let was_promoted = self.promoted & (1u64 << from) != 0;
+        next.promoted &= !((1u64 << from) | (1u64 << to) | (1u64 << ply.capture_square()));
+        match ply.kind {
+            PlyKind::Normal => {
+                next.board[from] = RawPiece::Empty;
+                next.board[to] = ply.piece;
+                if was_promoted {
+                    next.promoted |= 1u64 << to;
+                }
+            }
+            PlyKind::EnPassant => {
+                next.board[from] = RawPiece::Empty;
+                next.board[ply.capture_square() as usize] = RawPiece::Empty;
+                next.board[to] = ply.piece;
+            }
+            PlyKind::Castle { rook_from, rook_to } => {
+                let rook = next.board[rook_from as usize];
+                next.board[from] = RawPiece::Empty;
+                next.board[rook_from as usize] = RawPiece::Empty;
+                next.board[to] = ply.piece;
+                next.board[rook_to as usize] = rook;
+            }
+            PlyKind::Promotion(piece) => {
+                next.board[from] = RawPiece::Empty;
+                next.board[to] = piece;
+                next.promoted |= 1u64 << to;
+            }
+            PlyKind::Drop => {
+                next.board[to] = ply.piece;
+            }
+        }
+
+        if ply.piece.is_king() && ply.kind != PlyKind::Drop {
+            next.clear_castling(us);
+        }
+        next.refresh_castling();
+
+        next.en_passant = None;
+        if ply.piece.is_pawn()
+            && ply.kind == PlyKind::Normal
+            && rank_of(ply.from).abs_diff(rank_of(ply.to)) == 2
+        {
+            let enemy = RawPiece::from_kind(PieceKind::Pawn, us.opposite());
+            let passed = square(file_of(ply.from), (rank_of(ply.from) + rank_of(ply.to)) / 2);
+            if [-1, 1]
+                .iter()
+                .filter_map(|&df| offset(ply.to, df, 0))
+                .any(|s| next.piece_at(s) == enemy)
+            {
+                next.en_passant = Some(passed);
+            }
+        }
+
+        next.halfmove = if ply.piece.is_pawn() || ply.is_capture() {
+            0
+        } else {
+            self.halfmove + 1
+        };
+        if us == PieceColor::Black {
+            next.fullmove += 1;
+        }
+        next.to_move = us.opposite();
+        next
+    }
+
+    /// Drop both castling rights of `colour`
+    pub fn clear_castling(&mut self, colour: PieceColor) {
+        match colour {
+            PieceColor::White => {
+                self.castling.white_short = false;
+                self.castling.white_long = false;
+            }
+            PieceColor::Black => {
+                self.castling.black_short = false;
+                self.castling.black_long = false;
+            }
+            PieceColor::None => {}
+        }
+    }
+
+    /// Drop castling rights whose king or rook no longer stands on its original square
+    pub fn refresh_castling(&mut self) {
+        let files = self.castle_files;
+        let at = |file: u8, rank: u8| self.board[square(file, rank) as usize];
+        let white_king = at(files.king, 0) == RawPiece::WhiteKing;
+        let black_king = at(files.king, 7) == RawPiece::BlackKing;
+        self.castling.white_short &= white_king && at(files.king_rook, 0) == RawPiece::WhiteRook;
+        self.castling.white_long &= white_king && at(files.queen_rook, 0) == RawPiece::WhiteRook;
+        self.castling.black_short &= black_king && at(files.king_rook, 7) == RawPiece::BlackRook;
+        self.castling.black_long &= black_king && at(files.queen_rook, 7) == RawPiece::BlackRook;
+    }
+
+    /// Hash of everything that makes two positions the same for the repetition rules:
+    /// placement, side to move, castling rights, en passant and pockets
+    pub fn repetition_key(&self) -> u64 {
+        zobrist::hash(self)
+    }
+
+    /// Neither side can possibly checkmate: bare kings, a single minor piece, or
+    /// only bishops that all stand on squares of the same colour
+    pub fn insufficient_material(&self) -> bool {
+        let mut knights = 0;
+        let mut bishop_colours = [0; 2];
+        for (sq, piece) in self.board.iter().enumerate() {
+            match piece.kind() {
+                None | Some(PieceKind::King) => {}
+                Some(PieceKind::Knight) => knights += 1,
+                Some(PieceKind::Bishop) => bishop_colours[(sq / 8 + sq % 8) % 2] += 1,
+                Some(_) => return false,
+            }
+        }
+        let [dark, light] = bishop_colours;
+        match knights {
+            0 => dark == 0 || light == 0,
+            1 => dark + light == 0,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn perft(position: &Position, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        position
+            .legal_moves()
+            .iter()
+            .map(|ply| perft(&position.play(ply), depth - 1))
+            .sum()
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        for (fen, dead) in [
+            ("8/8/4k3/8/8/3K4/8/8 w - - 0 1", true),
+            ("8/8/4k3/8/8/3KN3/8/8 w - - 0 1", true),
+            // Bishops on c1 and f8 share dark squares
+            ("5b2/8/4k3/8/8/3K4/8/2B5 w - - 0 1", true),
+            ("4b3/8/4k3/8/8/3K4/8/2B5 w - - 0 1", false),
+            ("8/8/4k3/8/8/3KNN2/8/8 w - - 0 1", false),
+            ("8/8/4k3/8/8/3K4/4P3/8 w - - 0 1", false),
+        ] {
+            let position = Position::from_fen(fen).unwrap();
+            assert_eq!(position.insufficient_material(), dead, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_perft_start() {
+        let position = Position::starting();
+        assert_eq!(perft(&position, 1), 20);
+        assert_eq!(perft(&position, 2), 400);
+        assert_eq!(perft(&position, 3), 8902);
+    }
+
+    #[test]
+    fn test_perft_kiwipete() {
+        // Exercises castling, en passant and promotions
+        let position = Position::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        assert_eq!(perft(&position, 1), 48);
+        assert_eq!(perft(&position, 2), 2039);
+    }
+
+    #[test]
+    fn test_fen_roundtrip() {
+        for text in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/8/8/8/8/8/8/K6k b - - 12 40",
+        ] {
+            assert_eq!(Position::from_fen(text).unwrap().to_fen(), text);
+        }
+        assert!(Position::from_fen("8/8/8 w - - 0 1").is_none());
+        // Runs of empty squares too long for a rank, the last one for a byte
+        assert!(Position::from_fen("54/8/8/8/8/8/8/8 w - - 0 1").is_none());
+        assert!(
+            Position::from_fen("99999999999999999999999999999/8/8/8/8/8/8/8 w - - 0 1").is_none()
+        );
+    }
+
+    #[test]
+    fn test_checkmate_has_no_moves() {
+        // Fool's mate
+        let position =
+            Position::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3")
+                .unwrap();
+        assert!(position.in_check());
+        assert!(position.legal_moves().is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::event::Event;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A single event in a session log, stamped with wall-clock milliseconds since the Unix epoch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub timestamp_ms: u64,
+    pub event: Event,
+}
+
+/// Records game events as JSON lines so a session can be replayed later
+pub struct SessionLog<W: Write> {
+    writer: W,
+}
+
+impl SessionLog<BufWriter<File>> {
+    /// Create (or truncate) a session log file
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(SessionLog::new(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Open a session log to add to what it already holds, creating it if need be
+    pub fn append(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(SessionLog::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> SessionLog<W> {
+    pub fn new(writer: W) -> Self {
+        SessionLog { writer }
+    }
+
+    /// Record an event stamped with the current time
+    pub fn record(&mut self, event: &Event) -> io::Result<()> {
+        self.record_at(now_ms(), event)
+    }
+
+    /// Record an event with an explicit timestamp
+    pub fn record_at(&mut self, timestamp_ms: u64, event: &Event) -> io::Result<()> {
+        let record = SessionRecord {
+            timestamp_ms,
+            event: event.clone(),
+        };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        // Flush every record so a crash never loses the moves leading up to it
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Iterator over the records of a session log
+pub struct SessionReader<R: BufRead> {
+    lines: io::Lines<R>,
+}
+
+impl SessionReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(SessionReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> SessionReader<R> {
+    pub fn new(reader: R) -> Self {
+        SessionReader {
+            lines: reader.lines(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for SessionReader<R> {
+    type Item = io::Result<SessionRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(serde_json::from_str(&line).map_err(io::Error::from));
+        }
+    }
+}
+
+/// Current wall-clock time in milliseconds since the Unix epoch
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::*;
+
+    #[test]
+    fn test_session_roundtrip() {
+        let mut board = ChessBoard {
+            board: [RawPiece::Empty; 64],
+        };
+        board.board[3] = RawPiece::WhiteKing;
+        board.board[60] = RawPiece::BlackKing;
+        let events = vec![
+            Event::BoardDump(board),
+            Event::FieldUpdate(ChessMove {
+                grid: 3,
+                piece: RawPiece::Empty,
+            }),
+            Event::SerialNumber("12345".to_string()),
+        ];
+
+        let mut log = SessionLog::new(Vec::new());
+        for (i, event) in events.iter().enumerate() {
+            log.record_at(1000 + i as u64, event).unwrap();
+        }
+        let bytes = log.into_inner();
+
+        let records: Vec<SessionRecord> = SessionReader::new(&bytes[..])
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].timestamp_ms, 1000);
+        let replayed: Vec<Event> = records.into_iter().map(|r| r.event).collect();
+        assert_eq!(replayed, events);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::fen::{self, Castling};
+use crate::game::StartPosition;
+use crate::protocol::*;
+use std::time::{Duration, Instant};
+
+/// Free placement of pieces without move legality, used to compose arbitrary positions
+#[derive(Debug, Clone)]
+pub struct Setup {
+    board: ChessBoard,
+    start: StartPosition,
+    last_change: Instant,
+}
+
+impl Setup {
+    /// Start setting up from the current board, `start` only determines the board orientation
+    pub fn new(board: ChessBoard, start: StartPosition, now: Instant) -> Self {
+        Setup {
+            board,
+            start,
+            last_change: now,
+        }
+    }
+
+    pub fn board(&self) -> &ChessBoard {
+        &self.board
+    }
+
+    /// Record a placement or removal, returning true if the board changed
+    pub fn apply_move(&mut self, mv: ChessMove, now: Instant) -> bool {
+        let square = &mut self.board.board[mv.grid as usize];
+        if *square == mv.piece {
+            return false;
+        }
+        *square = mv.piece;
+        self.last_change = now;
+        true
+    }
+
+    /// Whether the board has been left untouched for at least `settle`
+    pub fn is_stable(&self, now: Instant, settle: Duration) -> bool {
+        now.saturating_duration_since(self.last_change) >= settle
+    }
+
+    /// FEN of the composed position with the given side to move and castling rights
+    pub fn fen(&self, to_move: PieceColor, castling: Castling) -> String {
+        fen::to_fen(&self.board, self.start, to_move, castling)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setup_stability_and_fen() {
+        let t0 = Instant::now();
+        let mut setup = Setup::new(
+            ChessBoard {
+                board: [RawPiece::Empty; 64],
+            },
+            StartPosition::Mirror,
+            t0,
+        );
+        let settle = Duration::from_secs(3);
+        // a1 and h8 in the DGT layout
+        assert!(setup.apply_move(
+            ChessMove {
+                grid: 56,
+                piece: RawPiece::WhiteKing
+            },
+            t0
+        ));
+        assert!(setup.apply_move(
+            ChessMove {
+                grid: 7,
+                piece: RawPiece::BlackKing
+            },
+            t0 + Duration::from_secs(2)
+        ));
+        assert!(!setup.is_stable(t0 + Duration::from_secs(4), settle));
+        assert!(setup.is_stable(t0 + Duration::from_secs(5), settle));
+        assert_eq!(
+            setup.fen(PieceColor::Black, Castling::default()),
+            "7k/8/8/8/8/8/8/K7 b - - 0 1"
+        );
+    }
+}
@@ -0,0 +1,276 @@
+use crate::board::changes;
+use crate::clock::{ClockAck, CLOCK_MESSAGE};
+use crate::game::StartPosition;
+use crate::protocol::*;
+use crate::rules::Position;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// A DGT board in software, for running the driver end to end without hardware. It
+/// answers requests like a board with the starting position on it, and once put in
+/// update mode plays its script of moves as field updates, lift before place.
+pub struct Simulator<P> {
+    port: P,
+    board: ChessBoard,
+    /// Position once the script has been played
+    scripted: Position,
+    updates: bool,
+    /// Field updates of every scripted move still to play
+    script: VecDeque<Vec<ChessMove>>,
+    /// Data of the clock time message, no clock attached until one is shown
+    clock: [u8; 7],
+}
+
+/// The squares of `position` in the DGT documented layout, a8 first
+fn grid_board(position: &Position) -> ChessBoard {
+    let mut board = ChessBoard {
+        board: [RawPiece::Empty; 64],
+    };
+    for (sq, piece) in position.board.iter().enumerate() {
+        board.board[StartPosition::Mirror.square_grid(sq as u8) as usize] = *piece;
+    }
+    board
+}
+
+impl<P: Read + Write> Simulator<P> {
+    pub const SERIAL_NUMBER: &'static str = "SIM01";
+
+    pub fn new(port: P) -> Self {
+        let position = Position::starting();
+        Simulator {
+            port,
+            board: grid_board(&position),
+            scripted: position,
+            updates: false,
+            script: VecDeque::new(),
+            clock: [0, 0, 0, 0, 0, 0, ClockStatus::NoCock.to_byte()],
+        }
+    }
+
+    /// Queue moves in UCI notation, continuing from the moves queued before
+    pub fn script(&mut self, moves: &[String]) -> Result<(), String> {
+        let mut position = self.scripted.clone();
+        let mut board = grid_board(&position);
+        for uci in moves {
+            let ply = position
+                .legal_moves()
+                .into_iter()
+                .find(|ply| ply.uci() == *uci)
+                .ok_or_else(|| format!("illegal move {} in {}", uci, position.to_fen()))?;
+            position = position.play(&ply);
+            let next = grid_board(&position);
+            self.script.push_back(changes(&board, &next));
+            board = next;
+        }
+        self.scripted = position;
+        Ok(())
+    }
+
+    /// Whether the script has been played to the end
+    pub fn is_done(&self) -> bool {
+        self.script.is_empty()
+    }
+
+    fn reply(&mut self, message_type: MessageType, data: &[u8]) -> io::Result<()> {
+        let frame = RawFrame {
+            message_type: message_type as u8,
+            data: data.to_vec(),
+        };
+        self.port.write_all(&frame.to_bytes())
+    }
+
+    /// Answer a command the driver sent
+    pub fn handle(&mut self, command: u8) -> io::Result<()> {
+        debug!(command, "command received");
+        if command == CLOCK_MESSAGE {
+            return self.clock_message();
+        }
+        match Command::try_from(command).ok() {
+            Some(Command::Reset) => self.updates = false,
+            Some(Command::EnableUpdate | Command::RequestUpdate | Command::RequestNiceUpdate) => {
+                self.updates = true
+            }
+            Some(Command::RequestBoard) => {
+                let squares = self.board.board.map(|piece| piece as u8);
+                self.reply(MessageType::BoardDump, &squares)?;
+            }
+            Some(Command::RequestDump93) => {
+                let mut data = self.board.board.map(|piece| piece as u8).to_vec();
+                // Status byte after the squares
+                data.push(0);
+                self.reply(MessageType::BoardDump93, &data)?;
+            }
+            Some(Command::RequestClock) => self.reply(MessageType::BWTime, &self.clock.clone())?,
+            Some(Command::RequestSerialNumber) => {
+                self.reply(MessageType::SerialNumber, Self::SERIAL_NUMBER.as_bytes())?;
+            }
+            Some(Command::RequestLongSerialNumber) => {
+                self.reply(MessageType::LongSerialNumber, b"SIM0000001")?;
+            }
+            Some(Command::RequestBusAddress) => self.reply(MessageType::BusAddress, &[0, 1])?,
+            Some(Command::RequestTrademark) => {
+                self.reply(MessageType::Trademark, b"jackolope simulator")?;
+            }
+            Some(Command::RequestVersion) => self.reply(MessageType::Version, &[1, 0])?,
+            Some(Command::RequestEEMoves) => self.reply(MessageType::EEMoves, &[])?,
+            None => debug!(command, "unknown command ignored"),
+        }
+        Ok(())
+    }
+
+    /// Read the rest of a message for the clock and acknowledge it as a DGT3000 would
+    fn clock_message(&mut self) -> io::Result<()> {
+        let mut length = [0; 1];
+        self.port.read_exact(&mut length)?;
+        let mut message = vec![0; length[0] as usize];
+        self.port.read_exact(&mut message)?;
+        let ack = match message.get(1) {
+            Some(0x09) => ClockAck::Version { major: 2, minor: 2 },
+            Some(&code) => ClockAck::Done(code),
+            None => return Ok(()),
+        };
+        self.reply(MessageType::BWTime, &ack.to_data())
+    }
+
+    /// Play the next scripted move if the board is in update mode
+    pub fn step(&mut self) -> io::Result<()> {
+        if !self.updates {
+            return Ok(());
+        }
+        let Some(updates) = self.script.pop_front() else {
+            return Ok(());
+        };
+        for mv in updates {
+            self.board.board[mv.grid as usize] = mv.piece;
+            self.reply(MessageType::FieldUpdate, &[mv.grid, mv.piece as u8])?;
+        }
+        Ok(())
+    }
+
+    /// Show `board` as if its pieces had been moved there by hand, the squares that
+    /// changed go out as field updates once the driver asked for updates
+    pub fn show(&mut self, board: &ChessBoard) -> io::Result<()> {
+        let updates = changes(&self.board, board);
+        self.board = *board;
+        if self.updates {
+            for mv in updates {
+                self.reply(MessageType::FieldUpdate, &[mv.grid, mv.piece as u8])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Show the times of a clock, sent right away once the driver asked for updates
+    pub fn show_clock(
+        &mut self,
+        white: Remaining,
+        black: Remaining,
+        status: ClockStatus,
+    ) -> io::Result<()> {
+        let [w0, w1, w2] = white.to_bcd();
+        let [b0, b1, b2] = black.to_bcd();
+        self.clock = [w0, w1, w2, b0, b1, b2, status.to_byte()];
+        if self.updates {
+            self.reply(MessageType::BWTime, &self.clock.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Answer the next command if one arrives before the read timeout of the port
+    pub fn poll(&mut self) -> io::Result<()> {
+        let mut byte = [0; 1];
+        match self.port.read(&mut byte) {
+            Ok(1) => self.handle(byte[0]),
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Serve the driver forever, playing a scripted move every `interval`. The port
+    /// should have a short read timeout so moves go out while the driver is quiet.
+    pub fn run(&mut self, interval: Duration) -> io::Result<()> {
+        let mut last_move = Instant::now();
+        loop {
+            self.poll()?;
+            if self.updates && !self.is_done() && last_move.elapsed() >= interval {
+                self.step()?;
+                last_move = Instant::now();
+                if self.is_done() {
+                    info!("script finished");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::read_frame;
+    use std::io::Cursor;
+
+    /// Commands in, responses collected
+    struct Wire {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Wire {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Wire {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_simulated_board() {
+        let mut sim = Simulator::new(Wire {
+            input: Cursor::new(Vec::new()),
+            output: Vec::new(),
+        });
+        sim.script(&["e2e4".to_string()]).unwrap();
+        assert!(sim.script(&["e2e5".to_string()]).is_err());
+        sim.handle(Command::RequestSerialNumber as u8).unwrap();
+        sim.step().unwrap();
+        sim.handle(Command::RequestUpdate as u8).unwrap();
+        sim.step().unwrap();
+        assert!(sim.is_done());
+        sim.handle(Command::RequestBoard as u8).unwrap();
+
+        let mut replies = Cursor::new(sim.port.output);
+        assert!(matches!(
+            read_frame(&mut replies).unwrap(),
+            Response::SerialNumber(serial) if serial == "SIM01"
+        ));
+        assert!(matches!(
+            read_frame(&mut replies).unwrap(),
+            Response::FieldUpdate(ChessMove {
+                grid: 52,
+                piece: RawPiece::Empty
+            })
+        ));
+        assert!(matches!(
+            read_frame(&mut replies).unwrap(),
+            Response::FieldUpdate(ChessMove {
+                grid: 36,
+                piece: RawPiece::WhitePawn
+            })
+        ));
+        let Response::BoardDump(board) = read_frame(&mut replies).unwrap() else {
+            panic!("no board dump");
+        };
+        assert_eq!(board.board[36], RawPiece::WhitePawn);
+    }
+}
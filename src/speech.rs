@@ -0,0 +1,213 @@
+//! Spoken announcements for players who can not see the screen: every move in words,
+//! the result, training hints and warnings as a clock runs low. Speech is left to a command line
+//! synthesizer such as espeak, or say on macOS.
+
+use crate::game::GameBoard;
+use crate::locale::{Locale, Msg};
+use crate::pgn;
+use crate::protocol::Remaining;
+use crate::relay::{Error, Relay};
+use std::process::{Command, Stdio};
+
+/// Remaining seconds announced when a clock passes them
+const WARNINGS: [u32; 2] = [60, 10];
+
+/// The synthesizer found on most systems of the kind this is built for
+pub const DEFAULT_PROGRAM: &str = if cfg!(target_os = "macos") {
+    "say"
+} else {
+    "espeak"
+};
+
+/// A move in standard algebraic notation as words, e.g. "knight takes f3, check"
+pub fn spoken(san: &str) -> String {
+    spoken_in(san, Locale::English)
+}
+
+/// A move in standard algebraic notation as words of `locale`
+pub fn spoken_in(san: &str, locale: Locale) -> String {
+    let (san, suffix) = match san.strip_suffix('#') {
+        Some(san) => (san, Some(locale.text(Msg::Checkmate))),
+        None => match san.strip_suffix('+') {
+            Some(san) => (san, Some(locale.text(Msg::Check))),
+            None => (san, None),
+        },
+    };
+    let piece_name = |letter| piece_name(letter).map(|piece| locale.text(piece));
+    let mut words = Vec::new();
+    match san {
+        "O-O" => words.push(locale.text(Msg::CastlesKingside).to_string()),
+        "O-O-O" => words.push(locale.text(Msg::CastlesQueenside).to_string()),
+        _ => {
+            let (san, promotion) = match san.split_once('=') {
+                Some((san, piece)) => (san, piece.chars().next()),
+                None => (san, None),
+            };
+            let mut chars = san.chars().peekable();
+            if let Some(piece) = chars.peek().and_then(|c| piece_name(*c)) {
+                words.push(piece.to_string());
+                chars.next();
+            }
+            let mut square = String::new();
+            for c in chars {
+                match c {
+                    'x' => {
+                        if !square.is_empty() {
+                            words.push(std::mem::take(&mut square));
+                        }
+                        words.push(locale.text(Msg::Takes).to_string());
+                    }
+                    '@' => words.push(locale.text(Msg::DroppedOn).to_string()),
+                    c => square.push(c),
+                }
+            }
+            if !square.is_empty() {
+                words.push(square);
+            }
+            if let Some(piece) = promotion.and_then(piece_name) {
+                words.push(format!("{} {}", locale.text(Msg::PromotesTo), piece));
+            }
+        }
+    }
+    let mut text = words.join(" ");
+    if let Some(suffix) = suffix {
+        text.push_str(", ");
+        text.push_str(suffix);
+    }
+    text
+}
+
+fn piece_name(letter: char) -> Option<Msg> {
+    match letter {
+        'K' => Some(Msg::King),
+        'Q' => Some(Msg::Queen),
+        'R' => Some(Msg::Rook),
+        'B' => Some(Msg::Bishop),
+        'N' => Some(Msg::Knight),
+        'P' => Some(Msg::Pawn),
+        _ => None,
+    }
+}
+
+/// The warning due when a clock goes from `before` to `after` seconds, if it passed one
+fn passed_warning(before: u32, after: u32) -> Option<u32> {
+    WARNINGS
+        .into_iter()
+        .find(|warning| before >= *warning && after < *warning)
+}
+
+/// Speaks the game through a synthesizer program that takes the text as its argument
+pub struct Speech {
+    program: String,
+    locale: Locale,
+    /// Clock times last reported, to notice a warning being passed
+    last_clock: Option<(Remaining, Remaining)>,
+}
+
+impl Speech {
+    pub fn new(program: impl Into<String>) -> Self {
+        Speech {
+            program: program.into(),
+            locale: Locale::English,
+            last_clock: None,
+        }
+    }
+
+    /// Speak in `locale`, which the synthesizer should be set up for as well
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Say `text`, waiting until it has been said so announcements never overlap
+    fn say(&self, text: &str) -> Result<(), Error> {
+        let status = Command::new(&self.program)
+            .arg(text)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        if !status.success() {
+            return Err(format!("{} failed with {}", self.program, status).into());
+        }
+        Ok(())
+    }
+}
+
+impl Relay for Speech {
+    fn name(&self) -> &str {
+        "speech"
+    }
+
+    fn on_new_game(&mut self, _game: &GameBoard) -> Result<(), Error> {
+        self.last_clock = None;
+        self.say(self.locale.text(Msg::NewGame))
+    }
+
+    fn on_move(&mut self, game: &GameBoard) -> Result<(), Error> {
+        match pgn::last_san(game) {
+            Some(san) => self.say(&spoken_in(&san, self.locale)),
+            None => Ok(()),
+        }
+    }
+
+    fn on_clock(&mut self, white: Remaining, black: Remaining) -> Result<(), Error> {
+        let last = self.last_clock.replace((white, black));
+        let Some((last_white, last_black)) = last else {
+            return Ok(());
+        };
+        for (side, before, after) in [
+            (Msg::White, last_white, white),
+            (Msg::Black, last_black, black),
+        ] {
+            if let Some(warning) = passed_warning(before.total_seconds(), after.total_seconds()) {
+                let left = match warning {
+                    60 => self.locale.text(Msg::OneMinuteLeft).to_string(),
+                    seconds => self.locale.format(Msg::SecondsLeft, seconds),
+                };
+                self.say(&format!("{}, {}", self.locale.text(side), left))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_result(&mut self, _game: &GameBoard, result: &str) -> Result<(), Error> {
+        let text = match result {
+            "1-0" => Msg::WhiteWins,
+            "0-1" => Msg::BlackWins,
+            "1/2-1/2" => Msg::Draw,
+            _ => Msg::GameOver,
+        };
+        self.say(self.locale.text(text))
+    }
+
+    fn on_hint(&mut self, hint: &str) -> Result<(), Error> {
+        self.say(hint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spoken() {
+        assert_eq!(spoken("e4"), "e4");
+        assert_eq!(spoken("Nxf3+"), "knight takes f3, check");
+        assert_eq!(spoken("exd5"), "e takes d5");
+        assert_eq!(spoken("Rae1"), "rook ae1");
+        assert_eq!(spoken("e8=Q#"), "e8 promotes to queen, checkmate");
+        assert_eq!(spoken("O-O-O"), "castles queenside");
+        assert_eq!(spoken("N@f7"), "knight dropped on f7");
+        assert_eq!(
+            spoken_in("Nxe5+", Locale::German),
+            "Springer schlägt e5, Schach"
+        );
+        assert_eq!(
+            spoken_in("O-O#", Locale::French),
+            "petit roque, échec et mat"
+        );
+        assert_eq!(passed_warning(61, 59), Some(60));
+        assert_eq!(passed_warning(59, 58), None);
+        assert_eq!(passed_warning(10, 9), Some(10));
+    }
+}
@@ -0,0 +1,265 @@
+//! Exit statuses of the binary and the status file it writes on exit, so a supervisor
+//! such as systemd or a monitoring check can tell why the driver stopped and what it
+//! last saw without reading the log.
+
+use crate::game::{DetectionState, GameBoard};
+use crate::pgn::GameTags;
+use crate::protocol::Remaining;
+use crate::relay::{Error, Relay};
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Why the process ended, each with its own exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitStatus {
+    Success,
+    /// Any failure not told apart below
+    Failure,
+    /// The command line did not parse, the code clap exits with
+    Usage,
+    /// The board was reached but did not answer in time
+    NoAnswer,
+    /// No board or serial port where one was looked for
+    NoDevice,
+    /// The port, a file or the other end of a connection refused access
+    PermissionDenied,
+    /// Something answered, but not the way a board does
+    HandshakeFailed,
+    /// A config file, PGN, session log or message did not parse
+    ParseFailure,
+    /// Stopped with Ctrl-C, the code a shell gives for SIGINT
+    Interrupted,
+}
+
+impl ExitStatus {
+    pub fn code(self) -> i32 {
+        match self {
+            ExitStatus::Success => 0,
+            ExitStatus::Failure => 1,
+            ExitStatus::Usage => 2,
+            ExitStatus::NoAnswer => 3,
+            ExitStatus::NoDevice => 4,
+            ExitStatus::PermissionDenied => 5,
+            ExitStatus::HandshakeFailed => 6,
+            ExitStatus::ParseFailure => 7,
+            ExitStatus::Interrupted => 130,
+        }
+    }
+
+    /// The status `error` ends the process with, going by the first error in its chain
+    /// that tells
+    pub fn of(error: &(dyn std::error::Error + 'static)) -> ExitStatus {
+        let mut next = Some(error);
+        while let Some(error) = next {
+            if let Some(status) = ExitStatus::told_by(error) {
+                return status;
+            }
+            next = error.source();
+        }
+        ExitStatus::Failure
+    }
+
+    fn told_by(error: &(dyn std::error::Error + 'static)) -> Option<ExitStatus> {
+        if let Some(failure) = error.downcast_ref::<Failure>() {
+            return Some(failure.status);
+        }
+        if let Some(e) = error.downcast_ref::<serialport::Error>() {
+            return match e.kind() {
+                serialport::ErrorKind::NoDevice
+                | serialport::ErrorKind::Io(io::ErrorKind::NotFound) => Some(ExitStatus::NoDevice),
+                serialport::ErrorKind::Io(kind) => ExitStatus::of_io(kind),
+                _ => None,
+            };
+        }
+        if let Some(e) = error.downcast_ref::<io::Error>() {
+            return ExitStatus::of_io(e.kind());
+        }
+        if let Some(e) = error.downcast_ref::<crate::config::Error>() {
+            return match e {
+                crate::config::Error::Io(e) => ExitStatus::of_io(e.kind()),
+                _ => Some(ExitStatus::ParseFailure),
+            };
+        }
+        if error.is::<serde_json::Error>() || error.is::<toml::de::Error>() {
+            return Some(ExitStatus::ParseFailure);
+        }
+        None
+    }
+
+    fn of_io(kind: io::ErrorKind) -> Option<ExitStatus> {
+        match kind {
+            io::ErrorKind::PermissionDenied => Some(ExitStatus::PermissionDenied),
+            // A board that never answers leaves the first read to time out
+            io::ErrorKind::TimedOut => Some(ExitStatus::HandshakeFailed),
+            io::ErrorKind::InvalidData => Some(ExitStatus::ParseFailure),
+            _ => None,
+        }
+    }
+}
+
+/// An error that knows the status to exit with
+#[derive(Debug)]
+pub struct Failure {
+    pub status: ExitStatus,
+    message: String,
+}
+
+impl Failure {
+    pub fn new(status: ExitStatus, message: impl Into<String>) -> Self {
+        Failure {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// What the driver last knew about the board and its game
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LastState {
+    /// Type of the board, once it was opened
+    pub board: Option<String>,
+    pub serial_number: Option<String>,
+    pub tags: Option<GameTags>,
+    pub fen: Option<String>,
+    /// Moves of the game in UCI notation
+    pub moves: Vec<String>,
+    pub clock: Option<(Remaining, Remaining)>,
+    /// PGN result once the game is over
+    pub result: Option<String>,
+    /// What move detection waits for
+    pub detection: Option<DetectionState>,
+}
+
+/// Kept up to date by `Tracker` and whoever opens a board
+pub static LAST_STATE: Mutex<LastState> = Mutex::new(LastState {
+    board: None,
+    serial_number: None,
+    tags: None,
+    fen: None,
+    moves: Vec::new(),
+    clock: None,
+    result: None,
+    detection: None,
+});
+
+/// Change the last known state
+pub fn update(change: impl FnOnce(&mut LastState)) {
+    change(&mut LAST_STATE.lock().unwrap());
+}
+
+/// Follows the game into `LAST_STATE`
+pub struct Tracker;
+
+impl Tracker {
+    fn show_game(game: &GameBoard) {
+        update(|state| {
+            state.tags = Some(game.tags().clone());
+            state.fen = Some(game.fen());
+            state.moves = game.moves().iter().map(|ply| ply.uci()).collect();
+        });
+    }
+}
+
+impl Relay for Tracker {
+    fn name(&self) -> &str {
+        "status"
+    }
+
+    fn on_new_game(&mut self, game: &GameBoard) -> Result<(), Error> {
+        update(|state| state.result = None);
+        Tracker::show_game(game);
+        Ok(())
+    }
+
+    fn on_move(&mut self, game: &GameBoard) -> Result<(), Error> {
+        Tracker::show_game(game);
+        Ok(())
+    }
+
+    fn on_clock(&mut self, white: Remaining, black: Remaining) -> Result<(), Error> {
+        update(|state| state.clock = Some((white, black)));
+        Ok(())
+    }
+
+    fn on_result(&mut self, game: &GameBoard, result: &str) -> Result<(), Error> {
+        update(|state| state.result = Some(result.to_string()));
+        Tracker::show_game(game);
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct StatusFile<'a> {
+    exit_code: i32,
+    status: ExitStatus,
+    error: Option<String>,
+    /// Milliseconds since the Unix epoch
+    time_ms: u64,
+    state: &'a LastState,
+}
+
+/// Write why the process ends and `LAST_STATE` to `path` as JSON. It is written next
+/// to the file and moved over it, so a supervisor never reads half of it.
+pub fn write_status_file(
+    path: &Path,
+    status: ExitStatus,
+    error: Option<&(dyn std::error::Error + 'static)>,
+) -> io::Result<()> {
+    let state = LAST_STATE.lock().unwrap();
+    let file = StatusFile {
+        exit_code: status.code(),
+        status,
+        error: error.map(|e| e.to_string()),
+        time_ms: crate::session::now_ms(),
+        state: &state,
+    };
+    let json = serde_json::to_string_pretty(&file).map_err(io::Error::other)?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, json + "\n")?;
+    std::fs::rename(tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_status_of_errors() {
+        let interrupted: Box<dyn std::error::Error> =
+            Box::new(Failure::new(ExitStatus::Interrupted, "interrupted"));
+        assert_eq!(ExitStatus::of(interrupted.as_ref()).code(), 130);
+        let denied = serialport::Error::new(
+            serialport::ErrorKind::Io(io::ErrorKind::PermissionDenied),
+            "Permission denied",
+        );
+        assert_eq!(ExitStatus::of(&denied), ExitStatus::PermissionDenied);
+        let json = serde_json::from_str::<u32>("x").unwrap_err();
+        assert_eq!(ExitStatus::of(&json), ExitStatus::ParseFailure);
+        let other: Box<dyn std::error::Error> = "something else".into();
+        assert_eq!(ExitStatus::of(other.as_ref()), ExitStatus::Failure);
+
+        let dir = std::env::temp_dir().join(format!("jackolope-status-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("status.json");
+        update(|state| state.fen = Some("8/8/8/8/8/8/8/8 w - - 0 1".to_string()));
+        write_status_file(&path, ExitStatus::NoDevice, Some(&denied)).unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["exit_code"], 4);
+        assert_eq!(written["status"], "no_device");
+        assert_eq!(written["state"]["fen"], "8/8/8/8/8/8/8/8 w - - 0 1");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
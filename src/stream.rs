@@ -0,0 +1,262 @@
+//! The event stream for programs on the same computer: the records of the session log
+//! as JSON lines, on a Unix domain socket, a named pipe made with mkfifo, or a Windows
+//! named pipe. Consumers attach and detach whenever they like, without a port to open
+//! in the firewall, and one that falls behind is let go rather than holding up the board.
+
+use crate::event::Event;
+use crate::queue::{self, Coalesce, Overflow, Publisher, Subscription};
+use crate::relay::QUEUE_CAPACITY;
+use crate::session::SessionRecord;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::{debug, info};
+
+/// One record as a line of JSON, shared by every consumer
+#[derive(Clone)]
+struct Line(Arc<str>);
+
+/// Every event counts
+impl Coalesce for Line {
+    fn supersedes(&self, _older: &Self) -> bool {
+        false
+    }
+}
+
+type Consumers = Arc<Mutex<Vec<Publisher<Line>>>>;
+
+/// Hands every published event to the consumers attached at the time
+pub struct EventStream {
+    consumers: Consumers,
+    /// Socket file to remove once done
+    socket: Option<PathBuf>,
+}
+
+impl EventStream {
+    /// Serve on `path`. On Unix that is a named pipe if one made with mkfifo is there,
+    /// written to whenever a reader has it open, and otherwise a Unix domain socket
+    /// created there. On Windows it is the name of a pipe like `\\.\pipe\jackolope`.
+    pub fn open(path: &Path) -> io::Result<EventStream> {
+        let consumers = Consumers::default();
+        let socket = serve(path, consumers.clone())?;
+        info!(path = %path.display(), "event stream ready");
+        Ok(EventStream { consumers, socket })
+    }
+
+    /// Send `event`, stamped with `timestamp_ms`, to every consumer
+    pub fn publish(&self, timestamp_ms: u64, event: &Event) {
+        let mut consumers = self.consumers.lock().unwrap();
+        if consumers.is_empty() {
+            return;
+        }
+        let record = SessionRecord {
+            timestamp_ms,
+            event: event.clone(),
+        };
+        let line = match serde_json::to_string(&record) {
+            Ok(json) => Line((json + "\n").into()),
+            Err(e) => {
+                debug!(error = %e, "event not streamed");
+                return;
+            }
+        };
+        consumers.retain(|consumer| consumer.push(line.clone()).is_ok());
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        if let Some(socket) = &self.socket {
+            let _ = std::fs::remove_file(socket);
+        }
+    }
+}
+
+/// A new consumer of the events from now on
+fn attach(consumers: &Consumers) -> Subscription<Line> {
+    let (publisher, subscription) = queue::bounded(QUEUE_CAPACITY, Overflow::Error);
+    consumers.lock().unwrap().push(publisher);
+    subscription
+}
+
+/// Write the lines of `subscription` to `writer` until the consumer goes away or falls
+/// behind
+fn feed(mut writer: impl Write, subscription: Subscription<Line>) {
+    while let Ok(Line(line)) = subscription.recv() {
+        if let Err(e) = writer
+            .write_all(line.as_bytes())
+            .and_then(|_| writer.flush())
+        {
+            debug!(error = %e, "event consumer detached");
+            return;
+        }
+    }
+    debug!(
+        dropped = subscription.dropped(),
+        "event consumer fell behind"
+    );
+}
+
+/// Feed `writer` from a thread of its own
+fn spawn_feed(writer: impl Write + Send + 'static, consumers: &Consumers) -> io::Result<()> {
+    let subscription = attach(consumers);
+    thread::Builder::new()
+        .name("event consumer".to_string())
+        .spawn(move || feed(writer, subscription))?;
+    Ok(())
+}
+
+/// Start taking consumers at `path`, returns the socket file to remove once done
+#[cfg(unix)]
+fn serve(path: &Path, consumers: Consumers) -> io::Result<Option<PathBuf>> {
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    let file_type = std::fs::symlink_metadata(path).map(|metadata| metadata.file_type());
+    match file_type {
+        Ok(file_type) if file_type.is_fifo() => {
+            let path = path.to_path_buf();
+            thread::Builder::new()
+                .name("event stream".to_string())
+                .spawn(move || loop {
+                    // Opening waits for a reader, one at a time is all a pipe takes
+                    match OpenOptions::new().write(true).open(&path) {
+                        Ok(pipe) => feed(pipe, attach(&consumers)),
+                        Err(e) => {
+                            debug!(error = %e, "event pipe not opened");
+                            thread::sleep(std::time::Duration::from_secs(1));
+                        }
+                    }
+                })?;
+            Ok(None)
+        }
+        // Left behind by an earlier run
+        Ok(file_type) if file_type.is_socket() => {
+            std::fs::remove_file(path)?;
+            serve(path, consumers)
+        }
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} is neither a named pipe nor a socket", path.display()),
+        )),
+        Err(_) => {
+            let listener = UnixListener::bind(path)?;
+            thread::Builder::new()
+                .name("event stream".to_string())
+                .spawn(move || {
+                    for stream in listener.incoming() {
+                        let attached = stream.and_then(|stream| spawn_feed(stream, &consumers));
+                        if let Err(e) = attached {
+                            debug!(error = %e, "event consumer not attached");
+                        }
+                    }
+                })?;
+            Ok(Some(path.to_path_buf()))
+        }
+    }
+}
+
+/// Start taking consumers on the pipe named `path`, a new instance of it for each
+#[cfg(windows)]
+fn serve(path: &Path, consumers: Consumers) -> io::Result<Option<PathBuf>> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::FromRawHandle;
+    use windows_sys::Win32::Foundation::{ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::PIPE_ACCESS_OUTBOUND;
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+        PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    let name: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    let create = move || {
+        // SAFETY: the name is a NUL terminated wide string that outlives the call
+        let pipe = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_OUTBOUND,
+                PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                64 * 1024,
+                0,
+                0,
+                std::ptr::null(),
+            )
+        };
+        match pipe == INVALID_HANDLE_VALUE {
+            true => Err(io::Error::last_os_error()),
+            // SAFETY: the handle was just created and nothing else owns it
+            false => Ok(unsafe { std::fs::File::from_raw_handle(pipe) }),
+        }
+    };
+    // The first instance is made here so a bad name fails at once
+    let mut pipe = create()?;
+    thread::Builder::new()
+        .name("event stream".to_string())
+        .spawn(move || loop {
+            use std::os::windows::io::AsRawHandle;
+
+            // SAFETY: the handle is open for as long as `pipe` lives
+            let connected = unsafe { ConnectNamedPipe(pipe.as_raw_handle(), std::ptr::null_mut()) };
+            let error = io::Error::last_os_error();
+            if connected != 0 || error.raw_os_error() == Some(ERROR_PIPE_CONNECTED as i32) {
+                if let Err(e) = spawn_feed(pipe, &consumers) {
+                    debug!(error = %e, "event consumer not attached");
+                }
+            } else {
+                debug!(%error, "event consumer not attached");
+            }
+            pipe = match create() {
+                Ok(pipe) => pipe,
+                Err(e) => {
+                    debug!(error = %e, "event pipe not created");
+                    return;
+                }
+            };
+        })?;
+    Ok(None)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    #[test]
+    fn test_event_socket() {
+        let path = std::env::temp_dir().join(format!("jackolope-events-{}", std::process::id()));
+        let stream = EventStream::open(&path).unwrap();
+        // Nobody is listening yet, which is fine
+        stream.publish(1, &Event::ClockPaused);
+        let consumer = UnixStream::connect(&path).unwrap();
+        consumer
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        // The consumer is attached once the connection is taken
+        while stream.consumers.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        stream.publish(2, &Event::ClockButton(3));
+        let mut lines = BufReader::new(consumer).lines();
+        let record: SessionRecord = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(record.event, Event::ClockButton(3));
+        assert_eq!(record.timestamp_ms, 2);
+
+        // A consumer that went away is let go
+        drop(lines);
+        for timestamp_ms in 3.. {
+            stream.publish(timestamp_ms, &Event::ClockPaused);
+            if stream.consumers.lock().unwrap().is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        // The socket is gone with the stream
+        drop(stream);
+        assert!(!path.exists());
+    }
+}
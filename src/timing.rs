@@ -0,0 +1,186 @@
+//! How long the players took over their moves, from when pieces were lifted and put
+//! down and from what the clock showed.
+
+use crate::eval::Material;
+use crate::protocol::{PieceColor, Remaining};
+use crate::rules::Position;
+use std::fmt::Write;
+
+/// Material on the board, both sides together in pawns, at or below which a position
+/// counts as an endgame
+const ENDGAME_MATERIAL: i32 = 30;
+/// Moves of each side that count as the opening
+const OPENING_MOVES: u16 = 10;
+
+/// When a move was made on the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveTiming {
+    /// First lift of the move, milliseconds since the Unix epoch
+    pub lifted: u64,
+    /// The placement that completed the move
+    pub completed: u64,
+    /// Time the mover had left on the clock when the move was completed
+    pub clock: Option<Remaining>,
+}
+
+/// Part of the game a move was made in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+impl Phase {
+    /// Phase of the game a move from `position` is made in
+    pub fn of(position: &Position) -> Phase {
+        let material = Material::of(position);
+        if material.white + material.black <= ENDGAME_MATERIAL {
+            Phase::Endgame
+        } else if position.fullmove <= OPENING_MOVES {
+            Phase::Opening
+        } else {
+            Phase::Middlegame
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Opening => "opening",
+            Phase::Middlegame => "middlegame",
+            Phase::Endgame => "endgame",
+        }
+    }
+}
+
+/// Time spent on one move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveTime {
+    pub colour: PieceColor,
+    pub phase: Phase,
+    /// Milliseconds from the move before being completed, or from the first lift for
+    /// the first move timed. None for moves entered rather than played on the board.
+    pub elapsed_ms: Option<u64>,
+    /// Time the mover had left on the clock after the move
+    pub clock: Option<Remaining>,
+}
+
+/// Thinking time over a whole game, see `GameBoard::timing_stats`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TimingStats {
+    pub moves: Vec<MoveTime>,
+}
+
+impl TimingStats {
+    /// Stats of the moves played from the positions `before` them, with their timings
+    pub fn new<'a>(
+        moves: impl IntoIterator<Item = (&'a Position, Option<MoveTiming>)>,
+    ) -> TimingStats {
+        let mut previous: Option<MoveTiming> = None;
+        let moves = moves
+            .into_iter()
+            .map(|(before, timing)| {
+                let elapsed_ms = timing.map(|timing| {
+                    let since = previous.map_or(timing.lifted, |previous| previous.completed);
+                    timing.completed.saturating_sub(since)
+                });
+                previous = timing;
+                MoveTime {
+                    colour: before.to_move,
+                    phase: Phase::of(before),
+                    elapsed_ms,
+                    clock: timing.and_then(|timing| timing.clock),
+                }
+            })
+            .collect();
+        TimingStats { moves }
+    }
+
+    fn timed(&self, colour: PieceColor) -> impl Iterator<Item = (Phase, u64)> + '_ {
+        self.moves
+            .iter()
+            .filter(move |m| m.colour == colour)
+            .filter_map(|m| Some((m.phase, m.elapsed_ms?)))
+    }
+
+    /// Milliseconds `colour` spent on the moves of `phase`
+    pub fn total(&self, colour: PieceColor, phase: Phase) -> u64 {
+        self.timed(colour)
+            .filter(|(p, _)| *p == phase)
+            .map(|(_, ms)| ms)
+            .sum()
+    }
+
+    /// Average milliseconds `colour` spent on a move, None if no move of theirs was timed
+    pub fn average(&self, colour: PieceColor) -> Option<u64> {
+        let (count, sum) = self
+            .timed(colour)
+            .fold((0, 0), |(count, sum), (_, ms)| (count + 1, sum + ms));
+        (count > 0).then(|| sum / count)
+    }
+
+    /// Human readable summary: average time per move and time per phase of each side
+    pub fn summary(&self) -> String {
+        let seconds = |ms: u64| format!("{:.1}s", ms as f64 / 1000.0);
+        let mut out = String::new();
+        for (colour, name) in [(PieceColor::White, "White"), (PieceColor::Black, "Black")] {
+            let Some(average) = self.average(colour) else {
+                continue;
+            };
+            let phases: Vec<String> = [Phase::Opening, Phase::Middlegame, Phase::Endgame]
+                .into_iter()
+                .map(|phase| (phase, self.total(colour, phase)))
+                .filter(|(_, ms)| *ms > 0)
+                .map(|(phase, ms)| format!("{} {}", phase.name(), seconds(ms)))
+                .collect();
+            let _ = write!(out, "{}: {} per move", name, seconds(average));
+            if !phases.is_empty() {
+                let _ = write!(out, ", {}", phases.join(", "));
+            }
+            let clock = self
+                .moves
+                .iter()
+                .rev()
+                .find(|m| m.colour == colour)
+                .and_then(|m| m.clock);
+            if let Some(clock) = clock {
+                let _ = write!(out, ", {} left", clock);
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elapsed_per_move() {
+        let start = Position::starting();
+        let timing = |lifted, completed| {
+            Some(MoveTiming {
+                lifted,
+                completed,
+                clock: None,
+            })
+        };
+        let mut after = start.clone();
+        after.to_move = PieceColor::Black;
+        let stats = TimingStats::new([
+            (&start, timing(1_000, 3_000)),
+            (&after, timing(9_000, 10_000)),
+            (&start, None),
+            (&after, timing(20_000, 21_000)),
+        ]);
+        let elapsed: Vec<_> = stats.moves.iter().map(|m| m.elapsed_ms).collect();
+        assert_eq!(elapsed, [Some(2_000), Some(7_000), None, Some(1_000)]);
+        assert_eq!(stats.average(PieceColor::Black), Some(4_000));
+        assert_eq!(stats.total(PieceColor::White, Phase::Opening), 2_000);
+        assert_eq!(
+            stats.summary(),
+            "White: 2.0s per move, opening 2.0s\nBlack: 4.0s per move, opening 8.0s\n"
+        );
+    }
+}
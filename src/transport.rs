@@ -0,0 +1,204 @@
+use crate::board::Kind;
+use serialport::{FlowControl, SerialPort, SerialPortBuilder, SerialPortType};
+use std::thread;
+use std::time::Duration;
+use tracing::debug;
+
+/// How the board is attached to the computer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// RS-232 cable, possibly through a plain USB serial adapter
+    Serial,
+    /// Board with a built in USB serial chip
+    Usb,
+    /// Bluetooth serial profile
+    Bluetooth,
+}
+
+impl std::str::FromStr for Transport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "serial" => Ok(Transport::Serial),
+            "usb" => Ok(Transport::Usb),
+            "bluetooth" => Ok(Transport::Bluetooth),
+            _ => Err(format!("unknown transport {:?}", s)),
+        }
+    }
+}
+
+impl Transport {
+    /// Guess the transport of `port` from the port list of the operating system and the
+    /// port name, falling back to a serial line
+    pub fn detect(port: &str) -> Transport {
+        let listed = serialport::available_ports()
+            .unwrap_or_default()
+            .into_iter()
+            .find(|info| info.port_name == port);
+        Transport::of(port, listed.as_ref().map(|info| &info.port_type))
+    }
+
+    fn of(port: &str, port_type: Option<&SerialPortType>) -> Transport {
+        let lower = port.to_lowercase();
+        match port_type {
+            Some(SerialPortType::BluetoothPort) => Transport::Bluetooth,
+            _ if lower.contains("rfcomm") || lower.contains("bluetooth") => Transport::Bluetooth,
+            // Bare adapters have no line to the board other than the RS-232 cable
+            Some(SerialPortType::UsbPort(info))
+                if !info
+                    .product
+                    .as_deref()
+                    .is_some_and(|product| product.to_lowercase().contains("serial")) =>
+            {
+                Transport::Usb
+            }
+            _ => Transport::Serial,
+        }
+    }
+}
+
+/// Line settings for a board, and how to wake it up after opening the port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineControl {
+    pub flow_control: FlowControl,
+    /// Level of DTR once the port is open, None leaves it as the driver set it
+    pub dtr: Option<bool>,
+    pub rts: Option<bool>,
+    /// Drop DTR for a moment after opening, which wakes boards that sleep on the link
+    pub wake_with_dtr: bool,
+}
+
+impl LineControl {
+    /// What the board family expects on the transport. Old DGT serial boards need
+    /// hardware handshaking, USB and Bluetooth boards hang with it and want DTR raised.
+    pub fn for_board(kind: Kind, transport: Transport) -> LineControl {
+        match (kind, transport) {
+            (Kind::Dgt, Transport::Serial) => LineControl {
+                flow_control: FlowControl::Hardware,
+                dtr: None,
+                rts: None,
+                wake_with_dtr: false,
+            },
+            #[cfg(feature = "millennium")]
+            (Kind::Millennium, Transport::Serial) => LineControl {
+                flow_control: FlowControl::None,
+                dtr: None,
+                rts: None,
+                wake_with_dtr: false,
+            },
+            (_, Transport::Usb) => LineControl {
+                flow_control: FlowControl::None,
+                dtr: Some(true),
+                rts: Some(true),
+                wake_with_dtr: false,
+            },
+            (_, Transport::Bluetooth) => LineControl {
+                flow_control: FlowControl::None,
+                dtr: Some(true),
+                rts: Some(true),
+                wake_with_dtr: true,
+            },
+        }
+    }
+
+    /// Open the port described by `builder` with these settings
+    pub fn open(&self, builder: SerialPortBuilder) -> serialport::Result<Box<dyn SerialPort>> {
+        let mut port = builder.flow_control(self.flow_control).open()?;
+        if self.wake_with_dtr {
+            debug!("waking the board with DTR");
+            port.write_data_terminal_ready(false)?;
+            thread::sleep(Duration::from_millis(100));
+        }
+        if let Some(dtr) = self.dtr {
+            port.write_data_terminal_ready(dtr)?;
+        }
+        if let Some(rts) = self.rts {
+            port.write_request_to_send(rts)?;
+        }
+        if self.wake_with_dtr {
+            thread::sleep(Duration::from_millis(200));
+        }
+        Ok(port)
+    }
+}
+
+/// Port name as the operating system wants it. Bare device names get their `/dev/`
+/// directory on Unix. Windows only knows `COM1` to `COM9` by their short name, higher
+/// numbers need the `\\.\` device namespace, which works for all of them.
+pub fn port_path(name: &str) -> String {
+    port_path_for(name, cfg!(windows))
+}
+
+fn port_path_for(name: &str, windows: bool) -> String {
+    let is_com = name.len() > 3
+        && name.get(..3).is_some_and(|p| p.eq_ignore_ascii_case("com"))
+        && name[3..].bytes().all(|b| b.is_ascii_digit());
+    if windows {
+        if is_com {
+            format!(r"\\.\COM{}", &name[3..])
+        } else {
+            name.to_string()
+        }
+    } else if !is_com && !name.contains('/') {
+        format!("/dev/{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// A serial port that might have a board behind it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub port: String,
+    pub transport: Transport,
+    pub description: Option<String>,
+}
+
+/// Ports that look like a board is attached: USB serial devices, Bluetooth links and
+/// the usual names of USB serial adapters. Built in serial ports come last.
+pub fn discover() -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = serialport::available_ports()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|info| {
+            let description = match &info.port_type {
+                SerialPortType::UsbPort(usb) => Some(format!(
+                    "{:04x}:{:04x} {}",
+                    usb.vid,
+                    usb.pid,
+                    usb.product.as_deref().unwrap_or("")
+                )),
+                SerialPortType::BluetoothPort => Some("Bluetooth".to_string()),
+                _ => None,
+            };
+            Candidate {
+                transport: Transport::of(&info.port_name, Some(&info.port_type)),
+                port: info.port_name,
+                description,
+            }
+        })
+        .collect();
+    candidates.sort_by_key(|candidate| candidate.description.is_none());
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_path() {
+        assert_eq!(port_path_for("COM7", true), "\\\\.\\COM7");
+        assert_eq!(port_path_for("com12", true), "\\\\.\\COM12");
+        assert_eq!(port_path_for("\\\\.\\COM3", true), "\\\\.\\COM3");
+        assert_eq!(port_path_for("ttyUSB0", false), "/dev/ttyUSB0");
+        assert_eq!(
+            port_path_for("/dev/tty.usbserial-1120", false),
+            "/dev/tty.usbserial-1120"
+        );
+        assert_eq!(port_path_for("COM7", false), "COM7");
+        // Not cut inside a character
+        assert_eq!(port_path_for("ab€x", true), "ab€x");
+    }
+}
@@ -0,0 +1,298 @@
+use crate::protocol::PieceColor;
+use crate::rules::Position;
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use tracing::{debug, trace, warn};
+
+/// Engine score from white's point of view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Score {
+    Centipawns(i32),
+    /// Mate in this many moves, negative when black mates
+    Mate(i32),
+}
+
+impl Score {
+    /// Centipawn value with mates mapped to ±1000, enough to rate any blunder
+    pub fn to_centipawns(self) -> i32 {
+        match self {
+            Score::Centipawns(cp) => cp.clamp(-1000, 1000),
+            Score::Mate(n) if n > 0 => 1000,
+            Score::Mate(_) => -1000,
+        }
+    }
+
+    fn flipped(self) -> Score {
+        match self {
+            Score::Centipawns(cp) => Score::Centipawns(-cp),
+            Score::Mate(n) => Score::Mate(-n),
+        }
+    }
+}
+
+/// One search update from the engine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Info {
+    pub depth: u32,
+    pub score: Score,
+    /// Principal variation in UCI notation
+    pub pv: Vec<String>,
+}
+
+/// Parse an `info` line carrying a score and principal variation. The score is
+/// reported for the side to move, `to_move` turns it into white's point of view.
+/// Lines other than the best one are skipped when the engine runs with MultiPV.
+pub fn parse_info(line: &str, to_move: PieceColor) -> Option<Info> {
+    match parse_multipv(line, to_move)? {
+        (1, info) => Some(info),
+        _ => None,
+    }
+}
+
+/// Like `parse_info`, but also returns the MultiPV rank of the line, 1 for the best
+pub fn parse_multipv(line: &str, to_move: PieceColor) -> Option<(u32, Info)> {
+    let mut tokens = line.split_whitespace();
+    if tokens.next()? != "info" {
+        return None;
+    }
+    let (mut depth, mut score, mut pv, mut multipv) = (None, None, Vec::new(), 1);
+    while let Some(token) = tokens.next() {
+        match token {
+            "depth" => depth = tokens.next()?.parse().ok(),
+            "multipv" => multipv = tokens.next()?.parse().ok()?,
+            "score" => {
+                score = match tokens.next()? {
+                    "cp" => Some(Score::Centipawns(tokens.next()?.parse().ok()?)),
+                    "mate" => Some(Score::Mate(tokens.next()?.parse().ok()?)),
+                    _ => None,
+                }
+            }
+            "pv" => {
+                pv = tokens.by_ref().map(str::to_string).collect();
+            }
+            _ => {}
+        }
+    }
+    let score = score?;
+    if pv.is_empty() {
+        return None;
+    }
+    let info = Info {
+        depth: depth?,
+        score: if to_move == PieceColor::Black {
+            score.flipped()
+        } else {
+            score
+        },
+        pv,
+    };
+    Some((multipv, info))
+}
+
+/// Start an engine and complete the UCI handshake, returning its name
+fn handshake(
+    path: &Path,
+) -> io::Result<(Child, ChildStdin, BufReader<ChildStdout>, Option<String>)> {
+    let mut child = Command::new(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("piped stdin");
+    let mut stdout = BufReader::new(child.stdout.take().expect("piped stdout"));
+
+    writeln!(stdin, "uci")?;
+    let mut name = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "engine exited during handshake",
+            ));
+        }
+        trace!(line = line.trim_end(), "engine");
+        if let Some(id) = line.trim_end().strip_prefix("id name ") {
+            name = Some(id.to_string());
+        } else if line.trim_end() == "uciok" {
+            break;
+        }
+    }
+    debug!(?name, "engine ready");
+    Ok((child, stdin, stdout, name))
+}
+
+#[derive(Debug)]
+struct SearchState {
+    to_move: PieceColor,
+    /// Searches that were stopped but have not reported their best move yet
+    stale: usize,
+    searching: bool,
+}
+
+/// A UCI engine running as a child process, analysing positions until told otherwise
+pub struct Engine {
+    child: Child,
+    stdin: ChildStdin,
+    name: Option<String>,
+    state: Arc<Mutex<SearchState>>,
+}
+
+impl Engine {
+    /// Start the engine at `path` and complete the UCI handshake. `on_info` is called
+    /// from a background thread whenever the search deepens or changes its mind.
+    pub fn spawn(path: &Path, mut on_info: impl FnMut(Info) + Send + 'static) -> io::Result<Self> {
+        let (child, stdin, stdout, name) = handshake(path)?;
+        let state = Arc::new(Mutex::new(SearchState {
+            to_move: PieceColor::White,
+            stale: 0,
+            searching: false,
+        }));
+        let shared = state.clone();
+        std::thread::spawn(move || {
+            let mut last: Option<(u32, String)> = None;
+            for line in stdout.lines() {
+                let Ok(line) = line else { break };
+                trace!(line, "engine");
+                let mut state = shared.lock().unwrap();
+                if line.starts_with("bestmove") {
+                    if state.stale > 0 {
+                        state.stale -= 1;
+                    }
+                    last = None;
+                    continue;
+                }
+                if state.stale > 0 {
+                    continue;
+                }
+                let Some(info) = parse_info(&line, state.to_move) else {
+                    continue;
+                };
+                drop(state);
+                let key = (info.depth, info.pv[0].clone());
+                if last.as_ref() != Some(&key) {
+                    last = Some(key);
+                    on_info(info);
+                }
+            }
+            debug!("engine output closed");
+        });
+
+        Ok(Engine {
+            child,
+            stdin,
+            name,
+            state,
+        })
+    }
+
+    /// Name the engine reported during the handshake
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Abandon the current search and analyse `position` indefinitely
+    pub fn analyze(&mut self, position: &Position) -> io::Result<()> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.searching {
+                state.stale += 1;
+                writeln!(self.stdin, "stop")?;
+            }
+            state.to_move = position.to_move;
+            state.searching = true;
+        }
+        writeln!(self.stdin, "position fen {}", position.to_fen())?;
+        writeln!(self.stdin, "go infinite")?;
+        self.stdin.flush()
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        if writeln!(self.stdin, "quit").is_err() {
+            warn!("engine did not accept quit");
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// A UCI engine driven one fixed depth search at a time, for reviewing finished games
+pub struct Searcher {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    lines: u32,
+}
+
+impl Searcher {
+    /// Start the engine at `path`, reporting the best `lines` variations of every search
+    pub fn spawn(path: &Path, lines: u32) -> io::Result<Self> {
+        let (child, mut stdin, stdout, _) = handshake(path)?;
+        writeln!(stdin, "setoption name MultiPV value {}", lines)?;
+        Ok(Searcher {
+            child,
+            stdin,
+            stdout,
+            lines,
+        })
+    }
+
+    /// Search `position` to `depth` and return the final best lines, best first
+    pub fn search(&mut self, position: &Position, depth: u32) -> io::Result<Vec<Info>> {
+        writeln!(self.stdin, "position fen {}", position.to_fen())?;
+        writeln!(self.stdin, "go depth {}", depth)?;
+        self.stdin.flush()?;
+        let mut best: Vec<Option<Info>> = vec![None; self.lines as usize];
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "engine exited during search",
+                ));
+            }
+            trace!(line = line.trim_end(), "engine");
+            if line.starts_with("bestmove") {
+                return Ok(best.into_iter().flatten().collect());
+            }
+            if let Some((rank, info)) = parse_multipv(&line, position.to_move) {
+                if let Some(slot) = (rank as usize).checked_sub(1).and_then(|i| best.get_mut(i)) {
+                    *slot = Some(info);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Searcher {
+    fn drop(&mut self) {
+        let _ = writeln!(self.stdin, "quit");
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_info() {
+        let line = "info depth 12 seldepth 18 multipv 1 score cp -35 nodes 1000 pv e7e5 g1f3 b8c6";
+        let info = parse_info(line, PieceColor::Black).unwrap();
+        assert_eq!(info.depth, 12);
+        assert_eq!(info.score, Score::Centipawns(35));
+        assert_eq!(info.pv, ["e7e5", "g1f3", "b8c6"]);
+        assert_eq!(
+            parse_info("info depth 3 currmove e2e4", PieceColor::White),
+            None
+        );
+        let second = "info depth 12 multipv 2 score mate 3 pv e2e4";
+        assert_eq!(parse_info(second, PieceColor::White), None);
+    }
+}
@@ -0,0 +1,354 @@
+use crate::protocol::*;
+use crate::rules::{self, Ply, PlyKind, Pockets, Position, PROMOTIONS};
+use std::fmt;
+use std::sync::Arc;
+
+/// How a finished game ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    /// Atomic: the king was caught in an explosion
+    KingExploded,
+    /// Antichess: the side to move has no pieces or no moves left and wins
+    NoMovesLeft,
+    Resignation,
+    /// The players agreed to a draw
+    Agreement,
+    /// The arbiter decided the result
+    Adjudication,
+}
+
+/// Result of a finished game, `winner` is PieceColor::None for a draw
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    pub winner: PieceColor,
+    pub termination: Termination,
+}
+
+/// Rules of a chess variant, deciding which moves are legal and when the game is over
+pub trait Variant: fmt::Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// All moves available to the side to move
+    fn legal_moves(&self, position: &Position) -> Vec<Ply>;
+
+    /// The position after a legal move, variants with side effects override this
+    fn play(&self, position: &Position, ply: &Ply) -> Position {
+        position.play(ply)
+    }
+
+    /// Result of the game if it has ended in `position`
+    fn outcome(&self, position: &Position) -> Option<Outcome>;
+
+    /// Neither side can win any more, so the game is drawn
+    fn insufficient_material(&self, position: &Position) -> bool {
+        position.insufficient_material()
+    }
+
+    /// Every position can be set up with one standard set, so board dumps that need
+    /// more pieces are garbled
+    fn standard_material(&self) -> bool {
+        true
+    }
+
+    /// Find the move that turns `position` into the physical `board` (indexed from a1).
+    /// Variants can recognise board changes that are not ordinary moves here, e.g. a piece
+    /// appearing from nowhere is a drop in crazyhouse.
+    fn interpret(&self, position: &Position, board: &[RawPiece; 64]) -> Option<Ply> {
+        self.legal_moves(position)
+            .into_iter()
+            .find(|ply| self.play(position, ply).board == *board)
+    }
+}
+
+/// Look up a built-in variant by its name
+pub fn from_name(name: &str) -> Option<Arc<dyn Variant>> {
+    match name {
+        "standard" | "chess960" => Some(Arc::new(Standard)),
+        "atomic" => Some(Arc::new(Atomic)),
+        "antichess" => Some(Arc::new(Antichess)),
+        "crazyhouse" => Some(Arc::new(Crazyhouse)),
+        _ => None,
+    }
+}
+
+/// Checkmate or stalemate once the side to move has no legal moves
+fn mate_outcome(variant: &dyn Variant, position: &Position) -> Option<Outcome> {
+    if !variant.legal_moves(position).is_empty() {
+        return None;
+    }
+    Some(if position.in_check() {
+        Outcome {
+            winner: position.to_move.opposite(),
+            termination: Termination::Checkmate,
+        }
+    } else {
+        Outcome {
+            winner: PieceColor::None,
+            termination: Termination::Stalemate,
+        }
+    })
+}
+
+/// Standard chess, including Chess960 castling
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Standard;
+
+impl Variant for Standard {
+    fn name(&self) -> &'static str {
+        "standard"
+    }
+
+    fn legal_moves(&self, position: &Position) -> Vec<Ply> {
+        position.legal_moves()
+    }
+
+    fn outcome(&self, position: &Position) -> Option<Outcome> {
+        mate_outcome(self, position)
+    }
+}
+
+/// Atomic chess: captures explode every piece other than pawns next to the capture square
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Atomic;
+
+impl Variant for Atomic {
+    fn name(&self) -> &'static str {
+        "atomic"
+    }
+
+    fn legal_moves(&self, position: &Position) -> Vec<Ply> {
+        let us = position.to_move;
+        let mut moves = position.pseudo_legal_moves(&PROMOTIONS);
+        moves.extend(position.castling_moves());
+        // Kings can not capture since they would explode themselves
+        moves.retain(|ply| !(ply.piece.is_king() && ply.is_capture()));
+        moves.retain(|ply| {
+            let next = self.play(position, ply).bitboards();
+            let Some(king) = next.king(us) else {
+                return false;
+            };
+            match next.king(us.opposite()) {
+                None => true,
+                // Connected kings can not give check
+                Some(enemy) if rules::neighbours(king).any(|sq| sq == enemy) => true,
+                Some(_) => !next.is_attacked(king, us.opposite()),
+            }
+        });
+        moves
+    }
+
+    fn play(&self, position: &Position, ply: &Ply) -> Position {
+        let mut next = position.play(ply);
+        if ply.is_capture() {
+            next.board[ply.to as usize] = RawPiece::Empty;
+            for sq in rules::neighbours(ply.to) {
+                if !next.board[sq as usize].is_pawn() {
+                    next.board[sq as usize] = RawPiece::Empty;
+                }
+            }
+            next.refresh_castling();
+        }
+        next
+    }
+
+    fn outcome(&self, position: &Position) -> Option<Outcome> {
+        for colour in [PieceColor::White, PieceColor::Black] {
+            if position.king(colour).is_none() {
+                return Some(Outcome {
+                    winner: colour.opposite(),
+                    termination: Termination::KingExploded,
+                });
+            }
+        }
+        mate_outcome(self, position)
+    }
+}
+
+/// Antichess: captures are compulsory, the king has no special status and the
+/// side that runs out of moves wins
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Antichess;
+
+impl Variant for Antichess {
+    fn name(&self) -> &'static str {
+        "antichess"
+    }
+
+    fn legal_moves(&self, position: &Position) -> Vec<Ply> {
+        let mut moves = position.pseudo_legal_moves(&[
+            PieceKind::Queen,
+            PieceKind::Rook,
+            PieceKind::Bishop,
+            PieceKind::Knight,
+            PieceKind::King,
+        ]);
+        if moves.iter().any(|ply| ply.is_capture()) {
+            moves.retain(|ply| ply.is_capture());
+        }
+        moves
+    }
+
+    fn outcome(&self, position: &Position) -> Option<Outcome> {
+        self.legal_moves(position).is_empty().then_some(Outcome {
+            winner: position.to_move,
+            termination: Termination::NoMovesLeft,
+        })
+    }
+
+    fn insufficient_material(&self, _position: &Position) -> bool {
+        false
+    }
+
+    /// Pawns promote to kings
+    fn standard_material(&self) -> bool {
+        false
+    }
+}
+
+/// Crazyhouse: captured pieces change sides and can be dropped back onto the board
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crazyhouse;
+
+impl Variant for Crazyhouse {
+    fn name(&self) -> &'static str {
+        "crazyhouse"
+    }
+
+    fn legal_moves(&self, position: &Position) -> Vec<Ply> {
+        let us = position.to_move;
+        let mut moves = position.legal_moves();
+        let pocket = position.pockets.get(us);
+        // A drop changes nothing but its square, so it is made on the bitboards
+        let bitboards = position.bitboards();
+        let kinds = [
+            PieceKind::Pawn,
+            PieceKind::Knight,
+            PieceKind::Bishop,
+            PieceKind::Rook,
+            PieceKind::Queen,
+        ];
+        for kind in kinds {
+            if pocket[Pockets::slot(kind).unwrap()] == 0 {
+                continue;
+            }
+            let piece = RawPiece::from_kind(kind, us);
+            for to in 0..64u8 {
+                let back_rank = rules::rank_of(to) == 0 || rules::rank_of(to) == 7;
+                if position.piece_at(to) != RawPiece::Empty || (piece.is_pawn() && back_rank) {
+                    continue;
+                }
+                let ply = Ply {
+                    piece,
+                    from: to,
+                    to,
+                    captured: RawPiece::Empty,
+                    kind: PlyKind::Drop,
+                };
+                let next = bitboards.play(&ply);
+                if next
+                    .king(us)
+                    .is_none_or(|k| !next.is_attacked(k, us.opposite()))
+                {
+                    moves.push(ply);
+                }
+            }
+        }
+        moves
+    }
+
+    fn play(&self, position: &Position, ply: &Ply) -> Position {
+        let us = position.to_move;
+        let mut next = position.play(ply);
+        if ply.kind == PlyKind::Drop {
+            if let Some(slot) = ply.piece.kind().and_then(Pockets::slot) {
+                let count = &mut next.pockets.get_mut(us)[slot];
+                *count = count.saturating_sub(1);
+            }
+        } else if ply.is_capture() {
+            // Promoted pieces go back to the pocket as pawns
+            let promoted = position.promoted & (1u64 << ply.capture_square()) != 0;
+            let kind = if promoted {
+                Some(PieceKind::Pawn)
+            } else {
+                ply.captured.kind()
+            };
+            if let Some(slot) = kind.and_then(Pockets::slot) {
+                next.pockets.get_mut(us)[slot] += 1;
+            }
+        }
+        next
+    }
+
+    fn outcome(&self, position: &Position) -> Option<Outcome> {
+        mate_outcome(self, position)
+    }
+
+    /// Captured pieces can always be dropped back in
+    fn insufficient_material(&self, _position: &Position) -> bool {
+        false
+    }
+
+    /// Dropped pieces come from a second set
+    fn standard_material(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::parse_square;
+
+    fn find(variant: &dyn Variant, position: &Position, uci: &str) -> Ply {
+        variant
+            .legal_moves(position)
+            .into_iter()
+            .find(|ply| ply.uci() == uci)
+            .unwrap_or_else(|| panic!("{} not legal", uci))
+    }
+
+    #[test]
+    fn test_atomic_explosion() {
+        let position = Position::from_fen("4k3/8/8/3pn3/4P3/8/8/4K3 w - - 0 1").unwrap();
+        let next = Atomic.play(&position, &find(&Atomic, &position, "e4d5"));
+        // Capturer, captured pawn and the neighbouring knight are gone
+        assert_eq!(next.to_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+    }
+
+    #[test]
+    fn test_atomic_king_explodes() {
+        let position = Position::from_fen("3qk3/8/8/8/8/8/8/3QK3 w - - 0 1").unwrap();
+        let next = Atomic.play(&position, &find(&Atomic, &position, "d1d8"));
+        assert_eq!(
+            Atomic.outcome(&next),
+            Some(Outcome {
+                winner: PieceColor::White,
+                termination: Termination::KingExploded
+            })
+        );
+    }
+
+    #[test]
+    fn test_antichess_forced_capture() {
+        let position = Position::from_fen("8/8/8/3p4/4P3/8/8/R7 w - - 0 1").unwrap();
+        let moves = Antichess.legal_moves(&position);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].uci(), "e4d5");
+    }
+
+    #[test]
+    fn test_crazyhouse_drop() {
+        let position = Position::from_fen("4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        let after = Crazyhouse.play(&position, &find(&Crazyhouse, &position, "e4d5"));
+        assert_eq!(after.pockets.white[1], 1);
+        let after = Crazyhouse.play(&after, &find(&Crazyhouse, &after, "e8d7"));
+        // The knight appears from nowhere on f3
+        let mut board = after.board;
+        board[parse_square("f3").unwrap() as usize] = RawPiece::WhiteKnight;
+        let ply = Crazyhouse.interpret(&after, &board).unwrap();
+        assert_eq!(ply.uci(), "N@f3");
+        assert_eq!(Crazyhouse.play(&after, &ply).pockets.white[1], 0);
+    }
+}
@@ -0,0 +1,131 @@
+//! Bindings for browser apps, built with
+//! `wasm-pack build --target web -- --features wasm`.
+//!
+//! The browser owns the serial port through the Web Serial API, see
+//! `web/serial.js`. It writes the bytes of `WebBoard::command` to the port and hands
+//! whatever it reads to `WebBoard::feed`, which frames, decodes and follows the game
+//! with the same code as the native driver.
+
+use crate::event::Event;
+use crate::game::{GameBoard, SyncState};
+use crate::pgn;
+use crate::protocol::*;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct WebBoard {
+    framer: Framer,
+    /// Game on the board, from the first board dump on
+    game: Option<GameBoard>,
+}
+
+/// Apply a field update, a draw that the moves it completes bring about
+fn follow(game: &mut GameBoard, mv: ChessMove) -> Option<Event> {
+    game.apply_move(mv)?;
+    let mut draw = None;
+    while let SyncState::Moved(_) = game.sync() {
+        draw = game.draw();
+    }
+    draw
+}
+
+#[wasm_bindgen]
+impl WebBoard {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WebBoard {
+        WebBoard::default()
+    }
+
+    /// Bytes of the command with this name, e.g. "RequestBoard"
+    pub fn command(name: &str) -> Result<Vec<u8>, JsError> {
+        let command: Command = name.parse().map_err(|e: String| JsError::new(&e))?;
+        Ok(command.as_byte().to_vec())
+    }
+
+    /// Commands to write once the port is open, one at a time with the pauses of
+    /// `Pacing`: reset, ask for the position and then for every change
+    #[wasm_bindgen(js_name = startCommands)]
+    pub fn start_commands() -> Vec<u8> {
+        [
+            Command::Reset,
+            Command::RequestBoard,
+            Command::RequestUpdate,
+        ]
+        .iter()
+        .map(|command| *command as u8)
+        .collect()
+    }
+
+    /// Take bytes read from the port, returning the events they complete as a JSON
+    /// array
+    pub fn feed(&mut self, bytes: &[u8]) -> String {
+        let mut events = Vec::new();
+        for response in self.framer.feed(bytes) {
+            let Some(event) = Event::from_response(response) else {
+                continue;
+            };
+            let draw = match (&event, &mut self.game) {
+                (Event::BoardDump(board), _) => {
+                    self.game = Some(GameBoard::new(*board));
+                    None
+                }
+                (Event::FieldUpdate(mv), Some(game)) => follow(game, *mv),
+                _ => None,
+            };
+            events.push(event);
+            events.extend(draw);
+        }
+        serde_json::to_string(&events).unwrap_or_default()
+    }
+
+    /// FEN of the game position, undefined before the board was read
+    pub fn fen(&self) -> Option<String> {
+        self.game.as_ref().map(GameBoard::fen)
+    }
+
+    /// Moves played so far
+    #[wasm_bindgen(js_name = moveCount)]
+    pub fn move_count(&self) -> usize {
+        self.game.as_ref().map_or(0, |game| game.moves().len())
+    }
+
+    /// The last move in standard algebraic notation
+    #[wasm_bindgen(js_name = lastMove)]
+    pub fn last_move(&self) -> Option<String> {
+        self.game.as_ref().and_then(pgn::last_san)
+    }
+
+    /// The game so far as PGN
+    pub fn pgn(&self) -> Option<String> {
+        self.game.as_ref().map(pgn::to_pgn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_web_board() {
+        let board: ChessBoard = serde_json::from_str(&format!(
+            "\"rnbqkbnrpppppppp{}PPPPPPPPRNBQKBNR\"",
+            " ".repeat(32)
+        ))
+        .unwrap();
+        let dump = RawFrame {
+            message_type: MessageType::BoardDump as u8,
+            data: board.board.map(|piece| piece as u8).to_vec(),
+        };
+        let mut web = WebBoard::new();
+        let mut bytes = dump.to_bytes();
+        bytes.extend([0x8e, 0x00, 0x05, 52, 0x00, 0x8e, 0x00, 0x05, 36]);
+        assert!(web.feed(&bytes).starts_with("[{\"BoardDump\""));
+        assert_eq!(web.move_count(), 0);
+        let events = web.feed(&[0x01]);
+        assert!(events.contains("\"grid\":36"));
+        assert_eq!(web.move_count(), 1);
+        assert_eq!(web.last_move().as_deref(), Some("e4"));
+        assert_eq!(WebBoard::start_commands(), vec![0x40, 0x42, 0x44]);
+    }
+}
@@ -0,0 +1,123 @@
+//! Zobrist keys of positions. Every feature of a position has a random key and a
+//! position's key is the XOR of the keys of its features, so a move only has to XOR
+//! out what it changed and XOR in what it created.
+
+use crate::protocol::{PieceColor, RawPiece};
+use crate::rules::{self, Position};
+
+/// One step of the splitmix64 generator, the new state and its output
+const fn splitmix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (state, z ^ (z >> 31))
+}
+
+const fn keys<const N: usize>(seed: u64) -> [u64; N] {
+    let mut out = [0; N];
+    let mut state = seed;
+    let mut i = 0;
+    while i < N {
+        let (next, key) = splitmix(state);
+        state = next;
+        out[i] = key;
+        i += 1;
+    }
+    out
+}
+
+/// Indexed by piece code minus one, then square
+const PIECES: [u64; 12 * 64] = keys(1);
+const BLACK_TO_MOVE: u64 = keys::<1>(2)[0];
+/// White short, white long, black short, black long
+const CASTLING: [u64; 4] = keys(3);
+/// Indexed by the file of the en passant square
+const EN_PASSANT: [u64; 8] = keys(4);
+/// Indexed by colour, pocket slot, then the number of pieces in it
+const POCKETS: [u64; 2 * 5 * 17] = keys(5);
+
+fn piece(square: usize, piece: RawPiece) -> u64 {
+    match piece {
+        RawPiece::Empty => 0,
+        piece => PIECES[(piece as usize - 1) * 64 + square],
+    }
+}
+
+/// Key of everything but the pieces on the board
+fn state(position: &Position) -> u64 {
+    let mut key = 0;
+    if position.to_move == PieceColor::Black {
+        key ^= BLACK_TO_MOVE;
+    }
+    let castling = &position.castling;
+    let rights = [
+        castling.white_short,
+        castling.white_long,
+        castling.black_short,
+        castling.black_long,
+    ];
+    for (right, castling_key) in rights.into_iter().zip(CASTLING) {
+        if right {
+            key ^= castling_key;
+        }
+    }
+    if let Some(square) = position.en_passant {
+        key ^= EN_PASSANT[rules::file_of(square) as usize];
+    }
+    let pockets = [position.pockets.white, position.pockets.black];
+    for (colour, pocket) in pockets.iter().enumerate() {
+        for (slot, count) in pocket.iter().enumerate() {
+            if *count > 0 {
+                key ^= POCKETS[(colour * 5 + slot) * 17 + (*count as usize).min(16)];
+            }
+        }
+    }
+    key
+}
+
+/// Key of everything that makes two positions the same for the repetition rules:
+/// placement, side to move, castling rights, en passant and pockets
+pub fn hash(position: &Position) -> u64 {
+    position
+        .board
+        .iter()
+        .enumerate()
+        .fold(state(position), |key, (square, p)| key ^ piece(square, *p))
+}
+
+/// The key of `after` from the key of `before`, touching only the squares that differ
+pub fn advance(key: u64, before: &Position, after: &Position) -> u64 {
+    let mut key = key ^ state(before) ^ state(after);
+    for (square, (old, new)) in before.board.iter().zip(&after.board).enumerate() {
+        if old != new {
+            key ^= piece(square, *old) ^ piece(square, *new);
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance_matches_hash() {
+        let mut position = Position::starting();
+        let mut key = hash(&position);
+        // En passant, captures and castling on both sides
+        let line = "e2e4 g8f6 e4e5 d7d5 e5d6 e7d6 g1f3 f8e7 f1c4 e8g8 e1g1";
+        for uci in line.split(' ') {
+            let ply = position
+                .legal_moves()
+                .into_iter()
+                .find(|ply| ply.uci() == uci)
+                .unwrap();
+            let next = position.play(&ply);
+            key = advance(key, &position, &next);
+            assert_eq!(key, hash(&next), "after {}", uci);
+            position = next;
+        }
+        assert_ne!(key, hash(&Position::starting()));
+    }
+}
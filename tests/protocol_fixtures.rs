@@ -0,0 +1,87 @@
+//! Protocol regression tests over the captured byte streams in `tests/fixtures/`.
+//!
+//! Every `.dgt` fixture holds the bytes a board sent on `>` lines, as hex, and the
+//! responses they decode to on `<` lines, in their `Debug` form. Frames that are
+//! rejected are listed as `! <error>`. Lines starting with `#` are comments.
+
+use jackolope::board::read_frame;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+
+struct Fixture {
+    input: Vec<u8>,
+    expected: Vec<String>,
+}
+
+fn load(path: &Path) -> Fixture {
+    let text = fs::read_to_string(path).unwrap();
+    let mut fixture = Fixture {
+        input: Vec::new(),
+        expected: Vec::new(),
+    };
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if let Some(hex) = line.strip_prefix('>') {
+            for byte in hex.split_whitespace() {
+                let byte = u8::from_str_radix(byte, 16).unwrap_or_else(|_| {
+                    panic!("{}:{}: bad hex byte {:?}", path.display(), number + 1, byte)
+                });
+                fixture.input.push(byte);
+            }
+        } else if line.starts_with('<') || line.starts_with('!') {
+            fixture.expected.push(line.to_string());
+        } else if !line.is_empty() && !line.starts_with('#') {
+            panic!(
+                "{}:{}: unexpected line {:?}",
+                path.display(),
+                number + 1,
+                line
+            );
+        }
+    }
+    fixture
+}
+
+/// Decode `input` to the fixture's notation, until the bytes run out
+fn decode(input: &[u8]) -> Vec<String> {
+    let mut reader = Cursor::new(input);
+    let mut decoded = Vec::new();
+    loop {
+        match read_frame(&mut reader) {
+            Ok(response) => decoded.push(format!("< {:?}", response)),
+            Err(e) => match e.downcast_ref::<io::Error>() {
+                Some(e) if e.kind() == io::ErrorKind::UnexpectedEof => return decoded,
+                _ => decoded.push(format!("! {}", e)),
+            },
+        }
+    }
+}
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "dgt"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn test_fixtures() {
+    let paths = fixtures();
+    assert!(!paths.is_empty(), "no fixtures found");
+    for path in paths {
+        let fixture = load(&path);
+        let decoded = decode(&fixture.input);
+        assert_eq!(
+            decoded,
+            fixture.expected,
+            "{} decodes to\n{}",
+            path.display(),
+            decoded.join("\n")
+        );
+    }
+}
@@ -0,0 +1,54 @@
+// Follow a DGT board from the browser over the Web Serial API, with the framing and
+// game logic of the crate built as WebAssembly:
+//
+//   wasm-pack build --target web -- --features wasm
+//
+//   import { connect } from "./serial.js";
+//   const board = await connect((event, web) => console.log(event, web.fen()));
+//   ...
+//   await board.close();
+//
+// `connect` has to be called from a user gesture, as the browser asks which port to use.
+
+import init, { WebBoard } from "../pkg/jackolope.js";
+
+export async function connect(onEvent, { baudRate = 9600 } = {}) {
+  await init();
+  const port = await navigator.serial.requestPort();
+  await port.open({ baudRate });
+  const web = new WebBoard();
+  const writer = port.writable.getWriter();
+  const reader = port.readable.getReader();
+
+  const send = (bytes) => writer.write(new Uint8Array(bytes));
+  const reading = (async () => {
+    for (;;) {
+      const { value, done } = await reader.read();
+      if (done) {
+        break;
+      }
+      for (const event of JSON.parse(web.feed(value))) {
+        onEvent(event, web);
+      }
+    }
+  })();
+
+  // The board drops commands that follow each other too closely, and needs longer
+  // after a reset
+  for (const byte of WebBoard.startCommands()) {
+    await send([byte]);
+    await new Promise((resolve) => setTimeout(resolve, byte === 0x40 ? 100 : 20));
+  }
+  return {
+    web,
+    // Send a command by name, e.g. "RequestVersion"
+    command: (name) => send(WebBoard.command(name)),
+    async close() {
+      await reader.cancel();
+      await reading;
+      reader.releaseLock();
+      writer.releaseLock();
+      await port.close();
+    },
+  };
+}